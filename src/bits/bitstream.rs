@@ -13,11 +13,11 @@ use std::fmt::Display;
 /// # Fields
 ///
 /// * 'len' - A u32 value representing the number of bits in
-///         the bistream.
+///   the bistream.
 /// * 'bits' - A Vec<u8> containing bytes within which the bitstream
-///         is stored, the vector will always be of length len/8 rounded
-///         up. Bits are stored left to right, with the most significant
-///         bit at the left.
+///   is stored, the vector will always be of length len/8 rounded
+///   up. Bits are stored left to right, with the most significant
+///   bit at the left.
 ///
 /// # Methods
 ///
@@ -26,14 +26,13 @@ use std::fmt::Display;
 ///
 /// # Examples
 ///
-/// '''
-/// let stream = BitStream::new();
-///
-/// print!("{}", bitstream); // output: 0: 00000000
+/// ```
+/// # use chameleon::bits::bitstream::BitStream;
+/// let mut stream = BitStream::new();
 /// stream.push(1);
-/// print!("{}", bitstream); // output: 0: 10000000
 ///
-/// '''
+/// assert_eq!(stream.to_string(), "0: 10000000\n");
+/// ```
 pub struct BitStream {
     pub len: u32,
     pub bytes: Vec<u8>,
@@ -48,10 +47,11 @@ impl BitStream {
     ///
     /// # Examples
     ///
-    /// '''
-    /// let stream = BitStream::new();
+    /// ```
+    /// # use chameleon::bits::bitstream::BitStream;
+    /// let mut stream = BitStream::new();
     /// stream.push(0);
-    /// '''
+    /// ```
     pub fn new() -> Self {
         Self {
             len: 0,
@@ -108,33 +108,33 @@ impl BitStream {
     /// RFC 1951 Section 3.1.1 describes the process of packing
     /// the bits into bytes as follows:
     ///
-    ///     * Data elements are packed into bytes in order of
-    ///     increasing bit number within the byte, i.e., starting
-    ///     with the least-significant bit of the byte.
+    /// * Data elements are packed into bytes in order of
+    ///   increasing bit number within the byte, i.e., starting
+    ///   with the least-significant bit of the byte.
     ///
-    ///     * Data elements other than Huffman codes are packed
-    ///     starting with the least-significant bit of the data
-    ///     element.
+    /// * Data elements other than Huffman codes are packed
+    ///   starting with the least-significant bit of the data
+    ///   element.
     ///
-    ///     * Huffman codes are packed starting with the most-
-    ///    significant bit of the code.
+    /// * Huffman codes are packed starting with the most-
+    ///   significant bit of the code.
     ///
     /// So, to reverse the parsing process, from the final compressed
     /// bytes to the original bit stream the process would be:
     ///
-    ///     * Print out the compressed data starting with the first byte
-    ///     at the right and proceeding left.
+    /// * Print out the compressed data starting with the first byte
+    ///   at the right and proceeding left.
     ///
-    ///     [0xED, 0x02] -> [0x02, 0xED]
+    ///   [0xED, 0x02] -> [0x02, 0xED]
     ///
-    ///     * Convert to bits with the most significant bit at the left.
+    /// * Convert to bits with the most significant bit at the left.
     ///
-    ///     [0x02, 0xED] -> [0b0000_0010, 0b1110_1101]
+    ///   [0x02, 0xED] -> [0b0000_0010, 0b1110_1101]
     ///
-    ///     * Now the bitstream can be read from right to left, so visualizing
-    ///     it from left to right looks like:
+    /// * Now the bitstream can be read from right to left, so visualizing
+    ///   it from left to right looks like:
     ///
-    ///      -> 1011_0111_01
+    ///   -> 1011_0111_01
     ///
     /// This function performs the inverse of this operation assuming.
     /// the huffman codes have already been pushed in with the right orientation.
@@ -1 +1,3 @@
 pub mod bitstream;
+pub mod reader;
+pub mod writer;
@@ -0,0 +1,340 @@
+//! Least significant bit first reader for walking DEFLATE
+//! streams as described in RFC 1951 section 3.1.1.
+//!
+use std::io::{self, Read};
+
+/// The operations needed to decode DEFLATE, shared by BitReader for
+/// data already in memory and StreamBitReader for data read from a
+/// stream, see BitReader for what each does.
+pub trait BitSource {
+    /// Returns the next 'bits' bits without consuming them, along with
+    /// how many of them are actually backed by input.
+    fn peek_bits(&mut self, bits: u32) -> (u32, u32);
+
+    /// Discards 'bits' bits which have previously been peeked at.
+    fn consume(&mut self, bits: u32);
+
+    /// Discards the remaining bits of the partially read byte.
+    fn align_to_byte(&mut self);
+
+    /// The total number of bits which have been consumed.
+    fn bit_position(&self) -> usize;
+
+    /// Reads 'bits' bits as an integer, None if the input ran out first.
+    fn read_bits(&mut self, bits: u32) -> Option<u32> {
+        if bits == 0 {
+            return Some(0);
+        }
+        let (value, available) = self.peek_bits(bits);
+        if available < bits {
+            return None;
+        }
+        self.consume(bits);
+        Some(value)
+    }
+}
+
+/// Struct for reading bits out of a byte slice in the order
+/// DEFLATE packs them: starting with the least significant bit
+/// of the first byte. Bytes are loaded into a small buffer so
+/// that multiple bits can be peeked at once for Huffman decoding.
+///
+/// # Fields
+///
+/// * 'bytes' - The byte slice being read.
+/// * 'position' - Index of the next byte to be loaded into the buffer.
+/// * 'buffer' - Loaded bits which have not been consumed yet, the next
+///   bit to be read is the least significant.
+/// * 'count' - The number of valid bits in the buffer.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::bits::reader::BitReader;
+/// let mut reader = BitReader::new(&[0b1010_1101]);
+///
+/// assert_eq!(reader.read_bits(3), Some(0b101));
+/// assert_eq!(reader.read_bits(5), Some(0b10101));
+/// assert_eq!(reader.read_bits(1), None);
+/// ```
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    /// Creates a new reader starting at the first bit of the slice.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// Loads whole bytes into the buffer until it either holds at
+    /// least 'bits' bits or the input is exhausted.
+    fn refill(&mut self, bits: u32) {
+        while self.count < bits && self.position < self.bytes.len() {
+            self.buffer |= (self.bytes[self.position] as u64) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+    }
+
+    /// Returns the next 'bits' bits without consuming them, along with
+    /// how many of them are actually backed by input. Missing bits past
+    /// the end of the input are read as zeroes.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bits' - How many bits to peek, at most 32.
+    pub fn peek_bits(&mut self, bits: u32) -> (u32, u32) {
+        self.refill(bits);
+        let mask = (1u64 << bits) - 1;
+        ((self.buffer & mask) as u32, self.count.min(bits))
+    }
+
+    /// Discards 'bits' bits which have previously been peeked at.
+    pub fn consume(&mut self, bits: u32) {
+        let bits = bits.min(self.count);
+        self.buffer >>= bits;
+        self.count -= bits;
+    }
+
+    /// Reads 'bits' bits as an integer, with the first bit read being
+    /// the least significant, as is used for every DEFLATE element other
+    /// than Huffman codes.
+    ///
+    /// # Returns
+    ///
+    /// The value read, or None if the input ran out first.
+    pub fn read_bits(&mut self, bits: u32) -> Option<u32> {
+        if bits == 0 {
+            return Some(0);
+        }
+        let (value, available) = self.peek_bits(bits);
+        if available < bits {
+            return None;
+        }
+        self.consume(bits);
+        Some(value)
+    }
+
+    /// Discards the remaining bits of the partially read byte, used
+    /// before stored blocks and at the end of a stream.
+    pub fn align_to_byte(&mut self) {
+        let partial = self.count % 8;
+        self.consume(partial);
+    }
+
+    /// Reads 'length' whole bytes, the reader has to be byte aligned.
+    ///
+    /// # Returns
+    ///
+    /// A slice of the bytes read, or None if the input ran out first.
+    pub fn read_bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        // Hand any buffered bytes back to the slice before reading.
+        let start = self.byte_position();
+        let end = start.checked_add(length)?;
+        if end > self.bytes.len() {
+            return None;
+        }
+        self.position = end;
+        self.buffer = 0;
+        self.count = 0;
+        Some(&self.bytes[start..end])
+    }
+
+    /// The index of the byte containing the next unread bit, or once
+    /// aligned, the index of the next unread byte.
+    pub fn byte_position(&self) -> usize {
+        self.position - self.count.div_ceil(8) as usize
+    }
+
+    /// The total number of bits which have been consumed.
+    pub fn bit_position(&self) -> usize {
+        self.position * 8 - self.count as usize
+    }
+}
+
+impl BitSource for BitReader<'_> {
+    fn peek_bits(&mut self, bits: u32) -> (u32, u32) {
+        BitReader::peek_bits(self, bits)
+    }
+
+    fn consume(&mut self, bits: u32) {
+        BitReader::consume(self, bits)
+    }
+
+    fn align_to_byte(&mut self) {
+        BitReader::align_to_byte(self)
+    }
+
+    fn bit_position(&self) -> usize {
+        BitReader::bit_position(self)
+    }
+}
+
+/// The number of bytes a StreamBitReader reads from its stream at once.
+const STREAM_BUFFER: usize = 8192;
+
+/// Same as BitReader, but pulling bytes from a stream as they are
+/// needed rather than holding all of the input.
+///
+/// # Fields
+///
+/// * 'reader' - The stream being read.
+/// * 'input' - Bytes read from the stream which have not been loaded
+///   into the bit buffer yet, from 'start' to 'end'.
+/// * 'buffer' - Loaded bits which have not been consumed yet, the next
+///   bit to be read is the least significant.
+/// * 'count' - The number of valid bits in the buffer.
+/// * 'consumed' - The total number of bits consumed.
+/// * 'error' - The error which stopped reading the stream, which is
+///   otherwise treated as having ended.
+/// * 'mark' - The buffer, count and consumed bits at the last mark.
+/// * 'replay' - The bytes taken from the stream since the last mark, to
+///   be read again if the reader is reset to it.
+pub struct StreamBitReader<R> {
+    reader: R,
+    input: Vec<u8>,
+    start: usize,
+    end: usize,
+    buffer: u64,
+    count: u32,
+    consumed: usize,
+    error: Option<io::Error>,
+    mark: Option<(u64, u32, usize)>,
+    replay: Vec<u8>,
+}
+
+impl<R: Read> StreamBitReader<R> {
+    /// Creates a new reader starting at the next byte of the stream.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            input: vec![0; STREAM_BUFFER],
+            start: 0,
+            end: 0,
+            buffer: 0,
+            count: 0,
+            consumed: 0,
+            error: None,
+            mark: None,
+            replay: Vec::new(),
+        }
+    }
+
+    /// Returns the next byte of the stream, None once it has ended or
+    /// failed.
+    fn next_byte(&mut self) -> Option<u8> {
+        while self.start == self.end {
+            if self.error.is_some() {
+                return None;
+            }
+            match self.reader.read(&mut self.input) {
+                Ok(0) => return None,
+                Ok(read) => (self.start, self.end) = (0, read),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => self.error = Some(e),
+            }
+        }
+        self.start += 1;
+        let byte = self.input[self.start - 1];
+        if self.mark.is_some() {
+            self.replay.push(byte);
+        }
+        Some(byte)
+    }
+
+    /// Takes the error which stopped reading the stream, if there was
+    /// one, to report instead of the input having ended.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Returns a mutable reference to the stream. Bytes already read
+    /// from it which have not been consumed are kept by this reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Returns the stream, dropping any bytes already read from it which
+    /// have not been consumed.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Whether the stream has run dry for now, having returned
+    /// io::ErrorKind::WouldBlock rather than ending.
+    pub fn is_blocked(&self) -> bool {
+        matches!(&self.error, Some(e) if e.kind() == io::ErrorKind::WouldBlock)
+    }
+
+    /// Remembers the current position, so that the reader can be reset
+    /// to it if the stream runs dry partway through an element. As the
+    /// stream may have more to give by now, it is no longer blocked.
+    pub fn mark(&mut self) {
+        if self.is_blocked() {
+            self.error = None;
+        }
+        self.mark = Some((self.buffer, self.count, self.consumed));
+        self.replay.clear();
+    }
+
+    /// Returns to the position of the last mark, so that the bytes taken
+    /// from the stream since are read again, and unblocks the stream.
+    pub fn reset(&mut self) {
+        let Some((buffer, count, consumed)) = self.mark.take() else {
+            return;
+        };
+        (self.buffer, self.count, self.consumed) = (buffer, count, consumed);
+
+        // Put the bytes read since the mark back in front of those which
+        // have not been loaded yet.
+        let mut input = std::mem::take(&mut self.replay);
+        input.extend_from_slice(&self.input[self.start..self.end]);
+        (self.start, self.end) = (0, input.len());
+        input.resize(input.len().max(STREAM_BUFFER), 0);
+        self.replay = std::mem::replace(&mut self.input, input);
+        self.replay.clear();
+
+        if self.is_blocked() {
+            self.error = None;
+        }
+    }
+}
+
+impl<R: Read> BitSource for StreamBitReader<R> {
+    fn peek_bits(&mut self, bits: u32) -> (u32, u32) {
+        while self.count < bits {
+            let Some(byte) = self.next_byte() else {
+                break;
+            };
+            self.buffer |= (byte as u64) << self.count;
+            self.count += 8;
+        }
+        let mask = (1u64 << bits) - 1;
+        ((self.buffer & mask) as u32, self.count.min(bits))
+    }
+
+    fn consume(&mut self, bits: u32) {
+        let bits = bits.min(self.count);
+        self.buffer >>= bits;
+        self.count -= bits;
+        self.consumed += bits as usize;
+    }
+
+    fn align_to_byte(&mut self) {
+        let partial = self.count % 8;
+        self.consume(partial);
+    }
+
+    fn bit_position(&self) -> usize {
+        self.consumed
+    }
+}
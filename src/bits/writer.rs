@@ -0,0 +1,105 @@
+//! Least significant bit first writer for producing DEFLATE streams,
+//! the counterpart of the BitReader.
+//!
+
+/// Struct for packing bits into bytes in the order DEFLATE expects:
+/// starting with the least significant bit of each byte.
+///
+/// # Fields
+///
+/// * 'bytes' - The completed bytes written so far.
+/// * 'buffer' - Bits which do not fill a byte yet, the oldest bit being
+///   the least significant.
+/// * 'count' - The number of valid bits in the buffer.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::bits::writer::BitWriter;
+/// let mut writer = BitWriter::new();
+/// writer.write_bits(0b101, 3);
+/// writer.write_bits(0b10101, 5);
+///
+/// assert_eq!(writer.into_bytes(), vec![0b1010_1101]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Creates a new empty writer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the lowest 'bits' bits of value, least significant first,
+    /// as is used for every DEFLATE element other than Huffman codes.
+    ///
+    /// # Arguments
+    ///
+    /// * 'value' - The value to write, bits above 'bits' are ignored.
+    /// * 'bits' - How many bits to write, at most 32.
+    pub fn write_bits(&mut self, value: u32, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        let mask = (1u64 << bits) - 1;
+        self.buffer |= (value as u64 & mask) << self.count;
+        self.count += bits;
+
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which is packed starting with its most
+    /// significant bit.
+    ///
+    /// # Arguments
+    ///
+    /// * 'code' - The code as assigned by canonical_codes.
+    /// * 'length' - The length of the code in bits.
+    pub fn write_code(&mut self, code: u16, length: u8) {
+        if length == 0 {
+            return;
+        }
+        let reversed = code.reverse_bits() >> (16 - length as u32);
+        self.write_bits(reversed as u32, length as u32);
+    }
+
+    /// Pads the partially written byte with zeroes.
+    pub fn align_to_byte(&mut self) {
+        if self.count > 0 {
+            self.write_bits(0, 8 - self.count);
+        }
+    }
+
+    /// Writes whole bytes, the writer has to be byte aligned.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        debug_assert!(self.count == 0, "write_bytes called while unaligned.");
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// The total number of bits written.
+    pub fn bit_len(&self) -> usize {
+        self.bytes.len() * 8 + self.count as usize
+    }
+
+    /// Removes and returns the completed bytes, keeping any partially
+    /// written byte so that writing can continue.
+    pub fn take_bytes(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bytes)
+    }
+
+    /// Consumes the writer, returning every byte written with the last
+    /// partial byte padded with zeroes.
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        self.align_to_byte();
+        self.bytes
+    }
+}
@@ -1,3 +1,4 @@
+use crate::png::optimize::StripMode;
 use std::env;
 use std::error::Error;
 use std::fmt::{self, Display};
@@ -8,35 +9,38 @@ use std::path::PathBuf;
 /// # Fields
 ///
 /// * 'MissingArgument' - Contains a static str for immediately returning
-///                 what argument is missing.
+///   what argument is missing.
 /// * 'InvalidArgument' - Contains a String for returning the InvalidArgument. A String
-///                 is used instead of a &'static str because it allows you to handle
-///                 the value once the string that cause the error goes out of scope.
+///   is used instead of a &'static str because it allows you to handle
+///   the value once the string that cause the error goes out of scope.
 ///
 /// # Examples
 ///
-/// '''
-/// // Pull input arguments and skip the operating path.
-/// let mut args = env::args().skip(1).enumerate();
+/// ```
+/// # use chameleon::cli::CliError;
+/// # use std::path::PathBuf;
+/// fn parse(args: &[&str]) -> Result<PathBuf, CliError> {
+///     let mut path = PathBuf::new();
+///     let mut args = args.iter();
 ///
-/// // Define a tuple to populate with values from args.
-/// let mut path_flags: (PathBuf, String) = (PathBuf::new(), Vec::new());
-///
-/// // Iterate through each argument: arg, with their position: i.
-/// while let Some((i, arg)) = args.next() {
-///     match (i, arg.trim()) {
-///         (_, "-path") => {
-///             let (_, next_arg) = args.next().ok_or(CliError::MissingArgument(
-///                 "Error: Missing path after -path flag.",
-///             )).unwrap();
-///             let path_flags.0 = PathBuf::from(next_arg);
-///         }
-///         (_, _) => {
-///             return Err(CliError::InvalidArgument(arg.to_string())),
+///     // Iterate through each argument.
+///     while let Some(arg) = args.next() {
+///         match arg.trim() {
+///             "-path" => {
+///                 let next_arg = args.next().ok_or(CliError::MissingArgument(
+///                     "Error: Missing path after -path flag.",
+///                 ))?;
+///                 path = PathBuf::from(next_arg);
+///             }
+///             _ => return Err(CliError::InvalidArgument(arg.to_string())),
 ///         }
 ///     }
+///     Ok(path)
 /// }
-/// '''
+///
+/// assert!(matches!(parse(&["-path"]), Err(CliError::MissingArgument(_))));
+/// assert!(matches!(parse(&["-size"]), Err(CliError::InvalidArgument(_))));
+/// ```
 #[derive(Debug)]
 pub enum CliError {
     MissingArgument(&'static str),
@@ -64,13 +68,17 @@ impl Error for CliError {}
 ///
 /// # Attributes
 ///
-/// * 'path' - A PathBuf containing the path to the image.
+/// * 'command' - What to do with the image.
+/// * 'input_path' - A PathBuf containing the path to the image.
+/// * 'output_path' - Where to write the result.
 /// * 'flags' - A vector containing the given flags for manipulating
-///                 the image.
+///   the image.
 ///
 /// # Examples
 ///
-/// '''
+/// ```no_run
+/// # use chameleon::cli::InputArguments;
+/// # use std::process;
 /// let args = match InputArguments::build() {
 ///     Ok(arguments) => arguments,
 ///     Err(e) => {
@@ -78,20 +86,41 @@ impl Error for CliError {}
 ///         process::exit(1);
 ///     }
 /// };
-/// '''
+/// ```
 pub struct InputArguments {
+    pub command: Command,
     pub input_path: PathBuf,
     pub output_path: Option<PathBuf>,
     pub flags: Vec<Flags>,
 }
 
+/// A enum containing the commands the first argument can select.
+///
+/// # Fields
+///
+/// * 'Convert' - Decode the input and write it back out, the default
+///   when no command is given.
+/// * 'Optimize' - Losslessly re-encode a PNG as small as possible,
+///   'chameleon optimize in.png -o out.png'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Command {
+    #[default]
+    Convert,
+    Optimize,
+}
+
 /// A enum containing possible flags for operating on
-/// images. Not yet implemented, but eventually the goal
-/// will be to support dithering to pallete, and more
-/// as the scope of this project inevitably increases.
+/// images, eventually the goal will be to support dithering
+/// to pallete, and more as the scope of this project
+/// inevitably increases.
+///
+/// # Fields
+///
+/// * 'Strip' - Which optional chunks to remove, given as
+///   '-strip safe' or '-strip all'.
 #[derive(Debug)]
 pub enum Flags {
-    NotYetImplemented,
+    Strip(StripMode),
 }
 
 impl InputArguments {
@@ -104,6 +133,7 @@ impl InputArguments {
     /// provided: A path and a vector containing the flags given.
     pub fn build() -> Result<InputArguments, CliError> {
         let mut path_flags = InputArguments {
+            command: Command::Convert,
             input_path: PathBuf::new(),
             output_path: None,
             flags: Vec::new(),
@@ -119,6 +149,9 @@ impl InputArguments {
 
         while let Some((i, arg)) = args.next() {
             match (i, arg.trim()) {
+                (0, "optimize") => {
+                    path_flags.command = Command::Optimize;
+                }
                 (_, "-i") | (_, "-input") => {
                     let (_, input_path) = args.next().ok_or(CliError::MissingArgument(
                         "Error: Missing input path. Is -input/-i followed by a valid path?",
@@ -143,18 +176,43 @@ impl InputArguments {
                     let output_file_path = PathBuf::from(&output_path);
                     path_flags.output_path = Some(output_file_path);
                 }
-                // Here flags will be implemented as added match arms appending Flags enum members
-                // to path_flags.flags.
+                (_, "-strip") => {
+                    let (_, mode) = args.next().ok_or(CliError::MissingArgument(
+                        "Error: Missing strip mode, -strip should be followed by safe or all.",
+                    ))?;
+
+                    let mode = match mode.trim() {
+                        "none" => StripMode::None,
+                        "safe" => StripMode::Safe,
+                        "all" => StripMode::All,
+                        _ => return Err(CliError::InvalidArgument(mode)),
+                    };
+                    path_flags.flags.push(Flags::Strip(mode));
+                }
+                // A bare path is taken as the input, 'chameleon optimize in.png'.
+                (_, arg)
+                    if !arg.starts_with('-') && path_flags.input_path.as_os_str().is_empty() =>
+                {
+                    let file_path = PathBuf::from(arg);
+                    if !file_path.exists() {
+                        eprintln!("Invalid input path given. Does the path exist?");
+                        return Err(CliError::InvalidArgument(arg.to_string()));
+                    }
+                    path_flags.input_path = file_path;
+                }
                 (_, arg) => {
                     return Err(CliError::InvalidArgument(arg.to_string()));
                 }
             }
         }
         if path_flags.output_path.is_none() && path_flags.input_path.exists() {
-            let output_path = match path_flags.input_path.clone().parent() {
+            let mut output_path = match path_flags.input_path.clone().parent() {
                 Some(p) => p.join("output"),
                 None => PathBuf::from("output"),
             };
+            if let Some(extension) = path_flags.input_path.extension() {
+                output_path.set_extension(extension);
+            }
 
             eprintln!("Warning: No output path given, defaulted to the same directory and extension as the input file but with name 'output'.");
             path_flags.output_path = Some(output_path);
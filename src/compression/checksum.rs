@@ -0,0 +1,308 @@
+//! Checksums used by the container formats wrapped around DEFLATE.
+//!
+use std::io::{self, Write};
+
+//      +----------+
+//      | ADLER-32 |
+//      +----------+
+
+/// The largest prime smaller than 2^16, see RFC 1950 section 9.
+const ADLER_MODULUS: u32 = 65521;
+
+/// The number of bytes which can be summed before the running sums
+/// risk overflowing a u32 and have to be reduced.
+const ADLER_BLOCK: usize = 5552;
+
+/// Running Adler-32 checksum as used in the zlib trailer.
+///
+/// # Fields
+///
+/// * 'a' - The sum of all bytes plus one, modulo 65521.
+/// * 'b' - The sum of every value 'a' has taken, modulo 65521.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::checksum::Adler32;
+/// let mut adler = Adler32::new();
+/// adler.update(b"Wiki");
+/// adler.update(b"pedia");
+///
+/// assert_eq!(adler.finish(), 0x11E6_0398);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    /// Creates the checksum of an empty input.
+    pub fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    /// Adds the given bytes to the checksum.
+    pub fn update(&mut self, bytes: &[u8]) {
+        for block in bytes.chunks(ADLER_BLOCK) {
+            for &byte in block {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= ADLER_MODULUS;
+            self.b %= ADLER_MODULUS;
+        }
+    }
+
+    /// Returns the checksum of every byte given so far.
+    pub fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Computes the Adler-32 checksum of a byte slice in one go.
+pub fn adler32(bytes: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.update(bytes);
+    adler.finish()
+}
+
+/// Combines the Adler-32 checksums of two pieces of input into the
+/// checksum of the two back to back, so that pieces can be summed in
+/// parallel.
+///
+/// # Arguments
+///
+/// * 'first' - The checksum of the first piece.
+/// * 'second' - The checksum of the second piece.
+/// * 'second_length' - The length of the second piece in bytes.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::checksum::{adler32, adler32_combine};
+/// let combined = adler32_combine(adler32(b"Wiki"), adler32(b"pedia"), 5);
+///
+/// assert_eq!(combined, adler32(b"Wikipedia"));
+/// ```
+pub fn adler32_combine(first: u32, second: u32, second_length: usize) -> u32 {
+    let modulus = ADLER_MODULUS as u64;
+    let (a1, b1) = ((first & 0xFFFF) as u64, (first >> 16) as u64);
+    let (a2, b2) = ((second & 0xFFFF) as u64, (second >> 16) as u64);
+    let length = second_length as u64 % modulus;
+
+    // Every sum in the second piece also counts all of the first piece,
+    // less the one both start from.
+    let a = (a1 + a2 + modulus - 1) % modulus;
+    let b = (b1 + b2 + length * ((a1 + modulus - 1) % modulus)) % modulus;
+    ((b << 16) | a) as u32
+}
+
+//      +--------+
+//      | CRC-32 |
+//      +--------+
+
+/// The reversed polynomial used by PNG, gzip and zip.
+const CRC_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// The CRC of every possible byte, computed at compile time so each
+/// byte of input only takes a single table lookup.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                CRC_POLYNOMIAL ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// Running CRC-32 as used by PNG chunks and the gzip trailer.
+///
+/// # Fields
+///
+/// * 'crc' - The CRC register, kept inverted between updates.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::checksum::Crc32;
+/// let mut crc = Crc32::new();
+/// crc.update(b"IEND");
+///
+/// assert_eq!(crc.finish(), 0xAE42_6082);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    /// Creates the checksum of an empty input.
+    pub fn new() -> Self {
+        Self { crc: 0xFFFF_FFFF }
+    }
+
+    /// Adds the given bytes to the checksum.
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.crc = CRC_TABLE[((self.crc ^ byte as u32) & 0xFF) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    /// Returns the checksum of every byte given so far.
+    pub fn finish(&self) -> u32 {
+        self.crc ^ 0xFFFF_FFFF
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Allows data to be checksummed with io::copy, without holding it.
+impl Write for Crc32 {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.update(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Computes the CRC-32 of a byte slice in one go.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+/// Combines the CRC-32s of two pieces of input into the CRC-32 of the
+/// two back to back, so that pieces can be checksummed in parallel.
+///
+/// Appending a zero bit to the input is a linear map on the register,
+/// so the first CRC is run through 'second_length' zero bytes by
+/// squaring that map once per bit of the length, and the second CRC then
+/// accounts for the actual bytes.
+///
+/// # Arguments
+///
+/// * 'first' - The CRC-32 of the first piece.
+/// * 'second' - The CRC-32 of the second piece.
+/// * 'second_length' - The length of the second piece in bytes.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::checksum::{crc32, crc32_combine};
+/// let combined = crc32_combine(crc32(b"Wiki"), crc32(b"pedia"), 5);
+///
+/// assert_eq!(combined, crc32(b"Wikipedia"));
+/// ```
+pub fn crc32_combine(first: u32, second: u32, second_length: usize) -> u32 {
+    if second_length == 0 {
+        return first;
+    }
+
+    // The map for a single zero bit, as the image of each register bit.
+    let mut odd = [0u32; 32];
+    odd[0] = CRC_POLYNOMIAL;
+    for (bit, row) in odd.iter_mut().enumerate().skip(1) {
+        *row = 1 << (bit - 1);
+    }
+    let mut even = [0u32; 32];
+    gf2_square(&mut even, &odd);
+    gf2_square(&mut odd, &even);
+
+    // Odd now holds four zero bits, each squaring doubles it, starting
+    // with a single zero byte.
+    let mut crc = first;
+    let mut length = second_length;
+    loop {
+        gf2_square(&mut even, &odd);
+        if length & 1 != 0 {
+            crc = gf2_times(&even, crc);
+        }
+        length >>= 1;
+        if length == 0 {
+            break;
+        }
+
+        gf2_square(&mut odd, &even);
+        if length & 1 != 0 {
+            crc = gf2_times(&odd, crc);
+        }
+        length >>= 1;
+        if length == 0 {
+            break;
+        }
+    }
+    crc ^ second
+}
+
+/// Multiplies a vector by a 32x32 matrix over GF(2), each entry of
+/// 'matrix' being the image of one bit.
+fn gf2_times(matrix: &[u32; 32], vector: u32) -> u32 {
+    matrix
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| vector >> bit & 1 != 0)
+        .fold(0, |sum, (_, row)| sum ^ row)
+}
+
+/// Sets 'square' to 'matrix' applied twice.
+fn gf2_square(square: &mut [u32; 32], matrix: &[u32; 32]) {
+    for (row, &image) in square.iter_mut().zip(matrix) {
+        *row = gf2_times(matrix, image);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adler32_combines_pieces() {
+        let bytes: Vec<u8> = (0..70_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let whole = adler32(&bytes);
+        // Splits at either end and past the modulus in length.
+        for split in [0, 1, 5552, 65_521, 65_522, bytes.len()] {
+            let (first, second) = bytes.split_at(split);
+            let combined = adler32_combine(adler32(first), adler32(second), second.len());
+            assert_eq!(combined, whole, "split at {split}");
+        }
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn crc32_combines_pieces() {
+        let bytes: Vec<u8> = (0..70_000u32).map(|i| (i * 13 % 253) as u8).collect();
+        let whole = crc32(&bytes);
+        for split in [0, 1, 3, 4096, 65_537, bytes.len()] {
+            let (first, second) = bytes.split_at(split);
+            let combined = crc32_combine(crc32(first), crc32(second), second.len());
+            assert_eq!(combined, whole, "split at {split}");
+        }
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
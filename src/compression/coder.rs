@@ -0,0 +1,234 @@
+//! Traits shared by the codecs in this module, so that code can swap
+//! between them generically. Coder works on whole slices at once, while
+//! StreamCoder takes its input a piece at a time.
+use std::io::{self, Read};
+
+/// The compression level the Coder implementations use, the same as
+/// zlib's default.
+pub const DEFAULT_LEVEL: u8 = 6;
+
+/// A trait for implementing encoding/decoding into structs.
+///
+/// Implemented by the marker types Huffman, Lzss, Deflate, Zlib and Gzip,
+/// so that code can be written once for any of them.
+///
+/// # Types
+///
+/// * 'I' - Represents the type of the input.
+/// * 'O' - Represents the type of the output.
+/// * 'Error' - What error type should be returned in the Result.
+///
+/// # Methods
+///
+/// * 'encode' - Takes in an array of type I and returns a Result containing either the given
+///   Error or a Vec of type O.
+/// * 'decode' - Takes in an array of type O and returns a Result containing either the given
+///   Error or a Vec of type I.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::coder::Coder;
+/// # use chameleon::compression::{deflate::Deflate, gzip::Gzip};
+/// # use std::fmt::Debug;
+/// fn round_trip<C: Coder<u8, u8>>(input: &[u8]) -> bool
+/// where
+///     C::Error: Debug,
+/// {
+///     let encoded = C::encode(input).unwrap();
+///     C::decode(encoded).unwrap() == input
+/// }
+///
+/// assert!(round_trip::<Deflate>(b"Hello Hello Hello"));
+/// assert!(round_trip::<Gzip>(b"Hello Hello Hello"));
+/// ```
+pub trait Coder<I: Copy, O: Copy> {
+    type Error;
+    fn encode(input: impl AsRef<[I]>) -> Result<Vec<O>, Self::Error>;
+    fn decode(input: impl AsRef<[O]>) -> Result<Vec<I>, Self::Error>;
+}
+
+/// Options shared by every StreamCoder, each using those which apply to
+/// it.
+///
+/// # Attributes
+///
+/// * 'level' - The compression level from 0, the fastest, to 9, which
+///   compresses hardest. Used by the LZSS, DEFLATE, zlib and
+///   gzip encoders.
+/// * 'checksum' - Whether to store a CRC-32 in formats where it is
+///   optional, only Huffman's container.
+/// * 'max_output' - The most bytes a decoder outputs before failing, to
+///   guard against decompression bombs. Used by the LZSS,
+///   DEFLATE, zlib and gzip decoders, Huffman can only expand
+///   its input eightfold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoderOptions {
+    pub level: u8,
+    pub checksum: bool,
+    pub max_output: usize,
+}
+
+// Creates a default for CoderOptions.
+impl Default for CoderOptions {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_LEVEL,
+            checksum: true,
+            max_output: usize::MAX,
+        }
+    }
+}
+
+/// A trait for encoders and decoders which take their input a piece at
+/// a time, producing output as soon as they can, the streaming
+/// counterpart of Coder.
+///
+/// # Types
+///
+/// * 'I' - Represents the type of the input.
+/// * 'O' - Represents the type of the output.
+/// * 'Error' - What error type should be returned in the Result.
+///
+/// # Methods
+///
+/// * 'with_options' - Creates a coder at the start of a stream.
+/// * 'update' - Takes the next piece of input, appending any output which
+///   is ready to the given Vec.
+/// * 'finish' - Ends the input, appending the rest of the output. The
+///   coder is then ready for a new stream with the same options.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::compression::coder::{CoderOptions, StreamCoder};
+/// # use chameleon::compression::gzip::GzipEncoder;
+/// # use std::{error::Error, fs::File, io::Read};
+/// fn pipe<C: StreamCoder<u8, u8>>(
+///     mut reader: impl Read,
+///     options: &CoderOptions,
+/// ) -> Result<Vec<u8>, Box<dyn Error>>
+/// where
+///     C::Error: Error + 'static,
+/// {
+///     let mut coder = C::with_options(options);
+///     let mut output = Vec::new();
+///     let mut chunk = [0u8; 4096];
+///     loop {
+///         let read = reader.read(&mut chunk)?;
+///         if read == 0 {
+///             break;
+///         }
+///         coder.update(&chunk[..read], &mut output)?;
+///     }
+///     coder.finish(&mut output)?;
+///     Ok(output)
+/// }
+///
+/// let file = File::open("access.log")?;
+/// let compressed = pipe::<GzipEncoder>(file, &CoderOptions { level: 9, ..Default::default() })?;
+/// # Ok::<(), Box<dyn Error>>(())
+/// ```
+pub trait StreamCoder<I: Copy, O: Copy> {
+    type Error;
+    fn with_options(options: &CoderOptions) -> Self
+    where
+        Self: Sized;
+    fn update(&mut self, input: &[I], output: &mut Vec<O>) -> Result<(), Self::Error>;
+    fn finish(&mut self, output: &mut Vec<O>) -> Result<(), Self::Error>;
+}
+
+/// Input handed to a streaming decoder a piece at a time, which reads as
+/// blocked rather than ended until it is told no more is coming, so that
+/// an Inflater waits for more rather than failing.
+///
+/// # Fields
+///
+/// * 'data' - The input, unread from 'start'.
+/// * 'finished' - Whether the end of the input has been reached.
+#[derive(Debug, Default)]
+pub(crate) struct PushInput {
+    data: Vec<u8>,
+    start: usize,
+    finished: bool,
+}
+
+impl PushInput {
+    /// Adds input to be read.
+    pub(crate) fn push(&mut self, input: &[u8]) {
+        // Move unread input to the front once most of the buffer is read.
+        if self.start > self.data.len() / 2 {
+            self.data.drain(..self.start);
+            self.start = 0;
+        }
+        self.data.extend_from_slice(input);
+    }
+
+    /// Marks the end of the input, after which reading returns 0.
+    pub(crate) fn finish(&mut self) {
+        self.finished = true;
+    }
+}
+
+// Reads pushed input, returning io::ErrorKind::WouldBlock once it runs
+// out before the end.
+impl Read for PushInput {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let unread = &self.data[self.start..];
+        if unread.is_empty() && !self.finished {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let length = unread.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&unread[..length]);
+        self.start += length;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::deflate::Deflate;
+    use crate::compression::gzip::Gzip;
+    use crate::compression::lzss::Lzss;
+    use crate::compression::zlib::Zlib;
+    use std::fmt::Debug;
+
+    /// Encodes and decodes the empty input, a single byte and text with
+    /// plenty of matches, then checks that cutting the encoded text short
+    /// fails to decode.
+    fn round_trip<C: Coder<u8, u8>>()
+    where
+        C::Error: Debug,
+    {
+        let text = b"The quick brown fox jumps over the lazy dog. ".repeat(500);
+        for input in [&b""[..], b"x", &text] {
+            let encoded = C::encode(input).unwrap();
+            assert_eq!(C::decode(&encoded).unwrap(), input);
+        }
+
+        let encoded = C::encode(&text).unwrap();
+        assert!(encoded.len() < text.len() / 10);
+        assert!(C::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn deflate_round_trips() {
+        round_trip::<Deflate>();
+    }
+
+    #[test]
+    fn zlib_round_trips() {
+        round_trip::<Zlib>();
+    }
+
+    #[test]
+    fn gzip_round_trips() {
+        round_trip::<Gzip>();
+    }
+
+    #[test]
+    fn lzss_round_trips() {
+        round_trip::<Lzss>();
+    }
+}
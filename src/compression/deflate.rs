@@ -1,3 +1,21 @@
+use crate::bits::reader::{BitReader, BitSource, StreamBitReader};
+use crate::bits::writer::BitWriter;
+use crate::compression::coder::{Coder, CoderOptions, PushInput, StreamCoder, DEFAULT_LEVEL};
+use crate::compression::huffman::{
+    canonical_codes, code_lengths, generate_fixed_huffman, HuffmanTable, MAX_CODE_LENGTH,
+};
+use crate::compression::lzss::{
+    distance_code, length_code, MatchFinder, Token, DISTANCE_CODE_RANGES, LENGTH_CODE_RANGES,
+    MAX_MATCH, MIN_MATCH, WINDOW_SIZE,
+};
+use crate::limits::LimitError;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io::{self, Read},
+    thread,
+};
+
 /// Accepts the first byte of a DEFLATE block and extracts
 /// the BFINAL and BTYPE values.
 ///
@@ -5,29 +23,31 @@
 /// block is the last and what type it is. The types are
 /// as follows:
 ///
-///     00 - Block Type 0: Store
-///             This block type stores uncompressed data. The header contains not
-///         only the regular three bits but also the length, and somewhat bizarrely
-///         the bitwise complement of the length. There is also 5 bits of padding
-///         added after the first three bits to byte-align the bitstream.
+/// ```text
+/// 00 - Block Type 0: Store
+///         This block type stores uncompressed data. The header contains not
+///     only the regular three bits but also the length, and somewhat bizarrely
+///     the bitwise complement of the length. There is also 5 bits of padding
+///     added after the first three bits to byte-align the bitstream.
+///
+///         BFINAL  BTYPE   PAD     LEN    ~LEN    BITSTREAM...
+///         1 bit   2 bits  5 bits  16 bits 16 bits
 ///
-///             BFINAL  BTYPE   PAD     LEN    ~LEN    BITSTREAM...
-///             1 bit   2 bits  5 bits  16 bits 16 bits
-///     
-///     01 - Block Type 1: LZSS with Fixed Codes
-///             This block type stores data which has been compressed using the
-///         LZSS algorithm, a derivative of the LZ77 data compression algorithm.
-///         Then, the data is compressed further using a fixed set of prefix codes.
+/// 01 - Block Type 1: LZSS with Fixed Codes
+///         This block type stores data which has been compressed using the
+///     LZSS algorithm, a derivative of the LZ77 data compression algorithm.
+///     Then, the data is compressed further using a fixed set of prefix codes.
 ///
-///             BFINAL  BTYPE   BITSTREAM... EOB
-///             1 bit   2 bits               9 bits
+///         BFINAL  BTYPE   BITSTREAM... EOB
+///         1 bit   2 bits               9 bits
 ///
-///     10 - Block Type 2: LZSS with Dynamic Codes
-///             This block type once more uses the LZSS algorithm to compress data,
-///         then uses dynamically created codes.
+/// 10 - Block Type 2: LZSS with Dynamic Codes
+///         This block type once more uses the LZSS algorithm to compress data,
+///     then uses dynamically created codes.
 ///
-///             BFINAL  BTYPE   BITSTREAM... EOB
-///             1 bit   2 bits               9 bits
+///         BFINAL  BTYPE   BITSTREAM... EOB
+///         1 bit   2 bits               9 bits
+/// ```
 ///
 /// # Arguments
 ///
@@ -45,3 +65,1638 @@ pub fn parse_block_header(byte: u8) -> (bool, u8) {
 
     (bfinal, btype)
 }
+
+//      +---------+
+//      | INFLATE |
+//      +---------+
+
+/// The order in which the code lengths of the code length alphabet are
+/// given in the header of a dynamic block, see RFC 1951 section 3.2.7.
+pub const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// The symbol marking the end of a block in the literal/length alphabet.
+pub const END_OF_BLOCK: u16 = 256;
+
+/// Decompresses a complete DEFLATE stream.
+///
+/// # Arguments
+///
+/// * 'input' - The compressed bytes, anything after the final block is
+///   ignored.
+///
+/// # Returns
+///
+/// A result containing either the decompressed bytes or a DeflateError.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::deflate::inflate;
+/// // A single stored block containing "hi".
+/// let data = inflate(&[0x01, 0x02, 0x00, 0xFD, 0xFF, b'h', b'i']).unwrap();
+///
+/// assert_eq!(data, b"hi");
+/// ```
+pub fn inflate(input: &[u8]) -> Result<Vec<u8>, DeflateError> {
+    let mut reader = BitReader::new(input);
+    let mut output = Vec::new();
+    inflate_from(&mut reader, &mut output, usize::MAX)?;
+    Ok(output)
+}
+
+/// Decompresses a complete DEFLATE stream which was compressed against a
+/// preset dictionary, see deflate_with_dictionary.
+///
+/// # Arguments
+///
+/// * 'input' - The compressed bytes, anything after the final block is
+///   ignored.
+/// * 'dictionary' - The same dictionary the stream was compressed with.
+///
+/// # Returns
+///
+/// A result containing either the decompressed bytes, without the
+/// dictionary, or a DeflateError.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::deflate::{deflate_with_dictionary, inflate_with_dictionary};
+/// let dictionary = b"\"level\":\"info\",\"message\":\"";
+/// let compressed = deflate_with_dictionary(b"{\"level\":\"info\"}", 6, dictionary);
+///
+/// assert_eq!(inflate_with_dictionary(&compressed, dictionary).unwrap(), b"{\"level\":\"info\"}");
+/// ```
+pub fn inflate_with_dictionary(input: &[u8], dictionary: &[u8]) -> Result<Vec<u8>, DeflateError> {
+    let mut reader = BitReader::new(input);
+    let mut output = Vec::new();
+    inflate_from_dictionary(&mut reader, &mut output, dictionary, usize::MAX)?;
+    Ok(output)
+}
+
+/// Decompresses blocks from the reader until the final block has been
+/// read, appending the result to output. Allows wrappers such as zlib
+/// and gzip to continue reading their trailer from where the DEFLATE
+/// stream ended.
+///
+/// # Arguments
+///
+/// * 'reader' - A BitReader positioned at the first block header.
+/// * 'output' - The Vec to append to, anything already in it can be
+///   referenced by back-references.
+/// * 'max_output' - The length output may grow to, past which decoding
+///   stops with DeflateError::LimitExceeded.
+pub fn inflate_from(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_output: usize,
+) -> Result<(), DeflateError> {
+    loop {
+        let header = reader
+            .read_bits(3)
+            .ok_or(DeflateError::UnexpectedEnd(reader.bit_position()))?;
+        let (bfinal, btype) = parse_block_header(header as u8);
+
+        match btype {
+            0 => inflate_stored(reader, output, max_output)?,
+            1 => {
+                let (literal_lengths, distance_lengths) = generate_fixed_huffman();
+                let literals = HuffmanTable::from_lengths(&literal_lengths)
+                    .ok_or(DeflateError::InvalidCodeLengths(reader.bit_position()))?;
+                let distances = HuffmanTable::from_lengths(&distance_lengths)
+                    .ok_or(DeflateError::InvalidCodeLengths(reader.bit_position()))?;
+                inflate_block(reader, output, &literals, &distances, max_output)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_tables(reader)?;
+                inflate_block(reader, output, &literals, &distances, max_output)?;
+            }
+            _ => return Err(DeflateError::InvalidBlockType(reader.bit_position() - 3)),
+        }
+
+        if bfinal {
+            return Ok(());
+        }
+    }
+}
+
+/// Same as inflate_from, but with the last 32K of 'dictionary' placed
+/// just before the output for back-references to reach into. The
+/// dictionary is taken back out afterwards, even on failure, so output
+/// only ever gains decompressed bytes.
+///
+/// # Arguments
+///
+/// * 'reader' - A BitReader positioned at the first block header.
+/// * 'output' - The Vec to append to.
+/// * 'dictionary' - The preset dictionary the stream was compressed with.
+/// * 'max_output' - The length output may grow to, not counting the
+///   dictionary, past which decoding stops with
+///   DeflateError::LimitExceeded.
+pub fn inflate_from_dictionary(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    dictionary: &[u8],
+    max_output: usize,
+) -> Result<(), DeflateError> {
+    let dictionary = &dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..];
+    let start = output.len();
+    output.extend_from_slice(dictionary);
+    let result = inflate_from(reader, output, max_output.saturating_add(dictionary.len()));
+    output.drain(start..start + dictionary.len());
+    result
+}
+
+/// Copies a stored block's bytes straight to the output after checking
+/// LEN against its complement NLEN.
+fn inflate_stored(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_output: usize,
+) -> Result<(), DeflateError> {
+    reader.align_to_byte();
+    let position = reader.bit_position();
+    let header = reader
+        .read_bytes(4)
+        .ok_or(DeflateError::UnexpectedEnd(position))?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if length != !complement {
+        return Err(DeflateError::StoredLengthMismatch(position));
+    }
+
+    let bytes = reader
+        .read_bytes(length as usize)
+        .ok_or(DeflateError::UnexpectedEnd(reader.bit_position()))?;
+    check_output(output, bytes.len(), max_output)?;
+    output.extend_from_slice(bytes);
+    Ok(())
+}
+
+/// Reads the code length alphabet and then the literal/length and
+/// distance code lengths from the header of a dynamic block.
+///
+/// # Returns
+///
+/// A tuple containing the literal/length table in the first field
+/// and the distance table in the second.
+fn read_dynamic_tables<B: BitSource>(
+    reader: &mut B,
+) -> Result<(HuffmanTable, HuffmanTable), DeflateError> {
+    let read = |reader: &mut B, bits| {
+        reader
+            .read_bits(bits)
+            .ok_or(DeflateError::UnexpectedEnd(reader.bit_position()))
+    };
+
+    let hlit = read(reader, 5)? as usize + 257;
+    let hdist = read(reader, 5)? as usize + 1;
+    let hclen = read(reader, 4)? as usize + 4;
+
+    let mut code_length_lengths = [0u8; 19];
+    for &symbol in CODE_LENGTH_ORDER.iter().take(hclen) {
+        code_length_lengths[symbol] = read(reader, 3)? as u8;
+    }
+    let code_lengths = HuffmanTable::from_lengths(&code_length_lengths)
+        .ok_or(DeflateError::InvalidCodeLengths(reader.bit_position()))?;
+
+    // The literal/length and distance code lengths are run length encoded
+    // as one sequence, so repeats can cross from one into the other.
+    let mut lengths = Vec::with_capacity(hlit + hdist);
+    while lengths.len() < hlit + hdist {
+        let position = reader.bit_position();
+        let symbol = decode_symbol(reader, &code_lengths)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or(DeflateError::InvalidCodeLengths(position))?;
+                (previous, 3 + read(reader, 2)?)
+            }
+            17 => (0, 3 + read(reader, 3)?),
+            _ => (0, 11 + read(reader, 7)?),
+        };
+        if lengths.len() + repeat as usize > hlit + hdist {
+            return Err(DeflateError::InvalidCodeLengths(position));
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(DeflateError::InvalidCodeLengths(reader.bit_position()));
+    }
+
+    let literals = HuffmanTable::from_lengths(&lengths[..hlit])
+        .ok_or(DeflateError::InvalidCodeLengths(reader.bit_position()))?;
+    let distances = HuffmanTable::from_lengths(&lengths[hlit..])
+        .ok_or(DeflateError::InvalidCodeLengths(reader.bit_position()))?;
+
+    Ok((literals, distances))
+}
+
+/// Decodes literals and back-references using the given tables until
+/// the end of block symbol is reached.
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &HuffmanTable,
+    distances: &HuffmanTable,
+    max_output: usize,
+) -> Result<(), DeflateError> {
+    loop {
+        let position = reader.bit_position();
+        let symbol = decode_symbol(reader, literals)?;
+
+        match symbol {
+            0..=255 => {
+                check_output(output, 1, max_output)?;
+                output.push(symbol as u8);
+            }
+            END_OF_BLOCK => return Ok(()),
+            257..=285 => {
+                // [SYMBOL, EXTRA BITS, RANGE START, RANGE END]
+                let [_, extra, start, _] = LENGTH_CODE_RANGES[(symbol - 257) as usize];
+                let length = start as usize + read_extra(reader, extra)?;
+
+                let distance_symbol = decode_symbol(reader, distances)?;
+                let [_, extra, start, _] = *DISTANCE_CODE_RANGES
+                    .get(distance_symbol as usize)
+                    .ok_or(DeflateError::InvalidCode(position))?;
+                let distance = start as usize + read_extra(reader, extra)?;
+
+                if distance > output.len() {
+                    return Err(DeflateError::InvalidDistance {
+                        position,
+                        distance,
+                        available: output.len(),
+                    });
+                }
+                check_output(output, length, max_output)?;
+                copy_back_reference(output, distance, length);
+            }
+            _ => return Err(DeflateError::InvalidCode(position)),
+        }
+    }
+}
+
+/// Appends 'length' bytes starting 'distance' bytes back from the end of
+/// the output. The ranges may overlap, in which case the bytes being
+/// written are repeated, so "AB" with distance 2 and length 5 gives
+/// "ABABABA".
+pub fn copy_back_reference(output: &mut Vec<u8>, distance: usize, length: usize) {
+    let start = output.len() - distance;
+    if distance >= length {
+        output.extend_from_within(start..start + length);
+    } else {
+        for i in 0..length {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+/// Decodes one symbol, telling apart running out of input from bits
+/// which do not form a code.
+fn decode_symbol<B: BitSource>(reader: &mut B, table: &HuffmanTable) -> Result<u16, DeflateError> {
+    let position = reader.bit_position();
+    table
+        .decode(reader)
+        .ok_or_else(|| match reader.peek_bits(1).1 {
+            0 => DeflateError::UnexpectedEnd(position),
+            _ => DeflateError::InvalidCode(position),
+        })
+}
+
+/// Checks that 'length' more bytes fit in the output.
+fn check_output(output: &[u8], length: usize, max_output: usize) -> Result<(), DeflateError> {
+    match output.len() + length > max_output {
+        true => Err(DeflateError::LimitExceeded(LimitError::Output {
+            max: max_output,
+        })),
+        false => Ok(()),
+    }
+}
+
+/// Reads the extra bits following a length or distance symbol.
+fn read_extra<B: BitSource>(reader: &mut B, bits: u16) -> Result<usize, DeflateError> {
+    reader
+        .read_bits(bits as u32)
+        .map(|value| value as usize)
+        .ok_or(DeflateError::UnexpectedEnd(reader.bit_position()))
+}
+
+//      +-------------------+
+//      | STREAMING INFLATE |
+//      +-------------------+
+
+/// Where an Inflater is within the stream.
+///
+/// # Fields
+///
+/// * 'BlockHeader' - The next bits are the header of a block.
+/// * 'Stored' - Inside a stored block, holds the bytes left in it.
+/// * 'Huffman' - Inside a fixed or dynamic block, holds its literal/length
+///   and distance tables.
+/// * 'Done' - The final block has ended.
+enum InflateState {
+    BlockHeader,
+    Stored(usize),
+    Huffman(HuffmanTable, HuffmanTable),
+    Done,
+}
+
+/// Decompresses a DEFLATE stream as it is read, the counterpart of the
+/// Deflater. Only the last 32K of output is kept, as that is as far back
+/// as back-references can reach, so memory use does not depend on the
+/// size of the data.
+///
+/// # Fields
+///
+/// * 'reader' - The compressed stream.
+/// * 'window' - The last WINDOW_SIZE bytes of output, indexed by the
+///   position in the dictionary followed by the output modulo
+///   WINDOW_SIZE.
+/// * 'primed' - The length of the preset dictionary at the start of the
+///   window, see set_dictionary.
+/// * 'total' - The number of bytes output so far.
+/// * 'max_output' - The most bytes to output before failing with
+///   DeflateError::LimitExceeded.
+/// * 'state' - Where the next bits belong.
+/// * 'last_block' - Whether the current block is the final one.
+/// * 'copy' - The distance and remaining length of a back-reference which
+///   did not fit in the last call.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::compression::deflate::Inflater;
+/// # use std::fs::File;
+/// # fn process(_: &[u8]) {}
+/// let mut inflater = Inflater::new(File::open("data.deflate")?);
+///
+/// let mut chunk = [0u8; 4096];
+/// loop {
+///     let read = inflater.inflate(&mut chunk)?;
+///     if read == 0 {
+///         break;
+///     }
+///     process(&chunk[..read]);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Inflater<R> {
+    reader: StreamBitReader<R>,
+    window: Vec<u8>,
+    primed: usize,
+    total: usize,
+    max_output: usize,
+    state: InflateState,
+    last_block: bool,
+    copy: (usize, usize),
+}
+
+impl<R: Read> Inflater<R> {
+    /// Creates an inflater reading from the start of a DEFLATE stream.
+    pub fn new(reader: R) -> Self {
+        Self::with_limit(reader, usize::MAX)
+    }
+
+    /// Same as new, but failing with DeflateError::LimitExceeded rather
+    /// than outputting more than 'max_output' bytes.
+    pub fn with_limit(reader: R, max_output: usize) -> Self {
+        Self {
+            reader: StreamBitReader::new(reader),
+            window: vec![0; WINDOW_SIZE],
+            primed: 0,
+            total: 0,
+            max_output,
+            state: InflateState::BlockHeader,
+            last_block: false,
+            copy: (0, 0),
+        }
+    }
+
+    /// Primes the window with the last 32K of 'dictionary', which
+    /// back-references can then reach into as though it were output just
+    /// before the stream, the counterpart of Deflater::with_dictionary.
+    /// Has no effect once anything has been inflated.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        if self.total > 0 {
+            return;
+        }
+        let dictionary = &dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..];
+        self.window[..dictionary.len()].copy_from_slice(dictionary);
+        self.primed = dictionary.len();
+    }
+
+    /// Whether the final block has been decompressed.
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, InflateState::Done)
+    }
+
+    /// The number of bytes output so far.
+    pub fn total_out(&self) -> usize {
+        self.total
+    }
+
+    /// The reader the compressed data comes from, positioned after the
+    /// final block once is_done, for reading a trailer.
+    pub fn bit_reader(&mut self) -> &mut StreamBitReader<R> {
+        &mut self.reader
+    }
+
+    /// Returns the stream, see StreamBitReader::into_inner.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// Decompresses into 'buffer'.
+    ///
+    /// If the stream returns io::ErrorKind::WouldBlock partway through a
+    /// code or header, the inflater returns to its start and stops there,
+    /// to carry on once the stream has more input.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the number of bytes written, which is
+    /// only 0 once the final block has ended or while the stream is
+    /// blocked, or a DeflateError. Other errors from the stream itself end
+    /// the input early, see StreamBitReader::take_error.
+    pub fn inflate(&mut self, buffer: &mut [u8]) -> Result<usize, DeflateError> {
+        let mut written = 0;
+        while written < buffer.len() {
+            let (distance, length) = self.copy;
+            if length > 0 {
+                let count = length.min(buffer.len() - written);
+                for _ in 0..count {
+                    let byte = self.window[(self.primed + self.total - distance) % WINDOW_SIZE];
+                    buffer[written] = self.output(byte)?;
+                    written += 1;
+                }
+                self.copy.1 -= count;
+                continue;
+            }
+
+            self.reader.mark();
+            let byte = match self.next_element() {
+                Ok(Some(byte)) => byte,
+                Ok(None) if self.is_done() => break,
+                Ok(None) => continue,
+                // The stream ran dry partway through, so start the element
+                // again once it has more.
+                Err(_) if self.reader.is_blocked() => {
+                    self.reader.reset();
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            buffer[written] = self.output(byte)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Reads the next element of the stream, a block header, a stored
+    /// byte or a Huffman code along with any extra bits following it.
+    /// Nothing is changed until the whole element has been read, so that
+    /// a blocked stream can be reset to its start.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the byte to output, None if the element
+    /// did not output one, or a DeflateError.
+    fn next_element(&mut self) -> Result<Option<u8>, DeflateError> {
+        let position = self.reader.bit_position();
+        match &self.state {
+            InflateState::Done => {}
+            InflateState::BlockHeader if self.last_block => self.state = InflateState::Done,
+            InflateState::BlockHeader => self.state = self.read_block_header()?,
+            InflateState::Stored(0) => self.state = InflateState::BlockHeader,
+            &InflateState::Stored(remaining) => {
+                let byte = read_extra(&mut self.reader, 8)? as u8;
+                self.state = InflateState::Stored(remaining - 1);
+                return Ok(Some(byte));
+            }
+            InflateState::Huffman(literals, distances) => {
+                match decode_symbol(&mut self.reader, literals)? {
+                    symbol @ 0..=255 => return Ok(Some(symbol as u8)),
+                    END_OF_BLOCK => self.state = InflateState::BlockHeader,
+                    symbol @ 257..=285 => {
+                        let [_, extra, start, _] = LENGTH_CODE_RANGES[(symbol - 257) as usize];
+                        let length = start as usize + read_extra(&mut self.reader, extra)?;
+
+                        let distance_symbol = decode_symbol(&mut self.reader, distances)?;
+                        let [_, extra, start, _] = *DISTANCE_CODE_RANGES
+                            .get(distance_symbol as usize)
+                            .ok_or(DeflateError::InvalidCode(position))?;
+                        let distance = start as usize + read_extra(&mut self.reader, extra)?;
+
+                        if distance > self.primed + self.total {
+                            return Err(DeflateError::InvalidDistance {
+                                position,
+                                distance,
+                                available: self.primed + self.total,
+                            });
+                        }
+                        self.copy = (distance, length);
+                    }
+                    _ => return Err(DeflateError::InvalidCode(position)),
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the header of the next block, along with the length of
+    /// stored blocks and the code lengths of dynamic blocks.
+    fn read_block_header(&mut self) -> Result<InflateState, DeflateError> {
+        let position = self.reader.bit_position();
+        let header = self
+            .reader
+            .read_bits(3)
+            .ok_or(DeflateError::UnexpectedEnd(position))?;
+        let (bfinal, btype) = parse_block_header(header as u8);
+
+        let state = match btype {
+            0 => {
+                self.reader.align_to_byte();
+                let position = self.reader.bit_position();
+                let length = read_extra(&mut self.reader, 16)?;
+                let complement = read_extra(&mut self.reader, 16)?;
+                if length != !complement & 0xFFFF {
+                    return Err(DeflateError::StoredLengthMismatch(position));
+                }
+                Ok(InflateState::Stored(length))
+            }
+            1 => {
+                let (literal_lengths, distance_lengths) = generate_fixed_huffman();
+                let literals = HuffmanTable::from_lengths(&literal_lengths)
+                    .ok_or(DeflateError::InvalidCodeLengths(position))?;
+                let distances = HuffmanTable::from_lengths(&distance_lengths)
+                    .ok_or(DeflateError::InvalidCodeLengths(position))?;
+                Ok(InflateState::Huffman(literals, distances))
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_tables(&mut self.reader)?;
+                Ok(InflateState::Huffman(literals, distances))
+            }
+            _ => Err(DeflateError::InvalidBlockType(position)),
+        }?;
+        self.last_block = bfinal;
+        Ok(state)
+    }
+
+    /// Adds a byte to the window, checking it stays within max_output.
+    fn output(&mut self, byte: u8) -> Result<u8, DeflateError> {
+        if self.total == self.max_output {
+            return Err(DeflateError::LimitExceeded(LimitError::Output {
+                max: self.max_output,
+            }));
+        }
+        self.window[(self.primed + self.total) % WINDOW_SIZE] = byte;
+        self.total += 1;
+        Ok(byte)
+    }
+}
+
+// Allows an Inflater to be used wherever a Read is expected.
+impl<R: Read> Read for Inflater<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self.inflate(buffer) {
+            Ok(0) if !buffer.is_empty() && !self.is_done() => Err(io::ErrorKind::WouldBlock.into()),
+            Ok(written) => Ok(written),
+            Err(e) => Err(self
+                .reader
+                .take_error()
+                .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidData, e))),
+        }
+    }
+}
+
+//      +---------+
+//      | DEFLATE |
+//      +---------+
+
+/// The most tokens gathered before they are written out as a block.
+const BLOCK_TOKENS: usize = 16384;
+
+/// The most input bytes covered by one block, bounding how much input
+/// has to be kept around in case the block is best stored.
+const BLOCK_BYTES: usize = 4 * MAX_STORED;
+
+/// The largest amount of data a single stored block can hold.
+const MAX_STORED: usize = 65535;
+
+/// Compresses a byte slice into a raw DEFLATE stream in one go.
+///
+/// # Arguments
+///
+/// * 'input' - The data to compress.
+/// * 'level' - The compression level from 0, which only stores, to 9,
+///   which searches hardest for matches.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::deflate::{deflate, inflate};
+/// let compressed = deflate(b"Hello Hello Hello Hello", 6);
+///
+/// assert_eq!(inflate(&compressed).unwrap(), b"Hello Hello Hello Hello");
+/// ```
+pub fn deflate(input: &[u8], level: u8) -> Vec<u8> {
+    let mut deflater = Deflater::new(level);
+    deflater.write(input);
+    deflater.finish()
+}
+
+/// Compresses a byte slice into a raw DEFLATE stream whose matches can
+/// also refer back into a preset dictionary, which helps most with small
+/// inputs sharing common strings. The dictionary is not output, so only
+/// inflate_with_dictionary given the same dictionary can decompress it.
+///
+/// # Arguments
+///
+/// * 'input' - The data to compress.
+/// * 'level' - The compression level from 0 through 9.
+/// * 'dictionary' - Data likely to recur in the input, of which only the
+///   last 32K is used, so the most common strings belong at the
+///   end.
+pub fn deflate_with_dictionary(input: &[u8], level: u8, dictionary: &[u8]) -> Vec<u8> {
+    let mut deflater = Deflater::with_dictionary(level, dictionary);
+    deflater.write(input);
+    deflater.finish()
+}
+
+/// The smallest piece of input worth compressing on its own thread.
+const MIN_SEGMENT: usize = 1 << 17;
+
+/// Compresses a byte slice into a single raw DEFLATE stream using up to
+/// 'threads' threads.
+///
+/// The input is split into segments, one per thread, and each segment is
+/// compressed on its own. Every segment but the last ends with a sync
+/// flush, so its output ends on a byte boundary without ending the
+/// stream and the outputs can simply be joined. Matches cannot reach
+/// across segments unless they are primed, in which case each starts
+/// with the last 32K of the segment before it as a dictionary.
+///
+/// # Arguments
+///
+/// * 'input' - The data to compress.
+/// * 'level' - The compression level from 0 through 9.
+/// * 'threads' - The most threads to use, segments are never smaller than
+///   MIN_SEGMENT bytes.
+/// * 'prime' - Whether to prime each segment with the one before it,
+///   which costs little and gets back most of the compression
+///   lost by splitting.
+/// * 'checksum' - Run over each segment on its thread, for the checksum
+///   of the container around the stream.
+///
+/// # Returns
+///
+/// The DEFLATE stream, along with the checksum and length of each
+/// segment in order.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::checksum::{adler32, adler32_combine};
+/// # use chameleon::compression::deflate::{deflate_parallel, inflate};
+/// # let data = b"Hello Hello Hello Hello".repeat(10_000);
+/// let (compressed, sums) = deflate_parallel(&data, 6, 8, true, adler32);
+/// let adler = sums
+///     .into_iter()
+///     .fold(adler32(&[]), |adler, (sum, length)| adler32_combine(adler, sum, length));
+///
+/// assert_eq!(adler, adler32(&data));
+/// assert_eq!(inflate(&compressed).unwrap(), data);
+/// ```
+pub fn deflate_parallel<C, F>(
+    input: &[u8],
+    level: u8,
+    threads: usize,
+    prime: bool,
+    checksum: F,
+) -> (Vec<u8>, Vec<(C, usize)>)
+where
+    C: Send,
+    F: Fn(&[u8]) -> C + Sync,
+{
+    let segments = threads.min(input.len().div_ceil(MIN_SEGMENT)).max(1);
+    let size = input.len().div_ceil(segments);
+    deflate_blocks(&[], input, size, level, prime, true, checksum)
+}
+
+/// Compresses input split into blocks of 'block_size' bytes, each on its
+/// own thread, as a run of segments within a larger stream, see
+/// deflate_parallel.
+///
+/// # Arguments
+///
+/// * 'dictionary' - The input preceding this run, used to prime the first
+///   block.
+/// * 'input' - The data to compress.
+/// * 'block_size' - The length of each block, the last may be shorter.
+/// * 'level' - The compression level from 0 through 9.
+/// * 'prime' - Whether each block starts with the 32K before it as a
+///   dictionary.
+/// * 'last' - Whether the last block ends the stream. Every other block
+///   ends with a sync flush.
+/// * 'checksum' - Run over each block on its thread.
+///
+/// # Returns
+///
+/// The compressed blocks joined together, along with the checksum and
+/// length of each block in order.
+pub fn deflate_blocks<C, F>(
+    dictionary: &[u8],
+    input: &[u8],
+    block_size: usize,
+    level: u8,
+    prime: bool,
+    last: bool,
+    checksum: F,
+) -> (Vec<u8>, Vec<(C, usize)>)
+where
+    C: Send,
+    F: Fn(&[u8]) -> C + Sync,
+{
+    // Even empty input has to be written as a block to end the stream.
+    let blocks: Vec<&[u8]> = match input.is_empty() {
+        true => vec![input],
+        false => input.chunks(block_size.max(1)).collect(),
+    };
+    let count = blocks.len();
+    let checksum = &checksum;
+
+    let results: Vec<(Vec<u8>, C, usize)> = thread::scope(|scope| {
+        let handles: Vec<_> = blocks
+            .into_iter()
+            .enumerate()
+            .map(|(index, block)| {
+                let start = index * block_size;
+                let dictionary = match (prime, index) {
+                    (false, _) => &[],
+                    (true, 0) => dictionary,
+                    (true, _) => &input[start.saturating_sub(WINDOW_SIZE)..start],
+                };
+                let last = last && index + 1 == count;
+                scope.spawn(move || {
+                    let compressed = deflate_segment(dictionary, block, level, last);
+                    (compressed, checksum(block), block.len())
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("compression thread panicked"))
+            .collect()
+    });
+
+    let mut output = Vec::new();
+    let mut sums = Vec::with_capacity(results.len());
+    for (compressed, sum, length) in results {
+        output.extend(compressed);
+        sums.push((sum, length));
+    }
+    (output, sums)
+}
+
+/// Compresses one segment of a larger stream, see deflate_parallel.
+///
+/// # Arguments
+///
+/// * 'dictionary' - The input preceding the segment, of which the last
+///   32K can be matched against but is not output.
+/// * 'input' - The segment to compress.
+/// * 'level' - The compression level from 0 through 9.
+/// * 'last' - Whether this segment ends the stream. Otherwise it ends
+///   with a sync flush, ready to be followed by the next.
+pub fn deflate_segment(dictionary: &[u8], input: &[u8], level: u8, last: bool) -> Vec<u8> {
+    let mut deflater = Deflater::with_dictionary(level, dictionary);
+    deflater.write(input);
+    if last {
+        return deflater.finish();
+    }
+    deflater.flush();
+    deflater.take_output()
+}
+
+/// Streaming DEFLATE compressor.
+///
+/// Input is split into LZSS tokens as it arrives, and every so often the
+/// tokens are written out as a block using whichever of the three block
+/// types comes out smallest.
+///
+/// # Fields
+///
+/// * 'finder' - The hash chains used to find back-references.
+/// * 'level' - The compression level, 0 only writes stored blocks.
+/// * 'buffer' - The input still needed, either as history for matches or
+///   as the raw bytes of the current block.
+/// * 'base' - The position in the whole input of buffer[0].
+/// * 'position' - The position in the whole input of the next byte to
+///   turn into tokens.
+/// * 'block_start' - The position in the whole input the current block
+///   starts at.
+/// * 'tokens' - The tokens of the current block.
+/// * 'writer' - The compressed output.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::deflate::{inflate, Deflater};
+/// let mut deflater = Deflater::new(6);
+/// deflater.write(b"Hello ");
+/// deflater.write(b"Hello");
+///
+/// let mut compressed = deflater.take_output();
+/// compressed.extend(deflater.finish());
+///
+/// assert_eq!(inflate(&compressed).unwrap(), b"Hello Hello");
+/// ```
+pub struct Deflater {
+    finder: MatchFinder,
+    level: u8,
+    buffer: Vec<u8>,
+    base: usize,
+    position: usize,
+    block_start: usize,
+    tokens: Vec<Token>,
+    writer: BitWriter,
+}
+
+impl Deflater {
+    /// Creates a compressor for the given level from 0 through 9.
+    pub fn new(level: u8) -> Self {
+        Self {
+            finder: MatchFinder::new(level),
+            level,
+            buffer: Vec::new(),
+            base: 0,
+            position: 0,
+            block_start: 0,
+            tokens: Vec::new(),
+            writer: BitWriter::new(),
+        }
+    }
+
+    /// Creates a compressor whose output can refer back to the last 32K
+    /// of 'dictionary', as though it had been compressed just before.
+    /// The dictionary itself is not output, so the decompressor has to be
+    /// given it too, unless the output continues an earlier stream which
+    /// ended with the dictionary, see deflate_segment.
+    pub fn with_dictionary(level: u8, dictionary: &[u8]) -> Self {
+        let mut deflater = Self::new(level);
+        let dictionary = &dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..];
+        deflater.buffer.extend_from_slice(dictionary);
+        for position in 0..dictionary.len() {
+            deflater.finder.insert(&deflater.buffer, 0, position);
+        }
+        deflater.position = dictionary.len();
+        deflater.block_start = dictionary.len();
+        deflater
+    }
+
+    /// Adds input to be compressed.
+    pub fn write(&mut self, input: &[u8]) {
+        self.buffer.extend_from_slice(input);
+        self.tokenize(false);
+    }
+
+    /// Compresses all input given so far and writes it out followed by
+    /// an empty stored block, so that the output ends on a byte boundary
+    /// and a decoder can decode everything written up to this point.
+    pub fn flush(&mut self) {
+        self.tokenize(true);
+        self.write_block(false);
+        self.writer.write_bits(0, 3);
+        self.writer.align_to_byte();
+        self.writer.write_bytes(&[0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    /// Removes and returns the compressed bytes produced so far.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.writer.take_bytes()
+    }
+
+    /// Compresses the remaining input and writes the final block.
+    ///
+    /// # Returns
+    ///
+    /// The compressed bytes not yet returned by take_output.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.tokenize(true);
+        self.write_block(true);
+        self.writer.into_bytes()
+    }
+
+    /// Turns buffered input into tokens, writing out blocks as they fill.
+    ///
+    /// Unless 'flush' is set, the last MAX_MATCH bytes are held back since
+    /// more input could still extend a match starting among them.
+    fn tokenize(&mut self, flush: bool) {
+        let end = self.base + self.buffer.len();
+        let limit = if flush {
+            end
+        } else {
+            end.saturating_sub(MAX_MATCH)
+        };
+        let lazy = self.finder.params.lazy;
+        let nice_length = self.finder.params.nice_length;
+
+        // The match at the current position when it was already found
+        // while checking whether to defer the previous match.
+        let mut found = None;
+
+        while self.position < limit {
+            let position = self.position;
+            let (length, distance) = found
+                .take()
+                .unwrap_or_else(|| self.finder.longest_match(&self.buffer, self.base, position));
+            self.finder.insert(&self.buffer, self.base, position);
+
+            if length < MIN_MATCH {
+                self.tokens
+                    .push(Token::Literal(self.buffer[position - self.base]));
+                self.position += 1;
+            } else {
+                // Lazy matching: if the match starting at the next byte is
+                // longer, emit this byte as a literal and take that one.
+                if lazy && length < nice_length && position + 1 < limit {
+                    let next = self
+                        .finder
+                        .longest_match(&self.buffer, self.base, position + 1);
+                    if next.0 > length {
+                        self.tokens
+                            .push(Token::Literal(self.buffer[position - self.base]));
+                        self.position += 1;
+                        found = Some(next);
+                        self.end_block_if_full();
+                        continue;
+                    }
+                }
+
+                self.tokens.push(Token::Match {
+                    length: length as u16,
+                    distance: distance as u16,
+                });
+                for skipped in position + 1..position + length {
+                    self.finder.insert(&self.buffer, self.base, skipped);
+                }
+                self.position += length;
+            }
+            self.end_block_if_full();
+        }
+
+        // Drop input which is neither within the window nor part of the
+        // current block, once enough has built up to be worth moving.
+        let keep = self
+            .block_start
+            .min(self.position.saturating_sub(WINDOW_SIZE));
+        if keep - self.base >= WINDOW_SIZE {
+            self.buffer.drain(..keep - self.base);
+            self.base = keep;
+        }
+    }
+
+    /// Writes the current block if it has reached its size limit.
+    fn end_block_if_full(&mut self) {
+        // Level 0 only writes stored blocks, which are best kept large.
+        let full_tokens = self.level > 0 && self.tokens.len() >= BLOCK_TOKENS;
+        if full_tokens || self.position - self.block_start >= BLOCK_BYTES {
+            self.write_block(false);
+        }
+    }
+
+    /// Writes the tokens gathered since the last block as a new block,
+    /// picking whichever block type gives the smallest output.
+    fn write_block(&mut self, last: bool) {
+        if !last && self.position == self.block_start {
+            return;
+        }
+
+        // Count symbol frequencies, along with the extra bits which cost
+        // the same whatever codes are used.
+        let mut literal_frequencies = [0u32; 286];
+        let mut distance_frequencies = [0u32; 30];
+        let mut extra_bits = 0;
+        for token in &self.tokens {
+            match *token {
+                Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+                Token::Match { length, distance } => {
+                    let [symbol, extra, _] = length_code(length);
+                    literal_frequencies[symbol as usize] += 1;
+                    let [distance_symbol, distance_extra, _] = distance_code(distance);
+                    distance_frequencies[distance_symbol as usize] += 1;
+                    extra_bits += (extra + distance_extra) as usize;
+                }
+            }
+        }
+        literal_frequencies[END_OF_BLOCK as usize] = 1;
+
+        let literal_lengths = code_lengths(&literal_frequencies, MAX_CODE_LENGTH as u8);
+        let mut distance_lengths = code_lengths(&distance_frequencies, MAX_CODE_LENGTH as u8);
+        // A block without back-references still needs one distance code.
+        if distance_lengths.iter().all(|&length| length == 0) {
+            distance_lengths[0] = 1;
+        }
+        let header = DynamicHeader::new(&literal_lengths, &distance_lengths);
+
+        let cost = |literals: &[u8], distances: &[u8]| -> usize {
+            let literal_bits: usize = literal_frequencies
+                .iter()
+                .zip(literals)
+                .map(|(&count, &length)| count as usize * length as usize)
+                .sum();
+            let distance_bits: usize = distance_frequencies
+                .iter()
+                .zip(distances)
+                .map(|(&count, &length)| count as usize * length as usize)
+                .sum();
+            literal_bits + distance_bits + extra_bits
+        };
+
+        let (fixed_literals, fixed_distances) = generate_fixed_huffman();
+        let raw = &self.buffer[self.block_start - self.base..self.position - self.base];
+        let stored_blocks = raw.len().div_ceil(MAX_STORED).max(1);
+
+        let dynamic_size = 3 + header.bit_len() + cost(&literal_lengths, &distance_lengths);
+        let fixed_size = 3 + cost(&fixed_literals, &fixed_distances);
+        let stored_size = (raw.len() + 5 * stored_blocks) * 8;
+
+        if self.level == 0 || (stored_size <= fixed_size && stored_size <= dynamic_size) {
+            let mut chunks = raw.chunks(MAX_STORED).peekable();
+            if chunks.peek().is_none() {
+                write_stored_block(&mut self.writer, &[], last);
+            }
+            while let Some(chunk) = chunks.next() {
+                write_stored_block(&mut self.writer, chunk, last && chunks.peek().is_none());
+            }
+        } else if fixed_size <= dynamic_size {
+            self.writer.write_bits(last as u32, 1);
+            self.writer.write_bits(1, 2);
+            write_tokens(
+                &mut self.writer,
+                &self.tokens,
+                &fixed_literals,
+                &fixed_distances,
+            );
+        } else {
+            self.writer.write_bits(last as u32, 1);
+            self.writer.write_bits(2, 2);
+            header.write(&mut self.writer);
+            write_tokens(
+                &mut self.writer,
+                &self.tokens,
+                &literal_lengths,
+                &distance_lengths,
+            );
+        }
+
+        self.tokens.clear();
+        self.block_start = self.position;
+    }
+}
+
+/// Writes a single stored block, which has to hold at most MAX_STORED bytes.
+fn write_stored_block(writer: &mut BitWriter, bytes: &[u8], last: bool) {
+    writer.write_bits(last as u32, 1);
+    writer.write_bits(0, 2);
+    writer.align_to_byte();
+    let length = bytes.len() as u16;
+    writer.write_bytes(&length.to_le_bytes());
+    writer.write_bytes(&(!length).to_le_bytes());
+    writer.write_bytes(bytes);
+}
+
+/// Writes the tokens of a block followed by the end of block code.
+fn write_tokens(writer: &mut BitWriter, tokens: &[Token], literals: &[u8], distances: &[u8]) {
+    let literal_codes = canonical_codes(literals).expect("Literal code lengths are valid.");
+    let distance_codes = canonical_codes(distances).expect("Distance code lengths are valid.");
+
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => {
+                let (code, length) = literal_codes[byte as usize];
+                writer.write_code(code, length);
+            }
+            Token::Match { length, distance } => {
+                let [symbol, extra, value] = length_code(length);
+                let (code, code_length) = literal_codes[symbol as usize];
+                writer.write_code(code, code_length);
+                writer.write_bits(value as u32, extra as u32);
+
+                let [symbol, extra, value] = distance_code(distance);
+                let (code, code_length) = distance_codes[symbol as usize];
+                writer.write_code(code, code_length);
+                writer.write_bits(value as u32, extra as u32);
+            }
+        }
+    }
+
+    let (code, length) = literal_codes[END_OF_BLOCK as usize];
+    writer.write_code(code, length);
+}
+
+/// The header of a dynamic block, describing its two Huffman codes
+/// through a third code over run-length encoded code lengths.
+///
+/// # Fields
+///
+/// * 'hlit' - The number of literal/length code lengths, 257 through 286.
+/// * 'hdist' - The number of distance code lengths, 1 through 30.
+/// * 'hclen' - The number of code length code lengths, 4 through 19.
+/// * 'runs' - The run-length encoded code lengths as pairs of a symbol
+///   from 0 through 18 and the value of its extra bits.
+/// * 'lengths' - The code lengths of the code length code.
+struct DynamicHeader {
+    hlit: usize,
+    hdist: usize,
+    hclen: usize,
+    runs: Vec<(u8, u8)>,
+    lengths: Vec<u8>,
+}
+
+impl DynamicHeader {
+    /// Builds the header for the given literal/length and distance code
+    /// lengths, trimming trailing unused codes.
+    fn new(literal_lengths: &[u8], distance_lengths: &[u8]) -> Self {
+        let used = |lengths: &[u8]| {
+            lengths
+                .iter()
+                .rposition(|&length| length != 0)
+                .map_or(0, |last| last + 1)
+        };
+        let hlit = used(literal_lengths).max(257);
+        let hdist = used(distance_lengths).max(1);
+
+        let mut all_lengths = literal_lengths[..hlit].to_vec();
+        all_lengths.extend_from_slice(&distance_lengths[..hdist]);
+        let runs = run_length_encode(&all_lengths);
+
+        let mut frequencies = [0u32; 19];
+        for &(symbol, _) in &runs {
+            frequencies[symbol as usize] += 1;
+        }
+        let lengths = code_lengths(&frequencies, 7);
+        let hclen = CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| lengths[symbol] != 0)
+            .map_or(0, |last| last + 1)
+            .max(4);
+
+        Self {
+            hlit,
+            hdist,
+            hclen,
+            runs,
+            lengths,
+        }
+    }
+
+    /// The number of extra bits following a code length symbol.
+    fn extra_bits(symbol: u8) -> u32 {
+        match symbol {
+            16 => 2,
+            17 => 3,
+            18 => 7,
+            _ => 0,
+        }
+    }
+
+    /// The size of the header in bits, not counting BFINAL and BTYPE.
+    fn bit_len(&self) -> usize {
+        let runs: usize = self
+            .runs
+            .iter()
+            .map(|&(symbol, _)| {
+                self.lengths[symbol as usize] as usize + Self::extra_bits(symbol) as usize
+            })
+            .sum();
+        14 + 3 * self.hclen + runs
+    }
+
+    /// Writes the header.
+    fn write(&self, writer: &mut BitWriter) {
+        writer.write_bits((self.hlit - 257) as u32, 5);
+        writer.write_bits((self.hdist - 1) as u32, 5);
+        writer.write_bits((self.hclen - 4) as u32, 4);
+        for &symbol in &CODE_LENGTH_ORDER[..self.hclen] {
+            writer.write_bits(self.lengths[symbol] as u32, 3);
+        }
+
+        let codes = canonical_codes(&self.lengths).expect("Code length code lengths are valid.");
+        for &(symbol, extra) in &self.runs {
+            let (code, length) = codes[symbol as usize];
+            writer.write_code(code, length);
+            writer.write_bits(extra as u32, Self::extra_bits(symbol));
+        }
+    }
+}
+
+/// Run-length encodes code lengths using the symbols of RFC 1951 section
+/// 3.2.7: 16 repeats the previous length 3 to 6 times, 17 repeats a zero
+/// 3 to 10 times and 18 repeats a zero 11 to 138 times.
+///
+/// # Returns
+///
+/// Pairs of a symbol and the value of its extra bits.
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut runs = Vec::new();
+    let mut index = 0;
+
+    while index < lengths.len() {
+        let value = lengths[index];
+        let run = lengths[index..]
+            .iter()
+            .take_while(|&&length| length == value)
+            .count();
+        index += run;
+
+        let mut left = run;
+        if value == 0 {
+            while left >= 11 {
+                let count = left.min(138);
+                runs.push((18, (count - 11) as u8));
+                left -= count;
+            }
+            if left >= 3 {
+                runs.push((17, (left - 3) as u8));
+                left = 0;
+            }
+        } else {
+            runs.push((value, 0));
+            left -= 1;
+            while left >= 3 {
+                let count = left.min(6);
+                runs.push((16, (count - 3) as u8));
+                left -= count;
+            }
+        }
+        runs.extend(std::iter::repeat_n((value, 0), left));
+    }
+
+    runs
+}
+
+//      +---------------+
+//      | STREAM CODERS |
+//      +---------------+
+
+/// The most output a streaming decoder inflates at a time.
+pub(crate) const CODER_CHUNK: usize = 16384;
+
+/// Inflates everything the inflater's stream has to give, until its
+/// final block has ended or the stream is blocked.
+///
+/// # Arguments
+///
+/// * 'inflater' - The inflater to drain.
+/// * 'output' - Where the decompressed bytes are appended.
+pub(crate) fn inflate_into<R: Read>(
+    inflater: &mut Inflater<R>,
+    output: &mut Vec<u8>,
+) -> Result<(), DeflateError> {
+    loop {
+        let start = output.len();
+        output.resize(start + CODER_CHUNK, 0);
+        let result = inflater.inflate(&mut output[start..]);
+        output.truncate(start + *result.as_ref().unwrap_or(&0));
+        if result? == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Raw DEFLATE through the Coder interface, compressing at DEFAULT_LEVEL.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::coder::Coder;
+/// # use chameleon::compression::deflate::Deflate;
+/// let compressed = Deflate::encode(b"Hello Hello Hello").unwrap();
+///
+/// assert_eq!(Deflate::decode(compressed).unwrap(), b"Hello Hello Hello");
+/// ```
+pub struct Deflate {}
+
+impl Coder<u8, u8> for Deflate {
+    type Error = DeflateError;
+
+    fn encode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
+        Ok(deflate(input.as_ref(), DEFAULT_LEVEL))
+    }
+
+    fn decode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
+        inflate(input.as_ref())
+    }
+}
+
+/// Compresses a raw DEFLATE stream through the StreamCoder interface,
+/// see Deflater for when output becomes available.
+///
+/// # Fields
+///
+/// * 'deflater' - Compresses the current stream.
+/// * 'level' - The compression level, for the next stream.
+pub struct DeflateEncoder {
+    deflater: Deflater,
+    level: u8,
+}
+
+impl StreamCoder<u8, u8> for DeflateEncoder {
+    type Error = DeflateError;
+
+    fn with_options(options: &CoderOptions) -> Self {
+        Self {
+            deflater: Deflater::new(options.level),
+            level: options.level,
+        }
+    }
+
+    fn update(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.deflater.write(input);
+        output.extend(self.deflater.take_output());
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        let deflater = std::mem::replace(&mut self.deflater, Deflater::new(self.level));
+        output.extend(deflater.finish());
+        Ok(())
+    }
+}
+
+/// Decompresses a raw DEFLATE stream through the StreamCoder interface,
+/// outputting everything the input given so far holds.
+///
+/// # Fields
+///
+/// * 'inflater' - Decompresses the current stream.
+/// * 'max_output' - The most bytes to output, for the next stream.
+pub struct DeflateDecoder {
+    inflater: Inflater<PushInput>,
+    max_output: usize,
+}
+
+impl StreamCoder<u8, u8> for DeflateDecoder {
+    type Error = DeflateError;
+
+    fn with_options(options: &CoderOptions) -> Self {
+        Self {
+            inflater: Inflater::with_limit(PushInput::default(), options.max_output),
+            max_output: options.max_output,
+        }
+    }
+
+    fn update(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.inflater.bit_reader().get_mut().push(input);
+        inflate_into(&mut self.inflater, output)
+    }
+
+    /// Fails with DeflateError::UnexpectedEnd unless the input held the
+    /// whole stream, anything following its final block is ignored.
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.inflater.bit_reader().get_mut().finish();
+        inflate_into(&mut self.inflater, output)?;
+        self.inflater = Inflater::with_limit(PushInput::default(), self.max_output);
+        Ok(())
+    }
+}
+
+//       +--------+
+//       | ERRORS |
+//       +--------+
+
+/// Enum containing possible errors raised while decompressing a DEFLATE
+/// stream. Every variant holds the bit offset into the stream at which
+/// the problem was found.
+///
+/// # Fields
+///
+/// * 'UnexpectedEnd' - The input ended before the final block did.
+/// * 'InvalidBlockType' - A block header used the reserved type 11.
+/// * 'StoredLengthMismatch' - LEN and NLEN of a stored block do not match.
+/// * 'InvalidCodeLengths' - The code lengths of a dynamic block do not
+///   describe a usable Huffman code.
+/// * 'InvalidCode' - The bits read are not a code, or decode to a symbol
+///   which cannot appear, such as the literal/length 286.
+/// * 'InvalidDistance' - A back-reference points before the start of
+///   the output.
+/// * 'LimitExceeded' - The output grew past the limit it was given.
+#[derive(Debug)]
+pub enum DeflateError {
+    UnexpectedEnd(usize),
+    InvalidBlockType(usize),
+    StoredLengthMismatch(usize),
+    InvalidCodeLengths(usize),
+    InvalidCode(usize),
+    InvalidDistance {
+        position: usize,
+        distance: usize,
+        available: usize,
+    },
+    LimitExceeded(LimitError),
+}
+
+// Defines how DeflateErrors are displayed.
+impl Display for DeflateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeflateError::UnexpectedEnd(bit) => {
+                write!(
+                    f,
+                    "Error: DEFLATE stream ended unexpectedly at bit '{bit}'."
+                )
+            }
+            DeflateError::InvalidBlockType(bit) => {
+                write!(f, "Error: Reserved DEFLATE block type at bit '{bit}'.")
+            }
+            DeflateError::StoredLengthMismatch(bit) => {
+                write!(
+                    f,
+                    "Error: Stored block length does not match its complement at bit '{bit}'."
+                )
+            }
+            DeflateError::InvalidCodeLengths(bit) => {
+                write!(f, "Error: Invalid Huffman code lengths at bit '{bit}'.")
+            }
+            DeflateError::InvalidCode(bit) => {
+                write!(f, "Error: Invalid Huffman code at bit '{bit}'.")
+            }
+            DeflateError::InvalidDistance {
+                position,
+                distance,
+                available,
+            } => {
+                write!(f, "Error: Back-reference distance '{distance}' at bit '{position}' exceeds the '{available}' bytes decoded so far.")
+            }
+            DeflateError::LimitExceeded(e) => {
+                write!(f, "{e}")
+            }
+        }
+    }
+}
+
+// Implements the Error interface for DeflateError, with the exceeded
+// limit as its source.
+impl Error for DeflateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DeflateError::LimitExceeded(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::checksum::crc32;
+
+    /// Repetitive data with some noise, long enough to split into several
+    /// segments.
+    fn sample(length: usize) -> Vec<u8> {
+        let words: [&[u8]; 4] = [b"chameleon ", b"deflate ", b"segment ", b"window "];
+        let mut state = 7u32;
+        let mut data = Vec::with_capacity(length);
+        while data.len() < length {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            match state >> 28 {
+                0 => data.push((state >> 8) as u8),
+                n => data.extend_from_slice(words[n as usize % 4]),
+            }
+        }
+        data.truncate(length);
+        data
+    }
+
+    #[test]
+    fn parallel_segments_join_into_one_stream() {
+        let data = sample(3 * MIN_SEGMENT + 1000);
+        for prime in [false, true] {
+            for threads in [1, 2, 4, 16] {
+                let (compressed, sums) = deflate_parallel(&data, 6, threads, prime, crc32);
+                assert_eq!(inflate(&compressed).unwrap(), data);
+
+                // One segment per thread, never smaller than MIN_SEGMENT.
+                assert_eq!(sums.len(), threads.min(4));
+                let mut start = 0;
+                for (sum, length) in sums {
+                    assert_eq!(sum, crc32(&data[start..start + length]));
+                    start += length;
+                }
+                assert_eq!(start, data.len());
+            }
+        }
+
+        let (primed, _) = deflate_parallel(&data, 6, 4, true, |_| ());
+        let (unprimed, _) = deflate_parallel(&data, 6, 4, false, |_| ());
+        assert!(primed.len() <= unprimed.len());
+    }
+
+    #[test]
+    fn small_and_empty_input() {
+        for data in [&b""[..], b"a", b"abcabcabcabc"] {
+            let (compressed, sums) = deflate_parallel(data, 9, 8, true, |segment| segment.len());
+            assert_eq!(inflate(&compressed).unwrap(), data);
+            assert_eq!(sums, [(data.len(), data.len())]);
+        }
+    }
+
+    #[test]
+    fn stream_coders_round_trip() {
+        let data = sample(200_000);
+        let options = CoderOptions::default();
+        let mut encoder = DeflateEncoder::with_options(&options);
+        let mut compressed = Vec::new();
+        for piece in data.chunks(3000) {
+            encoder.update(piece, &mut compressed).unwrap();
+        }
+        encoder.finish(&mut compressed).unwrap();
+        assert_eq!(inflate(&compressed).unwrap(), data);
+
+        let mut decoder = DeflateDecoder::with_options(&options);
+        for piece in [1, 777] {
+            let mut output = Vec::new();
+            for bytes in compressed.chunks(piece) {
+                decoder.update(bytes, &mut output).unwrap();
+            }
+            decoder.finish(&mut output).unwrap();
+            assert_eq!(output, data);
+        }
+    }
+
+    #[test]
+    fn stream_decoders_check_the_end_and_limit() {
+        // Stored blocks, so that the cut falls within their bytes.
+        let stored = deflate(&sample(50_000), 0);
+        let mut output = Vec::new();
+        let mut decoder = DeflateDecoder::with_options(&CoderOptions::default());
+        decoder
+            .update(&stored[..stored.len() / 2], &mut output)
+            .unwrap();
+        assert!(!output.is_empty());
+        assert!(matches!(
+            decoder.finish(&mut output),
+            Err(DeflateError::UnexpectedEnd(_))
+        ));
+
+        let compressed = deflate(&sample(50_000), 6);
+        let mut decoder = DeflateDecoder::with_options(&CoderOptions {
+            max_output: 10_000,
+            ..CoderOptions::default()
+        });
+        assert!(matches!(
+            decoder.update(&compressed, &mut Vec::new()),
+            Err(DeflateError::LimitExceeded(LimitError::Output {
+                max: 10_000
+            }))
+        ));
+    }
+
+    #[test]
+    fn dictionaries_prime_the_window() {
+        let dictionary = b"\"level\":\"info\",\"source\":\"sensor\",\"message\":\"";
+        let record = b"{\"level\":\"info\",\"source\":\"sensor\",\"message\":\"ready\"}";
+        let primed = deflate_with_dictionary(record, 9, dictionary);
+        assert!(primed.len() < deflate(record, 9).len());
+        assert_eq!(
+            inflate_with_dictionary(&primed, dictionary).unwrap(),
+            record
+        );
+
+        // Inflater reads the same stream once given the dictionary.
+        let mut inflater = Inflater::new(&primed[..]);
+        inflater.set_dictionary(dictionary);
+        let mut output = vec![0; 100];
+        let length = inflater.inflate(&mut output).unwrap();
+        assert_eq!(output[..length], record[..]);
+
+        // Only the last 32K of a longer dictionary is used.
+        let mut long = sample(3 * WINDOW_SIZE);
+        long.extend_from_slice(dictionary);
+        let primed = deflate_with_dictionary(record, 9, &long);
+        assert_eq!(inflate_with_dictionary(&primed, &long).unwrap(), record);
+        let window = &long[long.len() - WINDOW_SIZE..];
+        assert_eq!(inflate_with_dictionary(&primed, window).unwrap(), record);
+    }
+
+    #[test]
+    fn missing_dictionaries_are_detected() {
+        let dictionary = sample(1000);
+        let primed = deflate_with_dictionary(&dictionary[500..], 6, &dictionary);
+
+        // The matches reach back before the start of the output.
+        assert!(matches!(
+            inflate(&primed),
+            Err(DeflateError::InvalidDistance { available: 0, .. })
+        ));
+        assert!(inflate_with_dictionary(&primed, &sample(10)).is_err());
+    }
+}
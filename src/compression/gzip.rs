@@ -3,7 +3,23 @@
 //! test for the DEFLATE algorithm. For this reason, the
 //! documentation is less exhaustive than in the rest of this
 //! project.
-use std::{fs, io, path::Path};
+use crate::bits::reader::{BitReader, BitSource};
+use crate::compression::checksum::{crc32, crc32_combine, Crc32};
+use crate::compression::coder::{Coder, CoderOptions, PushInput, StreamCoder, DEFAULT_LEVEL};
+use crate::compression::deflate::{
+    deflate, deflate_segment, inflate_from, inflate_into, DeflateError, Deflater, Inflater,
+};
+use crate::compression::lzss::WINDOW_SIZE;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{self, Display},
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{mpsc, Mutex},
+    thread,
+};
 
 //      +------+
 //      | GZIP |
@@ -32,3 +48,715 @@ impl GzipFile {
         })
     }
 }
+
+/// The two bytes every gzip member starts with.
+pub const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+// Bits of the FLG byte marking optional header fields.
+const FHCRC: u8 = 0b0000_0010;
+const FEXTRA: u8 = 0b0000_0100;
+const FNAME: u8 = 0b0000_1000;
+const FCOMMENT: u8 = 0b0001_0000;
+
+/// Decompresses the first member of a gzip file, checking the CRC-32
+/// and length in its trailer.
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>, GzipError> {
+    decompress_limited(input, usize::MAX)
+}
+
+/// Same as decompress, but stops with DeflateError::LimitExceeded
+/// rather than decompressing more than 'max_output' bytes.
+pub fn decompress_limited(input: &[u8], max_output: usize) -> Result<Vec<u8>, GzipError> {
+    let start = header_length(input)?.ok_or(GzipError::InvalidHeader("Header is truncated."))?;
+    let mut reader = BitReader::new(&input[start..]);
+    let mut output = Vec::new();
+    inflate_from(&mut reader, &mut output, max_output)?;
+
+    // name:    CRC32   ISIZE
+    // bytes:   4       4
+    reader.align_to_byte();
+    let trailer = reader.read_bytes(8).ok_or(GzipError::MissingTrailer)?;
+    let expected = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let actual = crc32(&output);
+    if expected != actual {
+        return Err(GzipError::ChecksumMismatch { expected, actual });
+    }
+    // ISIZE is the length modulo 2^32.
+    let length = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if length != output.len() as u32 {
+        return Err(GzipError::LengthMismatch {
+            expected: length,
+            actual: output.len() as u32,
+        });
+    }
+    Ok(output)
+}
+
+/// Finds where the DEFLATE stream starts, after the header and its
+/// optional fields.
+///
+/// # Returns
+///
+/// A result containing either the index of the DEFLATE stream, None if
+/// the input ends within the header, or a GzipError.
+fn header_length(input: &[u8]) -> Result<Option<usize>, GzipError> {
+    if input.len() < 10 {
+        return Ok(None);
+    }
+    if input[..2] != GZIP_MAGIC {
+        return Err(GzipError::InvalidHeader("Missing gzip magic bytes."));
+    }
+    if input[2] != 8 {
+        return Err(GzipError::InvalidHeader(
+            "Compression method is not DEFLATE.",
+        ));
+    }
+
+    let flags = input[3];
+    let mut index = 10;
+    if flags & FEXTRA != 0 {
+        let Some(length) = input.get(index..index + 2) else {
+            return Ok(None);
+        };
+        index += 2 + u16::from_le_bytes([length[0], length[1]]) as usize;
+    }
+    // The name and comment are both zero terminated.
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let Some(length) = input
+                .get(index..)
+                .and_then(|field| field.iter().position(|&byte| byte == 0))
+            else {
+                return Ok(None);
+            };
+            index += length + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        index += 2;
+    }
+
+    Ok((index <= input.len()).then_some(index))
+}
+
+//      +---------+
+//      | WRITING |
+//      +---------+
+
+/// The size of the blocks compress_stream hands to each thread, the
+/// same as pigz uses.
+pub const DEFAULT_BLOCK_SIZE: usize = 128 * 1024;
+
+/// Compresses data into a gzip file with a single member and no optional
+/// header fields.
+///
+/// # Arguments
+///
+/// * 'input' - The data to compress.
+/// * 'level' - The DEFLATE compression level from 0 through 9.
+pub fn compress(input: &[u8], level: u8) -> Vec<u8> {
+    let mut output = header(level).to_vec();
+    output.extend(deflate(input, level));
+    output.extend_from_slice(&crc32(input).to_le_bytes());
+    output.extend_from_slice(&(input.len() as u32).to_le_bytes());
+    output
+}
+
+/// Same as compress, but compressing blocks of DEFAULT_BLOCK_SIZE bytes
+/// on up to 'threads' threads, see compress_stream.
+pub fn compress_parallel(input: &[u8], level: u8, threads: usize) -> Vec<u8> {
+    let mut output = Vec::new();
+    compress_stream(input, &mut output, level, threads, DEFAULT_BLOCK_SIZE)
+        .expect("reading from a slice and writing to a Vec cannot fail");
+    output
+}
+
+/// Compresses a stream into a gzip file the way pigz does, holding only
+/// a few blocks of input for each thread at a time.
+///
+/// Input is read a block at a time and handed to 'threads' workers over
+/// a channel, so the next blocks are read while the ones before them are
+/// compressed. Each block is primed with the 32K of input before it as a
+/// dictionary, so little is lost to splitting, and ends with a sync flush
+/// so the compressed blocks can simply be joined in order. The CRC-32 of
+/// each block is computed by its worker as well, and they are combined
+/// for the trailer.
+///
+/// # Arguments
+///
+/// * 'reader' - The data to compress.
+/// * 'writer' - Where the gzip file is written.
+/// * 'level' - The DEFLATE compression level from 0 through 9.
+/// * 'threads' - The number of blocks compressed at once.
+/// * 'block_size' - The number of bytes of input in each block.
+///
+/// # Returns
+///
+/// A result containing either the number of bytes of input compressed or
+/// an io::Error from either stream.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::compression::gzip;
+/// # use std::{fs::File, io::BufWriter};
+/// let input = File::open("access.log")?;
+/// let output = BufWriter::new(File::create("access.log.gz")?);
+///
+/// gzip::compress_stream(input, output, 6, 8, gzip::DEFAULT_BLOCK_SIZE)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn compress_stream<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    level: u8,
+    threads: usize,
+    block_size: usize,
+) -> io::Result<u64> {
+    let block_size = block_size.max(1);
+    let threads = threads.max(1);
+    writer.write_all(&header(level))?;
+
+    // Blocks go out numbered, along with their dictionary, and come back
+    // compressed in whatever order they finish. Only a block per thread
+    // waits in the queue, so reading stays ahead of the workers without
+    // holding much more of the input.
+    let (jobs, queue) = mpsc::sync_channel::<(usize, Vec<u8>, Vec<u8>)>(threads);
+    let (done, results) = mpsc::channel();
+    let queue = Mutex::new(queue);
+    let (crc, length) = thread::scope(|scope| {
+        // Owned here so that the queue closes, and the workers stop, even
+        // when an error ends the loop early.
+        let jobs = jobs;
+        for _ in 0..threads {
+            let (queue, done) = (&queue, done.clone());
+            scope.spawn(move || {
+                // The lock is only held while waiting for the next block.
+                while let Ok((index, dictionary, block)) =
+                    queue.lock().expect("compression thread panicked").recv()
+                {
+                    let compressed = deflate_segment(&dictionary, &block, level, false);
+                    if done
+                        .send((index, compressed, crc32(&block), block.len()))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(done);
+
+        let mut dictionary = Vec::new();
+        let mut pending = BTreeMap::new();
+        let (mut sent, mut written) = (0, 0);
+        let mut crc = crc32(&[]);
+        let mut length = 0u64;
+        loop {
+            let mut block = Vec::with_capacity(block_size);
+            (&mut reader)
+                .take(block_size as u64)
+                .read_to_end(&mut block)?;
+            let finished = block.is_empty();
+            if !finished {
+                let primer = dictionary.clone();
+                // Keep the last 32K of input, which may span several blocks.
+                dictionary.extend_from_slice(&block[block.len().saturating_sub(WINDOW_SIZE)..]);
+                dictionary.drain(..dictionary.len().saturating_sub(WINDOW_SIZE));
+                length += block.len() as u64;
+                jobs.send((sent, primer, block))
+                    .expect("compression thread panicked");
+                sent += 1;
+            }
+
+            // Write out whichever blocks are next and done, then once the
+            // input has run out, wait for the rest.
+            while written < sent {
+                let result = match finished {
+                    true => results.recv().expect("compression thread panicked"),
+                    false => match results.try_recv() {
+                        Ok(result) => result,
+                        Err(_) => break,
+                    },
+                };
+                let (index, compressed, sum, block_length) = result;
+                pending.insert(index, (compressed, sum, block_length));
+                while let Some((compressed, sum, block_length)) = pending.remove(&written) {
+                    writer.write_all(&compressed)?;
+                    crc = crc32_combine(crc, sum, block_length);
+                    written += 1;
+                }
+            }
+            if finished {
+                return Ok::<_, io::Error>((crc, length));
+            }
+        }
+    })?;
+
+    // Every block ended with a sync flush, so the stream still needs
+    // its final block.
+    writer.write_all(&deflate_segment(&[], &[], level, true))?;
+    writer.write_all(&crc.to_le_bytes())?;
+    // ISIZE is the length modulo 2^32.
+    writer.write_all(&(length as u32).to_le_bytes())?;
+    writer.flush()?;
+    Ok(length)
+}
+
+/// Builds the ten byte header of a gzip member without optional fields.
+///
+/// XFL tells a reader whether the compressor used its fastest (4) or
+/// slowest (2) setting, and OS 255 leaves the system unknown.
+fn header(level: u8) -> [u8; 10] {
+    let xfl = match level {
+        0 | 1 => 4,
+        9 => 2,
+        _ => 0,
+    };
+    // name:    MAGIC1  MAGIC2  CM      FLAGS   MTIME       XFL     OS
+    [GZIP_MAGIC[0], GZIP_MAGIC[1], 8, 0, 0, 0, 0, 0, xfl, 255]
+}
+
+//      +---------------+
+//      | STREAM CODERS |
+//      +---------------+
+
+/// Gzip files through the Coder interface, compressing at DEFAULT_LEVEL
+/// and decoding the first member.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::coder::Coder;
+/// # use chameleon::compression::gzip::Gzip;
+/// let compressed = Gzip::encode(b"Hello Hello Hello").unwrap();
+///
+/// assert_eq!(Gzip::decode(compressed).unwrap(), b"Hello Hello Hello");
+/// ```
+pub struct Gzip {}
+
+impl Coder<u8, u8> for Gzip {
+    type Error = GzipError;
+
+    fn encode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
+        Ok(compress(input.as_ref(), DEFAULT_LEVEL))
+    }
+
+    fn decode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
+        decompress(input.as_ref())
+    }
+}
+
+/// Compresses a single gzip member through the StreamCoder interface,
+/// see Deflater for when output becomes available.
+///
+/// # Fields
+///
+/// * 'deflater' - Compresses the current member.
+/// * 'crc' - The CRC-32 of the input so far.
+/// * 'length' - The length of the input so far, modulo 2^32.
+/// * 'started' - Whether the header has been output.
+/// * 'level' - The compression level.
+pub struct GzipEncoder {
+    deflater: Deflater,
+    crc: Crc32,
+    length: u32,
+    started: bool,
+    level: u8,
+}
+
+impl GzipEncoder {
+    /// Outputs the header before the first compressed bytes.
+    fn start(&mut self, output: &mut Vec<u8>) {
+        if !self.started {
+            output.extend_from_slice(&header(self.level));
+            self.started = true;
+        }
+    }
+}
+
+impl StreamCoder<u8, u8> for GzipEncoder {
+    type Error = GzipError;
+
+    fn with_options(options: &CoderOptions) -> Self {
+        Self {
+            deflater: Deflater::new(options.level),
+            crc: Crc32::new(),
+            length: 0,
+            started: false,
+            level: options.level,
+        }
+    }
+
+    fn update(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.start(output);
+        self.crc.update(input);
+        self.length = self.length.wrapping_add(input.len() as u32);
+        self.deflater.write(input);
+        output.extend(self.deflater.take_output());
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.start(output);
+        let deflater = std::mem::replace(&mut self.deflater, Deflater::new(self.level));
+        output.extend(deflater.finish());
+        output.extend_from_slice(&self.crc.finish().to_le_bytes());
+        output.extend_from_slice(&self.length.to_le_bytes());
+        (self.crc, self.length, self.started) = (Crc32::new(), 0, false);
+        Ok(())
+    }
+}
+
+/// Decompresses the first member of a gzip file through the StreamCoder
+/// interface, outputting everything the input given so far holds.
+///
+/// # Fields
+///
+/// * 'header' - The input so far, until the whole header has arrived.
+/// * 'inflater' - Decompresses the DEFLATE stream following the header.
+/// * 'crc' - The CRC-32 of the output so far.
+/// * 'finished' - Whether the trailer has been read and checked.
+/// * 'max_output' - The most bytes to output.
+pub struct GzipDecoder {
+    header: Vec<u8>,
+    inflater: Option<Inflater<PushInput>>,
+    crc: Crc32,
+    finished: bool,
+    max_output: usize,
+}
+
+impl GzipDecoder {
+    /// Decompresses everything the input given so far holds, then checks
+    /// the trailer once it has arrived.
+    fn drain(&mut self, output: &mut Vec<u8>) -> Result<(), GzipError> {
+        let Some(inflater) = self.inflater.as_mut().filter(|_| !self.finished) else {
+            return Ok(());
+        };
+        let start = output.len();
+        let result = inflate_into(inflater, output);
+        self.crc.update(&output[start..]);
+        result?;
+        if !inflater.is_done() {
+            return Ok(());
+        }
+
+        // name:    CRC32   ISIZE
+        // bytes:   4       4
+        let length = inflater.total_out() as u32;
+        let reader = inflater.bit_reader();
+        reader.align_to_byte();
+        reader.mark();
+        let (Some(expected), Some(expected_length)) = (reader.read_bits(32), reader.read_bits(32))
+        else {
+            if reader.is_blocked() {
+                reader.reset();
+                return Ok(());
+            }
+            return Err(GzipError::MissingTrailer);
+        };
+
+        let actual = self.crc.finish();
+        if expected != actual {
+            return Err(GzipError::ChecksumMismatch { expected, actual });
+        }
+        if expected_length != length {
+            return Err(GzipError::LengthMismatch {
+                expected: expected_length,
+                actual: length,
+            });
+        }
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl StreamCoder<u8, u8> for GzipDecoder {
+    type Error = GzipError;
+
+    fn with_options(options: &CoderOptions) -> Self {
+        Self {
+            header: Vec::new(),
+            inflater: None,
+            crc: Crc32::new(),
+            finished: false,
+            max_output: options.max_output,
+        }
+    }
+
+    fn update(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        if self.finished {
+            return Ok(());
+        }
+        match &mut self.inflater {
+            Some(inflater) => inflater.bit_reader().get_mut().push(input),
+            None => {
+                self.header.extend_from_slice(input);
+                let Some(start) = header_length(&self.header)? else {
+                    return Ok(());
+                };
+                let mut stream = PushInput::default();
+                stream.push(&self.header[start..]);
+                self.inflater = Some(Inflater::with_limit(stream, self.max_output));
+                self.header.clear();
+            }
+        }
+        self.drain(output)
+    }
+
+    /// Fails unless the input held the whole member and its trailer
+    /// matched, anything following it is ignored.
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        let Some(inflater) = &mut self.inflater else {
+            return Err(GzipError::InvalidHeader("Header is truncated."));
+        };
+        inflater.bit_reader().get_mut().finish();
+        self.drain(output)?;
+        if !self.finished {
+            return Err(GzipError::MissingTrailer);
+        }
+        *self = Self::with_options(&CoderOptions {
+            max_output: self.max_output,
+            ..CoderOptions::default()
+        });
+        Ok(())
+    }
+}
+
+//       +--------+
+//       | ERRORS |
+//       +--------+
+
+/// Enum containing possible errors raised while decoding a gzip file.
+///
+/// # Fields
+///
+/// * 'InvalidHeader' - The header is malformed or unsupported.
+/// * 'MissingTrailer' - The file ends before the CRC-32 and length.
+/// * 'ChecksumMismatch' - The CRC-32 does not match the data.
+/// * 'LengthMismatch' - The stored length does not match the data.
+/// * 'DeflateError' - Wrapper for errors in the compressed data itself.
+#[derive(Debug)]
+pub enum GzipError {
+    InvalidHeader(&'static str),
+    MissingTrailer,
+    ChecksumMismatch { expected: u32, actual: u32 },
+    LengthMismatch { expected: u32, actual: u32 },
+    DeflateError(DeflateError),
+}
+
+// Defines how GzipErrors are displayed.
+impl Display for GzipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GzipError::InvalidHeader(e) => {
+                write!(f, "Error: Invalid gzip header, '{e}'")
+            }
+            GzipError::MissingTrailer => {
+                write!(f, "Error: gzip file ends before its trailer.")
+            }
+            GzipError::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Error: CRC-32 mismatch, expected '{expected:08X}' but found '{actual:08X}'."
+                )
+            }
+            GzipError::LengthMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Error: Length mismatch, expected '{expected}' bytes but found '{actual}'."
+                )
+            }
+            GzipError::DeflateError(e) => {
+                write!(f, "{e}")
+            }
+        }
+    }
+}
+
+// Allows for conversion from DeflateError to GzipError.
+impl From<DeflateError> for GzipError {
+    fn from(error: DeflateError) -> Self {
+        GzipError::DeflateError(error)
+    }
+}
+
+// Implements the Error interface for GzipError, with the wrapped error
+// as its source.
+impl Error for GzipError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GzipError::DeflateError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out at most 1000 bytes per read, as pipes and sockets do.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let length = buffer.len().min(self.0.len()).min(1000);
+            buffer[..length].copy_from_slice(&self.0[..length]);
+            self.0 = &self.0[length..];
+            Ok(length)
+        }
+    }
+
+    /// Fails every read, as a closed pipe does.
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    fn sample(length: usize) -> Vec<u8> {
+        (0..length as u32)
+            .map(|i| b"gzip member "[(i % 12) as usize] ^ (i / 4099) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn compressed_files_round_trip() {
+        let data = sample(300_000);
+        for level in [0, 1, 6, 9] {
+            let compressed = compress(&data, level);
+            assert_eq!(compressed[..3], [0x1F, 0x8B, 8]);
+            assert_eq!(decompress(&compressed).unwrap(), data);
+        }
+        assert_eq!(decompress(&compress(&[], 6)).unwrap(), b"");
+        assert_eq!(decompress(&compress_parallel(&data, 6, 4)).unwrap(), data);
+    }
+
+    #[test]
+    fn streams_compress_across_batches() {
+        let data = sample(100_000);
+        for (threads, block_size) in [(1, 4096), (3, 10_000), (8, 1 << 20)] {
+            let mut output = Vec::new();
+            let read =
+                compress_stream(Trickle(&data), &mut output, 6, threads, block_size).unwrap();
+            assert_eq!(read, data.len() as u64);
+            assert_eq!(decompress(&output).unwrap(), data);
+        }
+
+        let mut output = Vec::new();
+        compress_stream(&[][..], &mut output, 6, 4, 1024).unwrap();
+        assert_eq!(decompress(&output).unwrap(), b"");
+
+        // Errors reading the input stop the workers rather than leaving
+        // them waiting for more.
+        let failing = (&data[..50_000]).chain(Failing);
+        let result = compress_stream(failing, &mut Vec::new(), 6, 4, 4096);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let data = sample(5000);
+        let compressed = compress(&data, 6);
+        let length = compressed.len();
+
+        let mut corrupt = compressed.clone();
+        corrupt[length - 8] ^= 1;
+        assert!(matches!(
+            decompress(&corrupt),
+            Err(GzipError::ChecksumMismatch { .. })
+        ));
+        let mut corrupt = compressed.clone();
+        corrupt[length - 4] ^= 1;
+        assert!(matches!(
+            decompress(&corrupt),
+            Err(GzipError::LengthMismatch { .. })
+        ));
+        assert!(matches!(
+            decompress(&compressed[..length - 3]),
+            Err(GzipError::MissingTrailer)
+        ));
+        assert!(matches!(
+            decompress(&compressed[1..]),
+            Err(GzipError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn optional_header_fields_are_skipped() {
+        let compressed = compress(b"named", 6);
+        let mut named = compressed[..10].to_vec();
+        named[3] = FNAME | FCOMMENT | FEXTRA;
+        named.extend_from_slice(&[2, 0, b'x', b'y']);
+        named.extend_from_slice(b"file.txt\0a comment\0");
+        named.extend_from_slice(&compressed[10..]);
+        assert_eq!(decompress(&named).unwrap(), b"named");
+
+        // The name is never terminated.
+        named.truncate(20);
+        assert!(matches!(
+            decompress(&named),
+            Err(GzipError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn stream_coders_round_trip() {
+        let data = sample(120_000);
+        let options = CoderOptions::default();
+        let mut encoder = GzipEncoder::with_options(&options);
+        let mut decoder = GzipDecoder::with_options(&options);
+
+        // Both are ready for another member after finish.
+        for piece in [1000, 33_333] {
+            let mut compressed = Vec::new();
+            for bytes in data.chunks(piece) {
+                encoder.update(bytes, &mut compressed).unwrap();
+            }
+            encoder.finish(&mut compressed).unwrap();
+            assert_eq!(decompress(&compressed).unwrap(), data);
+
+            let mut output = Vec::new();
+            for bytes in compressed.chunks(5) {
+                decoder.update(bytes, &mut output).unwrap();
+            }
+            decoder.finish(&mut output).unwrap();
+            assert_eq!(output, data);
+        }
+    }
+
+    #[test]
+    fn stream_decoders_check_the_trailer() {
+        let compressed = compress(&sample(5000), 6);
+        let options = CoderOptions::default();
+        let mut output = Vec::new();
+
+        let mut decoder = GzipDecoder::with_options(&options);
+        decoder.update(&compressed[..5], &mut output).unwrap();
+        assert!(matches!(
+            decoder.finish(&mut output),
+            Err(GzipError::InvalidHeader(_))
+        ));
+
+        let mut decoder = GzipDecoder::with_options(&options);
+        decoder
+            .update(&compressed[..compressed.len() - 2], &mut output)
+            .unwrap();
+        assert!(matches!(
+            decoder.finish(&mut output),
+            Err(GzipError::MissingTrailer)
+        ));
+
+        let mut corrupt = compressed.clone();
+        let length = corrupt.len();
+        corrupt[length - 5] ^= 1;
+        let mut decoder = GzipDecoder::with_options(&options);
+        assert!(matches!(
+            decoder.update(&corrupt, &mut output),
+            Err(GzipError::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -1,268 +1,401 @@
+use crate::bits::reader::BitSource;
+use crate::compression::checksum::{crc32, Crc32};
+pub use crate::compression::coder::Coder;
+use crate::compression::coder::{CoderOptions, StreamCoder};
 use std::{
-    cell::RefCell,
-    cmp::{Ordering, Reverse},
-    collections::{BinaryHeap, HashMap},
+    cmp::Reverse,
+    collections::BinaryHeap,
     error::Error,
-    rc::Rc,
+    fmt::{self, Display},
 };
 
-/// Struct representing each node of a Huffman Tree. Used to both
+//      +---------------+
+//      | HUFFMAN TREES |
+//      +---------------+
+
+/// The most symbols an alphabet can have, so that every symbol fits
+/// in a u16.
+pub const MAX_ALPHABET_SIZE: usize = 1 << 16;
+
+/// The longest code a HuffmanTree can assign, the width of the u64
+/// holding it.
+pub const MAX_TREE_DEPTH: usize = 64;
+
+/// Struct representing each node of a HuffmanTree. Used to both
 /// represent branches and leaves. Where branches are the inner
-/// nodes, and leaves are the outer nodes holding values.
+/// nodes, and leaves are the outer nodes holding symbols. Nodes
+/// refer to their children by index into the tree's arena.
 ///
-///          root
-///         /    \
-///       leaf   branch
-///              /    \
-///           leaf    leaf
+/// ```text
+///      root
+///     /    \
+///   leaf   branch
+///          /    \
+///       leaf    leaf
+/// ```
 ///
-/// FIX: Add examples.
+/// # Attributes
+///
+/// * 'symbol' - The symbol of a leaf, None for branches.
+/// * 'frequency' - How often the symbols below the node occur.
+/// * 'children' - The nodes reached by a 0 and a 1 bit, both None for
+///   leaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Node {
-    // Dictates whether the node is a leaf or a branch.
-    // If true, the value is read, if false, the connected
-    // nodes are read.
-    leaf: bool,
-    // The actual value stored which is irrelevant for branches.
-    value: u8,
-    // The frequency the of the value stored within the leaf.
-    frequency: u32,
-    // The code leading up to this node.
-    address: u32,
-    // The length of the address.
-    length: usize,
-    // The node to the left.
-    left: Option<Rc<RefCell<Node>>>,
-    // The node to the right.
-    right: Option<Rc<RefCell<Node>>>,
+    symbol: Option<u16>,
+    frequency: u64,
+    children: [Option<usize>; 2],
 }
 
-// Allows for nodes to be compared by their frequency.
-impl Ord for Node {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.frequency.cmp(&other.frequency)
-    }
+/// A Huffman tree over an alphabet of up to 65536 symbols, stored as a
+/// flat arena of Nodes so that building one per block only allocates
+/// a single Vec.
+///
+/// When built from frequencies, leaves are created first in order of
+/// symbol, then each branch after both of its children. Ties between
+/// equal frequencies are broken by node index, so the same frequencies
+/// always build the same tree.
+///
+/// # Attributes
+///
+/// * 'nodes' - The arena holding every leaf and branch.
+/// * 'root' - The index of the root node, None if no symbol is used.
+/// * 'codes' - The code and length of each symbol, the code held in its
+///   low bits and read from the most significant of them. A
+///   length of 0 marks unused symbols.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::huffman::HuffmanTree;
+/// let tree = HuffmanTree::from_frequencies(&[5, 0, 1, 1]).unwrap();
+///
+/// // Symbol 0 takes the short code, symbol 1 is unused.
+/// assert_eq!(tree.code(0).1, 1);
+/// assert_eq!(tree.code(1), (0, 0));
+/// assert_eq!(tree.code(2).1, 2);
+///
+/// // Walking the code of symbol 3 from the root leads to its leaf.
+/// let (code, length) = tree.code(3);
+/// let mut node = tree.root().unwrap();
+/// for shift in (0..length).rev() {
+///     node = tree.child(node, (code >> shift) & 1 == 1).unwrap();
+/// }
+/// assert_eq!(tree.symbol(node), Some(3));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HuffmanTree {
+    nodes: Vec<Node>,
+    root: Option<usize>,
+    codes: Vec<(u64, u8)>,
 }
 
-// Allows for nodes to be partially compared.
-impl PartialOrd for Node {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
+impl HuffmanTree {
+    /// Builds the tree for the given symbol frequencies, by repeatedly
+    /// merging the two least frequent nodes until only the root is left.
+    ///
+    /// # Arguments
+    ///
+    /// * 'frequencies' - How often each symbol occurs, unused symbols have
+    ///   a frequency of 0 and are given no code.
+    ///
+    /// # Returns
+    ///
+    /// The tree, or None if there are more than 65536 symbols or a code
+    /// would be longer than 64 bits, which takes frequencies totalling over
+    /// 10^13. A single used symbol is given a one bit code so that it can
+    /// still be written.
+    pub fn from_frequencies(frequencies: &[u32]) -> Option<Self> {
+        if frequencies.len() > MAX_ALPHABET_SIZE {
+            return None;
+        }
+
+        let mut nodes = Vec::with_capacity(frequencies.len() * 2);
+        let mut heap = BinaryHeap::new();
+        for (symbol, &frequency) in frequencies.iter().enumerate() {
+            if frequency == 0 {
+                continue;
+            }
+            heap.push(Reverse((frequency as u64, nodes.len())));
+            nodes.push(Node {
+                symbol: Some(symbol as u16),
+                frequency: frequency as u64,
+                children: [None, None],
+            });
+        }
+
+        // A lone leaf still needs a branch above it to be given a bit.
+        if nodes.len() == 1 {
+            nodes.push(Node {
+                symbol: None,
+                frequency: nodes[0].frequency,
+                children: [Some(0), None],
+            });
+        }
 
-// Allows for nodes to be compared with the == operator.
-impl Eq for Node {}
+        // .pop() returns the greatest item, which because of Reverse is the
+        // least frequent node, so the rarest symbols end up deepest.
+        while let (Some(Reverse((frequency_1, node_1))), Some(Reverse((frequency_2, node_2)))) =
+            (heap.pop(), heap.pop())
+        {
+            heap.push(Reverse((frequency_1 + frequency_2, nodes.len())));
+            nodes.push(Node {
+                symbol: None,
+                frequency: frequency_1 + frequency_2,
+                children: [Some(node_1), Some(node_2)],
+            });
+        }
 
-// Allows for node frequency to be compared with the == and != operators.
-impl PartialEq for Node {
-    fn eq(&self, other: &Self) -> bool {
-        self.frequency.eq(&other.frequency)
+        let mut tree = Self {
+            root: nodes.len().checked_sub(1),
+            nodes,
+            codes: vec![(0, 0); frequencies.len()],
+        };
+        tree.assign_codes()?;
+        Some(tree)
     }
-}
 
-// Implements the creation of new empty nodes.
-impl Node {
-    /// Creates a new empty HuffmanNode.
+    /// Builds the tree for the canonical codes of the given code lengths,
+    /// as assigned by canonical_codes, so that only the lengths need to be
+    /// stored alongside data to decode it. The frequencies of its nodes
+    /// are all 0.
     ///
     /// # Returns
     ///
-    /// A HuffmanNode with default values.
-    ///
-    /// FIX: Add examples.
-    pub fn new() -> Self {
-        Self {
-            leaf: false,
-            value: 0,
-            frequency: 0,
-            address: 0,
-            length: 0,
-            left: None,
-            right: None,
+    /// The tree, or None if there are more than 65536 symbols, a length is
+    /// longer than 15 bits or the lengths are over-subscribed.
+    pub fn from_lengths(lengths: &[u8]) -> Option<Self> {
+        if lengths.len() > MAX_ALPHABET_SIZE {
+            return None;
         }
+        let codes = canonical_codes(lengths)?;
+        let mut tree = Self {
+            nodes: Vec::with_capacity(lengths.len() * 2),
+            root: None,
+            codes: codes
+                .iter()
+                .map(|&(code, length)| (code as u64, length))
+                .collect(),
+        };
+
+        for (symbol, &(code, length)) in codes.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            let mut current = match tree.root {
+                Some(root) => root,
+                None => tree.push_branch(),
+            };
+            tree.root = Some(tree.root.unwrap_or(current));
+
+            // Follow the code from its most significant bit, adding
+            // branches where the path does not exist yet.
+            for shift in (0..length).rev() {
+                let bit = ((code >> shift) & 1) as usize;
+                if tree.nodes[current].symbol.is_some() {
+                    return None;
+                }
+                current = match tree.nodes[current].children[bit] {
+                    Some(child) if shift > 0 => child,
+                    Some(_) => return None,
+                    None => {
+                        let child = if shift > 0 {
+                            tree.push_branch()
+                        } else {
+                            tree.nodes.push(Node {
+                                symbol: Some(symbol as u16),
+                                frequency: 0,
+                                children: [None, None],
+                            });
+                            tree.nodes.len() - 1
+                        };
+                        tree.nodes[current].children[bit] = Some(child);
+                        child
+                    }
+                };
+            }
+        }
+
+        Some(tree)
     }
-}
 
-// Creates a default for HuffmanNode.
-impl Default for Node {
-    fn default() -> Self {
-        Self::new()
+    /// Adds an empty branch to the arena, returning its index.
+    fn push_branch(&mut self) -> usize {
+        self.nodes.push(Node {
+            symbol: None,
+            frequency: 0,
+            children: [None, None],
+        });
+        self.nodes.len() - 1
     }
-}
 
-/// FIX: Document.
-fn create_huffman_tree(frequencies: &[u32; 256]) -> ([Rc<RefCell<Node>>; 256], Rc<RefCell<Node>>) {
-    // Create a leaf for every value that can be stored in a byte.
-    let mut leaves: Vec<Rc<RefCell<Node>>> = (0..256)
-        .map(|_| Rc::new(RefCell::new(Node::new())))
-        .collect::<Vec<_>>();
-
-    // Input nodes into a BinaryHeap
-    // FIX: Learn more about BinaryHeaps, I only half know what this is doing.
-    let mut nodes = BinaryHeap::new();
-
-    // Iterate through each leaf and populate values before adding to the BinaryHeap.
-    for (i, node_) in leaves.iter_mut().enumerate() {
-        let mut node = node_.borrow_mut();
-        node.leaf = true;
-        node.value = i as u8;
-        node.frequency = frequencies[i];
-
-        // Drop the mutable borrow to free the node.
-        drop(node);
-
-        // Clone the smart pointer to the now mutated node.
-        nodes.push(Reverse(node_.clone()));
-    }
-
-    // Loop intil only the root node is left.
-    while nodes.len() > 1 {
-        // .pop() returns the greatest item from the BinaryHeap,
-        // and removes that item from the heap. Because the natural
-        // order of the node is reversed 7 lines up, .pop() returns
-        // the node with the lowest remaining frequency, which ensures
-        // the least frequent values are at the bottom of the tree.
-        // Unwrap can be called because the while loop ensures that
-        // there is a node left and .pop() will only return None if
-        // this is not true.
-        let node_1 = nodes.pop().unwrap().0;
-        let node_2 = nodes.pop().unwrap().0;
-
-        // Creates the parent node.
-        let parent = Node {
-            leaf: false,
-            value: 0,
-            frequency: RefCell::borrow(&node_1)
-                .frequency
-                .saturating_add(RefCell::borrow(&node_2).frequency),
-            address: 0,
-            length: 0,
-            left: Some(node_1.clone()),
-            right: Some(node_2.clone()),
-        };
+    /// Walks the tree from the root, giving each leaf the code of the
+    /// path leading to it.
+    ///
+    /// # Returns
+    ///
+    /// None if a code would be longer than 64 bits.
+    fn assign_codes(&mut self) -> Option<()> {
+        let mut stack: Vec<(usize, u64, u8)> =
+            self.root().map(|root| (root, 0, 0)).into_iter().collect();
 
-        nodes.push(Reverse(Rc::new(RefCell::new(parent))));
-    }
+        while let Some((index, code, length)) = stack.pop() {
+            let node = &self.nodes[index];
+            if let Some(symbol) = node.symbol {
+                self.codes[symbol as usize] = (code, length);
+                continue;
+            }
+            if length as usize == MAX_TREE_DEPTH {
+                return None;
+            }
+            for (bit, child) in node.children.iter().enumerate() {
+                if let Some(child) = child {
+                    stack.push((*child, code << 1 | bit as u64, length + 1));
+                }
+            }
+        }
 
-    // Once  more the while loop ensures .pop() will not return None.
-    let root = nodes.pop().unwrap().0;
+        Some(())
+    }
 
-    // Create a vector to hold the nodes to calculate the address for.
-    let mut queue = Vec::with_capacity(256);
-    queue.push(root.clone());
+    /// The code and length of every symbol, a length of 0 marking
+    /// unused symbols.
+    pub fn codes(&self) -> &[(u64, u8)] {
+        &self.codes
+    }
 
-    // Loop while the queue still has nodes in it.
-    while let Some(node) = queue.pop() {
-        let mut node = node.borrow_mut();
+    /// The code and length of a single symbol, (0, 0) if it is unused or
+    /// outside the alphabet.
+    pub fn code(&self, symbol: u16) -> (u64, u8) {
+        self.codes.get(symbol as usize).copied().unwrap_or((0, 0))
+    }
 
-        // Check if the node is not a leaf, calculate the address of
-        // it's branches.
-        if !node.leaf {
-            let left_option = node.left.as_ref();
-            let right_option = node.right.as_ref();
+    /// The code length of every symbol, the form canonical codes are
+    /// described by.
+    pub fn lengths(&self) -> Vec<u8> {
+        self.codes.iter().map(|&(_, length)| length).collect()
+    }
 
-            // Because the node is not marked as a leaf, it should have two child
-            // nodes, however, if somehow this isn't the case, the node will be
-            // assumed to be incorrectly labeled and will be treated as a leaf.
-            let (left, right) = match (left_option, right_option) {
-                (Some(left), Some(right)) => (left, right),
-                (_, _) => {
-                    eprintln!("Warning: Node not labeled as leaf has less than 2 children.");
-                    node.leaf = true;
-                    break;
-                }
-            };
+    /// The index of the root node, None if no symbol is used.
+    pub fn root(&self) -> Option<usize> {
+        self.root
+    }
 
-            {
-                let mut left_borrowed = left.borrow_mut();
-                let mut right_borrowed = right.borrow_mut();
-                left_borrowed.address = node.address << 1;
-                left_borrowed.length = node.length + 1;
-                right_borrowed.address = (node.address << 1) + 1;
-                right_borrowed.length = node.length + 1;
-            }
+    /// The child of a branch reached by the given bit, None for leaves
+    /// and for the missing side of a lone symbol's branch.
+    pub fn child(&self, node: usize, bit: bool) -> Option<usize> {
+        self.nodes.get(node)?.children[bit as usize]
+    }
 
-            queue.push(right.clone());
-            queue.push(left.clone());
-        }
+    /// The symbol held by a node, None for branches.
+    pub fn symbol(&self, node: usize) -> Option<u16> {
+        self.nodes.get(node)?.symbol
     }
 
-    // Panics only under extremely unforeseen circumstances.
-    (
-        leaves
-            .try_into()
-            .unwrap_or_else(|_| panic!("Error: Leaves could not be converted into array.")),
-        root,
-    )
+    /// How often the symbols below a node occur, for the root the
+    /// frequencies of every symbol added together.
+    pub fn frequency(&self, node: usize) -> u64 {
+        self.nodes.get(node).map_or(0, |node| node.frequency)
+    }
 }
 
-/// FIX: Document.
-pub struct Huffman {}
+//      +--------------------+
+//      | STANDALONE HUFFMAN |
+//      +--------------------+
 
-/// A trait for implementing encoding/decoding into structs.
+/// Byte-oriented Huffman coding on its own, without LZSS, in a small
+/// self-describing container. Only the code lengths are stored, decoding
+/// rebuilds the canonical codes from them.
 ///
-/// # Types
+/// ```text
+/// +------+---------+-------+--------+---------+---------+----------+-------+
+/// | HUFF | version | flags | length | symbols | lengths | [CRC-32] | codes |
+/// +------+---------+-------+--------+---------+---------+----------+-------+
+///    4        1        1     varint   varint     runs        4
+/// ```
 ///
-/// * 'I' - Represents the type of the input.
-/// * 'O' - Represents the type of the output.
-/// * 'Error' - What error type should be returned in the Result.
+/// * 'version' - HUFFMAN_VERSION, decoders reject newer ones.
+/// * 'flags' - Bit 0 is set if the CRC-32 is present, the others are 0.
+/// * 'length' - The number of bytes encoded, as a LEB128 varint.
+/// * 'symbols' - One past the greatest byte used, also a varint, lengths
+///   are only given for bytes below it.
+/// * 'lengths' - The code length of each byte, a run per byte holding the
+///   length in its high nibble and the run minus 1 in its low.
+///   A run of 0x0F of unused bytes is followed by a byte adding
+///   up to 255 more.
+/// * 'CRC-32' - Of the bytes encoded, little-endian.
+/// * 'codes' - The code of each byte, packed starting with the most
+///   significant bit, the last byte padded with 0s.
 ///
-/// # Methods
+/// # Examples
 ///
-/// * 'encode' - Takes in an array of type I and returns a Result containing either the given
-///             Error or a Vec of type O.
-/// * 'decode' - Takes in an array of type O and returns a Result containing either the given
-///             Error or a Vec of type I.
-pub trait Coder<I: Copy, O: Copy> {
-    type Error;
-    fn encode(input: impl AsRef<[I]>) -> Result<Vec<O>, Self::Error>;
-    fn decode(input: impl AsRef<[O]>) -> Result<Vec<I>, Self::Error>;
-}
+/// ```
+/// # use chameleon::compression::coder::Coder;
+/// # use chameleon::compression::huffman::Huffman;
+/// let encoded = Huffman::encode(b"abracadabra").unwrap();
+///
+/// // 8 bytes of header, 6 of code lengths, 4 of CRC-32 and 3 of codes.
+/// assert_eq!(encoded.len(), 21);
+/// assert_eq!(Huffman::decode(encoded).unwrap(), b"abracadabra");
+/// ```
+pub struct Huffman {}
 
-impl Coder<u8, u8> for Huffman {
-    type Error = Box<dyn Error + Send + Sync + 'static>;
-    /// Accepts a reference to an u8 array a encodes it into a
-    /// Huffman Tree.
-    fn encode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
-        let input = input.as_ref();
-        let mut output = Vec::new();
-        let mut frequencies = [0u32; 256];
+/// The bytes every Huffman container starts with.
+pub const HUFFMAN_MAGIC: [u8; 4] = *b"HUFF";
 
-        // Count the occurances of each input byte.
-        // FIX: Figure out how this works cause it does but I do not know why or how.
-        input.iter().fold(&mut frequencies, |acc, &byte| {
-            (*acc)[byte as usize] = (*acc)[byte as usize].saturating_add(1);
-            acc
-        });
+/// The version of the Huffman container written.
+pub const HUFFMAN_VERSION: u8 = 1;
+
+/// The flag marking a Huffman container which holds a CRC-32.
+const FLAG_CHECKSUM: u8 = 1;
 
-        // Prepend counts/more iterator magic.
-        frequencies
-            .into_iter()
-            .flat_map(|v| v.to_le_bytes())
-            .for_each(|b| output.push(b));
+impl Huffman {
+    /// Encodes the bytes into a Huffman container.
+    ///
+    /// # Arguments
+    ///
+    /// * 'input' - The bytes to encode.
+    /// * 'checksum' - Whether to store a CRC-32 of the input, which costs
+    ///   4 bytes and lets decoding detect corruption.
+    ///
+    /// # Returns
+    ///
+    /// The container holding the encoded bytes.
+    pub fn encode_with(input: &[u8], checksum: bool) -> Vec<u8> {
+        let mut frequencies = [0u32; 256];
+        for &byte in input {
+            frequencies[byte as usize] = frequencies[byte as usize].saturating_add(1);
+        }
+        let lengths = code_lengths(&frequencies, MAX_CODE_LENGTH as u8);
+        let symbols = lengths
+            .iter()
+            .rposition(|&length| length > 0)
+            .map_or(0, |symbol| symbol + 1);
 
-        // The leaves are all thats needed for encoding so the root node is ignored.
-        let (leaves, _) = create_huffman_tree(&frequencies);
+        let mut output = Vec::with_capacity(32 + input.len());
+        output.extend_from_slice(&HUFFMAN_MAGIC);
+        output.push(HUFFMAN_VERSION);
+        output.push(if checksum { FLAG_CHECKSUM } else { 0 });
+        write_varint(&mut output, input.len() as u64);
+        write_varint(&mut output, symbols as u64);
+        write_lengths(&mut output, &lengths[..symbols]);
+        if checksum {
+            output.extend_from_slice(&crc32(input).to_le_bytes());
+        }
+
+        // code_lengths always gives a complete set of codes within 15 bits,
+        // which the tree can always be built from.
+        let tree = HuffmanTree::from_lengths(&lengths).unwrap_or_default();
         let mut next: u8 = 0;
         let mut filled = 0;
 
-        // For each value in input, populate the tree with value.
-        for &v in input.iter() {
-            let leaf = RefCell::borrow(&leaves[v as usize]);
-            let length = leaf.length;
-            let mut code = leaf.address << (32 - length);
-
-            // Output needs to be entire bytes.
-            // FIX: It works but does not make sense.
-            for _ in 0..length {
-                // Set next equal to itself bitshifted left.
-                next <<= 1;
-                // If the most significant bit in a u32 is more than 0,
-                // increment next
-                if code & 0x8000_0000 > 0 {
-                    next += 1;
-                }
+        // Append the code of each byte, starting from its most significant
+        // bit, and push out whole bytes as they fill.
+        for &byte in input {
+            let (code, length) = tree.code(byte as u16);
+            for shift in (0..length).rev() {
+                next = next << 1 | ((code >> shift) & 1) as u8;
                 filled += 1;
-                code <<= 1;
                 if filled == 8 {
                     output.push(next);
                     next = 0;
@@ -275,74 +408,526 @@ impl Coder<u8, u8> for Huffman {
             next <<= 8 - filled;
             output.push(next);
         }
-        Ok(output)
+        output
+    }
+}
+
+impl Coder<u8, u8> for Huffman {
+    type Error = HuffmanError;
+    /// Accepts a reference to an u8 array and encodes it into a Huffman
+    /// container with a CRC-32.
+    fn encode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
+        Ok(Self::encode_with(input.as_ref(), true))
     }
-    /// FIX: Document.
+    /// Decodes a whole Huffman container, see HuffmanDecoder.
     fn decode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
-        let input = input.as_ref();
-        if input.len() < std::mem::size_of::<u32>() * 256 {
-            return Err(Self::Error::from("Error: Error decoding"));
-        }
+        let mut decoder = HuffmanDecoder::default();
         let mut output = Vec::new();
-        let mut count: u64 = 0;
-        let freqs = input
-            .iter()
-            .take(std::mem::size_of::<u32>() * 256) // only read the byte counts
-            .scan(([0u8; 4], 0usize), |s, b| {
-                s.0[s.1] = *b;
-                s.1 += 1;
-                if s.1 == 4 {
-                    s.1 = 0;
-                    let num = u32::from_le_bytes(s.0);
-                    count += num as u64;
-                    Some(Some(num))
-                } else {
-                    Some(None)
+        decoder.update(input.as_ref(), &mut output)?;
+        decoder.finish(&mut output)?;
+        Ok(output)
+    }
+}
+
+/// The header of a Huffman container, see Huffman.
+///
+/// # Attributes
+///
+/// * 'length' - The number of bytes encoded.
+/// * 'tree' - The tree of the canonical codes of the code lengths.
+/// * 'checksum' - The CRC-32 of the bytes encoded, if it was stored.
+#[derive(Debug, Clone)]
+struct ContainerHeader {
+    length: u64,
+    tree: HuffmanTree,
+    checksum: Option<u32>,
+}
+
+/// Reads the header of a Huffman container and rebuilds the canonical
+/// codes from its code lengths.
+///
+/// # Returns
+///
+/// A result containing either the header and the number of bytes it
+/// takes up, or a HuffmanError. HuffmanError::Truncated means the input
+/// ends within the header.
+fn read_header(input: &[u8]) -> Result<(ContainerHeader, usize), HuffmanError> {
+    if input.len() < HUFFMAN_MAGIC.len() + 2 {
+        return Err(HuffmanError::Truncated(input.len()));
+    }
+    if input[..4] != HUFFMAN_MAGIC {
+        return Err(HuffmanError::InvalidMagic);
+    }
+    if input[4] > HUFFMAN_VERSION {
+        return Err(HuffmanError::UnsupportedVersion(input[4]));
+    }
+    let flags = input[5];
+    if flags & !FLAG_CHECKSUM != 0 {
+        return Err(HuffmanError::InvalidHeader);
+    }
+
+    let mut position = 6;
+    let length = read_varint(input, &mut position)?;
+    let symbols = read_varint(input, &mut position)?;
+    if symbols > 256 {
+        return Err(HuffmanError::InvalidHeader);
+    }
+    let lengths = read_lengths(input, &mut position, symbols as usize)?;
+    let checksum = if flags & FLAG_CHECKSUM != 0 {
+        let bytes = input
+            .get(position..position + 4)
+            .ok_or(HuffmanError::Truncated(input.len()))?;
+        position += 4;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    } else {
+        None
+    };
+
+    let tree = HuffmanTree::from_lengths(&lengths).ok_or(HuffmanError::InvalidHeader)?;
+    if length > 0 && tree.root().is_none() {
+        return Err(HuffmanError::InvalidHeader);
+    }
+    let header = ContainerHeader {
+        length,
+        tree,
+        checksum,
+    };
+    Ok((header, position))
+}
+
+/// Encodes bytes into a Huffman container through the StreamCoder
+/// interface. As the codes depend on every byte, the input is held until
+/// finish, which outputs the whole container.
+///
+/// # Fields
+///
+/// * 'input' - The input so far.
+/// * 'checksum' - Whether to store a CRC-32 of the input.
+#[derive(Debug, Clone)]
+pub struct HuffmanEncoder {
+    input: Vec<u8>,
+    checksum: bool,
+}
+
+impl StreamCoder<u8, u8> for HuffmanEncoder {
+    type Error = HuffmanError;
+
+    fn with_options(options: &CoderOptions) -> Self {
+        Self {
+            input: Vec::new(),
+            checksum: options.checksum,
+        }
+    }
+
+    fn update(&mut self, input: &[u8], _: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.input.extend_from_slice(input);
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        output.extend(Huffman::encode_with(&self.input, self.checksum));
+        self.input.clear();
+        Ok(())
+    }
+}
+
+/// Decodes a Huffman container through the StreamCoder interface, once
+/// its header has arrived outputting each byte as soon as its code has.
+///
+/// # Fields
+///
+/// * 'pending' - The input so far, until the whole header has arrived.
+/// * 'header' - The header, once it has arrived.
+/// * 'consumed' - The number of input bytes read so far.
+/// * 'current' - The node reached by the bits of the current code.
+/// * 'decoded' - The number of bytes decoded so far.
+/// * 'crc' - The CRC-32 of the bytes decoded so far.
+#[derive(Debug, Clone, Default)]
+pub struct HuffmanDecoder {
+    pending: Vec<u8>,
+    header: Option<ContainerHeader>,
+    consumed: usize,
+    current: usize,
+    decoded: u64,
+    crc: Crc32,
+}
+
+impl HuffmanDecoder {
+    /// Walks the tree with the bits of 'codes', outputting each byte it
+    /// reaches, until as many bytes as were encoded have been decoded.
+    fn decode_codes(&mut self, codes: &[u8], output: &mut Vec<u8>) -> Result<(), HuffmanError> {
+        let Some(header) = &self.header else {
+            return Ok(());
+        };
+        let start = output.len();
+        let tree = &header.tree;
+        let root = tree.root().unwrap_or_default();
+
+        'bytes: for (index, &byte) in codes.iter().enumerate() {
+            for bit in 0..8 {
+                if self.decoded == header.length {
+                    break 'bytes;
                 }
-            })
-            .flatten() // exclude all Nones
-            .collect::<Vec<_>>();
-        let input = &input[std::mem::size_of::<u32>() * 256..];
-        let freqs: [u32; 256] = freqs
-            .try_into()
-            .map_err(|_| Self::Error::from("Error: Error decoding."))?;
-        let (_, root) = create_huffman_tree(&freqs);
-        let mut current: *const Node = root.as_ptr() as *const _;
-        for &v in input {
-            let mut v = v;
-            for _ in 0..8 {
-                let current_ = unsafe { &*current };
-                if v & 0x80 == 0 {
-                    let left = current_
-                        .left
-                        .as_ref()
-                        .ok_or_else(|| Self::Error::from("Error: Error while decoding."))?
-                        .as_ptr() as *const _;
-                    current = left;
-                } else {
-                    let right = current_
-                        .right
-                        .as_ref()
-                        .ok_or_else(|| Self::Error::from("Error: Error while decoding."))?
-                        .as_ptr() as *const _;
-                    current = right;
+                let offset = (self.consumed + index) * 8 + bit;
+                self.current = tree
+                    .child(self.current, byte << bit & 0x80 != 0)
+                    .ok_or(HuffmanError::InvalidCode(offset))?;
+
+                if let Some(symbol) = tree.symbol(self.current) {
+                    output.push(symbol as u8);
+                    self.decoded += 1;
+                    self.current = root;
                 }
-                v <<= 1;
-
-                let current_ = unsafe { &*current };
-                if current_.leaf {
-                    output.push(current_.value);
-                    count -= 1;
-                    if count == 0 {
-                        return Ok(output);
-                    }
-                    current = root.as_ptr();
+            }
+        }
+
+        self.consumed += codes.len();
+        self.crc.update(&output[start..]);
+        Ok(())
+    }
+}
+
+impl StreamCoder<u8, u8> for HuffmanDecoder {
+    type Error = HuffmanError;
+
+    fn with_options(_: &CoderOptions) -> Self {
+        Self::default()
+    }
+
+    fn update(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        if self.header.is_some() {
+            return self.decode_codes(input, output);
+        }
+
+        self.pending.extend_from_slice(input);
+        let (header, length) = match read_header(&self.pending) {
+            Ok(header) => header,
+            Err(HuffmanError::Truncated(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        self.current = header.tree.root().unwrap_or_default();
+        self.header = Some(header);
+        self.consumed = length;
+
+        let pending = std::mem::take(&mut self.pending);
+        self.decode_codes(&pending[length..], output)
+    }
+
+    /// Fails with HuffmanError::Truncated unless the input held every
+    /// byte encoded, anything following them is ignored.
+    fn finish(&mut self, _: &mut Vec<u8>) -> Result<(), Self::Error> {
+        let Some(header) = &self.header else {
+            return Err(read_header(&self.pending)
+                .err()
+                .unwrap_or(HuffmanError::Truncated(self.pending.len())));
+        };
+        if self.decoded < header.length {
+            return Err(HuffmanError::Truncated(self.consumed));
+        }
+        if let Some(expected) = header.checksum {
+            let actual = self.crc.finish();
+            if actual != expected {
+                return Err(HuffmanError::ChecksumMismatch { expected, actual });
+            }
+        }
+        *self = Self::default();
+        Ok(())
+    }
+}
+
+/// Writes a number as a LEB128 varint, 7 bits per byte starting with the
+/// least significant, the top bit set on every byte but the last.
+fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+/// Reads a LEB128 varint starting at 'position', moving it past the end.
+fn read_varint(input: &[u8], position: &mut usize) -> Result<u64, HuffmanError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *input
+            .get(*position)
+            .ok_or(HuffmanError::Truncated(input.len()))?;
+        *position += 1;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(HuffmanError::InvalidHeader)
+}
+
+/// Writes code lengths as runs of equal lengths, see Huffman.
+fn write_lengths(output: &mut Vec<u8>, lengths: &[u8]) {
+    let mut symbol = 0;
+    while symbol < lengths.len() {
+        let length = lengths[symbol];
+        let run = lengths[symbol..]
+            .iter()
+            .take_while(|&&other| other == length)
+            .count();
+
+        if length == 0 && run >= 16 {
+            let run = run.min(16 + 255);
+            output.push(0x0F);
+            output.push((run - 16) as u8);
+            symbol += run;
+        } else {
+            let run = run.min(16);
+            output.push(length << 4 | (run - 1) as u8);
+            symbol += run;
+        }
+    }
+}
+
+/// Reads the runs of code lengths for 'symbols' symbols starting at
+/// 'position', moving it past the end.
+fn read_lengths(
+    input: &[u8],
+    position: &mut usize,
+    symbols: usize,
+) -> Result<Vec<u8>, HuffmanError> {
+    let mut next = || {
+        let byte = input
+            .get(*position)
+            .copied()
+            .ok_or(HuffmanError::Truncated(input.len()));
+        *position += 1;
+        byte
+    };
+
+    let mut lengths = Vec::with_capacity(symbols);
+    while lengths.len() < symbols {
+        let byte = next()?;
+        let length = byte >> 4;
+        let mut run = (byte & 0x0F) as usize + 1;
+        if length == 0 && run == 16 {
+            run += next()? as usize;
+        }
+        if lengths.len() + run > symbols {
+            return Err(HuffmanError::InvalidHeader);
+        }
+        lengths.resize(lengths.len() + run, length);
+    }
+    Ok(lengths)
+}
+
+//      +-----------------+
+//      | CANONICAL CODES |
+//      +-----------------+
+
+/// The longest code DEFLATE allows, see RFC 1951 section 3.2.7.
+pub const MAX_CODE_LENGTH: usize = 15;
+
+/// Lookup table for decoding canonical Huffman codes, the form
+/// DEFLATE describes its trees in. Only the length of each symbol's
+/// code is stored, with the codes themselves being assigned in
+/// order of length and then symbol as described in RFC 1951
+/// section 3.2.2.
+///
+/// Since Huffman codes are packed starting with their most significant
+/// bit, the table is indexed by the next 'max_length' bits of the stream
+/// as read least significant bit first, so every entry whose index ends
+/// with a reversed code holds that code's symbol.
+///
+/// # Fields
+///
+/// * 'entries' - Pairs of symbol and code length, a length of 0 marks
+///   bit patterns which do not start with any code.
+/// * 'max_length' - The length of the longest code in the table.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::bits::reader::BitReader;
+/// # use chameleon::compression::huffman::HuffmanTable;
+/// // A, B, C, D with the lengths from RFC 1951 giving the codes
+/// // 10, 0, 110 and 111.
+/// let table = HuffmanTable::from_lengths(&[2, 1, 3, 3]).unwrap();
+///
+/// // 0 -> B, 10 -> A packed least significant bit first.
+/// let mut reader = BitReader::new(&[0b0000_0010]);
+/// assert_eq!(table.decode(&mut reader), Some(1));
+/// assert_eq!(table.decode(&mut reader), Some(0));
+/// ```
+pub struct HuffmanTable {
+    entries: Vec<(u16, u8)>,
+    max_length: u32,
+}
+
+impl HuffmanTable {
+    /// Builds the table from the code length of each symbol, where
+    /// a length of 0 means the symbol is unused.
+    ///
+    /// # Returns
+    ///
+    /// The table, or None if a length is longer than 15 bits or the
+    /// lengths describe more codes than can exist (an over-subscribed
+    /// set). Incomplete sets are allowed since DEFLATE permits a single
+    /// distance code.
+    pub fn from_lengths(lengths: &[u8]) -> Option<Self> {
+        let codes = canonical_codes(lengths)?;
+        let max_length = lengths.iter().copied().max().unwrap_or(0).max(1) as u32;
+        let mut entries = vec![(0u16, 0u8); 1 << max_length];
+
+        for (symbol, &(code, length)) in codes.iter().enumerate() {
+            if length == 0 {
+                continue;
+            }
+            // Every index whose low 'length' bits are the reversed code
+            // decodes to this symbol.
+            let reversed = reverse_bits(code, length) as usize;
+            for index in (reversed..entries.len()).step_by(1 << length) {
+                entries[index] = (symbol as u16, length);
+            }
+        }
+
+        Some(Self {
+            entries,
+            max_length,
+        })
+    }
+
+    /// Reads and decodes the next code from the reader.
+    ///
+    /// # Returns
+    ///
+    /// The decoded symbol, or None if the input ran out or the next bits
+    /// do not form a code in this table.
+    pub fn decode<B: BitSource>(&self, reader: &mut B) -> Option<u16> {
+        let (bits, available) = reader.peek_bits(self.max_length);
+        let (symbol, length) = self.entries[bits as usize];
+        if length == 0 || length as u32 > available {
+            return None;
+        }
+        reader.consume(length as u32);
+        Some(symbol)
+    }
+}
+
+/// Assigns canonical codes to the given code lengths using the
+/// algorithm from RFC 1951 section 3.2.2.
+///
+/// # Returns
+///
+/// A code and length pair for every symbol, the code of unused
+/// symbols is left as 0. None is returned if any length exceeds
+/// 15 bits or the lengths are over-subscribed.
+pub fn canonical_codes(lengths: &[u8]) -> Option<Vec<(u16, u8)>> {
+    // Count the number of codes for each code length.
+    let mut length_counts = [0u16; MAX_CODE_LENGTH + 1];
+    for &length in lengths {
+        if length as usize > MAX_CODE_LENGTH {
+            return None;
+        }
+        length_counts[length as usize] += 1;
+    }
+    length_counts[0] = 0;
+
+    // Find the smallest code of each length, checking along the way that
+    // there are not more codes of a length than there are bit patterns left.
+    let mut next_code = [0u16; MAX_CODE_LENGTH + 1];
+    let mut code: u32 = 0;
+    let mut left: i32 = 1;
+    for bits in 1..=MAX_CODE_LENGTH {
+        code = (code + length_counts[bits - 1] as u32) << 1;
+        next_code[bits] = code as u16;
+        left = (left << 1) - length_counts[bits] as i32;
+        if left < 0 {
+            return None;
+        }
+    }
+
+    Some(
+        lengths
+            .iter()
+            .map(|&length| {
+                if length == 0 {
+                    return (0, 0);
                 }
+                let code = next_code[length as usize];
+                next_code[length as usize] += 1;
+                (code, length)
+            })
+            .collect(),
+    )
+}
+
+/// Builds a length limited Huffman code for the given symbol frequencies,
+/// in the form DEFLATE stores its dynamic trees.
+///
+/// The optimal code lengths are the depths of the leaves of the
+/// HuffmanTree for the frequencies, then if any exceed the limit they
+/// are shortened with the adjustment from Annex K.3 of the JPEG
+/// specification: the two deepest leaves are removed and one of them
+/// takes the place of a shallower leaf which becomes a branch, until no
+/// leaf is too deep.
+///
+/// # Arguments
+///
+/// * 'frequencies' - How often each symbol occurs, unused symbols have a
+///   frequency of 0 and are given no code.
+/// * 'max_length' - The longest code allowed, 15 for the literal/length
+///   and distance alphabets and 7 for the code length alphabet.
+///
+/// # Returns
+///
+/// The code length of each symbol. A single used symbol is given a one
+/// bit code since DEFLATE cannot express a zero bit code.
+pub fn code_lengths(frequencies: &[u32], max_length: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    // Frequencies which fit in a u32 cannot build a tree deeper than 64
+    // bits, so this only fails for alphabets of over 65536 symbols.
+    let Some(tree) = HuffmanTree::from_frequencies(frequencies) else {
+        return lengths;
+    };
+    let used: Vec<usize> = (0..frequencies.len())
+        .filter(|&symbol| frequencies[symbol] > 0)
+        .collect();
+    let depths: Vec<usize> = used
+        .iter()
+        .map(|&symbol| tree.code(symbol as u16).1 as usize)
+        .collect();
+
+    let deepest = depths.iter().copied().max().unwrap_or(0);
+    let mut length_counts = vec![0usize; deepest.max(max_length as usize) + 1];
+    for &depth in &depths {
+        length_counts[depth] += 1;
+    }
+
+    for length in (max_length as usize + 1..=deepest).rev() {
+        while length_counts[length] > 0 {
+            let mut shallower = length - 2;
+            while length_counts[shallower] == 0 {
+                shallower -= 1;
             }
+            length_counts[length] -= 2;
+            length_counts[length - 1] += 1;
+            length_counts[shallower + 1] += 2;
+            length_counts[shallower] -= 1;
         }
-        let _ = root;
-        Ok(output)
     }
+
+    // Hand the shortest codes to the most frequent symbols.
+    let mut by_frequency = used;
+    by_frequency.sort_by_key(|&symbol| (Reverse(frequencies[symbol]), symbol));
+    let mut symbols = by_frequency.into_iter();
+    for (length, &count) in length_counts.iter().enumerate().skip(1) {
+        for symbol in symbols.by_ref().take(count) {
+            lengths[symbol] = length as u8;
+        }
+    }
+
+    lengths
+}
+
+/// Reverses the order of the lowest 'length' bits of the code.
+pub fn reverse_bits(code: u16, length: u8) -> u16 {
+    code.reverse_bits() >> (16 - length as u32)
 }
 
 //      +---------------------+
@@ -350,9 +935,8 @@ impl Coder<u8, u8> for Huffman {
 //      +---------------------+
 
 /// For block type 1, the bitstream after LZSS compression is encoded
-/// with the following table from RFC 1951. Instead of generating a
-/// Huffman tree for this, a hashmap will save some complexity while
-/// not compromising too much on speed.
+/// with the following table from RFC 1951. Since the codes are canonical
+/// only their lengths are needed to build a HuffmanTable for them.
 ///
 ///  Lit Value    Bits        Codes
 ///  ---------    ----        -----
@@ -364,6 +948,286 @@ impl Coder<u8, u8> for Huffman {
 ///                           0010111
 ///  280 - 287     8          11000000 through
 ///                           11000111
-pub fn generate_fixed_huffman() {
-    todo!();
+///
+/// # Returns
+///
+/// A tuple containing the 288 literal/length code lengths in the first
+/// field, and the 30 distance code lengths, which are all 5 bits, in the
+/// second field.
+pub fn generate_fixed_huffman() -> ([u8; 288], [u8; 30]) {
+    let mut literal_lengths = [0u8; 288];
+    literal_lengths[0..144].fill(8);
+    literal_lengths[144..256].fill(9);
+    literal_lengths[256..280].fill(7);
+    literal_lengths[280..288].fill(8);
+
+    (literal_lengths, [5u8; 30])
+}
+
+//       +--------+
+//       | ERRORS |
+//       +--------+
+
+/// Enum containing possible errors raised while decoding Huffman's
+/// output.
+///
+/// # Fields
+///
+/// * 'Truncated' - The input ends before its header or all of the symbols
+///   it holds, holds its length.
+/// * 'InvalidCode' - The bits read do not lead to a symbol, holds the bit
+///   offset into the input, counting from the most significant
+///   bit of each byte.
+/// * 'InvalidMagic' - The input does not start with HUFFMAN_MAGIC.
+/// * 'UnsupportedVersion' - The container is of a newer version, holds it.
+/// * 'InvalidHeader' - The header is malformed, such as code lengths which
+///   do not describe a set of codes.
+/// * 'ChecksumMismatch' - The CRC-32 of the decoded bytes does not match
+///   the one stored, holds the expected and actual values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HuffmanError {
+    Truncated(usize),
+    InvalidCode(usize),
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    InvalidHeader,
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+// Defines how HuffmanErrors are displayed.
+impl Display for HuffmanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HuffmanError::Truncated(length) => {
+                write!(f, "Error: Huffman input ends early at byte '{length}'.")
+            }
+            HuffmanError::InvalidCode(bit) => {
+                write!(f, "Error: Invalid Huffman code at bit '{bit}'.")
+            }
+            HuffmanError::InvalidMagic => {
+                write!(f, "Error: Input is not a Huffman container.")
+            }
+            HuffmanError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "Error: Unsupported Huffman container version '{version}'."
+                )
+            }
+            HuffmanError::InvalidHeader => {
+                write!(f, "Error: Invalid Huffman container header.")
+            }
+            HuffmanError::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Error: CRC-32 mismatch in Huffman container, expected '{expected:08X}' but found '{actual:08X}'."
+                )
+            }
+        }
+    }
+}
+
+// Implements the Error interface for HuffmanError.
+impl Error for HuffmanError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Follows a code from the root down to the symbol it leads to.
+    fn walk(tree: &HuffmanTree, (code, length): (u64, u8)) -> Option<u16> {
+        let mut node = tree.root()?;
+        for shift in (0..length).rev() {
+            node = tree.child(node, (code >> shift) & 1 == 1)?;
+        }
+        tree.symbol(node)
+    }
+
+    #[test]
+    fn trees_cover_wide_alphabets() {
+        // More than 256 symbols, as the literal/length alphabet has.
+        let frequencies: Vec<u32> = (0..300).map(|symbol| (symbol % 7) * 3).collect();
+        let tree = HuffmanTree::from_frequencies(&frequencies).unwrap();
+        let total: u64 = frequencies.iter().map(|&frequency| frequency as u64).sum();
+        assert_eq!(tree.frequency(tree.root().unwrap()), total);
+
+        // Every used symbol is reached by its own code, and together the
+        // codes fill the code space exactly.
+        let mut space = 0.0;
+        for (symbol, &frequency) in frequencies.iter().enumerate() {
+            let code = tree.code(symbol as u16);
+            if frequency == 0 {
+                assert_eq!(code, (0, 0));
+                continue;
+            }
+            assert_eq!(walk(&tree, code), Some(symbol as u16));
+            space += 0.5f64.powi(code.1 as i32);
+        }
+        assert_eq!(space, 1.0);
+
+        // The lengths are as short as those code_lengths finds, and the same
+        // frequencies always build the same codes.
+        let cost = |lengths: &[u8]| -> u64 {
+            lengths
+                .iter()
+                .zip(&frequencies)
+                .map(|(&length, &frequency)| length as u64 * frequency as u64)
+                .sum()
+        };
+        assert_eq!(cost(&tree.lengths()), cost(&code_lengths(&frequencies, 64)));
+        let again = HuffmanTree::from_frequencies(&frequencies).unwrap();
+        assert_eq!(tree.codes(), again.codes());
+    }
+
+    #[test]
+    fn lone_and_missing_symbols() {
+        let tree = HuffmanTree::from_frequencies(&[0, 0, 9]).unwrap();
+        assert_eq!(tree.code(2), (0, 1));
+        assert_eq!(walk(&tree, (0, 1)), Some(2));
+        assert_eq!(tree.child(tree.root().unwrap(), true), None);
+
+        let tree = HuffmanTree::from_frequencies(&[0; 4]).unwrap();
+        assert_eq!(tree.root(), None);
+        assert_eq!(tree.lengths(), [0; 4]);
+
+        assert!(HuffmanTree::from_frequencies(&vec![1; MAX_ALPHABET_SIZE]).is_some());
+        assert!(HuffmanTree::from_frequencies(&vec![1; MAX_ALPHABET_SIZE + 1]).is_none());
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let every_byte: Vec<u8> = (0..=255).chain(0..=255).collect();
+        for input in [&b""[..], b"aaaa", b"abracadabra", &every_byte] {
+            for checksum in [false, true] {
+                let encoded = Huffman::encode_with(input, checksum);
+                assert_eq!(encoded[..6], [b'H', b'U', b'F', b'F', 1, checksum as u8]);
+                assert_eq!(Huffman::decode(&encoded).unwrap(), input);
+            }
+        }
+        assert_eq!(Huffman::encode(b"abracadabra").unwrap().len(), 21);
+    }
+
+    #[test]
+    fn headers_are_compact() {
+        let mut output = Vec::new();
+        write_varint(&mut output, 300);
+        assert_eq!(output, [0xAC, 0x02]);
+        let mut position = 0;
+        assert_eq!(read_varint(&output, &mut position), Ok(300));
+        assert_eq!(position, 2);
+
+        // A run of three 2s, twenty unused bytes, then a 1.
+        let mut lengths = vec![2, 2, 2];
+        lengths.extend([0; 20]);
+        lengths.push(1);
+        let mut output = Vec::new();
+        write_lengths(&mut output, &lengths);
+        assert_eq!(output, [0x22, 0x0F, 0x04, 0x10]);
+        let mut position = 0;
+        assert_eq!(read_lengths(&output, &mut position, 24), Ok(lengths));
+        assert_eq!(position, 4);
+
+        // Runs longer than the symbols they describe.
+        let mut position = 0;
+        assert_eq!(
+            read_lengths(&output, &mut position, 2),
+            Err(HuffmanError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn damaged_containers_are_rejected() {
+        let encoded = Huffman::encode(b"abracadabra").unwrap();
+        let damage = |index: usize, value: u8| {
+            let mut damaged = encoded.clone();
+            damaged[index] = value;
+            Huffman::decode(damaged)
+        };
+
+        assert_eq!(damage(0, b'h'), Err(HuffmanError::InvalidMagic));
+        assert_eq!(damage(4, 2), Err(HuffmanError::UnsupportedVersion(2)));
+        assert_eq!(damage(5, 3), Err(HuffmanError::InvalidHeader));
+        assert!(matches!(
+            damage(14, encoded[14] ^ 1),
+            Err(HuffmanError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            Huffman::decode(&encoded[..encoded.len() - 1]),
+            Err(HuffmanError::Truncated(encoded.len() - 1))
+        );
+        assert_eq!(Huffman::decode(b"HUF"), Err(HuffmanError::Truncated(3)));
+
+        // A lone symbol only has a code starting with a 0 bit.
+        let mut encoded = Huffman::encode_with(b"aaaa", false);
+        let last = encoded.len() - 1;
+        encoded[last] = 0x80;
+        assert_eq!(
+            Huffman::decode(&encoded),
+            Err(HuffmanError::InvalidCode(last * 8))
+        );
+    }
+
+    #[test]
+    fn streams_match_whole_containers() {
+        let input = b"streaming huffman, a piece at a time. ".repeat(20);
+        let options = CoderOptions::default();
+        let mut encoder = HuffmanEncoder::with_options(&options);
+        let mut encoded = Vec::new();
+        for piece in input.chunks(7) {
+            encoder.update(piece, &mut encoded).unwrap();
+        }
+        assert!(encoded.is_empty());
+        encoder.finish(&mut encoded).unwrap();
+        assert_eq!(encoded, Huffman::encode(&input).unwrap());
+
+        // Bytes are output as soon as their codes arrive, and finish leaves
+        // the decoder ready for another container.
+        let mut decoder = HuffmanDecoder::with_options(&options);
+        for _ in 0..2 {
+            let mut decoded = Vec::new();
+            decoder.update(&encoded[..100], &mut decoded).unwrap();
+            assert!(!decoded.is_empty() && decoded.len() < input.len());
+            for byte in &encoded[100..] {
+                decoder.update(&[*byte], &mut decoded).unwrap();
+            }
+            decoder.finish(&mut decoded).unwrap();
+            assert_eq!(decoded, input);
+        }
+    }
+
+    #[test]
+    fn streams_are_checked_on_finish() {
+        let encoded = Huffman::encode(b"abracadabra").unwrap();
+        let options = CoderOptions::default();
+        let mut decoded = Vec::new();
+
+        // Still within the header.
+        let mut decoder = HuffmanDecoder::with_options(&options);
+        decoder.update(&encoded[..10], &mut decoded).unwrap();
+        assert_eq!(
+            decoder.finish(&mut decoded),
+            Err(HuffmanError::Truncated(10))
+        );
+
+        let mut decoder = HuffmanDecoder::with_options(&options);
+        decoder.update(&encoded[..20], &mut decoded).unwrap();
+        assert_eq!(
+            decoder.finish(&mut decoded),
+            Err(HuffmanError::Truncated(20))
+        );
+
+        let mut corrupt = encoded.clone();
+        corrupt[14] ^= 1;
+        let mut decoder = HuffmanDecoder::with_options(&options);
+        decoder.update(&corrupt, &mut decoded).unwrap();
+        assert!(matches!(
+            decoder.finish(&mut decoded),
+            Err(HuffmanError::ChecksumMismatch { .. })
+        ));
+
+        let mut decoder = HuffmanDecoder::with_options(&options);
+        assert_eq!(
+            decoder.update(b"PUFF\x01\x00\x00\x00", &mut decoded),
+            Err(HuffmanError::InvalidMagic)
+        );
+    }
 }
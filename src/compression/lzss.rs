@@ -1,17 +1,27 @@
 //! An implementation of the LZSS algorithm which works with the
 //! DEFLATE algorithm
+use crate::compression::coder::{Coder, CoderOptions, StreamCoder};
+use crate::limits::LimitError;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
 
 /// Representation of the length codes given in section 3.2.5 of RFC 1951.
 /// In the format:
 ///
-///     [SYMBOL, OFFSET BITS, RANGE START, RANGE END].
+/// ```text
+/// [SYMBOL, OFFSET BITS, RANGE START, RANGE END].
+/// ```
 ///
 /// So, to encode the string "ABBBBB" first the EOB marker 256 will be added
 /// to the end of the data, then the two literals A and B will be pushed into
 /// the bitstream, then the length code symbol, in this case 258 to represent
 /// the 4 B's we want the distance code 0 to represent the backwards distance 1.
 ///
-///     A B B B B B -> A B B B B B 256 -> A B 4:1 256 -> 65 66 258 0 256
+/// ```text
+/// A B B B B B -> A B B B B B 256 -> A B 4:1 256 -> 65 66 258 0 256
+/// ```
 ///
 /// The final symbols are then processed through either the fixed prefix codes
 /// if the block is of type 1, or the dynamic prefix codes given, if the block
@@ -51,7 +61,9 @@ pub const LENGTH_CODE_RANGES: [[u16; 4]; 29] = [
 /// Representation of the distance codes given in section 3.2.5 of RFC 1951.
 /// In the format:
 ///
-///     [SYMBOL, EXTRA BITS, DISTANCE RANGE START, DISTANCE RANGE END]
+/// ```text
+/// [SYMBOL, EXTRA BITS, DISTANCE RANGE START, DISTANCE RANGE END]
+/// ```
 ///
 /// The extra bits are used to define which element of the range is used.
 /// So, for distance code 4 there is 1 extra bit which is used to specify
@@ -88,3 +100,668 @@ pub const DISTANCE_CODE_RANGES: [[u16; 4]; 30] = [
     [28, 13, 16385, 24576],
     [29, 13, 24577, 32768],
 ];
+
+//      +---------------+
+//      | MATCH FINDING |
+//      +---------------+
+
+/// The furthest back a back-reference can reach.
+pub const WINDOW_SIZE: usize = 32768;
+
+/// The shortest match DEFLATE can encode.
+pub const MIN_MATCH: usize = 3;
+
+/// The longest match DEFLATE can encode.
+pub const MAX_MATCH: usize = 258;
+
+/// The number of bits of the hash used to find earlier occurrences of
+/// the next three bytes.
+const HASH_BITS: u32 = 15;
+
+/// A single element of LZSS compressed data, either a byte copied as
+/// is or a back-reference to earlier data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+/// Finds the length symbol, the number of extra bits and the value of
+/// those extra bits for a match length from 3 through 258.
+///
+/// # Returns
+///
+/// A tuple of [SYMBOL, EXTRA BITS, EXTRA VALUE].
+pub fn length_code(length: u16) -> [u16; 3] {
+    let index = LENGTH_CODE_RANGES.partition_point(|range| range[3] < length);
+    let [symbol, extra, start, _] = LENGTH_CODE_RANGES[index.min(28)];
+    [symbol, extra, length - start]
+}
+
+/// Finds the distance symbol, the number of extra bits and the value of
+/// those extra bits for a distance from 1 through 32768.
+///
+/// # Returns
+///
+/// A tuple of [SYMBOL, EXTRA BITS, EXTRA VALUE].
+pub fn distance_code(distance: u16) -> [u16; 3] {
+    let index = DISTANCE_CODE_RANGES.partition_point(|range| range[3] < distance);
+    let [symbol, extra, start, _] = DISTANCE_CODE_RANGES[index.min(29)];
+    [symbol, extra, distance - start]
+}
+
+/// How hard the match finder searches at a compression level, roughly
+/// following the levels of zlib.
+///
+/// # Attributes
+///
+/// * 'max_chain' - The most earlier occurrences checked for each match.
+/// * 'nice_length' - A match at least this long is taken immediately.
+/// * 'lazy' - Whether to check if starting a match one byte later gives a
+///   longer match before committing to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelParams {
+    pub max_chain: usize,
+    pub nice_length: usize,
+    pub lazy: bool,
+}
+
+impl LevelParams {
+    /// Returns the parameters for a level from 1 through 9, with levels
+    /// above 9 treated as 9. Level 0 does not search for matches at all.
+    pub fn for_level(level: u8) -> Self {
+        let (max_chain, nice_length, lazy) = match level {
+            0 => (0, 0, false),
+            1 => (4, 8, false),
+            2 => (8, 16, false),
+            3 => (16, 32, false),
+            4 => (16, 16, true),
+            5 => (32, 32, true),
+            6 => (128, 128, true),
+            7 => (256, 128, true),
+            8 => (1024, MAX_MATCH, true),
+            _ => (4096, MAX_MATCH, true),
+        };
+
+        Self {
+            max_chain,
+            nice_length,
+            lazy,
+        }
+    }
+}
+
+/// Hash chains over the last WINDOW_SIZE positions, used to find the
+/// longest earlier occurrence of the bytes at a position.
+///
+/// Positions are absolute offsets into the whole stream, so the data
+/// buffer can have old bytes dropped from its front as long as the
+/// absolute position of its first byte is passed along with it.
+///
+/// # Fields
+///
+/// * 'head' - The most recent position plus one for each hash, 0 if none.
+/// * 'prev' - For each position in the window, the previous position plus
+///   one with the same hash, indexed by position modulo the window.
+/// * 'params' - The search parameters of the compression level.
+pub struct MatchFinder {
+    head: Vec<usize>,
+    prev: Vec<usize>,
+    pub params: LevelParams,
+}
+
+impl MatchFinder {
+    /// Creates an empty match finder for the given compression level.
+    pub fn new(level: u8) -> Self {
+        Self {
+            head: vec![0; 1 << HASH_BITS],
+            prev: vec![0; WINDOW_SIZE],
+            params: LevelParams::for_level(level),
+        }
+    }
+
+    /// Hashes the three bytes starting at index.
+    fn hash(data: &[u8], index: usize) -> usize {
+        let value =
+            (data[index] as u32) << 16 | (data[index + 1] as u32) << 8 | data[index + 2] as u32;
+        (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Records the bytes at 'position' so later positions can match them.
+    ///
+    /// # Arguments
+    ///
+    /// * 'data' - The buffer holding the stream, starting at absolute 'base'.
+    /// * 'base' - The absolute position of data[0].
+    /// * 'position' - The absolute position to insert.
+    pub fn insert(&mut self, data: &[u8], base: usize, position: usize) {
+        let index = position - base;
+        if index + MIN_MATCH > data.len() {
+            return;
+        }
+        let hash = Self::hash(data, index);
+        self.prev[position % WINDOW_SIZE] = self.head[hash];
+        self.head[hash] = position + 1;
+    }
+
+    /// Finds the longest earlier occurrence of the bytes at 'position'.
+    ///
+    /// # Arguments
+    ///
+    /// * 'data' - The buffer holding the stream, starting at absolute 'base'.
+    /// * 'base' - The absolute position of data[0], positions before it
+    ///   cannot be matched.
+    /// * 'position' - The absolute position to find a match for.
+    ///
+    /// # Returns
+    ///
+    /// The length and distance of the best match, with a length of 0 if no
+    /// match of at least MIN_MATCH bytes was found.
+    pub fn longest_match(&self, data: &[u8], base: usize, position: usize) -> (usize, usize) {
+        let index = position - base;
+        let max_length = MAX_MATCH.min(data.len() - index);
+        if max_length < MIN_MATCH || self.params.max_chain == 0 {
+            return (0, 0);
+        }
+
+        let mut best = (0, 0);
+        let mut candidate = self.head[Self::hash(data, index)];
+        let mut chain = self.params.max_chain;
+        let current = &data[index..index + max_length];
+
+        // Candidates are stored plus one, stop at an empty entry, a
+        // position outside the window or the buffer, or a stale entry
+        // which does not point further back.
+        while candidate > 0 && chain > 0 {
+            let earlier = candidate - 1;
+            if earlier >= position || earlier < base || position - earlier > WINDOW_SIZE {
+                break;
+            }
+
+            let previous = &data[earlier - base..];
+            if previous[best.0.min(max_length - 1)] == current[best.0.min(max_length - 1)] {
+                let length = current
+                    .iter()
+                    .zip(previous)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best.0 {
+                    best = (length, position - earlier);
+                    if length >= self.params.nice_length.min(max_length) {
+                        break;
+                    }
+                }
+            }
+
+            let next = self.prev[earlier % WINDOW_SIZE];
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+            chain -= 1;
+        }
+
+        if best.0 < MIN_MATCH {
+            return (0, 0);
+        }
+        best
+    }
+}
+
+//      +---------------+
+//      | STREAM CODERS |
+//      +---------------+
+
+/// Standalone LZSS streams through the Coder interface, see LzssEncoder
+/// for the format.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::coder::Coder;
+/// # use chameleon::compression::lzss::Lzss;
+/// let compressed = Lzss::encode(b"Hello Hello Hello").unwrap();
+///
+/// assert_eq!(Lzss::decode(compressed).unwrap(), b"Hello Hello Hello");
+/// ```
+pub struct Lzss {}
+
+impl Coder<u8, u8> for Lzss {
+    type Error = LzssError;
+
+    fn encode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
+        let mut encoder = LzssEncoder::with_options(&CoderOptions::default());
+        let mut output = Vec::new();
+        encoder.update(input.as_ref(), &mut output)?;
+        encoder.finish(&mut output)?;
+        Ok(output)
+    }
+
+    fn decode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
+        let mut decoder = LzssDecoder::with_options(&CoderOptions::default());
+        let mut output = Vec::new();
+        decoder.update(input.as_ref(), &mut output)?;
+        decoder.finish(&mut output)?;
+        Ok(output)
+    }
+}
+
+/// Compresses bytes into a standalone LZSS stream through the
+/// StreamCoder interface, using the same match finder as DEFLATE but
+/// writing the tokens as bytes rather than Huffman codes.
+///
+/// The stream is made of groups of up to eight tokens, each group led by
+/// a flag byte whose bits, starting with the least significant, are set
+/// for the tokens which are matches.
+///
+/// ```text
+/// Literal:    BYTE
+/// Match:      DISTANCE - 1 (u16, little-endian)   LENGTH - 3
+/// ```
+///
+/// # Fields
+///
+/// * 'finder' - The hash chains used to find back-references.
+/// * 'level' - The compression level, 0 only writes literals.
+/// * 'buffer' - The input still needed as history for matches.
+/// * 'base' - The position in the whole input of buffer[0].
+/// * 'position' - The position in the whole input of the next byte to
+///   turn into tokens.
+/// * 'group' - The flag byte and tokens of the group being filled.
+/// * 'items' - The number of tokens in the group.
+pub struct LzssEncoder {
+    finder: MatchFinder,
+    level: u8,
+    buffer: Vec<u8>,
+    base: usize,
+    position: usize,
+    group: Vec<u8>,
+    items: u8,
+}
+
+impl LzssEncoder {
+    /// Turns buffered input into tokens, outputting groups as they fill.
+    ///
+    /// Unless 'flush' is set, the last MAX_MATCH bytes are held back since
+    /// more input could still extend a match starting among them.
+    fn tokenize(&mut self, flush: bool, output: &mut Vec<u8>) {
+        let end = self.base + self.buffer.len();
+        let limit = if flush {
+            end
+        } else {
+            end.saturating_sub(MAX_MATCH)
+        };
+        let LevelParams {
+            lazy, nice_length, ..
+        } = self.finder.params;
+
+        while self.position < limit {
+            let position = self.position;
+            let (mut length, distance) =
+                self.finder.longest_match(&self.buffer, self.base, position);
+            self.finder.insert(&self.buffer, self.base, position);
+
+            // Lazy matching: if the match starting at the next byte is
+            // longer, emit this byte as a literal instead.
+            if length >= MIN_MATCH && lazy && length < nice_length && position + 1 < limit {
+                let next = self
+                    .finder
+                    .longest_match(&self.buffer, self.base, position + 1);
+                if next.0 > length {
+                    length = 0;
+                }
+            }
+
+            if length < MIN_MATCH {
+                let byte = self.buffer[position - self.base];
+                self.push_token(Token::Literal(byte), output);
+                self.position += 1;
+            } else {
+                let token = Token::Match {
+                    length: length as u16,
+                    distance: distance as u16,
+                };
+                self.push_token(token, output);
+                for skipped in position + 1..position + length {
+                    self.finder.insert(&self.buffer, self.base, skipped);
+                }
+                self.position += length;
+            }
+        }
+
+        // Drop input which is outside the window, once enough has built
+        // up to be worth moving.
+        let keep = self.position.saturating_sub(WINDOW_SIZE);
+        if keep - self.base >= WINDOW_SIZE {
+            self.buffer.drain(..keep - self.base);
+            self.base = keep;
+        }
+    }
+
+    /// Adds a token to the current group, outputting the group once it
+    /// holds eight.
+    fn push_token(&mut self, token: Token, output: &mut Vec<u8>) {
+        if self.items == 0 {
+            self.group.push(0);
+        }
+        match token {
+            Token::Literal(byte) => self.group.push(byte),
+            Token::Match { length, distance } => {
+                self.group[0] |= 1 << self.items;
+                self.group.extend_from_slice(&(distance - 1).to_le_bytes());
+                self.group.push((length as usize - MIN_MATCH) as u8);
+            }
+        }
+        self.items += 1;
+        if self.items == 8 {
+            output.append(&mut self.group);
+            self.items = 0;
+        }
+    }
+}
+
+impl StreamCoder<u8, u8> for LzssEncoder {
+    type Error = LzssError;
+
+    fn with_options(options: &CoderOptions) -> Self {
+        Self {
+            finder: MatchFinder::new(options.level),
+            level: options.level,
+            buffer: Vec::new(),
+            base: 0,
+            position: 0,
+            group: Vec::with_capacity(1 + 8 * 3),
+            items: 0,
+        }
+    }
+
+    fn update(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.buffer.extend_from_slice(input);
+        self.tokenize(false, output);
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.tokenize(true, output);
+        output.append(&mut self.group);
+        *self = Self::with_options(&CoderOptions {
+            level: self.level,
+            ..CoderOptions::default()
+        });
+        Ok(())
+    }
+}
+
+/// Decompresses a standalone LZSS stream, see LzssEncoder, through the
+/// StreamCoder interface. Only the last 32K of output is kept for
+/// back-references to copy from.
+///
+/// # Fields
+///
+/// * 'pending' - Input which does not yet make up a whole token.
+/// * 'consumed' - The number of input bytes decoded so far.
+/// * 'history' - At least the last WINDOW_SIZE bytes of output.
+/// * 'total' - The number of bytes output so far.
+/// * 'flags' - The flags of the rest of the current group.
+/// * 'items' - The number of tokens left in the current group.
+/// * 'max_output' - The most bytes to output.
+pub struct LzssDecoder {
+    pending: Vec<u8>,
+    consumed: usize,
+    history: Vec<u8>,
+    total: usize,
+    flags: u8,
+    items: u8,
+    max_output: usize,
+}
+
+impl LzssDecoder {
+    /// Adds bytes to the output and the history, checking they stay
+    /// within max_output.
+    fn output(&mut self, bytes: &[u8], output: &mut Vec<u8>) -> Result<(), LzssError> {
+        if self.total + bytes.len() > self.max_output {
+            return Err(LzssError::LimitExceeded(LimitError::Output {
+                max: self.max_output,
+            }));
+        }
+        output.extend_from_slice(bytes);
+        self.history.extend_from_slice(bytes);
+        self.total += bytes.len();
+        if self.history.len() >= 2 * WINDOW_SIZE {
+            self.history.drain(..self.history.len() - WINDOW_SIZE);
+        }
+        Ok(())
+    }
+}
+
+impl StreamCoder<u8, u8> for LzssDecoder {
+    type Error = LzssError;
+
+    fn with_options(options: &CoderOptions) -> Self {
+        Self {
+            pending: Vec::new(),
+            consumed: 0,
+            history: Vec::new(),
+            total: 0,
+            flags: 0,
+            items: 0,
+            max_output: options.max_output,
+        }
+    }
+
+    fn update(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.pending.extend_from_slice(input);
+        let pending = std::mem::take(&mut self.pending);
+
+        let mut index = 0;
+        let result = loop {
+            if self.items == 0 {
+                let Some(&flags) = pending.get(index) else {
+                    break Ok(());
+                };
+                (self.flags, self.items) = (flags, 8);
+                index += 1;
+            }
+
+            if self.flags & 1 == 0 {
+                let Some(&byte) = pending.get(index) else {
+                    break Ok(());
+                };
+                if let Err(e) = self.output(&[byte], output) {
+                    break Err(e);
+                }
+                index += 1;
+            } else {
+                let Some(token) = pending.get(index..index + 3) else {
+                    break Ok(());
+                };
+                let distance = u16::from_le_bytes([token[0], token[1]]) as usize + 1;
+                let length = token[2] as usize + MIN_MATCH;
+                if distance > self.total {
+                    break Err(LzssError::InvalidDistance {
+                        position: self.consumed + index,
+                        distance,
+                        available: self.total,
+                    });
+                }
+
+                // Copy a byte at a time, as a match can overlap the bytes
+                // it outputs.
+                let mut copy = Vec::with_capacity(length);
+                let start = self.history.len() - distance;
+                for offset in 0..length {
+                    copy.push(match offset < distance {
+                        true => self.history[start + offset],
+                        false => copy[offset - distance],
+                    });
+                }
+                if let Err(e) = self.output(&copy, output) {
+                    break Err(e);
+                }
+                index += 3;
+            }
+            self.flags >>= 1;
+            self.items -= 1;
+        };
+
+        self.consumed += index;
+        self.pending = pending;
+        self.pending.drain(..index);
+        result
+    }
+
+    /// Fails with LzssError::Truncated if the input ends partway through
+    /// a token.
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.update(&[], output)?;
+        if !self.pending.is_empty() {
+            return Err(LzssError::Truncated(self.consumed + self.pending.len()));
+        }
+        *self = Self::with_options(&CoderOptions {
+            max_output: self.max_output,
+            ..CoderOptions::default()
+        });
+        Ok(())
+    }
+}
+
+//       +--------+
+//       | ERRORS |
+//       +--------+
+
+/// Enum containing possible errors raised while decoding a standalone
+/// LZSS stream.
+///
+/// # Fields
+///
+/// * 'Truncated' - The input ends partway through a token, holds its
+///   length.
+/// * 'InvalidDistance' - A match reaches back further than the output so
+///   far, holds the byte offset of the match, its distance and
+///   the number of bytes available.
+/// * 'LimitExceeded' - Decoding would output more than max_output bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LzssError {
+    Truncated(usize),
+    InvalidDistance {
+        position: usize,
+        distance: usize,
+        available: usize,
+    },
+    LimitExceeded(LimitError),
+}
+
+// Defines how LzssErrors are displayed.
+impl Display for LzssError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LzssError::Truncated(length) => {
+                write!(
+                    f,
+                    "Error: LZSS input ends partway through a token at byte '{length}'."
+                )
+            }
+            LzssError::InvalidDistance {
+                position,
+                distance,
+                available,
+            } => {
+                write!(f, "Error: LZSS match distance '{distance}' at byte '{position}' exceeds the '{available}' bytes decoded so far.")
+            }
+            LzssError::LimitExceeded(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+// Implements the Error interface for LzssError, with the exceeded limit
+// as its source.
+impl Error for LzssError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LzssError::LimitExceeded(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs input through a coder in pieces of the given size.
+    fn run<C: StreamCoder<u8, u8>>(
+        coder: &mut C,
+        input: &[u8],
+        piece: usize,
+    ) -> Result<Vec<u8>, C::Error> {
+        let mut output = Vec::new();
+        for piece in input.chunks(piece) {
+            coder.update(piece, &mut output)?;
+        }
+        coder.finish(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn streams_round_trip() {
+        let input: Vec<u8> = (0..100_000u32)
+            .map(|i| b"lzss window "[(i % 12) as usize] ^ (i / 5000) as u8)
+            .collect();
+        for level in [0, 1, 6, 9] {
+            let options = CoderOptions {
+                level,
+                ..CoderOptions::default()
+            };
+            let mut encoder = LzssEncoder::with_options(&options);
+            let encoded = run(&mut encoder, &input, 1000).unwrap();
+            if level == 0 {
+                // A flag byte for every eight literals.
+                assert_eq!(encoded.len(), input.len() + input.len().div_ceil(8));
+            } else {
+                assert!(encoded.len() < input.len() / 4);
+            }
+
+            // Piece sizes do not change the output, and finish starts a new
+            // stream.
+            assert_eq!(run(&mut encoder, &input, 7).unwrap(), encoded);
+
+            let mut decoder = LzssDecoder::with_options(&options);
+            assert_eq!(run(&mut decoder, &encoded, 1).unwrap(), input);
+            assert_eq!(run(&mut decoder, &encoded, 4096).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn damaged_streams_are_rejected() {
+        let options = CoderOptions::default();
+        let mut decoder = LzssDecoder::with_options(&options);
+
+        // A match before any output.
+        assert_eq!(
+            run(&mut decoder, &[0x01, 0x00, 0x00, 0x00], 4),
+            Err(LzssError::InvalidDistance {
+                position: 1,
+                distance: 1,
+                available: 0,
+            })
+        );
+
+        // A literal, then a match cut off after its distance.
+        let mut decoder = LzssDecoder::with_options(&options);
+        assert_eq!(
+            run(&mut decoder, &[0x02, b'a', 0x00, 0x00], 1),
+            Err(LzssError::Truncated(4))
+        );
+
+        let mut encoder = LzssEncoder::with_options(&options);
+        let encoded = run(&mut encoder, &[b'z'; 10_000], 10_000).unwrap();
+        let mut decoder = LzssDecoder::with_options(&CoderOptions {
+            max_output: 5000,
+            ..options
+        });
+        assert_eq!(
+            run(&mut decoder, &encoded, 100),
+            Err(LzssError::LimitExceeded(LimitError::Output { max: 5000 }))
+        );
+    }
+}
@@ -1,4 +1,7 @@
+pub mod checksum;
+pub mod coder;
 pub mod deflate;
 pub mod gzip;
 pub mod huffman;
 pub mod lzss;
+pub mod zlib;
@@ -0,0 +1,882 @@
+//! Zlib (RFC 1950) encoding and decoding, the wrapper PNG uses
+//! around the DEFLATE stream in its IDAT chunks.
+//!
+//! name:    CMF     FLG     [DICTID]    DEFLATE...  ADLER32
+//! bytes:   1       1       4           n           4
+use crate::bits::reader::BitReader;
+use crate::bits::reader::BitSource;
+use crate::bits::reader::StreamBitReader;
+use crate::compression::checksum::{adler32, adler32_combine, Adler32};
+use crate::compression::coder::{Coder, CoderOptions, PushInput, StreamCoder, DEFAULT_LEVEL};
+use crate::compression::deflate::{
+    deflate, deflate_parallel, deflate_with_dictionary, inflate_from, inflate_from_dictionary,
+    DeflateError, Deflater, Inflater, CODER_CHUNK,
+};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io::{self, Read},
+};
+
+//      +------+
+//      | ZLIB |
+//      +------+
+
+/// Compression method 8 is the only one defined, DEFLATE.
+pub const CM_DEFLATE: u8 = 8;
+
+/// The FLG bit set when a DICTID follows the header.
+const FDICT: u8 = 0b0010_0000;
+
+/// Decompresses a zlib stream and verifies its Adler-32 checksum.
+///
+/// # Arguments
+///
+/// * 'input' - The bytes of the zlib stream.
+///
+/// # Returns
+///
+/// A result containing either the decompressed bytes or a ZlibError.
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>, ZlibError> {
+    decompress_limited(input, usize::MAX)
+}
+
+/// Same as decompress, but stops with DeflateError::LimitExceeded
+/// rather than decompressing more than 'max_output' bytes, for input
+/// which cannot be trusted.
+pub fn decompress_limited(input: &[u8], max_output: usize) -> Result<Vec<u8>, ZlibError> {
+    let (output, result) = decompress_partial(input, max_output);
+    result.map(|_| output)
+}
+
+/// Decompresses as much of a zlib stream as possible, up to
+/// 'max_output' bytes, for recovering what remains of truncated or
+/// corrupted data.
+///
+/// # Returns
+///
+/// Everything decompressed before the first problem, which is complete
+/// and checked when the result is Ok, along with the result itself.
+pub fn decompress_partial(input: &[u8], max_output: usize) -> (Vec<u8>, Result<(), ZlibError>) {
+    let mut output = Vec::new();
+    let result = decompress_into(input, &mut output, None, max_output);
+    (output, result)
+}
+
+/// Same as decompress, but for streams compressed against a preset
+/// dictionary, see compress_with_dictionary. Streams without one
+/// decompress as normal, ignoring the dictionary.
+///
+/// # Arguments
+///
+/// * 'input' - The bytes of the zlib stream.
+/// * 'dictionary' - The dictionary the stream was compressed with, which
+///   has to match the Adler-32 the stream names.
+///
+/// # Returns
+///
+/// A result containing either the decompressed bytes or a ZlibError,
+/// ZlibError::WrongDictionary if the stream was compressed with another
+/// dictionary.
+pub fn decompress_with_dictionary(input: &[u8], dictionary: &[u8]) -> Result<Vec<u8>, ZlibError> {
+    let mut output = Vec::new();
+    decompress_into(input, &mut output, Some(dictionary), usize::MAX)?;
+    Ok(output)
+}
+
+/// Decompresses a zlib stream into output, leaving whatever was
+/// decompressed in place when an error is returned.
+fn decompress_into(
+    input: &[u8],
+    output: &mut Vec<u8>,
+    dictionary: Option<&[u8]>,
+    max_output: usize,
+) -> Result<(), ZlibError> {
+    if input.len() < 2 {
+        return Err(ZlibError::InvalidHeader(
+            "Stream is shorter than its header.",
+        ));
+    }
+    let mut start = 2;
+    let mut preset = None;
+    if check_header(input[0], input[1])? {
+        let id = input.get(2..6).ok_or(ZlibError::InvalidHeader(
+            "Stream is shorter than its dictionary ID.",
+        ))?;
+        let expected = u32::from_be_bytes([id[0], id[1], id[2], id[3]]);
+        preset = Some(check_dictionary(expected, dictionary)?);
+        start = 6;
+    }
+
+    let mut reader = BitReader::new(&input[start..]);
+    match preset {
+        Some(dictionary) => inflate_from_dictionary(&mut reader, output, dictionary, max_output)?,
+        None => inflate_from(&mut reader, output, max_output)?,
+    }
+
+    // The checksum follows the DEFLATE stream on the next byte boundary.
+    reader.align_to_byte();
+    let offset = start + reader.bit_position() / 8;
+    let trailer = reader
+        .read_bytes(4)
+        .ok_or(ZlibError::MissingChecksum(offset))?;
+    let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+
+    let mut adler = Adler32::new();
+    adler.update(output);
+    let actual = adler.finish();
+    if expected != actual {
+        return Err(ZlibError::ChecksumMismatch { expected, actual });
+    }
+
+    Ok(())
+}
+
+/// Checks the CMF and FLG bytes starting a zlib stream.
+///
+/// # Returns
+///
+/// A result containing either whether a DICTID follows, or a ZlibError.
+fn check_header(cmf: u8, flg: u8) -> Result<bool, ZlibError> {
+    // The lower nibble of CMF is the method, the upper nibble is the
+    // base 2 logarithm of the window size minus 8.
+    if cmf & 0x0F != CM_DEFLATE {
+        return Err(ZlibError::Unsupported("Compression method is not DEFLATE."));
+    }
+    if cmf >> 4 > 7 {
+        return Err(ZlibError::Unsupported("Window size exceeds 32K."));
+    }
+    // FCHECK makes CMF and FLG a multiple of 31 as a 16 bit integer.
+    if !u16::from_be_bytes([cmf, flg]).is_multiple_of(31) {
+        return Err(ZlibError::InvalidHeader("Header check bits are incorrect."));
+    }
+    Ok(flg & FDICT != 0)
+}
+
+/// Checks the dictionary given by the caller against the DICTID of a
+/// stream, the Adler-32 of the dictionary it was compressed with.
+fn check_dictionary(expected: u32, dictionary: Option<&[u8]>) -> Result<&[u8], ZlibError> {
+    let dictionary = dictionary.ok_or(ZlibError::DictionaryRequired(expected))?;
+    let actual = adler32(dictionary);
+    if expected != actual {
+        return Err(ZlibError::WrongDictionary { expected, actual });
+    }
+    Ok(dictionary)
+}
+
+/// Compresses data into a zlib stream.
+///
+/// # Arguments
+///
+/// * 'input' - The data to compress.
+/// * 'level' - The DEFLATE compression level from 0 through 9.
+///
+/// # Returns
+///
+/// The header, the DEFLATE stream and the Adler-32 trailer.
+pub fn compress(input: &[u8], level: u8) -> Vec<u8> {
+    let mut output = header(level).to_vec();
+    output.extend(deflate(input, level));
+    output.extend_from_slice(&adler32(input).to_be_bytes());
+    output
+}
+
+/// Same as compress, but with matches able to refer back into a preset
+/// dictionary, see deflate_with_dictionary. The header names the
+/// dictionary by its Adler-32, and only decompress_with_dictionary given
+/// the same dictionary can decompress the stream.
+///
+/// # Arguments
+///
+/// * 'input' - The data to compress.
+/// * 'level' - The DEFLATE compression level from 0 through 9.
+/// * 'dictionary' - Data likely to recur in the input, of which only the
+///   last 32K is matched against.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::zlib;
+/// # let event = b"{\"level\":\"info\",\"source\":\"sensor\",\"value\":21.5}".to_vec();
+/// let dictionary = b"{\"level\":\"info\",\"source\":\"sensor\",\"value\":";
+/// let compressed = zlib::compress_with_dictionary(&event, 6, dictionary);
+///
+/// assert_eq!(zlib::decompress_with_dictionary(&compressed, dictionary)?, event);
+/// # Ok::<(), zlib::ZlibError>(())
+/// ```
+pub fn compress_with_dictionary(input: &[u8], level: u8, dictionary: &[u8]) -> Vec<u8> {
+    let mut output = dictionary_header(level, dictionary).to_vec();
+    output.extend(deflate_with_dictionary(input, level, dictionary));
+    output.extend_from_slice(&adler32(input).to_be_bytes());
+    output
+}
+
+/// Same as compress, but compressing segments of the input on up to
+/// 'threads' threads, see deflate_parallel. The Adler-32 of each segment
+/// is computed on its thread and then combined.
+///
+/// # Arguments
+///
+/// * 'input' - The data to compress.
+/// * 'level' - The DEFLATE compression level from 0 through 9.
+/// * 'threads' - The most threads to use.
+/// * 'prime' - Whether each segment is primed with the last 32K of the
+///   one before it.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::zlib;
+/// # let filtered = vec![1u8; 300_000];
+/// let compressed = zlib::compress_parallel(&filtered, 9, 8, true);
+///
+/// assert_eq!(zlib::decompress(&compressed)?, filtered);
+/// # Ok::<(), zlib::ZlibError>(())
+/// ```
+pub fn compress_parallel(input: &[u8], level: u8, threads: usize, prime: bool) -> Vec<u8> {
+    let (compressed, sums) = deflate_parallel(input, level, threads, prime, adler32);
+    let adler = sums.into_iter().fold(adler32(&[]), |adler, (sum, length)| {
+        adler32_combine(adler, sum, length)
+    });
+
+    let mut output = header(level).to_vec();
+    output.extend(compressed);
+    output.extend_from_slice(&adler.to_be_bytes());
+    output
+}
+
+/// Builds the two header bytes for a 32K window at the given level.
+///
+/// FLEVEL only tells a reader roughly how hard the compressor tried:
+/// 0 fastest, 1 fast, 2 default and 3 maximum compression.
+pub fn header(level: u8) -> [u8; 2] {
+    header_with_flags(level, 0)
+}
+
+/// Builds the header for a stream compressed against a preset
+/// dictionary, with FDICT set and followed by the DICTID, the Adler-32
+/// of the dictionary.
+pub fn dictionary_header(level: u8, dictionary: &[u8]) -> [u8; 6] {
+    let [cmf, flg] = header_with_flags(level, FDICT);
+    let [a, b, c, d] = adler32(dictionary).to_be_bytes();
+    [cmf, flg, a, b, c, d]
+}
+
+/// Builds the two header bytes with the given FLG bits besides FLEVEL
+/// and FCHECK.
+fn header_with_flags(level: u8, flags: u8) -> [u8; 2] {
+    let cmf = 0x70 | CM_DEFLATE;
+    let flevel = match level {
+        0 | 1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let flg = flevel << 6 | flags;
+    // FCHECK makes CMF and FLG a multiple of 31 as a 16 bit integer.
+    let fcheck = 31 - (u16::from_be_bytes([cmf, flg]) % 31) as u8;
+    [cmf, flg | (fcheck % 31)]
+}
+
+//      +-----------+
+//      | STREAMING |
+//      +-----------+
+
+/// Decompresses a zlib stream as it is read, checking the Adler-32
+/// trailer once the DEFLATE stream ends. Memory use does not depend on
+/// the size of the data, see Inflater.
+///
+/// # Fields
+///
+/// * 'inflater' - Decompresses the DEFLATE stream following the header.
+/// * 'adler' - The checksum of everything output so far.
+/// * 'dictionary' - The preset dictionary, for streams which need one.
+/// * 'started' - Whether the header has been read.
+/// * 'finished' - Whether the trailer has been read and checked.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::compression::zlib::Decompressor;
+/// # use std::{fs::File, io::Read};
+/// let mut decompressor = Decompressor::new(File::open("data.zlib")?);
+///
+/// let mut text = String::new();
+/// decompressor.read_to_string(&mut text)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Decompressor<R> {
+    inflater: Inflater<R>,
+    adler: Adler32,
+    dictionary: Option<Vec<u8>>,
+    started: bool,
+    finished: bool,
+}
+
+impl<R: Read> Decompressor<R> {
+    /// Creates a decompressor reading from the start of a zlib stream.
+    pub fn new(reader: R) -> Self {
+        Self::with_limit(reader, usize::MAX)
+    }
+
+    /// Same as new, but stopping with DeflateError::LimitExceeded rather
+    /// than outputting more than 'max_output' bytes.
+    pub fn with_limit(reader: R, max_output: usize) -> Self {
+        Self {
+            inflater: Inflater::with_limit(reader, max_output),
+            adler: Adler32::new(),
+            dictionary: None,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Gives the preset dictionary to use if the header asks for one,
+    /// otherwise decompression fails with ZlibError::DictionaryRequired.
+    /// Has no effect once the header has been read.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) {
+        if !self.started {
+            self.dictionary = Some(dictionary.to_vec());
+        }
+    }
+
+    /// Whether the whole stream has been read and its checksum matched.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns a mutable reference to the stream being decompressed.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inflater.bit_reader().get_mut()
+    }
+
+    /// Returns the stream being decompressed, see
+    /// StreamBitReader::into_inner.
+    pub fn into_inner(self) -> R {
+        self.inflater.into_inner()
+    }
+
+    /// Decompresses into 'buffer'.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the number of bytes written, which is
+    /// only 0 once the trailer has been checked or while the stream is
+    /// blocked, see Inflater::inflate, or a ZlibError.
+    pub fn decompress(&mut self, buffer: &mut [u8]) -> Result<usize, ZlibError> {
+        let result = self.decompress_inner(buffer);
+        // Report why the stream ended rather than that it did.
+        match self.inflater.bit_reader().take_error() {
+            Some(e) if result.is_err() => Err(ZlibError::IoError(e)),
+            _ => result,
+        }
+    }
+
+    /// The body of decompress, before errors from the stream are told
+    /// apart from it ending.
+    fn decompress_inner(&mut self, buffer: &mut [u8]) -> Result<usize, ZlibError> {
+        if !self.started {
+            // Read the header and any DICTID together, so that both are
+            // read again if the stream runs dry in between.
+            let reader = self.inflater.bit_reader();
+            reader.mark();
+            let Some(header) = reader.read_bits(16) else {
+                return blocked_or(reader, "Stream is shorter than its header.");
+            };
+            if check_header(header as u8, (header >> 8) as u8)? {
+                let Some(id) = reader.read_bits(32) else {
+                    return blocked_or(reader, "Stream is shorter than its dictionary ID.");
+                };
+                let dictionary = check_dictionary(id.swap_bytes(), self.dictionary.as_deref())?;
+                self.inflater.set_dictionary(dictionary);
+            }
+            self.started = true;
+        }
+        if self.finished {
+            return Ok(0);
+        }
+
+        let written = self.inflater.inflate(buffer)?;
+        self.adler.update(&buffer[..written]);
+        if written > 0 || buffer.is_empty() || !self.inflater.is_done() {
+            return Ok(written);
+        }
+
+        // The checksum follows the DEFLATE stream on the next byte boundary,
+        // stored most significant byte first.
+        let reader = self.inflater.bit_reader();
+        reader.align_to_byte();
+        let Some(trailer) = reader.read_bits(32) else {
+            if reader.is_blocked() {
+                reader.take_error();
+                return Ok(0);
+            }
+            return Err(ZlibError::MissingChecksum(reader.bit_position() / 8));
+        };
+        let expected = trailer.swap_bytes();
+        let actual = self.adler.finish();
+        if expected != actual {
+            return Err(ZlibError::ChecksumMismatch { expected, actual });
+        }
+        self.finished = true;
+        Ok(0)
+    }
+}
+
+/// Returns to the start of the header if the stream ran dry partway
+/// through it, to read it again once there is more, otherwise fails with
+/// ZlibError::InvalidHeader holding 'message'.
+fn blocked_or<R: Read>(
+    reader: &mut StreamBitReader<R>,
+    message: &'static str,
+) -> Result<usize, ZlibError> {
+    if reader.is_blocked() {
+        reader.reset();
+        return Ok(0);
+    }
+    Err(ZlibError::InvalidHeader(message))
+}
+
+// Allows a Decompressor to be used wherever a Read is expected.
+impl<R: Read> Read for Decompressor<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self.decompress(buffer) {
+            Ok(0) if !buffer.is_empty() && !self.finished => Err(io::ErrorKind::WouldBlock.into()),
+            Ok(written) => Ok(written),
+            Err(ZlibError::IoError(e)) => Err(e),
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+}
+
+/// Compresses a zlib stream as input arrives, the counterpart of
+/// Decompressor. See Deflater for when output becomes available.
+///
+/// # Fields
+///
+/// * 'deflater' - Compresses the DEFLATE stream.
+/// * 'adler' - The checksum of everything written so far.
+/// * 'header' - The header, until it is taken along with the first output.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::zlib::Compressor;
+/// # use std::io::Write;
+/// # let rows = [[0u8; 16]; 4];
+/// # let mut output = Vec::new();
+/// let mut compressor = Compressor::new(6);
+/// for row in rows {
+///     compressor.write(&row);
+///     output.write_all(&compressor.take_output())?;
+/// }
+/// output.write_all(&compressor.finish())?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Compressor {
+    deflater: Deflater,
+    adler: Adler32,
+    header: Vec<u8>,
+}
+
+impl Compressor {
+    /// Creates a compressor for the given level from 0 through 9.
+    pub fn new(level: u8) -> Self {
+        Self {
+            deflater: Deflater::new(level),
+            adler: Adler32::new(),
+            header: header(level).to_vec(),
+        }
+    }
+
+    /// Same as new, but compressing against a preset dictionary, see
+    /// compress_with_dictionary.
+    pub fn with_dictionary(level: u8, dictionary: &[u8]) -> Self {
+        Self {
+            deflater: Deflater::with_dictionary(level, dictionary),
+            adler: Adler32::new(),
+            header: dictionary_header(level, dictionary).to_vec(),
+        }
+    }
+
+    /// Adds input to be compressed.
+    pub fn write(&mut self, input: &[u8]) {
+        self.adler.update(input);
+        self.deflater.write(input);
+    }
+
+    /// Compresses all input given so far, so that a decoder can decode
+    /// everything written up to this point, see Deflater::flush.
+    pub fn flush(&mut self) {
+        self.deflater.flush();
+    }
+
+    /// Removes and returns the compressed bytes produced so far.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        let mut output = std::mem::take(&mut self.header);
+        output.extend(self.deflater.take_output());
+        output
+    }
+
+    /// Compresses the remaining input and writes the final block and the
+    /// Adler-32 trailer.
+    ///
+    /// # Returns
+    ///
+    /// The compressed bytes not yet returned by take_output.
+    pub fn finish(mut self) -> Vec<u8> {
+        let mut output = std::mem::take(&mut self.header);
+        output.extend(self.deflater.finish());
+        output.extend_from_slice(&self.adler.finish().to_be_bytes());
+        output
+    }
+}
+
+//      +---------------+
+//      | STREAM CODERS |
+//      +---------------+
+
+/// Zlib streams through the Coder interface, compressing at
+/// DEFAULT_LEVEL.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::compression::coder::Coder;
+/// # use chameleon::compression::zlib::Zlib;
+/// let compressed = Zlib::encode(b"Hello Hello Hello").unwrap();
+///
+/// assert_eq!(Zlib::decode(compressed).unwrap(), b"Hello Hello Hello");
+/// ```
+pub struct Zlib {}
+
+impl Coder<u8, u8> for Zlib {
+    type Error = ZlibError;
+
+    fn encode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
+        Ok(compress(input.as_ref(), DEFAULT_LEVEL))
+    }
+
+    fn decode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, Self::Error> {
+        decompress(input.as_ref())
+    }
+}
+
+/// Compresses a zlib stream through the StreamCoder interface, see
+/// Compressor.
+///
+/// # Fields
+///
+/// * 'compressor' - Compresses the current stream.
+/// * 'level' - The compression level, for the next stream.
+pub struct ZlibEncoder {
+    compressor: Compressor,
+    level: u8,
+}
+
+impl StreamCoder<u8, u8> for ZlibEncoder {
+    type Error = ZlibError;
+
+    fn with_options(options: &CoderOptions) -> Self {
+        Self {
+            compressor: Compressor::new(options.level),
+            level: options.level,
+        }
+    }
+
+    fn update(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.compressor.write(input);
+        output.extend(self.compressor.take_output());
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        let compressor = std::mem::replace(&mut self.compressor, Compressor::new(self.level));
+        output.extend(compressor.finish());
+        Ok(())
+    }
+}
+
+/// Decompresses a zlib stream through the StreamCoder interface,
+/// outputting everything the input given so far holds, see Decompressor.
+///
+/// # Fields
+///
+/// * 'decompressor' - Decompresses the current stream.
+/// * 'max_output' - The most bytes to output, for the next stream.
+pub struct ZlibDecoder {
+    decompressor: Decompressor<PushInput>,
+    max_output: usize,
+}
+
+impl ZlibDecoder {
+    /// Decompresses everything the input given so far holds.
+    fn drain(&mut self, output: &mut Vec<u8>) -> Result<(), ZlibError> {
+        loop {
+            let start = output.len();
+            output.resize(start + CODER_CHUNK, 0);
+            let result = self.decompressor.decompress(&mut output[start..]);
+            output.truncate(start + *result.as_ref().unwrap_or(&0));
+            if result? == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl StreamCoder<u8, u8> for ZlibDecoder {
+    type Error = ZlibError;
+
+    fn with_options(options: &CoderOptions) -> Self {
+        Self {
+            decompressor: Decompressor::with_limit(PushInput::default(), options.max_output),
+            max_output: options.max_output,
+        }
+    }
+
+    fn update(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.decompressor.get_mut().push(input);
+        self.drain(output)
+    }
+
+    /// Fails unless the input held the whole stream and its checksum
+    /// matched, anything following the trailer is ignored.
+    fn finish(&mut self, output: &mut Vec<u8>) -> Result<(), Self::Error> {
+        self.decompressor.get_mut().finish();
+        self.drain(output)?;
+        self.decompressor = Decompressor::with_limit(PushInput::default(), self.max_output);
+        Ok(())
+    }
+}
+
+//       +--------+
+//       | ERRORS |
+//       +--------+
+
+/// Enum containing possible errors raised while decoding a zlib stream.
+///
+/// # Fields
+///
+/// * 'InvalidHeader' - The two byte header is malformed, holds a
+///   description of the problem.
+/// * 'Unsupported' - The header asks for something this decoder cannot
+///   do, holds a description.
+/// * 'DictionaryRequired' - The stream was compressed against a preset
+///   dictionary but none was given, holds the Adler-32 of the
+///   dictionary it needs.
+/// * 'WrongDictionary' - The dictionary given is not the one the stream
+///   was compressed against.
+/// * 'MissingChecksum' - The stream ends before the Adler-32 trailer,
+///   holds the index of the byte it should have started at.
+/// * 'ChecksumMismatch' - The trailer does not match the decompressed data.
+/// * 'DeflateError' - Wrapper for errors in the compressed data itself.
+/// * 'IoError' - Reading the stream given to a Decompressor failed.
+#[derive(Debug)]
+pub enum ZlibError {
+    InvalidHeader(&'static str),
+    Unsupported(&'static str),
+    DictionaryRequired(u32),
+    WrongDictionary { expected: u32, actual: u32 },
+    MissingChecksum(usize),
+    ChecksumMismatch { expected: u32, actual: u32 },
+    DeflateError(DeflateError),
+    IoError(io::Error),
+}
+
+// Defines how ZlibErrors are displayed.
+impl Display for ZlibError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZlibError::InvalidHeader(e) => {
+                write!(f, "Error: Invalid zlib header, '{e}'")
+            }
+            ZlibError::Unsupported(e) => {
+                write!(f, "Error: Unsupported zlib stream, '{e}'")
+            }
+            ZlibError::DictionaryRequired(id) => {
+                write!(
+                    f,
+                    "Error: zlib stream requires a preset dictionary with Adler-32 '{id:08X}'."
+                )
+            }
+            ZlibError::WrongDictionary { expected, actual } => {
+                write!(
+                    f,
+                    "Error: Wrong preset dictionary, expected Adler-32 '{expected:08X}' but found '{actual:08X}'."
+                )
+            }
+            ZlibError::MissingChecksum(offset) => {
+                write!(
+                    f,
+                    "Error: zlib stream ends before its Adler-32 checksum at byte '{offset}'."
+                )
+            }
+            ZlibError::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Error: Adler-32 mismatch, expected '{expected:08X}' but found '{actual:08X}'."
+                )
+            }
+            ZlibError::DeflateError(e) => {
+                write!(f, "{e}")
+            }
+            ZlibError::IoError(e) => {
+                write!(f, "Error: Could not read zlib stream, '{e}'.")
+            }
+        }
+    }
+}
+
+// Allows for conversion from DeflateError to ZlibError.
+impl From<DeflateError> for ZlibError {
+    fn from(error: DeflateError) -> Self {
+        ZlibError::DeflateError(error)
+    }
+}
+
+// Implements the Error interface for ZlibError, with the wrapped error
+// as its source.
+impl Error for ZlibError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ZlibError::DeflateError(e) => Some(e),
+            ZlibError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_compression_round_trips() {
+        let data: Vec<u8> = (0..400_000u32)
+            .map(|i| (i % 1000 / 7) as u8 ^ (i >> 12) as u8)
+            .collect();
+        for (threads, prime) in [(1, true), (3, false), (8, true)] {
+            let compressed = compress_parallel(&data, 6, threads, prime);
+            assert_eq!(compressed[..2], header(6));
+            assert_eq!(decompress(&compressed).unwrap(), data);
+        }
+
+        // The combined checksum is the one a reader checks.
+        let mut corrupt = compress_parallel(&data, 6, 4, true);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert!(matches!(
+            decompress(&corrupt),
+            Err(ZlibError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn stream_coders_round_trip() {
+        let data: Vec<u8> = (0..150_000u32).map(|i| (i % 251) as u8 & 0xF0).collect();
+        let options = CoderOptions {
+            level: 9,
+            ..CoderOptions::default()
+        };
+        let mut encoder = ZlibEncoder::with_options(&options);
+        let mut compressed = Vec::new();
+        for piece in data.chunks(10_000) {
+            encoder.update(piece, &mut compressed).unwrap();
+        }
+        encoder.finish(&mut compressed).unwrap();
+        assert_eq!(compressed[..2], header(9));
+        assert_eq!(decompress(&compressed).unwrap(), data);
+
+        // Bytes after the trailer are ignored.
+        compressed.extend_from_slice(b"trailing");
+        let mut decoder = ZlibDecoder::with_options(&options);
+        let mut output = Vec::new();
+        for byte in &compressed {
+            decoder.update(&[*byte], &mut output).unwrap();
+        }
+        decoder.finish(&mut output).unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn stream_decoders_check_the_trailer() {
+        let mut compressed = compress(b"checked on finish", 6);
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+        let mut decoder = ZlibDecoder::with_options(&CoderOptions::default());
+        let mut output = Vec::new();
+        let result = decoder
+            .update(&compressed, &mut output)
+            .and_then(|_| decoder.finish(&mut output));
+        assert!(matches!(result, Err(ZlibError::ChecksumMismatch { .. })));
+
+        // A cut trailer is reported where it starts.
+        let trailer = compressed.len() - 4;
+        let mut decoder = ZlibDecoder::with_options(&CoderOptions::default());
+        decoder.update(&compressed[..last], &mut output).unwrap();
+        assert!(matches!(
+            decoder.finish(&mut output),
+            Err(ZlibError::MissingChecksum(offset)) if offset == trailer
+        ));
+        assert!(matches!(
+            decompress(&compressed[..last]),
+            Err(ZlibError::MissingChecksum(offset)) if offset == trailer
+        ));
+
+        let mut decoder = ZlibDecoder::with_options(&CoderOptions::default());
+        assert!(matches!(
+            decoder.update(&[0x78, 0x9D], &mut output),
+            Err(ZlibError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn dictionaries_are_named_in_the_header() {
+        let dictionary = b"Content-Type: text/html; charset=utf-8\r\nCache-Control: no-cache\r\n";
+        let response = b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\r\n";
+        let compressed = compress_with_dictionary(response, 6, dictionary);
+        assert_eq!(compressed[..6], dictionary_header(6, dictionary));
+        assert_eq!(compressed[2..6], adler32(dictionary).to_be_bytes());
+        assert_eq!(
+            decompress_with_dictionary(&compressed, dictionary).unwrap(),
+            response
+        );
+
+        // Streams without a dictionary ignore the one given.
+        let plain = compress(response, 6);
+        assert_eq!(
+            decompress_with_dictionary(&plain, dictionary).unwrap(),
+            response
+        );
+
+        // As do the streaming coders.
+        let mut compressor = Compressor::with_dictionary(6, dictionary);
+        compressor.write(response);
+        let streamed = compressor.finish();
+        let mut decompressor = Decompressor::new(&streamed[..]);
+        decompressor.set_dictionary(dictionary);
+        let mut output = vec![0; 200];
+        let length = decompressor.decompress(&mut output).unwrap();
+        assert_eq!(output[..length], response[..]);
+    }
+
+    #[test]
+    fn dictionaries_have_to_match() {
+        let dictionary = b"Content-Length: ";
+        let compressed = compress_with_dictionary(b"Content-Length: 42", 6, dictionary);
+        let id = adler32(dictionary);
+
+        assert!(matches!(
+            decompress(&compressed),
+            Err(ZlibError::DictionaryRequired(expected)) if expected == id
+        ));
+        assert!(matches!(
+            decompress_with_dictionary(&compressed, b"Content-Type: "),
+            Err(ZlibError::WrongDictionary { expected, .. }) if expected == id
+        ));
+        assert!(matches!(
+            decompress_with_dictionary(&compressed[..4], dictionary),
+            Err(ZlibError::InvalidHeader(_))
+        ));
+    }
+}
@@ -0,0 +1,592 @@
+//! The crate-wide error type. Each module reports problems with its own
+//! error enum, which is the most precise description of what went wrong,
+//! while Error sorts them into an ErrorKind which callers can match on
+//! without knowing every module, keeping the module error to hand.
+//!
+//! ```text
+//! Png::from_path  ->  DecoderError::ZlibError
+//!                       ZlibError::DeflateError
+//!                         DeflateError::InvalidCode(1234)
+//!
+//! Error { kind: InvalidHuffmanCode(Bit(1234)), source: DecoderError }
+//! ```
+//!
+//! Error displays as the module error and source continues from there,
+//! so walking the chain gives each message once.
+use crate::cli::CliError;
+use crate::compression::deflate::DeflateError;
+use crate::compression::gzip::GzipError;
+use crate::compression::huffman::HuffmanError;
+use crate::compression::lzss::LzssError;
+use crate::compression::zlib::ZlibError;
+use crate::limits::LimitError;
+use crate::png::chunk::ChunkError;
+use crate::png::decoder::DecoderError;
+use crate::png::encoder::EncoderError;
+use crate::png::optimize::OptimizeError;
+use std::{
+    error,
+    fmt::{self, Display},
+    io,
+};
+
+/// A Result using the crate-wide Error.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Where in the input a problem was found.
+///
+/// # Fields
+///
+/// * 'Byte' - A byte offset.
+/// * 'Bit' - A bit offset, for problems within a bit stream such as
+///   DEFLATE.
+/// * 'Unknown' - The position was not recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Byte(usize),
+    Bit(usize),
+    Unknown,
+}
+
+/// The checksums which can fail to match.
+///
+/// # Fields
+///
+/// * 'Adler32' - The trailer of a zlib stream.
+/// * 'Crc32' - The CRC of a PNG chunk, or the trailer of a gzip member.
+/// * 'Length' - The length in the trailer of a gzip member, modulo 2^32.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    Adler32,
+    Crc32,
+    Length,
+}
+
+/// What kind of problem an Error is.
+///
+/// # Fields
+///
+/// * 'Io' - Reading or writing a file or stream failed.
+/// * 'Truncated' - The input ends before it should.
+/// * 'InvalidHuffmanCode' - Compressed data holds bits which are not a
+///   code, or a code which cannot appear.
+/// * 'InvalidDistance' - A back-reference points before the start of the
+///   output.
+/// * 'ChecksumMismatch' - Data does not match its checksum.
+/// * 'Unsupported' - The input is valid but uses a format or feature
+///   which is not supported, such as a file which is not a PNG.
+/// * 'LimitExceeded' - Decoding would exceed the configured Limits.
+/// * 'InvalidData' - The input is malformed in some other way.
+/// * 'InvalidInput' - The caller asked for something impossible, such as
+///   encoding an image with a palette which does not fit it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    Io(io::ErrorKind),
+    Truncated(Position),
+    InvalidHuffmanCode(Position),
+    InvalidDistance {
+        position: Position,
+        distance: usize,
+        available: usize,
+    },
+    ChecksumMismatch {
+        checksum: Checksum,
+        expected: u32,
+        actual: u32,
+    },
+    Unsupported(String),
+    LimitExceeded(LimitError),
+    InvalidData(String),
+    InvalidInput(String),
+}
+
+/// An error from anywhere in the crate.
+///
+/// # Attributes
+///
+/// * 'kind' - What kind of problem it is.
+/// * 'source' - The error of the module it came from, if any, see
+///   get_ref.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::png::decoder::Png;
+/// # use chameleon::ErrorKind;
+/// fn status(upload: &[u8]) -> u16 {
+///     match Png::from_bytes(upload.to_vec()).map_err(chameleon::Error::from) {
+///         Ok(_) => 200,
+///         Err(e) => match e.kind() {
+///             ErrorKind::Io(_) => 500,
+///             ErrorKind::LimitExceeded(_) => 413,
+///             ErrorKind::Unsupported(_) => 415,
+///             _ => 400,
+///         },
+///     }
+/// }
+///
+/// assert_eq!(status(b"GIF89a"), 415);
+/// ```
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    source: Option<Box<dyn error::Error + Send + Sync + 'static>>,
+}
+
+impl Error {
+    /// Creates an Error of the given kind caused by 'source'.
+    pub fn new<E>(kind: ErrorKind, source: E) -> Self
+    where
+        E: Into<Box<dyn error::Error + Send + Sync + 'static>>,
+    {
+        Self {
+            kind,
+            source: Some(source.into()),
+        }
+    }
+
+    /// What kind of problem this is.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Returns the module error this came from, which can be downcast to
+    /// the module's own error type for the full details.
+    pub fn get_ref(&self) -> Option<&(dyn error::Error + Send + Sync + 'static)> {
+        self.source.as_deref()
+    }
+
+    /// Consumes the Error, returning the module error it came from.
+    pub fn into_source(self) -> Option<Box<dyn error::Error + Send + Sync + 'static>> {
+        self.source
+    }
+}
+
+// Creates an Error without a source.
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self { kind, source: None }
+    }
+}
+
+// Defines how Errors are displayed, the message of the module error
+// being the more precise when there is one.
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(source) = &self.source {
+            return write!(f, "{source}");
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+// Implements the Error interface for Error. As its message is already
+// that of the module error, the chain continues with what caused that,
+// the same as for an io::Error with a custom error.
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.source.as_deref().and_then(|source| source.source())
+    }
+}
+
+// Defines how ErrorKinds are displayed.
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Io(kind) => write!(f, "Error: I/O error, '{kind}'."),
+            ErrorKind::Truncated(position) => {
+                write!(f, "Error: Input ends unexpectedly{position}.")
+            }
+            ErrorKind::InvalidHuffmanCode(position) => {
+                write!(f, "Error: Invalid Huffman code{position}.")
+            }
+            ErrorKind::InvalidDistance {
+                position,
+                distance,
+                available,
+            } => {
+                write!(f, "Error: Back-reference distance '{distance}'{position} exceeds the '{available}' bytes decoded so far.")
+            }
+            ErrorKind::ChecksumMismatch {
+                checksum,
+                expected,
+                actual,
+            } => {
+                write!(
+                    f,
+                    "Error: {checksum:?} mismatch, expected '{expected:08X}' but found '{actual:08X}'."
+                )
+            }
+            ErrorKind::Unsupported(e) => write!(f, "Error: Unsupported, '{e}'."),
+            ErrorKind::LimitExceeded(e) => write!(f, "{e}"),
+            ErrorKind::InvalidData(e) => write!(f, "Error: Invalid data, '{e}'."),
+            ErrorKind::InvalidInput(e) => write!(f, "Error: Invalid input, '{e}'."),
+        }
+    }
+}
+
+// Displays a position as a suffix, such as " at bit '100'".
+impl Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Position::Byte(byte) => write!(f, " at byte '{byte}'"),
+            Position::Bit(bit) => write!(f, " at bit '{bit}'"),
+            Position::Unknown => Ok(()),
+        }
+    }
+}
+
+//      +-------------+
+//      | CONVERSIONS |
+//      +-------------+
+
+/// Sorts a DeflateError into an ErrorKind, for it and every error which
+/// wraps it.
+fn deflate_kind(error: &DeflateError) -> ErrorKind {
+    match *error {
+        DeflateError::UnexpectedEnd(bit) => ErrorKind::Truncated(Position::Bit(bit)),
+        DeflateError::InvalidCode(bit) => ErrorKind::InvalidHuffmanCode(Position::Bit(bit)),
+        DeflateError::InvalidDistance {
+            position,
+            distance,
+            available,
+        } => ErrorKind::InvalidDistance {
+            position: Position::Bit(position),
+            distance,
+            available,
+        },
+        DeflateError::LimitExceeded(e) => ErrorKind::LimitExceeded(e),
+        DeflateError::InvalidBlockType(_)
+        | DeflateError::StoredLengthMismatch(_)
+        | DeflateError::InvalidCodeLengths(_) => ErrorKind::InvalidData(error.to_string()),
+    }
+}
+
+/// Sorts a ZlibError into an ErrorKind.
+fn zlib_kind(error: &ZlibError) -> ErrorKind {
+    match error {
+        ZlibError::InvalidHeader(e) => ErrorKind::InvalidData(e.to_string()),
+        ZlibError::Unsupported(e) => ErrorKind::Unsupported(e.to_string()),
+        ZlibError::DictionaryRequired(_) | ZlibError::WrongDictionary { .. } => {
+            ErrorKind::InvalidInput(error.to_string())
+        }
+        ZlibError::MissingChecksum(offset) => ErrorKind::Truncated(Position::Byte(*offset)),
+        ZlibError::ChecksumMismatch { expected, actual } => ErrorKind::ChecksumMismatch {
+            checksum: Checksum::Adler32,
+            expected: *expected,
+            actual: *actual,
+        },
+        ZlibError::DeflateError(e) => deflate_kind(e),
+        ZlibError::IoError(e) => ErrorKind::Io(e.kind()),
+    }
+}
+
+/// Sorts a DecoderError into an ErrorKind.
+fn decoder_kind(error: &DecoderError) -> ErrorKind {
+    match error {
+        DecoderError::TypeError(e) => ErrorKind::Unsupported(e.clone()),
+        DecoderError::IoError(e) => ErrorKind::Io(e.kind()),
+        DecoderError::NoMoreChunks(offset) | DecoderError::Truncated { offset, .. } => {
+            ErrorKind::Truncated(Position::Byte(*offset))
+        }
+        DecoderError::CrcError {
+            expected, actual, ..
+        } => ErrorKind::ChecksumMismatch {
+            checksum: Checksum::Crc32,
+            expected: *expected,
+            actual: *actual,
+        },
+        DecoderError::ZlibError(e) => zlib_kind(e),
+        DecoderError::LimitError(e) => ErrorKind::LimitExceeded(*e),
+        _ => ErrorKind::InvalidData(error.to_string()),
+    }
+}
+
+// Allows for conversion from io::Error to Error.
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::new(ErrorKind::Io(error.kind()), error)
+    }
+}
+
+// Allows for conversion from LimitError to Error.
+impl From<LimitError> for Error {
+    fn from(error: LimitError) -> Self {
+        Error::new(ErrorKind::LimitExceeded(error), error)
+    }
+}
+
+// Allows for conversion from HuffmanError to Error.
+impl From<HuffmanError> for Error {
+    fn from(error: HuffmanError) -> Self {
+        let kind = match error {
+            HuffmanError::Truncated(byte) => ErrorKind::Truncated(Position::Byte(byte)),
+            HuffmanError::InvalidCode(bit) => ErrorKind::InvalidHuffmanCode(Position::Bit(bit)),
+            HuffmanError::InvalidMagic | HuffmanError::UnsupportedVersion(_) => {
+                ErrorKind::Unsupported(error.to_string())
+            }
+            HuffmanError::InvalidHeader => ErrorKind::InvalidData(error.to_string()),
+            HuffmanError::ChecksumMismatch { expected, actual } => ErrorKind::ChecksumMismatch {
+                checksum: Checksum::Crc32,
+                expected,
+                actual,
+            },
+        };
+        Error::new(kind, error)
+    }
+}
+
+// Allows for conversion from LzssError to Error.
+impl From<LzssError> for Error {
+    fn from(error: LzssError) -> Self {
+        let kind = match error {
+            LzssError::Truncated(byte) => ErrorKind::Truncated(Position::Byte(byte)),
+            LzssError::InvalidDistance {
+                position,
+                distance,
+                available,
+            } => ErrorKind::InvalidDistance {
+                position: Position::Byte(position),
+                distance,
+                available,
+            },
+            LzssError::LimitExceeded(e) => ErrorKind::LimitExceeded(e),
+        };
+        Error::new(kind, error)
+    }
+}
+
+// Allows for conversion from DeflateError to Error.
+impl From<DeflateError> for Error {
+    fn from(error: DeflateError) -> Self {
+        Error::new(deflate_kind(&error), error)
+    }
+}
+
+// Allows for conversion from ZlibError to Error.
+impl From<ZlibError> for Error {
+    fn from(error: ZlibError) -> Self {
+        Error::new(zlib_kind(&error), error)
+    }
+}
+
+// Allows for conversion from GzipError to Error.
+impl From<GzipError> for Error {
+    fn from(error: GzipError) -> Self {
+        let kind = match &error {
+            GzipError::InvalidHeader(e) => ErrorKind::InvalidData(e.to_string()),
+            GzipError::MissingTrailer => ErrorKind::Truncated(Position::Unknown),
+            GzipError::ChecksumMismatch { expected, actual } => ErrorKind::ChecksumMismatch {
+                checksum: Checksum::Crc32,
+                expected: *expected,
+                actual: *actual,
+            },
+            GzipError::LengthMismatch { expected, actual } => ErrorKind::ChecksumMismatch {
+                checksum: Checksum::Length,
+                expected: *expected,
+                actual: *actual,
+            },
+            GzipError::DeflateError(e) => deflate_kind(e),
+        };
+        Error::new(kind, error)
+    }
+}
+
+// Allows for conversion from DecoderError to Error.
+impl From<DecoderError> for Error {
+    fn from(error: DecoderError) -> Self {
+        Error::new(decoder_kind(&error), error)
+    }
+}
+
+// Allows for conversion from EncoderError to Error.
+impl From<EncoderError> for Error {
+    fn from(error: EncoderError) -> Self {
+        let kind = match &error {
+            EncoderError::IoError(e) => ErrorKind::Io(e.kind()),
+            _ => ErrorKind::InvalidInput(error.to_string()),
+        };
+        Error::new(kind, error)
+    }
+}
+
+// Allows for conversion from ChunkError to Error.
+impl From<ChunkError> for Error {
+    fn from(error: ChunkError) -> Self {
+        let kind = match &error {
+            ChunkError::DecoderError(e) => decoder_kind(e),
+            _ => ErrorKind::InvalidInput(error.to_string()),
+        };
+        Error::new(kind, error)
+    }
+}
+
+// Allows for conversion from OptimizeError to Error.
+impl From<OptimizeError> for Error {
+    fn from(error: OptimizeError) -> Self {
+        let kind = match &error {
+            OptimizeError::DecoderError(e) => decoder_kind(e),
+            OptimizeError::UnsupportedChunk(e) => ErrorKind::Unsupported(e.clone()),
+            OptimizeError::NothingToTry => ErrorKind::InvalidInput(error.to_string()),
+        };
+        Error::new(kind, error)
+    }
+}
+
+// Allows for conversion from CliError to Error.
+impl From<CliError> for Error {
+    fn from(error: CliError) -> Self {
+        Error::new(ErrorKind::InvalidInput(error.to_string()), error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{deflate, gzip, zlib};
+    use crate::image::buffer::Image;
+    use crate::image::pixel::Luma;
+    use crate::limits::Limits;
+    use crate::png::decoder::{Png, PNG_HEADER};
+    use crate::png::encoder::{self, EncoderOptions};
+    use crate::png::ihdr::{ColorType, Ihdr};
+    use std::error::Error as _;
+
+    /// A 2x2 grayscale PNG whose IDAT holds the given zlib stream.
+    fn png(idat: &[u8]) -> Vec<u8> {
+        let header = Ihdr {
+            width: 2,
+            height: 2,
+            bit_depth: 8,
+            color_type: ColorType::Grayscale,
+            interlaced: false,
+        };
+        let mut bytes = PNG_HEADER.to_vec();
+        encoder::write_chunk(&mut bytes, b"IHDR", &header.to_bytes());
+        encoder::write_chunk(&mut bytes, b"IDAT", idat);
+        encoder::write_chunk(&mut bytes, b"IEND", &[]);
+        bytes
+    }
+
+    fn kind<E: Into<Error>>(error: E) -> ErrorKind {
+        error.into().kind().clone()
+    }
+
+    #[test]
+    fn compression_errors_keep_their_position() {
+        // A fixed Huffman block starting with a back-reference of distance 1.
+        let error = deflate::inflate(&[0x03, 0x02, 0x00]).unwrap_err();
+        assert!(matches!(
+            kind(error),
+            ErrorKind::InvalidDistance {
+                position: Position::Bit(_),
+                distance: 1,
+                available: 0,
+            }
+        ));
+        // The reserved block type.
+        let error = deflate::inflate(&[0x07]).unwrap_err();
+        assert!(matches!(kind(error), ErrorKind::InvalidData(_)));
+        let error = deflate::inflate(&[]).unwrap_err();
+        assert!(matches!(
+            kind(error),
+            ErrorKind::Truncated(Position::Bit(_))
+        ));
+
+        let mut compressed = zlib::compress(b"chameleon", 6);
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+        let error = zlib::decompress(&compressed).unwrap_err();
+        assert!(matches!(
+            kind(error),
+            ErrorKind::ChecksumMismatch {
+                checksum: Checksum::Adler32,
+                ..
+            }
+        ));
+        let error = zlib::decompress(&compressed[..last]).unwrap_err();
+        assert!(matches!(
+            kind(error),
+            ErrorKind::Truncated(Position::Byte(offset)) if offset == last - 3
+        ));
+
+        let mut compressed = gzip::compress(b"chameleon", 6);
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+        let error = gzip::decompress(&compressed).unwrap_err();
+        assert!(matches!(
+            kind(error),
+            ErrorKind::ChecksumMismatch {
+                checksum: Checksum::Length,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn decoder_errors_are_sorted_into_kinds() {
+        let image = Image::from_fn(4, 4, |x, y| Luma([(x * y) as u8]));
+        let bytes = encoder::encode_image(&image, &EncoderOptions::default()).unwrap();
+        assert!(Png::from_bytes(bytes.clone()).map_err(Error::from).is_ok());
+
+        let error = Png::from_bytes(b"GIF89a and more".to_vec()).err().unwrap();
+        assert!(matches!(kind(error), ErrorKind::Unsupported(_)));
+
+        // The CRC of IHDR.
+        let mut corrupt = bytes.clone();
+        corrupt[29] ^= 1;
+        let error = Png::from_bytes(corrupt).err().unwrap();
+        assert!(matches!(
+            kind(error),
+            ErrorKind::ChecksumMismatch {
+                checksum: Checksum::Crc32,
+                ..
+            }
+        ));
+
+        let limits = Limits {
+            max_width: 2,
+            ..Limits::default()
+        };
+        let error = Png::from_bytes_with_limits(bytes, &limits).err().unwrap();
+        assert_eq!(
+            kind(error),
+            ErrorKind::LimitExceeded(LimitError::Width { width: 4, max: 2 })
+        );
+
+        let error = Png::from_path("/nonexistent/chameleon.png").err().unwrap();
+        assert_eq!(kind(error), ErrorKind::Io(io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn module_errors_are_kept_as_the_source() {
+        let png = Png::from_bytes(png(&[0x78, 0x9C, 0x07])).ok().unwrap();
+        let error = Error::from(png.scanlines().unwrap_err());
+        assert!(matches!(error.kind(), ErrorKind::InvalidData(_)));
+
+        // Error displays as the DecoderError, and the chain continues with
+        // the ZlibError it wraps.
+        let decoder = error.get_ref().unwrap().downcast_ref::<DecoderError>();
+        assert_eq!(error.to_string(), decoder.unwrap().to_string());
+        let source = error.source().unwrap();
+        assert!(source.downcast_ref::<ZlibError>().is_some());
+        assert!(error.into_source().unwrap().is::<DecoderError>());
+    }
+
+    #[test]
+    fn kinds_display_their_position() {
+        let error = Error::from(ErrorKind::Truncated(Position::Byte(12)));
+        assert!(error.get_ref().is_none());
+        assert_eq!(
+            error.to_string(),
+            "Error: Input ends unexpectedly at byte '12'."
+        );
+        assert_eq!(
+            ErrorKind::Truncated(Position::Unknown).to_string(),
+            "Error: Input ends unexpectedly."
+        );
+        assert!(ErrorKind::InvalidHuffmanCode(Position::Bit(7))
+            .to_string()
+            .contains("at bit '7'"));
+    }
+}
@@ -0,0 +1,551 @@
+//! The in-memory image every decoder, encoder and filter works on,
+//! along with borrowed views into rectangular parts of it.
+use crate::image::pixel::Pixel;
+
+/// A struct containing an image as a grid of pixels, stored row by row.
+///
+/// # Attributes
+///
+/// * 'width' - The width of the image in pixels.
+/// * 'height' - The height of the image in pixels.
+/// * 'stride' - The number of pixels from the start of one row to the
+///   start of the next, at least width.
+/// * 'data' - The pixels, at least stride * height long.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::image::buffer::Image;
+/// # use chameleon::image::pixel::{Rgb, Rgb8};
+/// let mut image: Image<Rgb8> = Image::new(4, 4);
+/// image.put_pixel(1, 2, Rgb([255, 0, 0]));
+///
+/// assert_eq!(image.get_pixel(1, 2), Rgb([255, 0, 0]));
+/// assert_eq!(image.get_pixel_checked(4, 0), None);
+///
+/// for row in image.rows_mut() {
+///     row[0] = Rgb([0, 0, 255]);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image<P: Pixel> {
+    width: u32,
+    height: u32,
+    stride: usize,
+    data: Vec<P>,
+}
+
+impl<P: Pixel> Image<P> {
+    /// Creates an image of the given size filled with the default pixel,
+    /// which is black and, where there is alpha, fully transparent.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::from_pixel(width, height, P::default())
+    }
+
+    /// Creates an image of the given size filled with 'pixel'.
+    pub fn from_pixel(width: u32, height: u32, pixel: P) -> Self {
+        Self {
+            width,
+            height,
+            stride: width as usize,
+            data: vec![pixel; width as usize * height as usize],
+        }
+    }
+
+    /// Creates an image by calling 'f' with the position of each pixel.
+    pub fn from_fn<F: FnMut(u32, u32) -> P>(width: u32, height: u32, mut f: F) -> Self {
+        let mut data = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                data.push(f(x, y));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            stride: width as usize,
+            data,
+        }
+    }
+
+    /// Creates an image from pixels stored row by row without padding.
+    ///
+    /// # Returns
+    ///
+    /// The image, or None if data does not hold exactly width * height pixels.
+    pub fn from_vec(width: u32, height: u32, data: Vec<P>) -> Option<Self> {
+        Self::from_vec_with_stride(width, height, width as usize, data)
+    }
+
+    /// Creates an image from rows which are 'stride' pixels apart, where
+    /// the pixels past the width of each row are padding.
+    ///
+    /// # Returns
+    ///
+    /// The image, or None if the stride is smaller than the width or data
+    /// is too short to hold every row.
+    pub fn from_vec_with_stride(
+        width: u32,
+        height: u32,
+        stride: usize,
+        data: Vec<P>,
+    ) -> Option<Self> {
+        if stride < width as usize || data.len() != stride * height as usize {
+            return None;
+        }
+
+        Some(Self {
+            width,
+            height,
+            stride,
+            data,
+        })
+    }
+
+    /// Creates an image from the samples of each pixel back to back, such
+    /// as [R, G, B, R, G, B, ...] for Rgb8.
+    ///
+    /// # Returns
+    ///
+    /// The image, or None if there are not exactly width * height pixels.
+    pub fn from_samples(width: u32, height: u32, samples: &[P::Subpixel]) -> Option<Self> {
+        if samples.len() != width as usize * height as usize * P::CHANNELS {
+            return None;
+        }
+        let data = samples
+            .chunks_exact(P::CHANNELS)
+            .map(P::from_channels)
+            .collect();
+        Self::from_vec(width, height, data)
+    }
+
+    /// The width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The width and height of the image.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The number of pixels from the start of one row to the next.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns the pixel at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the image.
+    pub fn get_pixel(&self, x: u32, y: u32) -> P {
+        self.view(0, 0, self.width, self.height).get_pixel(x, y)
+    }
+
+    /// Returns the pixel at the given position, or None if the position is
+    /// outside the image.
+    pub fn get_pixel_checked(&self, x: u32, y: u32) -> Option<P> {
+        self.view(0, 0, self.width, self.height)
+            .get_pixel_checked(x, y)
+    }
+
+    /// Returns a mutable reference to the pixel at the given position, or
+    /// None if the position is outside the image.
+    pub fn get_pixel_mut(&mut self, x: u32, y: u32) -> Option<&mut P> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.data.get_mut(y as usize * self.stride + x as usize)
+    }
+
+    /// Replaces the pixel at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the image.
+    pub fn put_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        let (width, height) = self.dimensions();
+        match self.get_pixel_mut(x, y) {
+            Some(p) => *p = pixel,
+            None => panic!("Pixel ({x}, {y}) is outside the {width}x{height} image."),
+        }
+    }
+
+    /// Returns an iterator over the rows of the image, each being width
+    /// pixels long.
+    pub fn rows(&self) -> impl Iterator<Item = &[P]> {
+        let width = self.width as usize;
+        self.data
+            .chunks(self.stride.max(1))
+            .take(self.height as usize)
+            .map(move |row| &row[..width])
+    }
+
+    /// Returns an iterator over the mutable rows of the image, each being
+    /// width pixels long.
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [P]> {
+        let width = self.width as usize;
+        self.data
+            .chunks_mut(self.stride.max(1))
+            .take(self.height as usize)
+            .map(move |row| &mut row[..width])
+    }
+
+    /// Returns an iterator over every pixel along with its position, row
+    /// by row.
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (u32, u32, &P)> {
+        self.rows().enumerate().flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(x, pixel)| (x as u32, y as u32, pixel))
+        })
+    }
+
+    /// Returns the pixels of the image, including any row padding.
+    pub fn as_pixels(&self) -> &[P] {
+        &self.data
+    }
+
+    /// Consumes the image, returning its pixels row by row without padding.
+    pub fn into_vec(self) -> Vec<P> {
+        if self.stride == self.width as usize {
+            return self.data;
+        }
+        self.rows().flatten().copied().collect()
+    }
+
+    /// Returns a read-only view of a rectangular part of the image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rectangle does not fit inside the image.
+    pub fn view(&self, x: u32, y: u32, width: u32, height: u32) -> ImageView<'_, P> {
+        let start = sub_rect(self.dimensions(), self.stride, x, y, width, height);
+        ImageView {
+            width,
+            height,
+            stride: self.stride,
+            data: &self.data[start..],
+        }
+    }
+
+    /// Returns a mutable view of a rectangular part of the image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rectangle does not fit inside the image.
+    pub fn view_mut(&mut self, x: u32, y: u32, width: u32, height: u32) -> ImageViewMut<'_, P> {
+        let start = sub_rect(self.dimensions(), self.stride, x, y, width, height);
+        ImageViewMut {
+            width,
+            height,
+            stride: self.stride,
+            data: &mut self.data[start..],
+        }
+    }
+}
+
+/// Checks that the rectangle fits inside an image of the given size.
+///
+/// # Returns
+///
+/// The index of the first pixel of the rectangle.
+fn sub_rect(dimensions: (u32, u32), stride: usize, x: u32, y: u32, w: u32, h: u32) -> usize {
+    let (width, height) = dimensions;
+    let fits = x.checked_add(w).is_some_and(|right| right <= width)
+        && y.checked_add(h).is_some_and(|bottom| bottom <= height);
+    assert!(
+        fits,
+        "View {w}x{h} at ({x}, {y}) does not fit inside the {width}x{height} image."
+    );
+    // Empty views start at the beginning so they never index past the end.
+    if w == 0 || h == 0 {
+        return 0;
+    }
+    y as usize * stride + x as usize
+}
+
+/// A read-only view of a rectangular part of an Image. Rows are read
+/// directly from the parent image, so creating a view does not copy.
+///
+/// # Attributes
+///
+/// * 'width', 'height' - The size of the view in pixels.
+/// * 'stride' - The stride of the parent image.
+/// * 'data' - The parent's pixels starting at the view's first pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageView<'a, P: Pixel> {
+    width: u32,
+    height: u32,
+    stride: usize,
+    data: &'a [P],
+}
+
+impl<'a, P: Pixel> ImageView<'a, P> {
+    /// The width of the view in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the view in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The width and height of the view.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the pixel at the given position relative to the view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the view.
+    pub fn get_pixel(&self, x: u32, y: u32) -> P {
+        self.get_pixel_checked(x, y).unwrap_or_else(|| {
+            panic!(
+                "Pixel ({x}, {y}) is outside the {}x{} image.",
+                self.width, self.height
+            )
+        })
+    }
+
+    /// Returns the pixel at the given position relative to the view, or
+    /// None if the position is outside the view.
+    pub fn get_pixel_checked(&self, x: u32, y: u32) -> Option<P> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.data
+            .get(y as usize * self.stride + x as usize)
+            .copied()
+    }
+
+    /// Returns an iterator over the rows of the view.
+    pub fn rows(&self) -> impl Iterator<Item = &'a [P]> {
+        let width = self.width as usize;
+        let data = self.data;
+        data.chunks(self.stride.max(1))
+            .take(self.height as usize)
+            .map(move |row| &row[..width])
+    }
+
+    /// Returns a view of a rectangular part of this view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rectangle does not fit inside the view.
+    pub fn view(&self, x: u32, y: u32, width: u32, height: u32) -> ImageView<'a, P> {
+        let start = sub_rect(self.dimensions(), self.stride, x, y, width, height);
+        ImageView {
+            width,
+            height,
+            stride: self.stride,
+            data: &self.data[start..],
+        }
+    }
+
+    /// Copies the pixels of the view into a new Image.
+    pub fn to_image(&self) -> Image<P> {
+        let data = self.rows().flatten().copied().collect();
+        Image {
+            width: self.width,
+            height: self.height,
+            stride: self.width as usize,
+            data,
+        }
+    }
+}
+
+/// A mutable view of a rectangular part of an Image, writing through
+/// to the parent image.
+///
+/// # Attributes
+///
+/// * 'width', 'height' - The size of the view in pixels.
+/// * 'stride' - The stride of the parent image.
+/// * 'data' - The parent's pixels starting at the view's first pixel.
+#[derive(Debug)]
+pub struct ImageViewMut<'a, P: Pixel> {
+    width: u32,
+    height: u32,
+    stride: usize,
+    data: &'a mut [P],
+}
+
+impl<P: Pixel> ImageViewMut<'_, P> {
+    /// The width of the view in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the view in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The width and height of the view.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Reborrows the view as read-only.
+    pub fn as_view(&self) -> ImageView<'_, P> {
+        ImageView {
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+            data: self.data,
+        }
+    }
+
+    /// Returns the pixel at the given position relative to the view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the view.
+    pub fn get_pixel(&self, x: u32, y: u32) -> P {
+        self.as_view().get_pixel(x, y)
+    }
+
+    /// Returns a mutable reference to the pixel at the given position
+    /// relative to the view, or None if the position is outside the view.
+    pub fn get_pixel_mut(&mut self, x: u32, y: u32) -> Option<&mut P> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.data.get_mut(y as usize * self.stride + x as usize)
+    }
+
+    /// Replaces the pixel at the given position relative to the view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the view.
+    pub fn put_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        let (width, height) = self.dimensions();
+        match self.get_pixel_mut(x, y) {
+            Some(p) => *p = pixel,
+            None => panic!("Pixel ({x}, {y}) is outside the {width}x{height} image."),
+        }
+    }
+
+    /// Returns an iterator over the mutable rows of the view.
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [P]> {
+        let width = self.width as usize;
+        self.data
+            .chunks_mut(self.stride.max(1))
+            .take(self.height as usize)
+            .map(move |row| &mut row[..width])
+    }
+
+    /// Returns a mutable view of a rectangular part of this view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rectangle does not fit inside the view.
+    pub fn view_mut(&mut self, x: u32, y: u32, width: u32, height: u32) -> ImageViewMut<'_, P> {
+        let start = sub_rect(self.dimensions(), self.stride, x, y, width, height);
+        ImageViewMut {
+            width,
+            height,
+            stride: self.stride,
+            data: &mut self.data[start..],
+        }
+    }
+
+    /// Sets every pixel of the view to 'pixel'.
+    pub fn fill(&mut self, pixel: P) {
+        for row in self.rows_mut() {
+            row.fill(pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::pixel::{Luma, Luma8, Rgb, Rgb8};
+
+    #[test]
+    fn pixel_access() {
+        let mut image: Image<Rgb8> = Image::new(4, 3);
+        image.put_pixel(1, 2, Rgb([255, 0, 0]));
+
+        assert_eq!(image.get_pixel(1, 2), Rgb([255, 0, 0]));
+        assert_eq!(image.get_pixel(2, 1), Rgb([0, 0, 0]));
+        assert_eq!(image.get_pixel_checked(4, 0), None);
+        assert_eq!(image.get_pixel_checked(0, 3), None);
+        assert!(image.get_pixel_mut(4, 0).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "outside the 4x3 image")]
+    fn put_pixel_out_of_bounds() {
+        let mut image: Image<Rgb8> = Image::new(4, 3);
+        image.put_pixel(0, 3, Rgb([1, 2, 3]));
+    }
+
+    #[test]
+    fn rows_skip_padding() {
+        // Two rows of three pixels, each padded to a stride of five.
+        let data = (0..10).map(|value| Luma([value])).collect();
+        let mut image: Image<Luma8> = Image::from_vec_with_stride(3, 2, 5, data).unwrap();
+
+        let rows: Vec<Vec<u8>> = image
+            .rows()
+            .map(|row| row.iter().map(|pixel| pixel.0[0]).collect())
+            .collect();
+        assert_eq!(rows, [vec![0, 1, 2], vec![5, 6, 7]]);
+
+        for row in image.rows_mut() {
+            row[0] = Luma([99]);
+        }
+        let pixels: Vec<u8> = image.into_vec().iter().map(|pixel| pixel.0[0]).collect();
+        assert_eq!(pixels, [99, 1, 2, 99, 6, 7]);
+    }
+
+    #[test]
+    fn constructors_check_sizes() {
+        assert!(Image::<Luma8>::from_vec(2, 2, vec![Luma([0]); 3]).is_none());
+        assert!(Image::<Luma8>::from_vec_with_stride(3, 2, 2, vec![Luma([0]); 6]).is_none());
+        assert!(Image::<Rgb8>::from_samples(1, 1, &[1, 2]).is_none());
+
+        let image = Image::<Rgb8>::from_samples(2, 1, &[1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(image.get_pixel(1, 0), Rgb([4, 5, 6]));
+    }
+
+    #[test]
+    fn views() {
+        let mut image: Image<Luma8> = Image::from_fn(6, 5, |x, y| Luma([(y * 10 + x) as u8]));
+
+        let view = image.view(1, 2, 3, 2);
+        assert_eq!(view.dimensions(), (3, 2));
+        assert_eq!(view.get_pixel(0, 0), Luma([21]));
+        assert_eq!(view.get_pixel_checked(3, 0), None);
+        assert_eq!(view.view(1, 1, 2, 1).get_pixel(1, 0), Luma([33]));
+        assert_eq!(view.to_image().into_vec().len(), 6);
+
+        image.view_mut(4, 0, 2, 5).fill(Luma([0]));
+        assert_eq!(image.get_pixel(4, 4), Luma([0]));
+        assert_eq!(image.get_pixel(3, 4), Luma([43]));
+
+        // Empty views are allowed right up to the edge.
+        assert_eq!(image.view(6, 5, 0, 0).rows().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit inside the 6x5 image")]
+    fn view_out_of_bounds() {
+        let image: Image<Luma8> = Image::new(6, 5);
+        image.view(4, 0, 3, 1);
+    }
+}
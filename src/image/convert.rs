@@ -0,0 +1,360 @@
+//! Conversion between pixel types, covering changes of sample type,
+//! color model and alpha handling.
+//!
+//! Every conversion goes through straight (not premultiplied) RGBA
+//! with samples normalized to 0.0 through 1.0:
+//!
+//! ```text
+//! P -> [R, G, B, A] -> Q
+//! ```
+//!
+//! Integer samples are rounded to the nearest value on the way back, so
+//! widening conversions such as Luma8 -> Rgba16 are lossless and
+//! narrowing them back returns the original pixels.
+use crate::image::buffer::Image;
+use crate::image::dynamic::DynamicImage;
+use crate::image::pixel::{Luma8, Pixel, Primitive};
+
+/// The weights used to compute luminance from red, green and blue.
+///
+/// # Fields
+///
+/// * 'Rec601' - 0.299 R + 0.587 G + 0.114 B, as used by JPEG and most
+///   image tools.
+/// * 'Rec709' - 0.2126 R + 0.7152 G + 0.0722 B, matching the sRGB primaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LumaWeights {
+    #[default]
+    Rec601,
+    Rec709,
+}
+
+impl LumaWeights {
+    /// Returns the red, green and blue weights, which sum to 1.
+    pub fn weights(&self) -> [f32; 3] {
+        match self {
+            LumaWeights::Rec601 => [0.299, 0.587, 0.114],
+            LumaWeights::Rec709 => [0.2126, 0.7152, 0.0722],
+        }
+    }
+
+    /// Computes the luminance of a normalized red, green and blue triple.
+    pub fn luma(&self, rgb: [f32; 3]) -> f32 {
+        let [r, g, b] = self.weights();
+        r * rgb[0] + g * rgb[1] + b * rgb[2]
+    }
+}
+
+/// Options controlling conversions which lose information.
+///
+/// # Attributes
+///
+/// * 'luma' - The weights used when converting color to grayscale.
+/// * 'matte' - When converting to a type without alpha, the normalized
+///   red, green and blue color to composite transparent pixels
+///   over. When None the alpha channel is simply dropped.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::image::buffer::Image;
+/// # use chameleon::image::convert::{ConvertOptions, LumaWeights};
+/// # use chameleon::image::pixel::{Luma8, Rgba, Rgba8};
+/// # let rgba: Image<Rgba8> = Image::from_pixel(2, 2, Rgba([255, 0, 0, 128]));
+/// // Flatten onto white using the HDTV weights.
+/// let options = ConvertOptions {
+///     luma: LumaWeights::Rec709,
+///     matte: Some([1.0, 1.0, 1.0]),
+/// };
+///
+/// let gray: Image<Luma8> = rgba.convert_with(&options);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ConvertOptions {
+    pub luma: LumaWeights,
+    pub matte: Option<[f32; 3]>,
+}
+
+/// Expands a pixel of any type into normalized straight RGBA.
+pub fn to_rgba<P: Pixel>(pixel: &P) -> [f32; 4] {
+    let mut c = [0.0f32; 4];
+    for (value, sample) in c.iter_mut().zip(pixel.channels()) {
+        *value = sample.to_normalized();
+    }
+
+    match P::CHANNELS {
+        1 => [c[0], c[0], c[0], 1.0],
+        2 => [c[0], c[0], c[0], c[1]],
+        3 => [c[0], c[1], c[2], 1.0],
+        _ => [c[0], c[1], c[2], c[3]],
+    }
+}
+
+/// Builds a pixel of any type from normalized straight RGBA.
+///
+/// # Arguments
+///
+/// * 'rgba' - The red, green, blue and alpha values.
+/// * 'options' - How to compute luminance and what to do with alpha when
+///   the pixel type has none.
+pub fn from_rgba<P: Pixel>(rgba: [f32; 4], options: &ConvertOptions) -> P {
+    let [mut r, mut g, mut b, a] = rgba;
+
+    if !P::HAS_ALPHA {
+        if let Some([mr, mg, mb]) = options.matte {
+            r = r * a + mr * (1.0 - a);
+            g = g * a + mg * (1.0 - a);
+            b = b * a + mb * (1.0 - a);
+        }
+    }
+
+    // Gray pixels are kept exact rather than passed through the weights,
+    // which do not sum to exactly 1 in floating point.
+    let luma = if r == g && g == b {
+        r
+    } else {
+        options.luma.luma([r, g, b])
+    };
+
+    let values = [
+        [luma, 0.0, 0.0, 0.0],
+        [luma, a, 0.0, 0.0],
+        [r, g, b, 0.0],
+        [r, g, b, a],
+    ][P::CHANNELS - 1];
+
+    let mut samples = [P::Subpixel::default(); 4];
+    for (sample, &value) in samples.iter_mut().zip(&values) {
+        *sample = P::Subpixel::from_normalized(value);
+    }
+    P::from_channels(&samples[..P::CHANNELS])
+}
+
+/// Converts a single pixel between types.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::image::convert::{convert_pixel, ConvertOptions};
+/// # use chameleon::image::pixel::{Luma, Luma8, Rgb, Rgba, Rgba16};
+/// let gray: Luma8 = convert_pixel(&Rgb([255u8, 0, 0]), &ConvertOptions::default());
+/// assert_eq!(gray, Luma([76]));
+///
+/// let wide: Rgba16 = convert_pixel(&Rgb([255u8, 128, 0]), &ConvertOptions::default());
+/// assert_eq!(wide, Rgba([65535, 32896, 0, 65535]));
+/// ```
+pub fn convert_pixel<P: Pixel, Q: Pixel>(pixel: &P, options: &ConvertOptions) -> Q {
+    from_rgba(to_rgba(pixel), options)
+}
+
+impl<P: Pixel> Image<P> {
+    /// Converts the image to another pixel type using the default
+    /// options, Rec. 601 luminance and dropping alpha.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chameleon::image::buffer::Image;
+    /// # use chameleon::image::pixel::{Rgb16, Rgb8, Rgba8};
+    /// # let png_image: Image<Rgb8> = Image::new(2, 2);
+    /// let rgba: Image<Rgba8> = png_image.convert::<Rgba8>();
+    /// let deep: Image<Rgb16> = rgba.convert();
+    /// ```
+    pub fn convert<Q: Pixel>(&self) -> Image<Q> {
+        self.convert_with(&ConvertOptions::default())
+    }
+
+    /// Converts the image to another pixel type using the given options.
+    pub fn convert_with<Q: Pixel>(&self, options: &ConvertOptions) -> Image<Q> {
+        Image::from_fn(self.width(), self.height(), |x, y| {
+            convert_pixel(&self.get_pixel(x, y), options)
+        })
+    }
+
+    /// Multiplies the color samples of every pixel by its alpha, as
+    /// expected by most compositing code. Does nothing for pixel types
+    /// without alpha.
+    pub fn premultiply_alpha(&mut self) {
+        self.map_color_by_alpha(|sample, alpha| sample * alpha);
+    }
+
+    /// Divides the color samples of every pixel by its alpha, reversing
+    /// premultiply_alpha. Fully transparent pixels become black. Does
+    /// nothing for pixel types without alpha.
+    pub fn unpremultiply_alpha(&mut self) {
+        self.map_color_by_alpha(|sample, alpha| match alpha {
+            0.0 => 0.0,
+            _ => sample / alpha,
+        });
+    }
+
+    /// Replaces every color sample with f(sample, alpha), using normalized
+    /// values, and leaves the alpha sample untouched.
+    fn map_color_by_alpha<F: Fn(f32, f32) -> f32>(&mut self, f: F) {
+        if !P::HAS_ALPHA {
+            return;
+        }
+
+        for row in self.rows_mut() {
+            for pixel in row.iter_mut() {
+                let channels = pixel.channels_mut();
+                let (color, alpha) = channels.split_at_mut(P::CHANNELS - 1);
+                let alpha = alpha[0].to_normalized();
+                for sample in color.iter_mut() {
+                    *sample = P::Subpixel::from_normalized(f(sample.to_normalized(), alpha));
+                }
+            }
+        }
+    }
+}
+
+/// Replaces each index of an indexed image with its palette entry.
+///
+/// # Arguments
+///
+/// * 'indices' - An image of palette indices.
+/// * 'palette' - The palette entries, which can be of any pixel type, such
+///   as Rgb8 for a PLTE chunk or Rgba8 once tRNS has been applied.
+///
+/// # Returns
+///
+/// The expanded image, or None if an index is past the end of the palette.
+pub fn expand_palette<P: Pixel>(indices: &Image<Luma8>, palette: &[P]) -> Option<Image<P>> {
+    let mut pixels = Vec::with_capacity(indices.width() as usize * indices.height() as usize);
+    for row in indices.rows() {
+        for index in row {
+            pixels.push(*palette.get(index.0[0] as usize)?);
+        }
+    }
+    Image::from_vec(indices.width(), indices.height(), pixels)
+}
+
+impl DynamicImage {
+    /// Converts the image, whatever its pixel type, to the given type
+    /// using the default options.
+    pub fn convert<Q: Pixel>(&self) -> Image<Q> {
+        self.convert_with(&ConvertOptions::default())
+    }
+
+    /// Converts the image, whatever its pixel type, to the given type
+    /// using the given options.
+    pub fn convert_with<Q: Pixel>(&self, options: &ConvertOptions) -> Image<Q> {
+        match self {
+            DynamicImage::Luma8(image) => image.convert_with(options),
+            DynamicImage::LumaA8(image) => image.convert_with(options),
+            DynamicImage::Rgb8(image) => image.convert_with(options),
+            DynamicImage::Rgba8(image) => image.convert_with(options),
+            DynamicImage::Luma16(image) => image.convert_with(options),
+            DynamicImage::LumaA16(image) => image.convert_with(options),
+            DynamicImage::Rgb16(image) => image.convert_with(options),
+            DynamicImage::Rgba16(image) => image.convert_with(options),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::pixel::{Luma, LumaA, LumaA8, Rgb, Rgb8, Rgba, Rgba16, Rgba8};
+
+    #[test]
+    fn luma_weights() {
+        let red = Rgb([255u8, 0, 0]);
+        let rec709 = ConvertOptions {
+            luma: LumaWeights::Rec709,
+            ..Default::default()
+        };
+        assert_eq!(
+            convert_pixel::<_, Luma8>(&red, &Default::default()),
+            Luma([76])
+        );
+        assert_eq!(convert_pixel::<_, Luma8>(&red, &rec709), Luma([54]));
+        // Grays keep their exact value whatever the weights.
+        assert_eq!(
+            convert_pixel::<_, Luma8>(&Rgb([77u8, 77, 77]), &rec709),
+            Luma([77])
+        );
+    }
+
+    #[test]
+    fn widening_is_lossless() {
+        let options = ConvertOptions::default();
+        for value in 0..=255u8 {
+            let wide: Rgba16 = convert_pixel(&Luma([value]), &options);
+            assert_eq!(
+                wide,
+                Rgba([
+                    value as u16 * 257,
+                    value as u16 * 257,
+                    value as u16 * 257,
+                    65535
+                ])
+            );
+            assert_eq!(convert_pixel::<_, Luma8>(&wide, &options), Luma([value]));
+        }
+        // Narrowing rounds to the nearest value.
+        assert_eq!(
+            convert_pixel::<_, Luma8>(&Luma([32896u16]), &options),
+            Luma([128])
+        );
+    }
+
+    #[test]
+    fn alpha_is_dropped_or_matted() {
+        let half_red = Rgba([255u8, 0, 0, 128]);
+        assert_eq!(
+            convert_pixel::<_, Rgb8>(&half_red, &Default::default()),
+            Rgb([255, 0, 0])
+        );
+
+        let white = ConvertOptions {
+            matte: Some([1.0, 1.0, 1.0]),
+            ..Default::default()
+        };
+        assert_eq!(
+            convert_pixel::<_, Rgb8>(&half_red, &white),
+            Rgb([255, 127, 127])
+        );
+        // Types with alpha keep it rather than being matted.
+        assert_eq!(
+            convert_pixel::<_, LumaA8>(&Rgba([9u8, 9, 9, 7]), &white),
+            LumaA([9, 7])
+        );
+    }
+
+    #[test]
+    fn premultiplied_alpha() {
+        let mut image: Image<Rgba8> =
+            Image::from_fn(3, 1, |x, _| Rgba([200, 100, 50, [255, 128, 0][x as usize]]));
+        image.premultiply_alpha();
+        assert_eq!(image.get_pixel(0, 0), Rgba([200, 100, 50, 255]));
+        assert_eq!(image.get_pixel(1, 0), Rgba([100, 50, 25, 128]));
+
+        image.unpremultiply_alpha();
+        assert_eq!(image.get_pixel(1, 0), Rgba([199, 100, 50, 128]));
+        assert_eq!(image.get_pixel(2, 0), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn palette_expansion() {
+        let palette = [Rgb([255u8, 0, 0]), Rgb([0, 0, 255])];
+        let indices: Image<Luma8> = Image::from_fn(2, 2, |x, y| Luma([((x + y) % 2) as u8]));
+        let expanded = expand_palette(&indices, &palette).unwrap();
+        assert_eq!(expanded.get_pixel(1, 0), Rgb([0, 0, 255]));
+        assert_eq!(expanded.get_pixel(1, 1), Rgb([255, 0, 0]));
+
+        let out_of_range: Image<Luma8> = Image::from_pixel(1, 1, Luma([2]));
+        assert!(expand_palette(&out_of_range, &palette).is_none());
+    }
+
+    #[test]
+    fn image_convert() {
+        let image: Image<Rgb8> =
+            Image::from_fn(4, 2, |x, y| Rgb([x as u8 * 60, y as u8 * 200, 30]));
+        let round_trip: Image<Rgb8> = image.convert::<Rgba16>().convert();
+        assert_eq!(round_trip, image);
+
+        let dynamic = DynamicImage::Rgb8(image.clone());
+        assert_eq!(dynamic.convert::<Rgb8>(), image);
+    }
+}
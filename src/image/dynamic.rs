@@ -0,0 +1,83 @@
+//! An image whose pixel type is only known at runtime, as returned by
+//! decoders before the caller has picked a pixel type to work in.
+use crate::image::buffer::Image;
+use crate::image::pixel::{Luma16, Luma8, LumaA16, LumaA8, Pixel, Rgb16, Rgb8, Rgba16, Rgba8};
+use crate::png::pixels::{PixelBuffer, PixelFormat};
+
+/// Enum holding an Image of any of the integer pixel types decoders
+/// produce.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::image::dynamic::DynamicImage;
+/// # use chameleon::png::decoder::Png;
+/// let png = Png::from_path("image.png")?;
+///
+/// match DynamicImage::from(png.decode()?) {
+///     DynamicImage::Rgba8(image) => println!("{:?}", image.get_pixel(0, 0)),
+///     other => println!("Decoded as {:?}", other.dimensions()),
+/// }
+/// # Ok::<(), chameleon::png::decoder::DecoderError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicImage {
+    Luma8(Image<Luma8>),
+    LumaA8(Image<LumaA8>),
+    Rgb8(Image<Rgb8>),
+    Rgba8(Image<Rgba8>),
+    Luma16(Image<Luma16>),
+    LumaA16(Image<LumaA16>),
+    Rgb16(Image<Rgb16>),
+    Rgba16(Image<Rgba16>),
+}
+
+impl DynamicImage {
+    /// The width and height of the image.
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            DynamicImage::Luma8(image) => image.dimensions(),
+            DynamicImage::LumaA8(image) => image.dimensions(),
+            DynamicImage::Rgb8(image) => image.dimensions(),
+            DynamicImage::Rgba8(image) => image.dimensions(),
+            DynamicImage::Luma16(image) => image.dimensions(),
+            DynamicImage::LumaA16(image) => image.dimensions(),
+            DynamicImage::Rgb16(image) => image.dimensions(),
+            DynamicImage::Rgba16(image) => image.dimensions(),
+        }
+    }
+}
+
+/// Builds an image from a buffer of 8 bit samples.
+fn from_bytes<P: Pixel<Subpixel = u8>>(buffer: &PixelBuffer) -> Image<P> {
+    Image::from_samples(buffer.width, buffer.height, &buffer.data)
+        .expect("PixelBuffer data does not match its dimensions.")
+}
+
+/// Builds an image from a buffer of big-endian 16 bit samples.
+fn from_words<P: Pixel<Subpixel = u16>>(buffer: &PixelBuffer) -> Image<P> {
+    let samples: Vec<u16> = buffer
+        .data
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    Image::from_samples(buffer.width, buffer.height, &samples)
+        .expect("PixelBuffer data does not match its dimensions.")
+}
+
+// Allows for conversion from a decoded PixelBuffer to a DynamicImage,
+// panics if the length of the data does not match the dimensions.
+impl From<PixelBuffer> for DynamicImage {
+    fn from(buffer: PixelBuffer) -> Self {
+        match buffer.format {
+            PixelFormat::Luma8 => DynamicImage::Luma8(from_bytes(&buffer)),
+            PixelFormat::LumaA8 => DynamicImage::LumaA8(from_bytes(&buffer)),
+            PixelFormat::Rgb8 => DynamicImage::Rgb8(from_bytes(&buffer)),
+            PixelFormat::Rgba8 => DynamicImage::Rgba8(from_bytes(&buffer)),
+            PixelFormat::Luma16 => DynamicImage::Luma16(from_words(&buffer)),
+            PixelFormat::LumaA16 => DynamicImage::LumaA16(from_words(&buffer)),
+            PixelFormat::Rgb16 => DynamicImage::Rgb16(from_words(&buffer)),
+            PixelFormat::Rgba16 => DynamicImage::Rgba16(from_words(&buffer)),
+        }
+    }
+}
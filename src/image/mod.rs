@@ -0,0 +1,4 @@
+pub mod buffer;
+pub mod convert;
+pub mod dynamic;
+pub mod pixel;
@@ -0,0 +1,161 @@
+//! Pixel types which can be stored in an Image, along with the sample
+//! types they can be made up of.
+//!
+//! Every pixel is a fixed size array of samples, so for example an
+//! 8 bit RGB pixel is stored as:
+//!
+//! ```text
+//! Rgb8([RED, GREEN, BLUE])
+//! ```
+use std::fmt::Debug;
+
+/// A type which can be used as a single sample of a pixel.
+///
+/// # Constants
+///
+/// * 'MIN' - The value representing no intensity.
+/// * 'MAX' - The value representing full intensity, for integers the largest
+///   value of the type and for floats 1.0.
+///
+/// # Methods
+///
+/// * 'to_normalized' - Maps the sample onto 0.0 through 1.0.
+/// * 'from_normalized' - Maps a value from 0.0 through 1.0 onto the nearest
+///   sample, clamping integers to their range.
+pub trait Primitive: Copy + Clone + Debug + Default + PartialEq + PartialOrd + 'static {
+    const MIN: Self;
+    const MAX: Self;
+    fn to_normalized(self) -> f32;
+    fn from_normalized(value: f32) -> Self;
+}
+
+impl Primitive for u8 {
+    const MIN: Self = 0;
+    const MAX: Self = u8::MAX;
+
+    fn to_normalized(self) -> f32 {
+        self as f32 / 255.0
+    }
+
+    fn from_normalized(value: f32) -> Self {
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+}
+
+impl Primitive for u16 {
+    const MIN: Self = 0;
+    const MAX: Self = u16::MAX;
+
+    fn to_normalized(self) -> f32 {
+        self as f32 / 65535.0
+    }
+
+    fn from_normalized(value: f32) -> Self {
+        (value.clamp(0.0, 1.0) * 65535.0).round() as u16
+    }
+}
+
+impl Primitive for f32 {
+    const MIN: Self = 0.0;
+    const MAX: Self = 1.0;
+
+    // Floats are left unclamped so values outside the range survive
+    // conversions between float pixel types.
+    fn to_normalized(self) -> f32 {
+        self
+    }
+
+    fn from_normalized(value: f32) -> Self {
+        value
+    }
+}
+
+/// A trait for types which can be stored in an Image.
+///
+/// # Types
+///
+/// * 'Subpixel' - The type of each sample.
+///
+/// # Constants
+///
+/// * 'CHANNELS' - The number of samples making up each pixel.
+/// * 'HAS_ALPHA' - Whether the last sample is an alpha channel.
+///
+/// # Methods
+///
+/// * 'channels' - Returns the samples of the pixel.
+/// * 'channels_mut' - Returns the samples of the pixel mutably.
+/// * 'from_channels' - Builds a pixel from a slice of exactly CHANNELS samples.
+pub trait Pixel: Copy + Clone + Debug + Default + PartialEq + 'static {
+    type Subpixel: Primitive;
+    const CHANNELS: usize;
+    const HAS_ALPHA: bool;
+    fn channels(&self) -> &[Self::Subpixel];
+    fn channels_mut(&mut self) -> &mut [Self::Subpixel];
+    fn from_channels(channels: &[Self::Subpixel]) -> Self;
+}
+
+/// Grayscale pixel with a single luminance sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(transparent)]
+pub struct Luma<T: Primitive>(pub [T; 1]);
+
+/// Grayscale pixel with luminance and alpha samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(transparent)]
+pub struct LumaA<T: Primitive>(pub [T; 2]);
+
+/// Color pixel with red, green and blue samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(transparent)]
+pub struct Rgb<T: Primitive>(pub [T; 3]);
+
+/// Color pixel with red, green, blue and alpha samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(transparent)]
+pub struct Rgba<T: Primitive>(pub [T; 4]);
+
+// Implements Pixel for each of the array backed pixel types.
+macro_rules! impl_pixel {
+    ($name:ident, $channels:expr, $alpha:expr) => {
+        impl<T: Primitive> Pixel for $name<T> {
+            type Subpixel = T;
+            const CHANNELS: usize = $channels;
+            const HAS_ALPHA: bool = $alpha;
+
+            fn channels(&self) -> &[T] {
+                &self.0
+            }
+
+            fn channels_mut(&mut self) -> &mut [T] {
+                &mut self.0
+            }
+
+            fn from_channels(channels: &[T]) -> Self {
+                let mut pixel = Self::default();
+                pixel.0.copy_from_slice(channels);
+                pixel
+            }
+        }
+    };
+}
+
+impl_pixel!(Luma, 1, false);
+impl_pixel!(LumaA, 2, true);
+impl_pixel!(Rgb, 3, false);
+impl_pixel!(Rgba, 4, true);
+
+pub type Luma8 = Luma<u8>;
+pub type LumaA8 = LumaA<u8>;
+pub type Rgb8 = Rgb<u8>;
+pub type Rgba8 = Rgba<u8>;
+
+pub type Luma16 = Luma<u16>;
+pub type LumaA16 = LumaA<u16>;
+pub type Rgb16 = Rgb<u16>;
+pub type Rgba16 = Rgba<u16>;
+
+pub type Luma32F = Luma<f32>;
+pub type LumaA32F = LumaA<f32>;
+pub type Rgb32F = Rgb<f32>;
+pub type Rgba32F = Rgba<f32>;
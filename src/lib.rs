@@ -1,4 +1,9 @@
 pub mod bits;
 pub mod cli;
 pub mod compression;
+pub mod error;
+pub mod image;
+pub mod limits;
 pub mod png;
+
+pub use error::{Error, ErrorKind, Result};
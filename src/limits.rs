@@ -0,0 +1,386 @@
+//! Resource limits for decoding untrusted input. A few hundred bytes of
+//! PNG can declare an image of 65535x65535 pixels, and a DEFLATE stream
+//! can expand over a thousand times, so every size taken from the input
+//! is checked against these limits before anything is allocated.
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// The most a decoder is allowed to allocate.
+///
+/// # Attributes
+///
+/// * 'max_width' - The widest image accepted, in pixels.
+/// * 'max_height' - The tallest image accepted, in pixels.
+/// * 'max_pixels' - The most pixels an image may have in total. As
+///   StreamDecoder holds only a few rows, it only checks this
+///   for interlaced images, which it holds whole.
+/// * 'max_alloc' - The most bytes held at once while decoding, covering
+///   the input, the decompressed data and the decoded pixels.
+/// * 'max_text' - The largest a single compressed text chunk may grow to
+///   when decompressed.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::limits::Limits;
+/// # use chameleon::png::decoder::Png;
+/// # let upload = Vec::new();
+/// let limits = Limits {
+///     max_pixels: 40_000_000,
+///     max_alloc: 256 * 1024 * 1024,
+///     ..Limits::default()
+/// };
+/// let png = Png::from_bytes_with_limits(upload, &limits)?;
+/// # Ok::<(), chameleon::png::decoder::DecoderError>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64,
+    pub max_alloc: usize,
+    pub max_text: usize,
+}
+
+impl Limits {
+    /// Limits which allow anything, leaving only the format itself and
+    /// available memory to stop a decoder.
+    pub fn none() -> Self {
+        Self {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: u64::MAX,
+            max_alloc: usize::MAX,
+            max_text: usize::MAX,
+        }
+    }
+
+    /// Checks the dimensions of an image against max_width, max_height
+    /// and max_pixels.
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<(), LimitError> {
+        if width > self.max_width {
+            return Err(LimitError::Width {
+                width,
+                max: self.max_width,
+            });
+        }
+        if height > self.max_height {
+            return Err(LimitError::Height {
+                height,
+                max: self.max_height,
+            });
+        }
+        let pixels = width as u64 * height as u64;
+        if pixels > self.max_pixels {
+            return Err(LimitError::Pixels {
+                pixels,
+                max: self.max_pixels,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks a number of bytes against max_alloc, None standing for a
+    /// size too large to be represented at all.
+    pub fn check_alloc(&self, bytes: Option<usize>) -> Result<(), LimitError> {
+        match bytes {
+            Some(bytes) if bytes <= self.max_alloc => Ok(()),
+            _ => Err(LimitError::Allocation {
+                bytes,
+                max: self.max_alloc,
+            }),
+        }
+    }
+}
+
+// Defaults which fit images of up to 16384x16384 RGBA8 pixels.
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_width: 1 << 24,
+            max_height: 1 << 24,
+            max_pixels: 1 << 28,
+            max_alloc: 3 << 30,
+            max_text: 1 << 23,
+        }
+    }
+}
+
+//       +--------+
+//       | ERRORS |
+//       +--------+
+
+/// Enum containing the limits which can be exceeded.
+///
+/// # Fields
+///
+/// * 'Width' - The image is wider than max_width.
+/// * 'Height' - The image is taller than max_height.
+/// * 'Pixels' - The image has more than max_pixels pixels.
+/// * 'Allocation' - Decoding would need more than max_alloc bytes, the
+///   size is None when it overflows.
+/// * 'Output' - Decompressed data grew past the given number of bytes.
+/// * 'Text' - A text chunk decompressed to more than max_text bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    Width { width: u32, max: u32 },
+    Height { height: u32, max: u32 },
+    Pixels { pixels: u64, max: u64 },
+    Allocation { bytes: Option<usize>, max: usize },
+    Output { max: usize },
+    Text { max: usize },
+}
+
+// Defines how LimitErrors are displayed.
+impl Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::Width { width, max } => {
+                write!(f, "Error: Width '{width}' exceeds the limit of '{max}'.")
+            }
+            LimitError::Height { height, max } => {
+                write!(f, "Error: Height '{height}' exceeds the limit of '{max}'.")
+            }
+            LimitError::Pixels { pixels, max } => {
+                write!(f, "Error: '{pixels}' pixels exceeds the limit of '{max}'.")
+            }
+            LimitError::Allocation {
+                bytes: Some(bytes),
+                max,
+            } => {
+                write!(
+                    f,
+                    "Error: Allocating '{bytes}' bytes exceeds the limit of '{max}'."
+                )
+            }
+            LimitError::Allocation { bytes: None, max } => {
+                write!(
+                    f,
+                    "Error: Allocation overflows, the limit is '{max}' bytes."
+                )
+            }
+            LimitError::Output { max } => {
+                write!(
+                    f,
+                    "Error: Decompressed data exceeds the limit of '{max}' bytes."
+                )
+            }
+            LimitError::Text { max } => {
+                write!(
+                    f,
+                    "Error: Decompressed text exceeds the limit of '{max}' bytes."
+                )
+            }
+        }
+    }
+}
+
+// Implements the Error interface for LimitError.
+impl Error for LimitError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::zlib;
+    use crate::image::buffer::Image;
+    use crate::image::pixel::{Luma, Luma8};
+    use crate::png::decoder::{DecoderError, Png, PNG_HEADER};
+    use crate::png::encoder::{self, EncoderOptions};
+    use crate::png::ihdr::{ColorType, Ihdr};
+    use crate::png::text::{TextChunk, TextFormat};
+
+    /// A PNG with the given header and image data, whatever they hold.
+    fn png(header: &Ihdr, idat: &[u8]) -> Vec<u8> {
+        let mut bytes = PNG_HEADER.to_vec();
+        encoder::write_chunk(&mut bytes, b"IHDR", &header.to_bytes());
+        encoder::write_chunk(&mut bytes, b"IDAT", idat);
+        encoder::write_chunk(&mut bytes, b"IEND", &[]);
+        bytes
+    }
+
+    fn gray(width: u32, height: u32) -> Ihdr {
+        Ihdr {
+            width,
+            height,
+            bit_depth: 8,
+            color_type: ColorType::Grayscale,
+            interlaced: false,
+        }
+    }
+
+    #[test]
+    fn checks() {
+        let limits = Limits {
+            max_width: 100,
+            max_height: 50,
+            max_pixels: 1000,
+            max_alloc: 4096,
+            ..Limits::default()
+        };
+        assert_eq!(limits.check_dimensions(100, 10), Ok(()));
+        assert_eq!(
+            limits.check_dimensions(101, 1),
+            Err(LimitError::Width {
+                width: 101,
+                max: 100
+            })
+        );
+        assert_eq!(
+            limits.check_dimensions(1, 51),
+            Err(LimitError::Height {
+                height: 51,
+                max: 50
+            })
+        );
+        assert_eq!(
+            limits.check_dimensions(50, 50),
+            Err(LimitError::Pixels {
+                pixels: 2500,
+                max: 1000
+            })
+        );
+
+        assert_eq!(limits.check_alloc(Some(4096)), Ok(()));
+        assert!(limits.check_alloc(Some(4097)).is_err());
+        assert!(limits.check_alloc(None).is_err());
+        assert_eq!(Limits::none().check_alloc(Some(usize::MAX)), Ok(()));
+    }
+
+    #[test]
+    fn oversized_images_are_rejected_before_decoding() {
+        // 65535x65535 pixels declared in a handful of bytes.
+        let bytes = png(&gray(65535, 65535), &zlib::compress(&[0; 16], 6));
+        assert!(matches!(
+            Png::from_bytes(bytes.clone()),
+            Err(DecoderError::LimitError(LimitError::Pixels { .. }))
+        ));
+
+        // Allowed through parsing, the decoded size is then too large.
+        let limits = Limits {
+            max_alloc: 1 << 20,
+            ..Limits::none()
+        };
+        let png = Png::from_bytes_with_limits(bytes.clone(), &limits).unwrap();
+        assert!(matches!(
+            png.decode(),
+            Err(DecoderError::LimitError(LimitError::Allocation { .. }))
+        ));
+
+        // The input itself counts against the allocation limit.
+        let tiny = Limits {
+            max_alloc: bytes.len() - 1,
+            ..Limits::none()
+        };
+        assert!(matches!(
+            Png::from_bytes_with_limits(bytes, &tiny),
+            Err(DecoderError::LimitError(LimitError::Allocation { .. }))
+        ));
+    }
+
+    #[test]
+    fn inflate_bombs_stop_at_the_limit() {
+        // Eight megabytes of zeroes behind a 2x2 header.
+        let bomb = zlib::compress(&vec![0; 8 << 20], 9);
+        assert!(bomb.len() < 16 << 10);
+        let limits = Limits {
+            max_alloc: 1 << 20,
+            ..Limits::default()
+        };
+        let png = Png::from_bytes_with_limits(png(&gray(2, 2), &bomb), &limits).unwrap();
+        assert!(matches!(
+            png.decode(),
+            Err(DecoderError::LimitError(LimitError::Output { .. }))
+        ));
+
+        // Within the limit, trailing data past the image is ignored.
+        let image: Image<Luma8> = Image::from_fn(2, 2, |x, y| Luma([(x + y) as u8]));
+        let bytes = encoder::encode_image(&image, &EncoderOptions::default()).unwrap();
+        assert!(Png::from_bytes_with_limits(bytes, &limits)
+            .unwrap()
+            .decode()
+            .is_ok());
+    }
+
+    #[test]
+    fn held_bytes_count_towards_the_allocation() {
+        // 100x100 pixels take 20100 bytes to decode, behind 30000 bytes of
+        // an ancillary chunk.
+        let filtered = vec![0; 101 * 100];
+        let compressed = zlib::compress(&filtered, 9);
+        let mut bytes = PNG_HEADER.to_vec();
+        encoder::write_chunk(&mut bytes, b"IHDR", &gray(100, 100).to_bytes());
+        encoder::write_chunk(&mut bytes, b"prVt", &[7; 30_000]);
+        encoder::write_chunk(&mut bytes, b"IDAT", &compressed);
+        encoder::write_chunk(&mut bytes, b"IEND", &[]);
+
+        let limits = Limits {
+            max_alloc: 40_000,
+            ..Limits::default()
+        };
+        let png = Png::from_bytes_with_limits(bytes.clone(), &limits).unwrap();
+        assert!(matches!(
+            png.decode(),
+            Err(DecoderError::LimitError(LimitError::Allocation {
+                bytes: Some(50_100..),
+                ..
+            }))
+        ));
+        let roomy = Limits {
+            max_alloc: 60_000,
+            ..Limits::default()
+        };
+        let png = Png::from_bytes_with_limits(bytes, &roomy).unwrap();
+        assert_eq!(png.scanlines().unwrap(), vec![0; 100 * 100]);
+
+        // IDAT split into several chunks is joined into a copy, which
+        // counts as well.
+        let mut split = PNG_HEADER.to_vec();
+        encoder::write_chunk(&mut split, b"IHDR", &gray(100, 100).to_bytes());
+        for piece in compressed.chunks(10) {
+            encoder::write_chunk(&mut split, b"IDAT", piece);
+        }
+        encoder::write_chunk(&mut split, b"IEND", &[]);
+        let png = Png::from_bytes(split.clone()).unwrap();
+        assert_eq!(png.data.idat.len(), compressed.len().div_ceil(10));
+        let (idat, held) = png.data.image_data(&Limits::default()).unwrap();
+        assert_eq!(
+            (&idat[..], held),
+            (&compressed[..], split.len() + compressed.len())
+        );
+        assert_eq!(png.scanlines().unwrap(), vec![0; 100 * 100]);
+
+        let tight = Limits {
+            max_alloc: split.len() + compressed.len() - 1,
+            ..Limits::none()
+        };
+        assert!(png.data.image_data(&tight).is_err());
+    }
+
+    #[test]
+    fn oversized_text_is_rejected() {
+        let image: Image<Luma8> = Image::from_pixel(1, 1, Luma([0]));
+        let note = TextChunk {
+            keyword: "Comment".to_string(),
+            text: "a".repeat(100_000),
+            format: TextFormat::Compressed,
+        };
+        let options = EncoderOptions {
+            text: vec![note],
+            ..Default::default()
+        };
+        let bytes = encoder::encode_image(&image, &options).unwrap();
+
+        let limits = Limits {
+            max_text: 1000,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            Png::from_bytes_with_limits(bytes.clone(), &limits),
+            Err(DecoderError::LimitError(LimitError::Text { max: 1000 }))
+        ));
+        let png = Png::from_bytes(bytes).unwrap();
+        assert_eq!(png.text[0].text.len(), 100_000);
+    }
+}
@@ -1,18 +1,63 @@
-use chameleon::bits::bitstream::BitStream;
-use std::error::Error;
-use std::fs;
+use chameleon::cli::{Command, Flags, InputArguments};
+use chameleon::png::decoder::Png;
+use chameleon::png::encoder::{self, EncoderOptions};
+use chameleon::png::optimize::{self, OptimizeOptions};
+use std::{fs, process};
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut bitstre = BitStream::new();
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
+
+/// Runs the command given on the command line.
+fn run() -> chameleon::Result<()> {
+    let args = InputArguments::build()?;
+
+    let png = Png::from_path(&args.input_path)?;
 
-    bitstre.len = 11;
-    bitstre.bytes = vec![255u8, 0b1110_0000];
+    let bytes = match args.command {
+        // Decode the input down to its scanlines and write them back out
+        // unchanged, keeping the color type, bit depth, palette, text,
+        // colorimetry and other metadata.
+        Command::Convert => {
+            let scanlines = png.scanlines()?;
+            encoder::encode_scanlines(
+                &png.header,
+                png.palette.as_deref(),
+                png.transparency.as_ref(),
+                &scanlines,
+                &EncoderOptions {
+                    text: png.text.clone(),
+                    colorimetry: png.colorimetry.clone(),
+                    metadata: png.metadata.clone(),
+                    ..Default::default()
+                },
+            )?
+        }
+        Command::Optimize => {
+            let mut options = OptimizeOptions::default();
+            for flag in &args.flags {
+                match flag {
+                    Flags::Strip(mode) => options.strip = *mode,
+                }
+            }
 
-    println!("{}", bitstre);
+            let bytes = optimize::optimize(&png, &options)?;
+            let before = png.data.raw_data.len();
+            println!(
+                "{}: {before} -> {} bytes ({:.1}%)",
+                args.input_path.display(),
+                bytes.len(),
+                bytes.len() as f64 * 100.0 / before as f64
+            );
+            bytes
+        }
+    };
 
-    for _ in 0..10 {
-        bitstre.push(1);
+    if let Some(output_path) = &args.output_path {
+        fs::write(output_path, bytes)?;
     }
-    println!("{}", bitstre);
     Ok(())
 }
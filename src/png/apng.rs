@@ -0,0 +1,1077 @@
+//! Animated PNG, which adds three chunks to a normal PNG file:
+//!
+//! ```text
+//! acTL    The number of frames and how many times to play them.
+//! fcTL    The size, position, delay and disposal of the next frame.
+//! fdAT    The image data of a frame, a sequence number followed by
+//!         data laid out like IDAT.
+//! ```
+//!
+//! fcTL and fdAT share a sequence number which starts at 0 and counts up
+//! by one, so reordered chunks can be detected. When an fcTL comes before
+//! IDAT the default image is the first frame, otherwise it is only shown
+//! by decoders which do not understand animation:
+//!
+//! ```text
+//! IHDR  acTL  [fcTL]  IDAT...  (fcTL  fdAT...)...  IEND
+//! ```
+//!
+//! This module decodes animations into composited frames, and encodes
+//! frames into animations which only store what changed between them.
+use crate::image::buffer::Image;
+use crate::image::pixel::{Rgba, Rgba8};
+use crate::png::chunk::{read_u32s, Chunks};
+use crate::png::decoder::{self, DecoderError, Png, PngData};
+use crate::png::encoder::{self, EncoderError, EncoderOptions};
+use crate::png::ihdr::{ColorType, Ihdr};
+use crate::png::pixels::{self, OutputFormat};
+use std::time::Duration;
+
+/// The acTL chunk.
+///
+/// # Attributes
+///
+/// * 'num_frames' - The number of frames, including the default image if
+///   it is one of them.
+/// * 'num_plays' - How many times to loop the animation, 0 for forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    pub num_plays: u32,
+}
+
+impl AnimationControl {
+    /// Parses the data of an acTL chunk.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecoderError> {
+        let v = read_u32s::<2>(bytes, "acTL", error)?;
+        if v[0] == 0 {
+            return Err(error("acTL: Animation has no frames".to_string()));
+        }
+        Ok(Self {
+            num_frames: v[0],
+            num_plays: v[1],
+        })
+    }
+
+    /// Serializes the control into the data of an acTL chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.num_frames.to_be_bytes().to_vec();
+        bytes.extend(self.num_plays.to_be_bytes());
+        bytes
+    }
+}
+
+/// What happens to the area of a frame once it has been shown.
+///
+/// # Fields
+///
+/// * 'None' - The frame is left on the canvas.
+/// * 'Background' - The area is cleared to transparent black.
+/// * 'Previous' - The area is restored to what it was before the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp {
+    None = 0,
+    Background = 1,
+    Previous = 2,
+}
+
+/// How a frame is drawn onto the canvas.
+///
+/// # Fields
+///
+/// * 'Source' - The frame replaces the area, alpha included.
+/// * 'Over' - The frame is alpha composited over the area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp {
+    Source = 0,
+    Over = 1,
+}
+
+/// The fcTL chunk.
+///
+/// # Attributes
+///
+/// * 'sequence_number' - The position of the chunk in the sequence.
+/// * 'width', 'height' - The size of the frame, which has to fit inside
+///   the image when placed at its offset.
+/// * 'x_offset', 'y_offset' - Where the frame is placed on the canvas.
+/// * 'delay_num', 'delay_den' - How long to show the frame in seconds,
+///   as a fraction. A denominator of 0 means 100.
+/// * 'dispose_op' - What to do with the area after showing the frame.
+/// * 'blend_op' - How to draw the frame onto the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+impl FrameControl {
+    /// Parses the data of an fcTL chunk, checking the frame fits inside
+    /// the image.
+    pub fn parse(bytes: &[u8], header: &Ihdr) -> Result<Self, DecoderError> {
+        if bytes.len() != 26 {
+            return Err(error(format!("fcTL: Length {} is not 26", bytes.len())));
+        }
+        let v = read_u32s::<5>(&bytes[..20], "fcTL", error)?;
+        let control = Self {
+            sequence_number: v[0],
+            width: v[1],
+            height: v[2],
+            x_offset: v[3],
+            y_offset: v[4],
+            delay_num: u16::from_be_bytes([bytes[20], bytes[21]]),
+            delay_den: u16::from_be_bytes([bytes[22], bytes[23]]),
+            dispose_op: match bytes[24] {
+                0 => DisposeOp::None,
+                1 => DisposeOp::Background,
+                2 => DisposeOp::Previous,
+                op => return Err(error(format!("fcTL: Unknown dispose op {op}"))),
+            },
+            blend_op: match bytes[25] {
+                0 => BlendOp::Source,
+                1 => BlendOp::Over,
+                op => return Err(error(format!("fcTL: Unknown blend op {op}"))),
+            },
+        };
+
+        let fits = |offset: u32, size: u32, limit: u32| {
+            size > 0 && offset.checked_add(size).is_some_and(|end| end <= limit)
+        };
+        if !fits(control.x_offset, control.width, header.width)
+            || !fits(control.y_offset, control.height, header.height)
+        {
+            return Err(error(format!(
+                "fcTL: {}x{} frame at ({}, {}) does not fit the {}x{} image",
+                control.width,
+                control.height,
+                control.x_offset,
+                control.y_offset,
+                header.width,
+                header.height
+            )));
+        }
+        Ok(control)
+    }
+
+    /// Serializes the control into the data of an fcTL chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(26);
+        for value in [
+            self.sequence_number,
+            self.width,
+            self.height,
+            self.x_offset,
+            self.y_offset,
+        ] {
+            bytes.extend(value.to_be_bytes());
+        }
+        bytes.extend(self.delay_num.to_be_bytes());
+        bytes.extend(self.delay_den.to_be_bytes());
+        bytes.extend([self.dispose_op as u8, self.blend_op as u8]);
+        bytes
+    }
+
+    /// How long the frame is shown for.
+    pub fn delay(&self) -> Duration {
+        let den = match self.delay_den {
+            0 => 100,
+            den => den,
+        };
+        Duration::from_secs_f64(self.delay_num as f64 / den as f64)
+    }
+
+    /// The IHDR describing the image data of the frame, which shares the
+    /// layout of the default image but not its size.
+    pub fn header(&self, header: &Ihdr) -> Ihdr {
+        Ihdr {
+            width: self.width,
+            height: self.height,
+            ..*header
+        }
+    }
+}
+
+/// A frame as stored in the file.
+///
+/// # Attributes
+///
+/// * 'control' - The fcTL chunk of the frame.
+/// * 'data' - The data of its IDAT or fdAT chunks concatenated, without
+///   the sequence numbers, making up a single zlib stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnimationFrame {
+    pub control: FrameControl,
+    pub data: Vec<u8>,
+}
+
+/// The animation of an APNG file.
+///
+/// # Attributes
+///
+/// * 'control' - The acTL chunk.
+/// * 'default_image_is_frame' - Whether the first frame is the default
+///   image, stored in IDAT.
+/// * 'frames' - Every frame in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Animation {
+    pub control: AnimationControl,
+    pub default_image_is_frame: bool,
+    pub frames: Vec<AnimationFrame>,
+}
+
+impl Animation {
+    /// Collects the frames of an APNG file, checking sequence numbers and
+    /// frame counts.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the Animation, None for images without
+    /// a valid acTL before IDAT, or a DecoderError::AnimationError.
+    pub fn parse(data: &PngData, header: &Ihdr) -> Result<Option<Self>, DecoderError> {
+        let chunk = |(_, start, end): &([u8; 4], usize, usize)| &data.raw_data[*start..*end];
+
+        // An acTL after the image data means the file is not animated.
+        let control = match data
+            .chunks
+            .iter()
+            .take_while(|(kind, _, _)| kind != b"IDAT")
+            .find(|(kind, _, _)| kind == b"acTL")
+        {
+            Some(actl) => AnimationControl::parse(chunk(actl))?,
+            None => return Ok(None),
+        };
+
+        let mut frames: Vec<AnimationFrame> = Vec::new();
+        let mut default_image_is_frame = false;
+        let mut sequence_number = 0;
+        let mut seen_idat = false;
+        let mut check_sequence = |number: u32| {
+            if number != sequence_number {
+                return Err(error(format!(
+                    "Sequence number {number} where {sequence_number} was expected"
+                )));
+            }
+            sequence_number += 1;
+            Ok(())
+        };
+
+        for entry in &data.chunks {
+            let bytes = chunk(entry);
+            match &entry.0 {
+                b"fcTL" => {
+                    let frame_control = FrameControl::parse(bytes, header)?;
+                    check_sequence(frame_control.sequence_number)?;
+                    if !seen_idat {
+                        if !frames.is_empty() {
+                            return Err(error("Two fcTL chunks precede IDAT".to_string()));
+                        }
+                        if frame_control.header(header) != *header
+                            || frame_control.x_offset != 0
+                            || frame_control.y_offset != 0
+                        {
+                            return Err(error(
+                                "The default image frame does not cover the image".to_string(),
+                            ));
+                        }
+                        default_image_is_frame = true;
+                    }
+                    frames.push(AnimationFrame {
+                        control: frame_control,
+                        data: Vec::new(),
+                    });
+                }
+                b"IDAT" => {
+                    seen_idat = true;
+                    if default_image_is_frame {
+                        frames[0].data.extend_from_slice(bytes);
+                    }
+                }
+                b"fdAT" => {
+                    if bytes.len() < 4 {
+                        return Err(error("fdAT: Missing sequence number".to_string()));
+                    }
+                    check_sequence(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))?;
+                    // The default image frame is only ever stored in IDAT.
+                    let open = seen_idat && !(default_image_is_frame && frames.len() == 1);
+                    match frames.last_mut() {
+                        Some(frame) if open => frame.data.extend_from_slice(&bytes[4..]),
+                        _ => return Err(error("fdAT without a preceding fcTL".to_string())),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if frames.len() != control.num_frames as usize {
+            return Err(error(format!(
+                "acTL declares {} frames but {} were found",
+                control.num_frames,
+                frames.len()
+            )));
+        }
+        if let Some(frame) = frames.iter().find(|frame| frame.data.is_empty()) {
+            return Err(error(format!(
+                "Frame {} has no image data",
+                frame.control.sequence_number
+            )));
+        }
+
+        Ok(Some(Self {
+            control,
+            default_image_is_frame,
+            frames,
+        }))
+    }
+}
+
+//      +-------------+
+//      | COMPOSITION |
+//      +-------------+
+
+/// A fully composited frame, ready to be shown.
+///
+/// # Attributes
+///
+/// * 'image' - The whole canvas after drawing the frame.
+/// * 'delay' - How long to show it for.
+/// * 'control' - The fcTL chunk of the frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub image: Image<Rgba8>,
+    pub delay: Duration,
+    pub control: FrameControl,
+}
+
+/// An iterator over the composited frames of an image, created by
+/// Png::frames. Images which are not animated yield their default image
+/// as a single frame with no delay.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::decoder::Png;
+/// # use std::thread;
+/// # fn show<T>(_: &T) {}
+/// let png = Png::from_path("spinner.png")?;
+///
+/// for frame in png.frames() {
+///     let frame = frame?;
+///     show(&frame.image);
+///     thread::sleep(frame.delay);
+/// }
+/// # Ok::<(), chameleon::png::decoder::DecoderError>(())
+/// ```
+pub struct Frames<'a> {
+    png: &'a Png,
+    index: usize,
+    canvas: Image<Rgba8>,
+}
+
+impl<'a> Frames<'a> {
+    /// Creates an iterator starting from a transparent black canvas,
+    /// which is only allocated once the first frame is composited.
+    pub fn new(png: &'a Png) -> Self {
+        Self {
+            png,
+            index: 0,
+            canvas: Image::new(0, 0),
+        }
+    }
+
+    /// Decodes the image data of a frame on its own.
+    fn decode_frame(&self, frame: &AnimationFrame) -> Result<Image<Rgba8>, DecoderError> {
+        let header = frame.control.header(&self.png.header);
+        // The data of every frame is held along with the file.
+        let frames = self
+            .png
+            .animation
+            .iter()
+            .flat_map(|animation| &animation.frames);
+        let held =
+            self.png.data.raw_data.len() + frames.map(|frame| frame.data.len()).sum::<usize>();
+        let filtered = decoder::decompress_image(&header, &frame.data, held, &self.png.limits)?;
+        let scanlines = decoder::reconstruct(&header, &filtered, |_, _| {})?;
+        let pixels = pixels::expand(
+            &header,
+            self.png.palette.as_deref(),
+            self.png.transparency.as_ref(),
+            &scanlines,
+            OutputFormat::Rgba8,
+        )?;
+        Image::from_samples(header.width, header.height, &pixels.data)
+            .ok_or_else(|| error("Frame data does not match its size".to_string()))
+    }
+
+    /// Draws the next frame onto the canvas and returns a copy of it,
+    /// then disposes of the frame's area.
+    fn compose(&mut self, frame: &AnimationFrame) -> Result<Frame, DecoderError> {
+        if self.index == 0 {
+            // The canvas, the copy of it returned and the frame drawn.
+            let (width, height) = (self.png.header.width, self.png.header.height);
+            let canvas = (width as usize * height as usize).checked_mul(4 * 3);
+            self.png.limits.check_alloc(canvas)?;
+            self.canvas = Image::new(width, height);
+        }
+        let control = frame.control;
+        let image = self.decode_frame(frame)?;
+        let (x, y) = (control.x_offset, control.y_offset);
+
+        let previous = match control.dispose_op {
+            DisposeOp::Previous => Some(
+                self.canvas
+                    .view(x, y, control.width, control.height)
+                    .to_image(),
+            ),
+            _ => None,
+        };
+
+        let mut area = self.canvas.view_mut(x, y, control.width, control.height);
+        for (px, py, &pixel) in image.enumerate_pixels() {
+            let blended = match control.blend_op {
+                BlendOp::Source => pixel,
+                BlendOp::Over => blend_over(pixel, area.get_pixel(px, py)),
+            };
+            area.put_pixel(px, py, blended);
+        }
+        let output = Frame {
+            image: self.canvas.clone(),
+            delay: control.delay(),
+            control,
+        };
+
+        // Restoring the first frame to 'previous' clears it, as there is
+        // nothing before it.
+        let mut area = self.canvas.view_mut(x, y, control.width, control.height);
+        match (control.dispose_op, previous) {
+            (DisposeOp::None, _) => {}
+            (DisposeOp::Previous, Some(previous)) if self.index > 0 => {
+                for (px, py, &pixel) in previous.enumerate_pixels() {
+                    area.put_pixel(px, py, pixel);
+                }
+            }
+            _ => area.fill(Rgba([0; 4])),
+        }
+
+        Ok(output)
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Result<Frame, DecoderError>;
+
+    /// Composites the next frame, stopping after the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = match &self.png.animation {
+            Some(animation) => {
+                let frame = animation.frames.get(self.index)?;
+                self.compose(frame)
+            }
+            None if self.index == 0 => self.png.decode_as(OutputFormat::Rgba8).and_then(|pixels| {
+                let header = &self.png.header;
+                let image = Image::from_samples(header.width, header.height, &pixels.data)
+                    .ok_or_else(|| error("Image data does not match its size".to_string()))?;
+                Ok(Frame {
+                    image,
+                    delay: Duration::ZERO,
+                    control: FrameControl {
+                        sequence_number: 0,
+                        width: header.width,
+                        height: header.height,
+                        x_offset: 0,
+                        y_offset: 0,
+                        delay_num: 0,
+                        delay_den: 0,
+                        dispose_op: DisposeOp::None,
+                        blend_op: BlendOp::Source,
+                    },
+                })
+            }),
+            None => return None,
+        };
+
+        self.index = match result {
+            Ok(_) => self.index + 1,
+            Err(_) => usize::MAX,
+        };
+        Some(result)
+    }
+}
+
+//      +---------+
+//      | ENCODER |
+//      +---------+
+
+/// A frame chosen by encode_animation, before it is written.
+///
+/// # Attributes
+///
+/// * 'region' - The x, y, width and height of the area the frame covers.
+/// * 'delay' - How long to show the frame.
+/// * 'dispose_op', 'blend_op' - As in FrameControl.
+/// * 'data' - The compressed image data of the frame.
+struct PlannedFrame {
+    region: [u32; 4],
+    delay: Duration,
+    dispose_op: DisposeOp,
+    blend_op: BlendOp,
+    data: Vec<u8>,
+}
+
+/// Encodes frames of equal size into an APNG file. The first frame is
+/// also the default image, shown by decoders without APNG support.
+///
+/// Each later frame is cropped to the area which changed, and the dispose
+/// and blend ops which give the smallest data are picked, while making
+/// sure every frame decodes to exactly the pixels given. Consecutive
+/// identical frames are merged, adding their delays together.
+///
+/// # Arguments
+///
+/// * 'frames' - Each frame along with how long to show it. Delays are
+///   stored in milliseconds, or in coarser units when longer
+///   than 65 seconds.
+/// * 'num_plays' - How many times to loop the animation, 0 for forever.
+/// * 'options' - How to filter and compress the frames, along with the
+///   metadata to write. Metadata has to suit an RGBA image.
+///
+/// # Returns
+///
+/// A result containing either the bytes of the APNG file, or an
+/// EncoderError if there are no frames, their sizes differ or the
+/// metadata cannot be written.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::image::buffer::Image;
+/// # use chameleon::image::pixel::{Rgba, Rgba8};
+/// # use chameleon::png::apng::encode_animation;
+/// # use chameleon::png::encoder::EncoderOptions;
+/// # use std::time::Duration;
+/// # let recording: Vec<Image<Rgba8>> = (0..4u8)
+/// #     .map(|i| Image::from_pixel(8, 8, Rgba([i * 60, 0, 0, 255])))
+/// #     .collect();
+/// let frames: Vec<(Image<Rgba8>, Duration)> = recording
+///     .iter()
+///     .map(|capture| (capture.clone(), Duration::from_millis(40)))
+///     .collect();
+///
+/// let bytes = encode_animation(&frames, 0, &EncoderOptions::default())?;
+/// # Ok::<(), chameleon::png::encoder::EncoderError>(())
+/// ```
+pub fn encode_animation(
+    frames: &[(Image<Rgba8>, Duration)],
+    num_plays: u32,
+    options: &EncoderOptions,
+) -> Result<Vec<u8>, EncoderError> {
+    let (first, _) = frames
+        .first()
+        .ok_or_else(|| EncoderError::ImageDataError("Animation has no frames".to_string()))?;
+    let (width, height) = first.dimensions();
+    if let Some((image, _)) = frames
+        .iter()
+        .find(|(image, _)| image.dimensions() != (width, height))
+    {
+        return Err(EncoderError::ImageDataError(format!(
+            "{}x{} frame in a {width}x{height} animation",
+            image.width(),
+            image.height()
+        )));
+    }
+    let header = Ihdr {
+        width,
+        height,
+        bit_depth: 8,
+        color_type: ColorType::Rgba,
+        interlaced: options.interlace,
+    };
+
+    let mut targets: Vec<(Vec<[u8; 4]>, Duration)> = Vec::new();
+    for (image, delay) in frames {
+        let pixels: Vec<[u8; 4]> = image.rows().flatten().map(|pixel| pixel.0).collect();
+        match targets.last_mut() {
+            Some((last, last_delay)) if *last == pixels => *last_delay += *delay,
+            _ => targets.push((pixels, *delay)),
+        }
+    }
+
+    // The default image is written as the first frame by encode_scanlines.
+    let mut planned = vec![PlannedFrame {
+        region: [0, 0, width, height],
+        delay: targets[0].1,
+        dispose_op: DisposeOp::None,
+        blend_op: BlendOp::Source,
+        data: Vec::new(),
+    }];
+    // The canvas just before the last planned frame was drawn.
+    let mut before = vec![[0u8; 4]; targets[0].0.len()];
+
+    for index in 1..targets.len() {
+        let (target, delay) = &targets[index];
+        let (previous, _) = &targets[index - 1];
+
+        // The canvas each dispose op of the last frame leaves behind.
+        let mut cleared = previous.clone();
+        fill_region(&mut cleared, width, planned[index - 1].region, [0; 4]);
+        let mut canvases = vec![
+            (DisposeOp::None, previous.clone()),
+            (DisposeOp::Background, cleared),
+        ];
+        // Disposing the first frame to 'previous' clears it instead.
+        if index > 1 {
+            canvases.push((DisposeOp::Previous, before));
+        }
+
+        let mut best: Option<(usize, PlannedFrame)> = None;
+        for (i, (_, canvas)) in canvases.iter().enumerate() {
+            for frame in delta_frames(canvas, target, &header, options) {
+                if best
+                    .as_ref()
+                    .is_none_or(|(_, best)| frame.data.len() < best.data.len())
+                {
+                    best = Some((i, frame));
+                }
+            }
+        }
+
+        let (i, mut frame) = best.expect("delta_frames always returns a source frame");
+        let (dispose_op, canvas) = canvases.swap_remove(i);
+        before = canvas;
+        planned[index - 1].dispose_op = dispose_op;
+        frame.delay = *delay;
+        planned.push(frame);
+    }
+
+    let scanlines: Vec<u8> = targets[0].0.iter().flatten().copied().collect();
+    let still = encoder::encode_scanlines(&header, None, None, &scanlines, options)?;
+    let first_idat = Chunks::new(&still)
+        .flatten()
+        .find(|chunk| &chunk.kind == b"IDAT")
+        .map(|chunk| chunk.offset - 8)
+        .unwrap_or(still.len() - 12);
+
+    let mut sequence_number = 0;
+    let mut output = still[..first_idat].to_vec();
+    let control = AnimationControl {
+        num_frames: planned.len() as u32,
+        num_plays,
+    };
+    encoder::write_chunk(&mut output, b"acTL", &control.to_bytes());
+    let fctl = frame_control(&planned[0], &mut sequence_number);
+    encoder::write_chunk(&mut output, b"fcTL", &fctl);
+    // Everything from the first IDAT up to, but not including, IEND.
+    output.extend_from_slice(&still[first_idat..still.len() - 12]);
+
+    for frame in &planned[1..] {
+        let fctl = frame_control(frame, &mut sequence_number);
+        encoder::write_chunk(&mut output, b"fcTL", &fctl);
+        for data in frame.data.chunks(options.idat_size.max(1)) {
+            let mut fdat = sequence_number.to_be_bytes().to_vec();
+            fdat.extend_from_slice(data);
+            encoder::write_chunk(&mut output, b"fdAT", &fdat);
+            sequence_number += 1;
+        }
+    }
+    encoder::write_chunk(&mut output, b"IEND", &[]);
+
+    Ok(output)
+}
+
+/// Serializes the fcTL chunk of a planned frame, taking the next
+/// sequence number.
+fn frame_control(frame: &PlannedFrame, sequence_number: &mut u32) -> Vec<u8> {
+    let [x_offset, y_offset, width, height] = frame.region;
+    let (delay_num, delay_den) = delay_fraction(frame.delay);
+    let control = FrameControl {
+        sequence_number: *sequence_number,
+        width,
+        height,
+        x_offset,
+        y_offset,
+        delay_num,
+        delay_den,
+        dispose_op: frame.dispose_op,
+        blend_op: frame.blend_op,
+    };
+    *sequence_number += 1;
+    control.to_bytes()
+}
+
+/// Builds the ways of drawing 'target' over 'canvas': cropped to the
+/// pixels which differ, using the 'source' blend op and, when it gives
+/// the exact same result, the 'over' blend op with unchanged pixels left
+/// transparent so they compress well.
+fn delta_frames(
+    canvas: &[[u8; 4]],
+    target: &[[u8; 4]],
+    header: &Ihdr,
+    options: &EncoderOptions,
+) -> Vec<PlannedFrame> {
+    let width = header.width as usize;
+    let changed = |i: usize| canvas[i] != target[i];
+
+    let mut bounds: Option<[usize; 4]> = None;
+    for i in (0..target.len()).filter(|&i| changed(i)) {
+        let (x, y) = (i % width, i / width);
+        bounds = Some(match bounds {
+            Some([x0, y0, x1, y1]) => [x0.min(x), y0.min(y), x1.max(x), y1.max(y)],
+            None => [x, y, x, y],
+        });
+    }
+    // Frames cannot be empty, so an unchanged canvas gets a single pixel.
+    let [x0, y0, x1, y1] = bounds.unwrap_or([0, 0, 0, 0]);
+    let region = [x0, y0, x1 - x0 + 1, y1 - y0 + 1].map(|v| v as u32);
+    let frame_header = Ihdr {
+        width: region[2],
+        height: region[3],
+        ..*header
+    };
+
+    let indices = || (y0..=y1).flat_map(move |y| (x0..=x1).map(move |x| y * width + x));
+    // Over reproduces the target when each changed pixel is either opaque
+    // or drawn onto a fully transparent pixel.
+    let over_exact = indices()
+        .all(|i| !changed(i) || target[i][3] == 255 || (canvas[i][3] == 0 && target[i][3] != 0));
+
+    let mut blends: Vec<(BlendOp, Vec<[u8; 4]>)> =
+        vec![(BlendOp::Source, indices().map(|i| target[i]).collect())];
+    if over_exact {
+        let pixels = indices()
+            .map(|i| if changed(i) { target[i] } else { [0; 4] })
+            .collect();
+        blends.push((BlendOp::Over, pixels));
+    }
+
+    blends
+        .into_iter()
+        .map(|(blend_op, pixels)| {
+            let scanlines: Vec<u8> = pixels.into_iter().flatten().collect();
+            let filtered = encoder::filter_scanlines(&frame_header, &scanlines, options);
+            PlannedFrame {
+                region,
+                delay: Duration::ZERO,
+                dispose_op: DisposeOp::None,
+                blend_op,
+                data: encoder::compress(&filtered, options),
+            }
+        })
+        .collect()
+}
+
+/// Sets every pixel of a region of a canvas to 'pixel'.
+fn fill_region(canvas: &mut [[u8; 4]], width: u32, region: [u32; 4], pixel: [u8; 4]) {
+    let [x, y, region_width, region_height] = region.map(|v| v as usize);
+    for row in canvas
+        .chunks_mut(width as usize)
+        .skip(y)
+        .take(region_height)
+    {
+        row[x..x + region_width].fill(pixel);
+    }
+}
+
+/// Converts a delay into the fraction stored in fcTL, in milliseconds
+/// when it fits, then hundredths of a second, then whole seconds.
+fn delay_fraction(delay: Duration) -> (u16, u16) {
+    let milliseconds = delay.as_millis();
+    if milliseconds <= u16::MAX as u128 {
+        return (milliseconds as u16, 1000);
+    }
+    let hundredths = (milliseconds + 5) / 10;
+    if hundredths <= u16::MAX as u128 {
+        return (hundredths as u16, 100);
+    }
+    (delay.as_secs().min(u16::MAX as u64) as u16, 1)
+}
+
+//      +-----------+
+//      | UTILITIES |
+//      +-----------+
+
+/// Composites a non-premultiplied pixel over another, as given by
+/// section 4.3 of the APNG specification.
+fn blend_over(source: Rgba8, destination: Rgba8) -> Rgba8 {
+    let [.., source_alpha] = source.0;
+    let [.., destination_alpha] = destination.0;
+    match source_alpha {
+        255 => return source,
+        0 => return destination,
+        _ => {}
+    }
+
+    let (source_alpha, destination_alpha) = (source_alpha as u32, destination_alpha as u32);
+    // The destination alpha left showing through the source, times 255.
+    let remaining = destination_alpha * (255 - source_alpha);
+    let alpha = source_alpha * 255 + remaining;
+
+    let mut output = [0u8; 4];
+    for (channel, value) in output.iter_mut().take(3).enumerate() {
+        let color = source.0[channel] as u32 * source_alpha * 255
+            + destination.0[channel] as u32 * remaining;
+        *value = ((color + alpha / 2) / alpha) as u8;
+    }
+    output[3] = ((alpha + 127) / 255) as u8;
+    Rgba(output)
+}
+
+/// Builds a DecoderError::AnimationError.
+fn error(e: String) -> DecoderError {
+    DecoderError::AnimationError(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::chunk::Chunks;
+    use crate::png::decoder::PNG_HEADER;
+    use crate::png::encoder::{self, EncoderOptions};
+
+    /// The type and data of every chunk of a file, in order.
+    type RawChunks = Vec<([u8; 4], Vec<u8>)>;
+
+    const RED: Rgba8 = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba8 = Rgba([0, 255, 0, 255]);
+    const CLEAR: Rgba8 = Rgba([0, 0, 0, 0]);
+
+    /// Encodes an image, returning its IHDR data and its image data.
+    fn encode(image: &Image<Rgba8>) -> (Vec<u8>, Vec<u8>) {
+        let bytes = encoder::encode_image(image, &EncoderOptions::default()).unwrap();
+        let mut ihdr = Vec::new();
+        let mut idat = Vec::new();
+        for chunk in Chunks::new(&bytes) {
+            let chunk = chunk.unwrap();
+            match &chunk.kind {
+                b"IHDR" => ihdr = chunk.data.to_vec(),
+                b"IDAT" => idat.extend_from_slice(chunk.data),
+                _ => {}
+            }
+        }
+        (ihdr, idat)
+    }
+
+    fn control(
+        image: &Image<Rgba8>,
+        x: u32,
+        y: u32,
+        dispose: DisposeOp,
+        blend: BlendOp,
+    ) -> FrameControl {
+        FrameControl {
+            sequence_number: 0,
+            width: image.width(),
+            height: image.height(),
+            x_offset: x,
+            y_offset: y,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: dispose,
+            blend_op: blend,
+        }
+    }
+
+    /// Lays out the chunks of an APNG whose first frame is the default
+    /// image, numbering fcTL and fdAT chunks in order.
+    fn animation(frames: &[(FrameControl, Image<Rgba8>)]) -> RawChunks {
+        let actl = AnimationControl {
+            num_frames: frames.len() as u32,
+            num_plays: 0,
+        };
+        let mut chunks = vec![
+            (*b"IHDR", encode(&frames[0].1).0),
+            (*b"acTL", actl.to_bytes()),
+        ];
+        let mut sequence_number = 0;
+        for (i, (control, image)) in frames.iter().enumerate() {
+            let control = FrameControl {
+                sequence_number,
+                ..*control
+            };
+            chunks.push((*b"fcTL", control.to_bytes()));
+            sequence_number += 1;
+
+            let data = encode(image).1;
+            if i == 0 {
+                chunks.push((*b"IDAT", data));
+            } else {
+                let mut fdat = sequence_number.to_be_bytes().to_vec();
+                fdat.extend(data);
+                chunks.push((*b"fdAT", fdat));
+                sequence_number += 1;
+            }
+        }
+        chunks.push((*b"IEND", Vec::new()));
+        chunks
+    }
+
+    fn to_bytes(chunks: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = PNG_HEADER.to_vec();
+        for (kind, data) in chunks {
+            encoder::write_chunk(&mut bytes, kind, data);
+        }
+        bytes
+    }
+
+    #[test]
+    fn frames_are_composited() {
+        let background = Image::from_fn(4, 4, |_, _| RED);
+        let overlay = Image::from_fn(2, 2, |_, _| Rgba([0, 0, 255, 128]));
+        let dot = Image::from_fn(1, 1, |_, _| GREEN);
+        let clear = Image::from_fn(1, 1, |_, _| CLEAR);
+        let frames = [
+            (
+                control(&background, 0, 0, DisposeOp::None, BlendOp::Source),
+                background,
+            ),
+            (
+                control(&overlay, 1, 1, DisposeOp::Previous, BlendOp::Over),
+                overlay,
+            ),
+            (
+                control(&dot, 0, 0, DisposeOp::Background, BlendOp::Source),
+                dot,
+            ),
+            (control(&clear, 3, 3, DisposeOp::None, BlendOp::Over), clear),
+        ];
+        let png = Png::from_bytes(to_bytes(&animation(&frames))).unwrap();
+        let animation = png.animation.as_ref().unwrap();
+        assert!(animation.default_image_is_frame);
+        assert_eq!(animation.frames.len(), 4);
+
+        let shown: Vec<Frame> = png.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(shown.len(), 4);
+        assert_eq!(shown[0].delay, Duration::from_millis(100));
+        assert!(shown[0].image.as_pixels().iter().all(|&pixel| pixel == RED));
+
+        // Half transparent blue over red.
+        assert_eq!(shown[1].image.get_pixel(1, 1), Rgba([127, 0, 128, 255]));
+        assert_eq!(shown[1].image.get_pixel(0, 0), RED);
+
+        // The overlay was restored away, then the dot cleared to nothing.
+        assert_eq!(shown[2].image.get_pixel(0, 0), GREEN);
+        assert_eq!(shown[2].image.get_pixel(1, 1), RED);
+        assert_eq!(shown[3].image.get_pixel(0, 0), CLEAR);
+        assert_eq!(shown[3].image.get_pixel(3, 3), RED);
+    }
+
+    #[test]
+    fn still_images_are_a_single_frame() {
+        let image = Image::from_fn(3, 2, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let bytes = encoder::encode_image(&image, &EncoderOptions::default()).unwrap();
+        let png = Png::from_bytes(bytes).unwrap();
+        assert!(png.animation.is_none());
+
+        let frames: Vec<Frame> = png.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].image, image);
+        assert_eq!(frames[0].delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn broken_animations_are_rejected() {
+        let image = Image::from_fn(2, 2, |_, _| RED);
+        let small = Image::from_fn(1, 1, |_, _| GREEN);
+        let frames = [
+            (
+                control(&image, 0, 0, DisposeOp::None, BlendOp::Source),
+                image,
+            ),
+            (control(&small, 1, 0, DisposeOp::None, BlendOp::Over), small),
+        ];
+        let chunks = animation(&frames);
+        assert!(Png::from_bytes(to_bytes(&chunks)).is_ok());
+
+        let broken = |name: &str, change: &dyn Fn(&mut RawChunks)| {
+            let mut chunks = chunks.clone();
+            change(&mut chunks);
+            let result = Png::from_bytes(to_bytes(&chunks));
+            assert!(
+                matches!(result, Err(DecoderError::AnimationError(_))),
+                "{name}"
+            );
+        };
+        // fcTL of the second frame is chunk 4, its fdAT chunk 5.
+        broken("apng-sequence", &|chunks| chunks[5].1[3] = 7);
+        broken("apng-count", &|chunks| chunks[1].1[3] = 3);
+        broken("apng-outside", &|chunks| chunks[4].1[15] = 2);
+        broken("apng-dispose", &|chunks| chunks[4].1[24] = 3);
+        broken("apng-orphan", &|chunks| {
+            chunks.remove(4);
+            chunks[1].1[3] = 1;
+            chunks[4].1[3] = 1;
+        });
+    }
+
+    #[test]
+    fn encoded_animations_decode_to_the_same_frames() {
+        // A square moving over a gradient, with a transparent corner.
+        fn frame(step: u32) -> Image<Rgba8> {
+            Image::from_fn(24, 16, |x, y| match (x, y) {
+                _ if (step..step + 4).contains(&x) && (4..8).contains(&y) => GREEN,
+                (0..=2, 0..=2) => Rgba([0, 0, 255, (x * 60) as u8]),
+                _ => Rgba([(x * 10) as u8, (y * 15) as u8, 90, 255]),
+            })
+        }
+        let delay = Duration::from_millis(40);
+        let input = [
+            (frame(0), delay),
+            (frame(3), delay),
+            (frame(3), delay),
+            (frame(6), delay),
+            (frame(20), Duration::from_secs(70)),
+        ];
+        let bytes = encode_animation(&input, 2, &EncoderOptions::default()).unwrap();
+        let png = Png::from_bytes(bytes).unwrap();
+
+        let animation = png.animation.as_ref().unwrap();
+        assert_eq!(
+            animation.control,
+            AnimationControl {
+                num_frames: 4,
+                num_plays: 2
+            }
+        );
+        // Only the area the square moved through is stored.
+        let control = animation.frames[1].control;
+        assert_eq!((control.width, control.height), (7, 4));
+        assert_eq!((control.x_offset, control.y_offset), (0, 4));
+
+        let frames: Vec<Frame> = png.frames().collect::<Result<_, _>>().unwrap();
+        let delays: Vec<Duration> = frames.iter().map(|frame| frame.delay).collect();
+        assert_eq!(delays, [delay, delay * 2, delay, Duration::from_secs(70)]);
+        for (shown, step) in frames.iter().zip([0, 3, 6, 20]) {
+            assert_eq!(shown.image, frame(step));
+        }
+    }
+
+    #[test]
+    fn long_delays_use_coarser_units() {
+        assert_eq!(delay_fraction(Duration::from_millis(40)), (40, 1000));
+        assert_eq!(delay_fraction(Duration::from_secs(70)), (7000, 100));
+        assert_eq!(delay_fraction(Duration::from_secs(1000)), (1000, 1));
+    }
+
+    #[test]
+    fn invalid_frames_are_rejected() {
+        let options = EncoderOptions::default();
+        assert!(matches!(
+            encode_animation(&[], 0, &options),
+            Err(EncoderError::ImageDataError(_))
+        ));
+
+        let frames = [
+            (Image::new(4, 4), Duration::ZERO),
+            (Image::new(4, 5), Duration::ZERO),
+        ];
+        assert!(matches!(
+            encode_animation(&frames, 0, &options),
+            Err(EncoderError::ImageDataError(_))
+        ));
+    }
+}
@@ -0,0 +1,693 @@
+//! Chunk-level access to PNG files, for editing metadata without decoding
+//! or re-encoding the image data.
+//!
+//! Every chunk is laid out as:
+//!
+//! name:    LENGTH  TYPE    DATA    CRC
+//! bytes:   4       4       n       4
+//!
+//! The case of each letter of the type is a property bit:
+//!
+//! ```text
+//! 1st  uppercase = critical, needed to display the image
+//! 2nd  uppercase = public, defined by the specification
+//! 3rd  uppercase = always, lowercase is reserved
+//! 4th  uppercase = unsafe to copy once the image data changes
+//! ```
+use crate::compression::checksum::Crc32;
+use crate::png::decoder::{is_png, DecoderError, PNG_HEADER};
+use crate::png::optimize::{StripMode, DISPLAY_CHUNKS};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs,
+    path::Path,
+};
+
+//      +----------+
+//      | ITERATOR |
+//      +----------+
+
+/// A chunk borrowed from the bytes of a PNG file.
+///
+/// # Attributes
+///
+/// * 'kind' - The four letter chunk type.
+/// * 'data' - The data of the chunk.
+/// * 'crc' - The CRC stored in the file, which is not checked.
+/// * 'offset' - The index of the start of the data in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    pub kind: [u8; 4],
+    pub data: &'a [u8],
+    pub crc: u32,
+    pub offset: usize,
+}
+
+impl ChunkRef<'_> {
+    /// Checks the stored CRC against the type and data.
+    pub fn crc_is_valid(&self) -> bool {
+        self.computed_crc() == self.crc
+    }
+
+    /// The CRC of the type and data, which the stored CRC should match.
+    pub fn computed_crc(&self) -> u32 {
+        chunk_crc(&self.kind, self.data)
+    }
+}
+
+/// An iterator over the chunks of a PNG file, ending after IEND or at
+/// the end of the file.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::chunk::Chunks;
+/// # use std::fs;
+/// let bytes = fs::read("input.png")?;
+///
+/// for chunk in Chunks::new(&bytes) {
+///     let chunk = chunk?;
+///     println!("{} {} bytes", String::from_utf8_lossy(&chunk.kind), chunk.data.len());
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Chunks<'a> {
+    bytes: &'a [u8],
+    index: usize,
+    done: bool,
+}
+
+impl<'a> Chunks<'a> {
+    /// Creates an iterator over the chunks following the signature at
+    /// the start of 'bytes'. The signature itself is not checked.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            index: PNG_HEADER.len(),
+            done: false,
+        }
+    }
+
+    /// Returns the index of the next chunk to be read, or of the chunk
+    /// which failed to be read once the iterator has stopped.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<ChunkRef<'a>, DecoderError>;
+
+    /// Reads the next chunk, returning a DecoderError::NoMoreChunks and
+    /// then stopping if it runs past the end of the file.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.index >= self.bytes.len() {
+            return None;
+        }
+
+        let start = self.index + 8;
+        let Some(header) = self.bytes.get(self.index..start) else {
+            self.done = true;
+            return Some(Err(DecoderError::NoMoreChunks(start)));
+        };
+        let end = start + u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if end + 4 > self.bytes.len() {
+            self.done = true;
+            return Some(Err(DecoderError::NoMoreChunks(end + 4)));
+        }
+
+        let kind: [u8; 4] = self.bytes[self.index + 4..start]
+            .try_into()
+            .unwrap_or_default();
+        let crc = &self.bytes[end..end + 4];
+        let chunk = ChunkRef {
+            kind,
+            data: &self.bytes[start..end],
+            crc: u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]),
+            offset: start,
+        };
+
+        self.index = end + 4;
+        self.done = &kind == b"IEND";
+        Some(Ok(chunk))
+    }
+}
+
+//      +--------+
+//      | EDITOR |
+//      +--------+
+
+/// Chunks which may appear at most once.
+const UNIQUE_CHUNKS: [&[u8; 4]; 16] = [
+    b"IHDR", b"PLTE", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sRGB", b"sBIT", b"cICP", b"mDCV",
+    b"cLLI", b"bKGD", b"hIST", b"tRNS", b"pHYs", b"eXIf",
+];
+
+/// Chunks which have to come before PLTE and IDAT.
+pub(crate) const BEFORE_PLTE: [&[u8; 4]; 8] = [
+    b"cHRM", b"gAMA", b"iCCP", b"sRGB", b"sBIT", b"cICP", b"mDCV", b"cLLI",
+];
+
+/// Chunks which have to come after PLTE, when the image has one.
+const AFTER_PLTE: [&[u8; 4]; 3] = [b"bKGD", b"hIST", b"tRNS"];
+
+/// A single chunk of a PNG file held by a ChunkEditor. The CRC is kept
+/// from the file until the data is changed, so untouched chunks are
+/// written back byte for byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    kind: [u8; 4],
+    data: Vec<u8>,
+    crc: u32,
+}
+
+impl Chunk {
+    /// Creates a chunk, computing its CRC.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use chameleon::png::chunk::Chunk;
+    /// # use chameleon::png::metadata::PhysicalDimensions;
+    /// let physical = PhysicalDimensions::from_dpi(300.0, 300.0);
+    /// let chunk = Chunk::new(*b"pHYs", physical.to_bytes());
+    /// ```
+    pub fn new(kind: [u8; 4], data: Vec<u8>) -> Self {
+        let crc = chunk_crc(&kind, &data);
+        Self { kind, data, crc }
+    }
+
+    /// The four letter chunk type.
+    pub fn kind(&self) -> &[u8; 4] {
+        &self.kind
+    }
+
+    /// The data of the chunk.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The CRC written for the chunk.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Replaces the data of the chunk, recomputing its CRC.
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.crc = chunk_crc(&self.kind, &data);
+        self.data = data;
+    }
+
+    /// Whether the chunk is needed to display the image.
+    pub fn is_critical(&self) -> bool {
+        self.kind[0].is_ascii_uppercase()
+    }
+}
+
+// Allows for conversion from a borrowed chunk, keeping its CRC.
+impl From<ChunkRef<'_>> for Chunk {
+    fn from(chunk: ChunkRef<'_>) -> Self {
+        Self {
+            kind: chunk.kind,
+            data: chunk.data.to_vec(),
+            crc: chunk.crc,
+        }
+    }
+}
+
+/// Lists, inserts, removes and replaces the chunks of a PNG file while
+/// copying the image data verbatim. Critical chunks other than PLTE can
+/// neither be added nor removed, and added chunks are placed where the
+/// ordering rules of the specification allow.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::chunk::{Chunk, ChunkEditor};
+/// # use chameleon::png::metadata::PhysicalDimensions;
+/// # use chameleon::png::optimize::StripMode;
+/// # use chameleon::png::text::TextChunk;
+/// # use std::fs;
+/// let mut editor = ChunkEditor::from_path("scan.png")?;
+///
+/// editor.strip(StripMode::Safe);
+/// editor.replace(Chunk::new(*b"pHYs", PhysicalDimensions::from_dpi(600.0, 600.0).to_bytes()))?;
+///
+/// let text = TextChunk::new("Software", "chameleon");
+/// editor.insert(Chunk::new(text.chunk_type(), text.to_bytes(6)?))?;
+///
+/// fs::write("scan.png", editor.to_bytes())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct ChunkEditor {
+    chunks: Vec<Chunk>,
+}
+
+impl ChunkEditor {
+    /// Reads every chunk of a PNG file.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the ChunkEditor or a ChunkError if the
+    /// file is not a PNG, is truncated or does not start with IHDR and
+    /// end with IEND.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ChunkError> {
+        if !is_png(bytes) {
+            return Err(DecoderError::TypeError("Missing PNG signature".to_string()).into());
+        }
+        let chunks = Chunks::new(bytes)
+            .map(|chunk| chunk.map(Chunk::from))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if chunks.first().map(|chunk| &chunk.kind) != Some(b"IHDR") {
+            return Err(ChunkError::Misplaced(
+                "IHDR is not the first chunk".to_string(),
+            ));
+        }
+        if chunks.last().map(|chunk| &chunk.kind) != Some(b"IEND") {
+            return Err(ChunkError::Misplaced(
+                "IEND is not the last chunk".to_string(),
+            ));
+        }
+        Ok(Self { chunks })
+    }
+
+    /// Reads every chunk of the PNG file at the given path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ChunkError> {
+        let bytes = fs::read(path).map_err(DecoderError::from)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// The chunks in file order.
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// The first chunk of the given type.
+    pub fn find(&self, kind: &[u8; 4]) -> Option<&Chunk> {
+        self.chunks.iter().find(|chunk| &chunk.kind == kind)
+    }
+
+    /// The first chunk of the given type, for changing its data in place.
+    pub fn find_mut(&mut self, kind: &[u8; 4]) -> Option<&mut Chunk> {
+        self.chunks.iter_mut().find(|chunk| &chunk.kind == kind)
+    }
+
+    /// Adds an ancillary chunk, or a PLTE to an image without one, in the
+    /// first position the ordering rules allow:
+    ///
+    /// ```text
+    /// cHRM, gAMA, iCCP, sRGB, sBIT, cICP...   Before PLTE and IDAT.
+    /// PLTE                                    Before IDAT.
+    /// bKGD, hIST, tRNS                        After PLTE, before IDAT.
+    /// Anything else                           Before IDAT.
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// A result which is either empty or holds a ChunkError if the type
+    /// is invalid, critical, or already present and allowed only once.
+    pub fn insert(&mut self, chunk: Chunk) -> Result<(), ChunkError> {
+        validate_kind(&chunk.kind)?;
+        let name = String::from_utf8_lossy(&chunk.kind).to_string();
+
+        if chunk.is_critical() && &chunk.kind != b"PLTE" {
+            return Err(ChunkError::Critical(name));
+        }
+        if UNIQUE_CHUNKS.contains(&&chunk.kind) && self.find(&chunk.kind).is_some() {
+            return Err(ChunkError::Duplicate(name));
+        }
+
+        let first = |kinds: &[&[u8; 4]]| {
+            self.chunks
+                .iter()
+                .position(|chunk| kinds.contains(&&chunk.kind))
+        };
+        let idat = first(&[b"IDAT"]).ok_or_else(|| {
+            ChunkError::Misplaced("The file has no IDAT to insert before".to_string())
+        })?;
+
+        let index = match &chunk.kind {
+            kind if BEFORE_PLTE.contains(&kind) => first(&[b"PLTE", b"IDAT"]).unwrap_or(idat),
+            b"PLTE" => first(&AFTER_PLTE).unwrap_or(idat),
+            b"hIST" if self.find(b"PLTE").is_none() => {
+                return Err(ChunkError::Misplaced("hIST requires a PLTE".to_string()));
+            }
+            _ => idat,
+        };
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// Replaces the data of the first chunk of the same type, keeping its
+    /// position, or inserts the chunk if there is none.
+    ///
+    /// # Returns
+    ///
+    /// A result which is either empty or holds a ChunkError if the chunk
+    /// cannot be inserted, or replaces IHDR, IDAT or IEND.
+    pub fn replace(&mut self, chunk: Chunk) -> Result<(), ChunkError> {
+        if matches!(&chunk.kind, b"IHDR" | b"IDAT" | b"IEND") {
+            return Err(ChunkError::Critical(
+                String::from_utf8_lossy(&chunk.kind).to_string(),
+            ));
+        }
+        match self.find_mut(&chunk.kind) {
+            Some(existing) => *existing = chunk,
+            None => self.insert(chunk)?,
+        }
+        Ok(())
+    }
+
+    /// Removes every chunk of the given type.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the number of chunks removed, or a
+    /// ChunkError if the type is critical.
+    pub fn remove(&mut self, kind: &[u8; 4]) -> Result<usize, ChunkError> {
+        if kind[0].is_ascii_uppercase() {
+            return Err(ChunkError::Critical(
+                String::from_utf8_lossy(kind).to_string(),
+            ));
+        }
+        Ok(self.remove_where(|chunk| &chunk.kind == kind))
+    }
+
+    /// Removes every ancillary chunk for which 'predicate' returns true.
+    /// Critical chunks are always kept.
+    ///
+    /// # Returns
+    ///
+    /// The number of chunks removed.
+    pub fn remove_where<F>(&mut self, mut predicate: F) -> usize
+    where
+        F: FnMut(&Chunk) -> bool,
+    {
+        let before = self.chunks.len();
+        self.chunks
+            .retain(|chunk| chunk.is_critical() || !predicate(chunk));
+        before - self.chunks.len()
+    }
+
+    /// Removes optional chunks the same way the optimizer does, without
+    /// touching the image data.
+    ///
+    /// # Returns
+    ///
+    /// The number of chunks removed.
+    pub fn strip(&mut self, mode: StripMode) -> usize {
+        match mode {
+            StripMode::None => 0,
+            StripMode::Safe => self.remove_where(|chunk| !DISPLAY_CHUNKS.contains(&&chunk.kind)),
+            StripMode::All => self.remove_where(|_| true),
+        }
+    }
+
+    /// Writes the signature and every chunk back out.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = PNG_HEADER.to_vec();
+        for chunk in &self.chunks {
+            output.extend_from_slice(&(chunk.data.len() as u32).to_be_bytes());
+            output.extend_from_slice(&chunk.kind);
+            output.extend_from_slice(&chunk.data);
+            output.extend_from_slice(&chunk.crc.to_be_bytes());
+        }
+        output
+    }
+}
+
+/// Checks a chunk type is four ASCII letters with the reserved bit clear.
+fn validate_kind(kind: &[u8; 4]) -> Result<(), ChunkError> {
+    if !kind.iter().all(u8::is_ascii_alphabetic) || kind[2].is_ascii_lowercase() {
+        return Err(ChunkError::InvalidType(
+            String::from_utf8_lossy(kind).to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads chunk data made up of exactly N big-endian u32 values.
+///
+/// # Arguments
+///
+/// * 'bytes' - The data of the chunk.
+/// * 'kind' - The chunk type, to name in the error.
+/// * 'error' - Builds the error to return if the length is wrong, such
+///   as DecoderError::ColorError.
+pub(crate) fn read_u32s<const N: usize>(
+    bytes: &[u8],
+    kind: &str,
+    error: fn(String) -> DecoderError,
+) -> Result<[u32; N], DecoderError> {
+    if bytes.len() != N * 4 {
+        return Err(error(format!(
+            "{kind}: Length {} is not {}",
+            bytes.len(),
+            N * 4
+        )));
+    }
+    Ok(std::array::from_fn(|i| {
+        u32::from_be_bytes([
+            bytes[i * 4],
+            bytes[i * 4 + 1],
+            bytes[i * 4 + 2],
+            bytes[i * 4 + 3],
+        ])
+    }))
+}
+
+/// The CRC-32 of a chunk, taken over its type and data.
+fn chunk_crc(kind: &[u8; 4], data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    crc.finish()
+}
+
+//       +--------+
+//       | ERRORS |
+//       +--------+
+
+/// Enum containing possible errors raised while editing chunks.
+///
+/// # Fields
+///
+/// * 'DecoderError' - The file could not be read or split into chunks.
+/// * 'InvalidType' - The chunk type is not four letters, or has the
+///   reserved bit set.
+/// * 'Critical' - The chunk is critical and cannot be added or removed,
+///   holds its type.
+/// * 'Duplicate' - The chunk may appear only once and already does,
+///   holds its type.
+/// * 'Misplaced' - The chunks are not in an order the specification
+///   allows, holds a description.
+#[derive(Debug)]
+pub enum ChunkError {
+    DecoderError(DecoderError),
+    InvalidType(String),
+    Critical(String),
+    Duplicate(String),
+    Misplaced(String),
+}
+
+// Defines how ChunkErrors are displayed.
+impl Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::DecoderError(e) => write!(f, "{e}"),
+            ChunkError::InvalidType(kind) => {
+                write!(f, "Error: Invalid chunk type '{kind}'.")
+            }
+            ChunkError::Critical(kind) => {
+                write!(f, "Error: Critical chunk '{kind}' cannot be changed.")
+            }
+            ChunkError::Duplicate(kind) => {
+                write!(f, "Error: Chunk '{kind}' is only allowed once.")
+            }
+            ChunkError::Misplaced(e) => {
+                write!(f, "Error: Invalid chunk order, '{e}'.")
+            }
+        }
+    }
+}
+
+// Allows for conversion from DecoderError to ChunkError.
+impl From<DecoderError> for ChunkError {
+    fn from(error: DecoderError) -> Self {
+        ChunkError::DecoderError(error)
+    }
+}
+
+// Implements the Error interface for ChunkError, with the wrapped
+// DecoderError as its source.
+impl Error for ChunkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ChunkError::DecoderError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::buffer::Image;
+    use crate::image::pixel::{Luma, Rgb};
+    use crate::png::encoder::{self, EncoderOptions};
+
+    /// A small indexed image with PLTE and tRNS chunks.
+    fn indexed_png() -> Vec<u8> {
+        let indices = Image::from_fn(7, 5, |x, y| Luma([((x + y) % 3) as u8]));
+        let palette = [Rgb([255, 0, 0]), Rgb([0, 255, 0]), Rgb([0, 0, 255])];
+        encoder::encode_indexed(
+            &indices,
+            &palette,
+            Some(&[0, 255, 128]),
+            &EncoderOptions::default(),
+        )
+        .unwrap()
+    }
+
+    fn kinds(editor: &ChunkEditor) -> Vec<&[u8; 4]> {
+        editor.chunks().iter().map(Chunk::kind).collect()
+    }
+
+    #[test]
+    fn iterates_chunks() {
+        let bytes = indexed_png();
+        let chunks: Vec<ChunkRef> = Chunks::new(&bytes).collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(chunks.first().unwrap().kind, *b"IHDR");
+        assert_eq!(chunks.first().unwrap().offset, 16);
+        assert_eq!(chunks.first().unwrap().data.len(), 13);
+        assert_eq!(chunks.last().unwrap().kind, *b"IEND");
+        assert!(chunks.iter().all(ChunkRef::crc_is_valid));
+
+        // Nothing after IEND is read.
+        let mut trailing = bytes.clone();
+        trailing.extend_from_slice(b"garbage");
+        assert_eq!(Chunks::new(&trailing).count(), chunks.len());
+    }
+
+    #[test]
+    fn truncated_chunks_end_with_an_error() {
+        let bytes = indexed_png();
+        let truncated = &bytes[..bytes.len() - 20];
+        let results: Vec<_> = Chunks::new(truncated).collect();
+
+        assert!(matches!(
+            results.last(),
+            Some(Err(DecoderError::NoMoreChunks(_)))
+        ));
+        assert!(results[..results.len() - 1].iter().all(Result::is_ok));
+        assert!(ChunkEditor::from_bytes(truncated).is_err());
+        assert!(ChunkEditor::from_bytes(&bytes[8..]).is_err());
+    }
+
+    #[test]
+    fn unchanged_files_are_written_back_verbatim() {
+        let bytes = indexed_png();
+        let editor = ChunkEditor::from_bytes(&bytes).unwrap();
+        assert_eq!(editor.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn inserted_chunks_follow_the_ordering_rules() {
+        let mut editor = ChunkEditor::from_bytes(&indexed_png()).unwrap();
+        editor
+            .insert(Chunk::new(*b"tEXt", b"Comment\0hello".to_vec()))
+            .unwrap();
+        editor
+            .insert(Chunk::new(*b"gAMA", 45455u32.to_be_bytes().to_vec()))
+            .unwrap();
+        editor.insert(Chunk::new(*b"bKGD", vec![1])).unwrap();
+
+        assert_eq!(
+            kinds(&editor),
+            [b"IHDR", b"gAMA", b"PLTE", b"tRNS", b"tEXt", b"bKGD", b"IDAT", b"IEND"]
+        );
+        // Rewritten chunks carry fresh CRCs, so the file still reads.
+        let bytes = editor.to_bytes();
+        assert!(Chunks::new(&bytes).all(|chunk| chunk.unwrap().crc_is_valid()));
+    }
+
+    #[test]
+    fn replace_keeps_position() {
+        let mut editor = ChunkEditor::from_bytes(&indexed_png()).unwrap();
+        editor.replace(Chunk::new(*b"tRNS", vec![255, 0])).unwrap();
+        editor
+            .replace(Chunk::new(*b"PLTE", vec![1, 2, 3, 4, 5, 6, 7, 8, 9]))
+            .unwrap();
+
+        assert_eq!(
+            kinds(&editor),
+            [b"IHDR", b"PLTE", b"tRNS", b"IDAT", b"IEND"]
+        );
+        assert_eq!(editor.find(b"tRNS").unwrap().data(), [255, 0]);
+        assert!(matches!(
+            editor.replace(Chunk::new(*b"IDAT", Vec::new())),
+            Err(ChunkError::Critical(_))
+        ));
+    }
+
+    #[test]
+    fn remove_and_strip() {
+        let mut editor = ChunkEditor::from_bytes(&indexed_png()).unwrap();
+        for kind in [b"tEXt", b"tEXt", b"sRGB", b"pHYs"] {
+            editor.insert(Chunk::new(*kind, vec![0; 9])).unwrap();
+        }
+
+        assert_eq!(editor.remove(b"tEXt").unwrap(), 2);
+        assert_eq!(editor.remove(b"zTXt").unwrap(), 0);
+        assert!(matches!(
+            editor.remove(b"PLTE"),
+            Err(ChunkError::Critical(_))
+        ));
+
+        // Safe keeps what affects how the image looks.
+        assert_eq!(editor.strip(StripMode::Safe), 1);
+        assert!(editor.find(b"sRGB").is_some());
+        assert_eq!(editor.strip(StripMode::All), 2);
+        assert_eq!(kinds(&editor), [b"IHDR", b"PLTE", b"IDAT", b"IEND"]);
+    }
+
+    #[test]
+    fn invalid_insertions_are_rejected() {
+        let mut editor = ChunkEditor::from_bytes(&indexed_png()).unwrap();
+        let insert =
+            |editor: &mut ChunkEditor, kind: &[u8; 4]| editor.insert(Chunk::new(*kind, vec![0]));
+
+        assert!(matches!(
+            insert(&mut editor, b"IDAT"),
+            Err(ChunkError::Critical(_))
+        ));
+        assert!(matches!(
+            insert(&mut editor, b"PLTE"),
+            Err(ChunkError::Duplicate(_))
+        ));
+        assert!(matches!(
+            insert(&mut editor, b"tRNS"),
+            Err(ChunkError::Duplicate(_))
+        ));
+        assert!(matches!(
+            insert(&mut editor, b"tExt"),
+            Err(ChunkError::InvalidType(_))
+        ));
+        assert!(matches!(
+            insert(&mut editor, b"t3Xt"),
+            Err(ChunkError::InvalidType(_))
+        ));
+
+        // hIST only makes sense with a palette.
+        let gray = Image::from_fn(3, 3, |x, _| Luma([x as u8]));
+        let bytes = encoder::encode_image(&gray, &EncoderOptions::default()).unwrap();
+        let mut editor = ChunkEditor::from_bytes(&bytes).unwrap();
+        assert!(matches!(
+            insert(&mut editor, b"hIST"),
+            Err(ChunkError::Misplaced(_))
+        ));
+    }
+}
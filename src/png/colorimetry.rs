@@ -0,0 +1,558 @@
+//! The colorimetry chunks gAMA, cHRM, sRGB, iCCP and cICP, which tell a
+//! decoder how the stored samples map to real colors, see section 11.3.2
+//! of the PNG specification.
+//!
+//! When more than one is present they take precedence in this order:
+//!
+//! ```text
+//! cICP > iCCP > sRGB > gAMA and cHRM
+//! ```
+use crate::compression::deflate::DeflateError;
+use crate::compression::zlib::{self, ZlibError};
+use crate::png::chunk::read_u32s;
+use crate::png::decoder::DecoderError;
+use crate::png::encoder::RawChunk;
+use crate::png::pixels::{PixelBuffer, PixelFormat};
+use crate::png::text::{latin1_decode, latin1_encode, split_at_null, validate_keyword};
+
+/// The gamma used for images tagged sRGB by decoders which only apply
+/// a power law, as given by the specification.
+pub const SRGB_GAMMA: u32 = 45455;
+
+/// The gAMA chunk: the exponent relating light intensity to the stored
+/// samples, sample = intensity ^ gamma.
+///
+/// # Attributes
+///
+/// * 'value' - The gamma times 100000, so 45455 for the usual 1 / 2.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gamma {
+    pub value: u32,
+}
+
+impl Gamma {
+    /// Parses the data of a gAMA chunk, which has to be non-zero.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecoderError> {
+        let value = read_u32s::<1>(bytes, "gAMA", DecoderError::ColorError)?[0];
+        if value == 0 {
+            return Err(DecoderError::ColorError("gAMA: Gamma is 0".to_string()));
+        }
+        Ok(Self { value })
+    }
+
+    /// Serializes the gamma into the data of a gAMA chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.value.to_be_bytes().to_vec()
+    }
+
+    /// The gamma as a float.
+    pub fn gamma(&self) -> f64 {
+        self.value as f64 / 100000.0
+    }
+}
+
+/// The cHRM chunk: the CIE 1931 x and y coordinates of the white point
+/// and the three primaries, each times 100000.
+///
+/// # Attributes
+///
+/// * 'white', 'red', 'green', 'blue' - The [x, y] coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticities {
+    pub white: [u32; 2],
+    pub red: [u32; 2],
+    pub green: [u32; 2],
+    pub blue: [u32; 2],
+}
+
+impl Chromaticities {
+    /// The chromaticities of sRGB and BT.709.
+    pub const SRGB: Self = Self {
+        white: [31270, 32900],
+        red: [64000, 33000],
+        green: [30000, 60000],
+        blue: [15000, 6000],
+    };
+
+    /// Parses the data of a cHRM chunk.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecoderError> {
+        let v = read_u32s::<8>(bytes, "cHRM", DecoderError::ColorError)?;
+        Ok(Self {
+            white: [v[0], v[1]],
+            red: [v[2], v[3]],
+            green: [v[4], v[5]],
+            blue: [v[6], v[7]],
+        })
+    }
+
+    /// Serializes the chromaticities into the data of a cHRM chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.white, self.red, self.green, self.blue]
+            .iter()
+            .flatten()
+            .flat_map(|value| value.to_be_bytes())
+            .collect()
+    }
+}
+
+/// The sRGB chunk: the image is in the sRGB color space, and should be
+/// mapped to the display using the given ICC rendering intent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual = 0,
+    RelativeColorimetric = 1,
+    Saturation = 2,
+    AbsoluteColorimetric = 3,
+}
+
+impl RenderingIntent {
+    /// Parses the data of an sRGB chunk.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecoderError> {
+        match bytes {
+            [0] => Ok(RenderingIntent::Perceptual),
+            [1] => Ok(RenderingIntent::RelativeColorimetric),
+            [2] => Ok(RenderingIntent::Saturation),
+            [3] => Ok(RenderingIntent::AbsoluteColorimetric),
+            _ => Err(DecoderError::ColorError(format!(
+                "sRGB: Invalid rendering intent {bytes:?}"
+            ))),
+        }
+    }
+
+    /// Serializes the intent into the data of an sRGB chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+}
+
+/// The iCCP chunk: an embedded ICC color profile.
+///
+/// # Attributes
+///
+/// * 'name' - The name of the profile, following the rules of text keywords.
+/// * 'profile' - The decompressed ICC profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IccProfile {
+    pub name: String,
+    pub profile: Vec<u8>,
+}
+
+impl IccProfile {
+    /// Parses the data of an iCCP chunk, decompressing the profile as
+    /// long as it is no larger than 'max_size' bytes.
+    pub fn parse(bytes: &[u8], max_size: usize) -> Result<Self, DecoderError> {
+        let error = |e: String| DecoderError::ColorError(format!("iCCP: {e}"));
+
+        let (name, rest) = split_at_null(bytes)
+            .ok_or_else(|| error("Missing profile name separator".to_string()))?;
+        let name = latin1_decode(name);
+        validate_keyword(&name).map_err(error)?;
+
+        let compressed = match rest.split_first() {
+            Some((0, compressed)) => compressed,
+            method => {
+                let method = method.map(|(method, _)| method);
+                return Err(error(format!("Unknown compression method {method:?}")));
+            }
+        };
+        let profile = zlib::decompress_limited(compressed, max_size).map_err(|e| match e {
+            ZlibError::DeflateError(DeflateError::LimitExceeded(_)) => DecoderError::from(e),
+            e => error(e.to_string()),
+        })?;
+
+        Ok(Self { name, profile })
+    }
+
+    /// Serializes the profile into the data of an iCCP chunk.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the chunk data or a description of why
+    /// the profile name is invalid.
+    pub fn to_bytes(&self, level: u8) -> Result<Vec<u8>, String> {
+        validate_keyword(&self.name)?;
+        let mut bytes = latin1_encode(&self.name).ok_or("Profile name is not Latin-1")?;
+        bytes.extend([0, 0]);
+        bytes.extend(zlib::compress(&self.profile, level));
+        Ok(bytes)
+    }
+}
+
+/// The cICP chunk: coding-independent code points from ITU-T H.273,
+/// mostly used to mark HDR images using BT.2100 PQ or HLG.
+///
+/// # Attributes
+///
+/// * 'color_primaries' - The primaries, 1 for BT.709 and sRGB, 9 for BT.2020.
+/// * 'transfer_function' - The transfer function, 13 for sRGB, 16 for PQ
+///   and 18 for HLG.
+/// * 'matrix_coefficients' - Always 0 in PNG, since samples are RGB.
+/// * 'full_range' - Whether samples use the full range rather than the
+///   narrow range of video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cicp {
+    pub color_primaries: u8,
+    pub transfer_function: u8,
+    pub matrix_coefficients: u8,
+    pub full_range: bool,
+}
+
+impl Cicp {
+    /// Parses the data of a cICP chunk.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecoderError> {
+        let error = |e: String| DecoderError::ColorError(format!("cICP: {e}"));
+
+        let [color_primaries, transfer_function, matrix_coefficients, range] = bytes else {
+            return Err(error(format!("Length {} is not 4", bytes.len())));
+        };
+        if *matrix_coefficients != 0 {
+            return Err(error(format!(
+                "Matrix coefficients {matrix_coefficients} are not 0"
+            )));
+        }
+        let full_range = match range {
+            0 => false,
+            1 => true,
+            v => return Err(error(format!("Invalid range flag {v}"))),
+        };
+
+        Ok(Self {
+            color_primaries: *color_primaries,
+            transfer_function: *transfer_function,
+            matrix_coefficients: *matrix_coefficients,
+            full_range,
+        })
+    }
+
+    /// Serializes the code points into the data of a cICP chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        vec![
+            self.color_primaries,
+            self.transfer_function,
+            self.matrix_coefficients,
+            self.full_range as u8,
+        ]
+    }
+
+    /// Approximates the transfer function as a power law, returning the
+    /// gamma the samples were encoded with, or None for transfer functions
+    /// such as PQ and HLG which are not close to one.
+    pub fn gamma(&self) -> Option<f64> {
+        match self.transfer_function {
+            // BT.709, BT.601 and BT.2020 share an exponent of 0.45.
+            1 | 6 | 14 | 15 => Some(0.45),
+            4 => Some(1.0 / 2.2),
+            5 => Some(1.0 / 2.8),
+            8 => Some(1.0),
+            13 => Some(SRGB_GAMMA as f64 / 100000.0),
+            _ => None,
+        }
+    }
+}
+
+/// All colorimetry information of an image.
+///
+/// # Attributes
+///
+/// * 'gamma' - The gAMA chunk.
+/// * 'chromaticities' - The cHRM chunk.
+/// * 'srgb' - The sRGB chunk.
+/// * 'icc_profile' - The iCCP chunk.
+/// * 'cicp' - The cICP chunk.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::decoder::Png;
+/// # use std::fs;
+/// let png = Png::from_path("design.png")?;
+///
+/// if let Some(profile) = &png.colorimetry.icc_profile {
+///     fs::write(format!("{}.icc", profile.name), &profile.profile)?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Colorimetry {
+    pub gamma: Option<Gamma>,
+    pub chromaticities: Option<Chromaticities>,
+    pub srgb: Option<RenderingIntent>,
+    pub icc_profile: Option<IccProfile>,
+    pub cicp: Option<Cicp>,
+}
+
+impl Colorimetry {
+    /// Parses a colorimetry chunk into the matching field. Only the first
+    /// of each chunk is used, as the specification allows only one.
+    ///
+    /// # Arguments
+    ///
+    /// * 'kind' - The chunk type, chunks other than colorimetry are ignored.
+    /// * 'bytes' - The data of the chunk.
+    /// * 'max_profile' - The most bytes an ICC profile may decompress to.
+    ///
+    /// # Returns
+    ///
+    /// A result which is either empty or holds a DecoderError::ColorError.
+    pub fn parse_chunk(
+        &mut self,
+        kind: &[u8; 4],
+        bytes: &[u8],
+        max_profile: usize,
+    ) -> Result<(), DecoderError> {
+        match kind {
+            b"gAMA" if self.gamma.is_none() => self.gamma = Some(Gamma::parse(bytes)?),
+            b"cHRM" if self.chromaticities.is_none() => {
+                self.chromaticities = Some(Chromaticities::parse(bytes)?)
+            }
+            b"sRGB" if self.srgb.is_none() => self.srgb = Some(RenderingIntent::parse(bytes)?),
+            b"iCCP" if self.icc_profile.is_none() => {
+                self.icc_profile = Some(IccProfile::parse(bytes, max_profile)?)
+            }
+            b"cICP" if self.cicp.is_none() => self.cicp = Some(Cicp::parse(bytes)?),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Serializes every chunk present, in the order they are written.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either each chunk type along with its data, or
+    /// a description of why a chunk cannot be written.
+    pub fn to_chunks(&self, level: u8) -> Result<Vec<RawChunk>, String> {
+        let mut chunks = Vec::new();
+        if let Some(chromaticities) = &self.chromaticities {
+            chunks.push((*b"cHRM", chromaticities.to_bytes()));
+        }
+        if let Some(gamma) = &self.gamma {
+            chunks.push((*b"gAMA", gamma.to_bytes()));
+        }
+        if let Some(profile) = &self.icc_profile {
+            chunks.push((*b"iCCP", profile.to_bytes(level)?));
+        }
+        if let Some(intent) = &self.srgb {
+            chunks.push((*b"sRGB", intent.to_bytes()));
+        }
+        if let Some(cicp) = &self.cicp {
+            chunks.push((*b"cICP", cicp.to_bytes()));
+        }
+        Ok(chunks)
+    }
+
+    /// The gamma the samples were encoded with, following the precedence
+    /// of the chunks. An embedded ICC profile is not interpreted, so the
+    /// gAMA chunk is used as its fallback.
+    ///
+    /// # Returns
+    ///
+    /// The gamma, or None if the image does not say or uses a transfer
+    /// function which is not close to a power law.
+    pub fn file_gamma(&self) -> Option<f64> {
+        if let Some(cicp) = &self.cicp {
+            return cicp.gamma();
+        }
+        if self.srgb.is_some() && self.icc_profile.is_none() {
+            return Some(SRGB_GAMMA as f64 / 100000.0);
+        }
+        self.gamma.map(|gamma| gamma.gamma())
+    }
+}
+
+/// Gamma corrects the color samples of decoded pixels for display, using
+/// the power law from section 13.13 of the specification:
+///
+/// ```text
+/// output = sample ^ (1 / (file gamma * display gamma))
+/// ```
+///
+/// Alpha samples are left untouched.
+///
+/// # Arguments
+///
+/// * 'pixels' - The decoded pixels, corrected in place.
+/// * 'file_gamma' - The gamma the samples were encoded with.
+/// * 'display_gamma' - The gamma of the display, usually 2.2.
+pub fn apply_gamma(pixels: &mut PixelBuffer, file_gamma: f64, display_gamma: f64) {
+    GammaTable::new(pixels.format, file_gamma, display_gamma).apply(&mut pixels.data);
+}
+
+/// The lookup table behind apply_gamma, built once for a pixel format so
+/// that it can be applied to any number of rows.
+///
+/// # Attributes
+///
+/// * 'format' - The layout of the pixels corrected.
+/// * 'table' - The corrected value of every possible sample.
+#[derive(Debug, Clone)]
+pub struct GammaTable {
+    format: PixelFormat,
+    table: Vec<u16>,
+}
+
+impl GammaTable {
+    /// Builds the table for pixels laid out as 'format', see apply_gamma.
+    pub fn new(format: PixelFormat, file_gamma: f64, display_gamma: f64) -> Self {
+        let exponent = 1.0 / (file_gamma * display_gamma);
+        let max = match format.bytes_per_sample() {
+            1 => 255u32,
+            _ => 65535,
+        };
+        let table = (0..=max)
+            .map(|v| ((v as f64 / max as f64).powf(exponent) * max as f64).round() as u16)
+            .collect();
+        Self { format, table }
+    }
+
+    /// Gamma corrects pixels laid out as the format of the table, in
+    /// place, leaving alpha samples untouched.
+    pub fn apply(&self, data: &mut [u8]) {
+        let channels = self.format.channels();
+        // Gray with alpha and RGBA have alpha as their last sample.
+        let color_channels = match channels {
+            2 | 4 => channels - 1,
+            _ => channels,
+        };
+
+        match self.format.bytes_per_sample() {
+            1 => {
+                for pixel in data.chunks_exact_mut(channels) {
+                    for sample in &mut pixel[..color_channels] {
+                        *sample = self.table[*sample as usize] as u8;
+                    }
+                }
+            }
+            _ => {
+                for pixel in data.chunks_exact_mut(channels * 2) {
+                    for sample in pixel[..color_channels * 2].chunks_exact_mut(2) {
+                        let value = self.table[u16::from_be_bytes([sample[0], sample[1]]) as usize];
+                        sample.copy_from_slice(&value.to_be_bytes());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::pixels::PixelFormat;
+
+    #[test]
+    fn chunks_round_trip() {
+        let colorimetry = Colorimetry {
+            gamma: Some(Gamma { value: 45455 }),
+            chromaticities: Some(Chromaticities::SRGB),
+            srgb: Some(RenderingIntent::Saturation),
+            icc_profile: Some(IccProfile {
+                name: "Display P3".to_string(),
+                profile: b"acsp".repeat(100),
+            }),
+            cicp: Some(Cicp {
+                color_primaries: 9,
+                transfer_function: 16,
+                matrix_coefficients: 0,
+                full_range: true,
+            }),
+        };
+
+        let chunks = colorimetry.to_chunks(6).unwrap();
+        let kinds: Vec<[u8; 4]> = chunks.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, [*b"cHRM", *b"gAMA", *b"iCCP", *b"sRGB", *b"cICP"]);
+
+        let mut parsed = Colorimetry::default();
+        for (kind, bytes) in &chunks {
+            parsed.parse_chunk(kind, bytes, usize::MAX).unwrap();
+        }
+        assert_eq!(parsed, colorimetry);
+
+        // Only the first of each chunk counts.
+        parsed
+            .parse_chunk(b"gAMA", &100000u32.to_be_bytes(), usize::MAX)
+            .unwrap();
+        assert_eq!(parsed.gamma, Some(Gamma { value: 45455 }));
+    }
+
+    #[test]
+    fn file_gamma_precedence() {
+        let mut colorimetry = Colorimetry {
+            gamma: Some(Gamma { value: 100000 }),
+            ..Default::default()
+        };
+        assert_eq!(colorimetry.file_gamma(), Some(1.0));
+
+        colorimetry.srgb = Some(RenderingIntent::Perceptual);
+        assert_eq!(colorimetry.file_gamma(), Some(0.45455));
+
+        // An ICC profile overrides sRGB, leaving gAMA as its fallback.
+        colorimetry.icc_profile = Some(IccProfile {
+            name: "Custom".to_string(),
+            profile: Vec::new(),
+        });
+        assert_eq!(colorimetry.file_gamma(), Some(1.0));
+
+        // PQ is not a power law.
+        colorimetry.cicp = Some(Cicp {
+            color_primaries: 9,
+            transfer_function: 16,
+            matrix_coefficients: 0,
+            full_range: true,
+        });
+        assert_eq!(colorimetry.file_gamma(), None);
+    }
+
+    #[test]
+    fn invalid_chunks_are_rejected() {
+        let invalid: [(&[u8; 4], &[u8]); 8] = [
+            (b"gAMA", &[0, 0, 0, 0]),
+            (b"gAMA", &[0, 0, 177]),
+            (b"cHRM", &[0; 31]),
+            (b"sRGB", &[4]),
+            (b"iCCP", b"No separator"),
+            (b"iCCP", b"Profile\0\x01\x78\x9C"),
+            (b"cICP", &[1, 13, 1, 1]),
+            (b"cICP", &[1, 13, 0, 2]),
+        ];
+        for (kind, bytes) in invalid {
+            let result = Colorimetry::default().parse_chunk(kind, bytes, usize::MAX);
+            assert!(
+                matches!(result, Err(DecoderError::ColorError(_))),
+                "{bytes:?}"
+            );
+        }
+
+        let unnamed = IccProfile {
+            name: " Padded".to_string(),
+            profile: Vec::new(),
+        };
+        assert!(unnamed.to_bytes(6).is_err());
+    }
+
+    #[test]
+    fn gamma_correction() {
+        let mut pixels = PixelBuffer {
+            width: 2,
+            height: 1,
+            format: PixelFormat::Rgba8,
+            data: vec![0, 128, 255, 128, 128, 128, 128, 7],
+        };
+        // Matching file and display gammas cancel out.
+        apply_gamma(&mut pixels, 1.0 / 2.2, 2.2);
+        assert_eq!(pixels.data, [0, 128, 255, 128, 128, 128, 128, 7]);
+
+        // Linear samples are brightened, leaving alpha alone.
+        apply_gamma(&mut pixels, 1.0, 2.2);
+        assert_eq!(pixels.data, [0, 186, 255, 128, 186, 186, 186, 7]);
+
+        let mut deep = PixelBuffer {
+            width: 1,
+            height: 1,
+            format: PixelFormat::LumaA16,
+            data: vec![128, 0, 128, 0],
+        };
+        apply_gamma(&mut deep, 1.0, 2.2);
+        assert_eq!(deep.data, [&47824u16.to_be_bytes()[..], &[128, 0]].concat());
+    }
+}
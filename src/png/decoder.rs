@@ -1,9 +1,27 @@
+use crate::compression::deflate::DeflateError;
+use crate::compression::zlib::{self, ZlibError};
+use crate::image::dynamic::DynamicImage;
+use crate::image::pixel::Rgb8;
+use crate::limits::{LimitError, Limits};
+use crate::png::apng::{Animation, Frames};
+use crate::png::chunk::Chunks;
+use crate::png::colorimetry::{self, Colorimetry};
+use crate::png::filter;
+use crate::png::ihdr::{ColorType, Ihdr};
+use crate::png::interlace::{self, Adam7Pass};
+use crate::png::metadata::Metadata;
+use crate::png::palette::{self, Transparency};
+use crate::png::pixels::{self, OutputFormat, PixelBuffer};
+use crate::png::recovery::Problem;
+use crate::png::stream;
+use crate::png::text::TextChunk;
 use std::{
+    borrow::Cow,
     error::Error,
     fmt::{self, Display},
-    fs, io,
-    path::{Path, PathBuf},
-    str,
+    fs::File,
+    io::{self, Read},
+    path::Path,
 };
 
 //      +--------+
@@ -21,13 +39,31 @@ pub const PNG_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 /// # Attributes
 ///
 /// * 'data' - The Vec<u8> storing the raw data.
+/// * 'header' - The parsed IHDR chunk.
+/// * 'palette' - The parsed PLTE chunk, always present for indexed images.
+/// * 'transparency' - The parsed tRNS chunk.
+/// * 'text' - The parsed tEXt, zTXt and iTXt chunks in file order.
+/// * 'colorimetry' - The parsed gAMA, cHRM, sRGB, iCCP and cICP chunks.
+/// * 'metadata' - The parsed pHYs, tIME, bKGD, sBIT, hIST, sPLT and eXIf chunks.
+/// * 'animation' - The frames of an APNG file, None for still images.
+/// * 'limits' - The limits the image was parsed with, which decoding
+///   also keeps to.
 pub struct Png {
     pub data: PngData,
+    pub header: Ihdr,
+    pub palette: Option<Vec<Rgb8>>,
+    pub transparency: Option<Transparency>,
+    pub text: Vec<TextChunk>,
+    pub colorimetry: Colorimetry,
+    pub metadata: Metadata,
+    pub animation: Option<Animation>,
+    pub limits: Limits,
 }
 
 // Defines behavior related to creating Png structs
 impl Png {
-    /// Creates a Png struct from the given path.
+    /// Creates a Png struct from the given path, using the default
+    /// Limits.
     ///
     /// # Arguments
     ///
@@ -37,18 +73,302 @@ impl Png {
     ///
     /// A result containing either the constructed Png or a DecoderError.
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Png, DecoderError> {
+        Png::from_path_with_limits(path, &Limits::default())
+    }
+
+    /// Same as from_path, but with the given limits. Reading stops as soon
+    /// as the chunks grow past limits.max_alloc.
+    pub fn from_path_with_limits<P: AsRef<Path>>(
+        path: P,
+        limits: &Limits,
+    ) -> Result<Png, DecoderError> {
         let path = path.as_ref();
 
-        let file_bytes = fs::read(path)?;
+        Png::from_reader_with_limits(File::open(path)?, limits).map_err(|e| match e {
+            DecoderError::TypeError(_) => {
+                DecoderError::TypeError(format!("{:?} is not a PNG.", path))
+            }
+            e => e,
+        })
+    }
+
+    /// Creates a Png struct from a stream holding a PNG file, such as a
+    /// socket, using the default Limits. Reading stops after IEND.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the constructed Png or a DecoderError.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use chameleon::png::decoder::Png;
+    /// # use std::io;
+    /// let png = Png::from_reader(io::stdin().lock())?;
+    /// # Ok::<(), chameleon::png::decoder::DecoderError>(())
+    /// ```
+    pub fn from_reader<R: Read>(reader: R) -> Result<Png, DecoderError> {
+        Png::from_reader_with_limits(reader, &Limits::default())
+    }
+
+    /// Same as from_reader, but with the given limits.
+    ///
+    /// Built on StreamDecoder, which reads the chunks before the image
+    /// data, after which the rest of the file is read without decoding
+    /// it, see stream::read_png.
+    pub fn from_reader_with_limits<R: Read>(
+        reader: R,
+        limits: &Limits,
+    ) -> Result<Png, DecoderError> {
+        stream::read_png(reader, limits)
+    }
+
+    /// Creates a Png struct from the bytes of a PNG file, using the
+    /// default Limits.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the constructed Png or a DecoderError.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Png, DecoderError> {
+        Png::from_bytes_with_limits(bytes, &Limits::default())
+    }
+
+    /// Same as from_bytes, but with the given limits.
+    pub fn from_bytes_with_limits(bytes: Vec<u8>, limits: &Limits) -> Result<Png, DecoderError> {
+        limits.check_alloc(Some(bytes.len()))?;
 
-        if !is_png(file_bytes.clone()) {
-            return Err(DecoderError::TypeError(format!("{:?} is not a PNG.", path)));
+        if !is_png(&bytes) {
+            return Err(DecoderError::TypeError(
+                "Bytes do not start with the PNG signature.".to_string(),
+            ));
         }
 
-        let data = PngData::build(file_bytes)?;
+        Png::from_data(PngData::build(bytes)?, limits, None)
+    }
 
-        Ok(Png { data })
+    /// Parses the chunks split out by PngData.
+    ///
+    /// # Arguments
+    ///
+    /// * 'data' - The chunks of the file.
+    /// * 'limits' - Checked against the dimensions of the image and the
+    ///   size of compressed chunks, then kept for decoding.
+    /// * 'problems' - When given, ancillary chunks which fail to parse are
+    ///   recorded here and skipped rather than failing the
+    ///   whole image, see recovery::recover.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the constructed Png or a DecoderError.
+    pub fn from_data(
+        data: PngData,
+        limits: &Limits,
+        mut problems: Option<&mut Vec<Problem>>,
+    ) -> Result<Png, DecoderError> {
+        let header = Ihdr::parse(&data.ihdr)?;
+        limits.check_dimensions(header.width, header.height)?;
+
+        let palette = match &data.plte {
+            Some(plte) => Some(palette::parse_palette(plte, &header)?),
+            None if header.color_type == ColorType::Indexed => {
+                return Err(DecoderError::PlteError(
+                    "Indexed image has no PLTE chunk".to_string(),
+                ));
+            }
+            None => None,
+        };
+        let transparency = match &data.trns {
+            Some(trns) => tolerate(
+                Transparency::parse(trns, &header, palette.as_deref()),
+                b"tRNS",
+                &mut problems,
+            )?,
+            None => None,
+        };
+
+        let mut text = Vec::new();
+        let mut colorimetry = Colorimetry::default();
+        let mut metadata = Metadata::default();
+        for (kind, start, end) in &data.chunks {
+            let bytes = &data.raw_data[*start..*end];
+            if matches!(kind, b"tEXt" | b"zTXt" | b"iTXt") {
+                text.extend(tolerate(
+                    TextChunk::parse(kind, bytes, limits.max_text),
+                    kind,
+                    &mut problems,
+                )?);
+            }
+            tolerate(
+                colorimetry.parse_chunk(kind, bytes, limits.max_alloc),
+                kind,
+                &mut problems,
+            )?;
+            tolerate(
+                metadata.parse_chunk(kind, bytes, &header, palette.as_deref()),
+                kind,
+                &mut problems,
+            )?;
+        }
+
+        let animation =
+            tolerate(Animation::parse(&data, &header), b"acTL", &mut problems)?.flatten();
+
+        Ok(Png {
+            data,
+            header,
+            palette,
+            transparency,
+            text,
+            colorimetry,
+            metadata,
+            animation,
+            limits: *limits,
+        })
     }
+
+    /// Decompresses the image data and reverses the filtering and, if
+    /// used, the Adam7 interlacing.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the rows of the image packed back to
+    /// back, each being Ihdr::row_bytes(width) long, or a DecoderError.
+    pub fn scanlines(&self) -> Result<Vec<u8>, DecoderError> {
+        self.scanlines_progressive(|_, _| {})
+    }
+
+    /// Same as scanlines, but for interlaced images calls 'on_pass' as
+    /// soon as each pass has been unfiltered, allowing a low resolution
+    /// preview to be shown before the whole image has been decoded.
+    /// Not called for images without interlacing.
+    ///
+    /// # Arguments
+    ///
+    /// * 'on_pass' - Called with each non-empty pass and its unfiltered
+    ///   rows, which make up a reduced image of pass.width by
+    ///   pass.height pixels.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use chameleon::png::decoder::Png;
+    /// # fn show(_: &[u8]) {}
+    /// let png = Png::from_path("interlaced.png")?;
+    ///
+    /// let rows = png.scanlines_progressive(|pass, data| {
+    ///     println!("Pass {} is {}x{}", pass.index + 1, pass.width, pass.height);
+    ///     show(data);
+    /// })?;
+    /// # Ok::<(), chameleon::png::decoder::DecoderError>(())
+    /// ```
+    pub fn scanlines_progressive<F>(&self, on_pass: F) -> Result<Vec<u8>, DecoderError>
+    where
+        F: FnMut(&Adam7Pass, &[u8]),
+    {
+        let (idat, held) = self.data.image_data(&self.limits)?;
+        let filtered = decompress_image(&self.header, &idat, held, &self.limits)?;
+        reconstruct(&self.header, &filtered, on_pass)
+    }
+
+    /// Composites the frames of an animated image in order, see Frames.
+    /// Images which are not animated yield just their default image,
+    /// which is also what decode returns for animated images.
+    pub fn frames(&self) -> Frames<'_> {
+        Frames::new(self)
+    }
+
+    /// Decodes the image into a PixelBuffer in its native layout, see
+    /// OutputFormat::Native.
+    pub fn decode(&self) -> Result<PixelBuffer, DecoderError> {
+        self.decode_as(OutputFormat::Native)
+    }
+
+    /// Decodes the image into a DynamicImage holding an Image of the
+    /// pixel type matching the native layout.
+    pub fn to_image(&self) -> Result<DynamicImage, DecoderError> {
+        Ok(DynamicImage::from(self.decode()?))
+    }
+
+    /// Decodes the image into a PixelBuffer normalized to the given layout.
+    ///
+    /// # Arguments
+    ///
+    /// * 'output' - Either OutputFormat::Native or OutputFormat::Rgba8.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the PixelBuffer or a DecoderError.
+    pub fn decode_as(&self, output: OutputFormat) -> Result<PixelBuffer, DecoderError> {
+        self.decode_with(&DecodeOptions {
+            output,
+            ..Default::default()
+        })
+    }
+
+    /// Decodes the image into a PixelBuffer using the given options.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use chameleon::png::decoder::{DecodeOptions, Png};
+    /// # use chameleon::png::pixels::OutputFormat;
+    /// # let png = Png::from_path("image.png")?;
+    /// // Preview on a typical display, whatever gamma the file was saved with.
+    /// let pixels = png.decode_with(&DecodeOptions {
+    ///     output: OutputFormat::Rgba8,
+    ///     display_gamma: Some(2.2),
+    /// })?;
+    /// # Ok::<(), chameleon::png::decoder::DecoderError>(())
+    /// ```
+    pub fn decode_with(&self, options: &DecodeOptions) -> Result<PixelBuffer, DecoderError> {
+        let scanlines = self.scanlines()?;
+        self.expand_scanlines(&scanlines, options)
+    }
+
+    /// Converts scanlines of this image, as returned by scanlines, into a
+    /// PixelBuffer using the given options.
+    pub fn expand_scanlines(
+        &self,
+        scanlines: &[u8],
+        options: &DecodeOptions,
+    ) -> Result<PixelBuffer, DecoderError> {
+        let format =
+            pixels::output_format(&self.header, self.transparency.is_some(), options.output);
+        let pixel_bytes = (self.header.width as usize)
+            .checked_mul(self.header.height as usize)
+            .and_then(|pixels| pixels.checked_mul(format.bytes_per_pixel()));
+        self.limits
+            .check_alloc(pixel_bytes.and_then(|bytes| bytes.checked_add(scanlines.len())))?;
+
+        let mut pixels = pixels::expand(
+            &self.header,
+            self.palette.as_deref(),
+            self.transparency.as_ref(),
+            scanlines,
+            options.output,
+        )?;
+
+        if let (Some(display_gamma), Some(file_gamma)) =
+            (options.display_gamma, self.colorimetry.file_gamma())
+        {
+            colorimetry::apply_gamma(&mut pixels, file_gamma, display_gamma);
+        }
+        Ok(pixels)
+    }
+}
+
+/// Options controlling how pixels are decoded.
+///
+/// # Attributes
+///
+/// * 'output' - The layout of the decoded pixels.
+/// * 'display_gamma' - When set, color samples are gamma corrected for a
+///   display with this gamma, usually 2.2. Images which do not
+///   state their gamma are left as is.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DecodeOptions {
+    pub output: OutputFormat,
+    pub display_gamma: Option<f64>,
 }
 
 /// A struct containing the roughly parsed data of a PNG file.
@@ -58,13 +378,21 @@ impl Png {
 /// * 'raw_data' - A Vec<u8> containing the raw byte data.
 /// * 'ihdr' - An array storing the 13 byte IHDR chunk.
 /// * 'plte' - Contains the optional PLTE chunk.
-/// * 'IDAT' - Contains a vector of Vec<u8>'s containing the IDAT chunk/chunks.
+/// * 'trns' - Contains the optional tRNS chunk.
+/// * 'idat' - The start and end index in raw_data of the data of each
+///   IDAT chunk, which together make up a single zlib stream.
+/// * 'crc' - The CRC of the IEND chunk.
+/// * 'chunks' - The type of every chunk in file order, along with the start
+///   and end index of its data in raw_data.
+/// * 'index' - The position in raw_data reached while walking the chunks.
 pub struct PngData {
     pub raw_data: Vec<u8>,
     pub ihdr: Vec<u8>,
     pub plte: Option<Vec<u8>>,
-    pub idat: Vec<u8>,
+    pub trns: Option<Vec<u8>>,
+    pub idat: Vec<(usize, usize)>,
     pub crc: Vec<u8>,
+    pub chunks: Vec<([u8; 4], usize, usize)>,
     pub index: usize,
 }
 
@@ -82,69 +410,137 @@ impl PngData {
     /// A result containing either the built PngData struct or a DecoderError.
     pub fn build(raw_data: Vec<u8>) -> Result<Self, DecoderError> {
         let mut data = PngData {
-            raw_data: raw_data.clone(),
+            raw_data,
             ihdr: Vec::with_capacity(13),
             plte: None,
+            trns: None,
             idat: Vec::new(),
             crc: Vec::new(),
+            chunks: Vec::new(),
             index: PNG_HEADER.len(),
         };
 
-        let chunks = data.get_chunk_indexes().unwrap();
+        data.chunks = data.get_chunk_indexes()?;
+        data.split_chunks()?;
+        Ok(data)
+    }
 
-        data.ihdr = raw_data[chunks[0].0..chunks[0].1].to_vec();
-        if chunks[1] != (0, 0) {
-            data.plte = Some(raw_data[chunks[1].0..chunks[1].1].to_vec());
-        }
-        data.idat = raw_data[chunks[2].0..chunks[2].1].to_vec();
-        data.crc = raw_data[chunks[3].0..chunks[3].1].to_vec();
+    /// Same as build, but for chunks which have already been located,
+    /// such as those recovered from a damaged file.
+    ///
+    /// # Arguments
+    ///
+    /// * 'raw_data' - A Vec<u8> containing the raw byte data of the PNG file.
+    /// * 'chunks' - The type of each chunk to use, along with the start and
+    ///   end index of its data in raw_data.
+    pub fn from_chunks(
+        raw_data: Vec<u8>,
+        chunks: Vec<([u8; 4], usize, usize)>,
+    ) -> Result<Self, DecoderError> {
+        let mut data = PngData {
+            index: raw_data.len(),
+            raw_data,
+            ihdr: Vec::with_capacity(13),
+            plte: None,
+            trns: None,
+            idat: Vec::new(),
+            crc: Vec::new(),
+            chunks,
+        };
 
+        data.split_chunks()?;
         Ok(data)
     }
+
+    /// Copies the critical chunks other than IDAT out of raw_data into
+    /// their fields.
+    fn split_chunks(&mut self) -> Result<(), DecoderError> {
+        let raw_data = &self.raw_data;
+        for &(chunk_type, start, end) in &self.chunks {
+            match &chunk_type {
+                b"IHDR" => self.ihdr = raw_data[start..end].to_vec(),
+                b"PLTE" => self.plte = Some(raw_data[start..end].to_vec()),
+                b"tRNS" => self.trns = Some(raw_data[start..end].to_vec()),
+                b"IDAT" => self.idat.push((start, end)),
+                b"IEND" => self.crc = raw_data.get(end..end + 4).unwrap_or_default().to_vec(),
+                _ => {}
+            }
+        }
+
+        if self.ihdr.is_empty() {
+            return Err(DecoderError::IhdrError("Missing IHDR chunk".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Gathers the data of the IDAT chunks, borrowing it from raw_data
+    /// when there is a single chunk and otherwise joining the chunks into
+    /// a copy, once it has been checked that the copy fits within
+    /// limits.max_alloc along with raw_data.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the image data and the number of bytes
+    /// held for it, raw_data and any copy, or a LimitError.
+    pub fn image_data(&self, limits: &Limits) -> Result<(Cow<'_, [u8]>, usize), LimitError> {
+        if let [(start, end)] = self.idat[..] {
+            return Ok((
+                Cow::Borrowed(&self.raw_data[start..end]),
+                self.raw_data.len(),
+            ));
+        }
+
+        let length: usize = self.idat.iter().map(|(start, end)| end - start).sum();
+        let held = self.raw_data.len() + length;
+        limits.check_alloc(Some(held))?;
+        let mut idat = Vec::with_capacity(length);
+        for &(start, end) in &self.idat {
+            idat.extend_from_slice(&self.raw_data[start..end]);
+        }
+        Ok((Cow::Owned(idat), held))
+    }
+
+    /// Returns the next 'length' bytes and moves the index past them.
     pub fn walk(&mut self, length: usize) -> Result<Vec<u8>, DecoderError> {
         if self.index + length > self.raw_data.len() {
             return Err(DecoderError::NoMoreChunks(self.index + length));
         }
 
         let chunk = self.raw_data[self.index..self.index + length].to_vec();
-        self.index += 1;
+        self.index += length;
         Ok(chunk)
     }
-    pub fn get_chunk_indexes(&mut self) -> Result<[(usize, usize); 4], DecoderError> {
-        let mut ihdr_index: (usize, usize) = (0, 0);
-        let mut plte_index: (usize, usize) = (0, 0);
-        let mut idat_index: (usize, usize) = (0, 0);
-        let mut crc_index: (usize, usize) = (0, self.raw_data.len());
-        while let Ok(v) = self.walk(4) {
-            let str_result = str::from_utf8(v.as_slice());
-            match str_result {
-                Ok("IHDR") => {
-                    ihdr_index.0 = self.index + 4;
-                    ihdr_index.1 = self.index + 4 + 13;
-                }
-                Ok("PLTE") => {
-                    plte_index.0 = self.index + 4;
-                }
-                Ok("IDAT") => {
-                    println!("FOUND IDAT");
-                    if idat_index == (0, 0) {
-                        if plte_index != (0, 0) {
-                            idat_index.0 = self.index + 4;
-                            plte_index.1 = self.index - 1;
-                        } else {
-                            idat_index.0 = self.index + 4;
-                        }
-                    }
-                }
-                Ok("IEND") => {
-                    idat_index.1 = self.index - 1;
-                    crc_index.0 = self.index + 4;
-                }
-                _ => {}
+    /// Walks the chunks from the current index up to and including IEND.
+    /// Each chunk is laid out as:
+    ///
+    /// ```text
+    /// LENGTH  TYPE    DATA...     CRC
+    /// 4       4       LENGTH      4
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// A result containing a vector of each chunk's type along with the
+    /// start and end index of its data in raw_data, or a DecoderError if
+    /// a chunk runs past the end of the file or its CRC does not match.
+    pub fn get_chunk_indexes(&mut self) -> Result<Vec<([u8; 4], usize, usize)>, DecoderError> {
+        let mut chunks = Vec::new();
+
+        for chunk in Chunks::new(&self.raw_data) {
+            let chunk = chunk?;
+            if !chunk.crc_is_valid() {
+                return Err(DecoderError::CrcError {
+                    kind: String::from_utf8_lossy(&chunk.kind).to_string(),
+                    expected: chunk.crc,
+                    actual: chunk.computed_crc(),
+                });
             }
+            let end = chunk.offset + chunk.data.len();
+            chunks.push((chunk.kind, chunk.offset, end));
+            self.index = end + 4;
         }
 
-        Ok([ihdr_index, plte_index, idat_index, crc_index])
+        Ok(chunks)
     }
 }
 
@@ -157,35 +553,81 @@ impl PngData {
 /// # Fields
 ///
 /// * 'TypeError' - Used when the decoder is called on a file with a different type,
-///             or an invalid file of the correct type. Takes a String as an argument
-///             to store the name of the file causing the error.
+///   or an invalid file of the correct type. Takes a String as an argument
+///   to store the name of the file causing the error.
 /// * 'IoError' - Wrapper for io::Error for errors while reading and writing to files.
+/// * 'NoMoreChunks' - A chunk runs past the end of the file, holds the index it
+///   would have ended at.
+/// * 'Truncated' - A stream ends partway through a chunk, holds what it
+///   ended within and the index that would have ended at, the
+///   same index NoMoreChunks holds for the bytes of the file.
+/// * 'CrcError' - The CRC of a chunk does not match its contents, holds
+///   the chunk type along with the stored and computed CRCs.
+/// * 'IhdrError' - The IHDR chunk is missing or invalid, holds a description.
+/// * 'FilterError' - A row uses an unknown filter type, holds the type byte.
+/// * 'PlteError' - The PLTE chunk is missing or invalid, holds a description.
+/// * 'TrnsError' - The tRNS chunk is invalid, holds a description.
+/// * 'TextError' - A tEXt, zTXt or iTXt chunk is invalid, holds a description.
+/// * 'ColorError' - A gAMA, cHRM, sRGB, iCCP or cICP chunk is invalid, holds
+///   a description.
+/// * 'MetadataError' - A pHYs, tIME, bKGD, sBIT, hIST, sPLT or eXIf chunk is
+///   invalid, holds a description.
+/// * 'AnimationError' - The acTL, fcTL or fdAT chunks are invalid or out of
+///   sequence, holds a description.
+/// * 'ImageDataError' - The decompressed image data does not match the size
+///   the IHDR describes, holds a description.
+/// * 'ZlibError' - Wrapper for errors while decompressing the IDAT chunks.
+/// * 'LimitError' - Decoding would exceed the Limits it was given.
 ///
 /// # Examples
 ///
-/// '''
+/// ```
+/// # use chameleon::png::decoder::DecoderError;
+/// # use std::{fs, path::Path};
 /// fn get_file_bytes<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, DecoderError> {
-///     let png_header: [u8; 8] = [137, 80, 78, 71, 13, 10, 27, 10];
+///     let png_header: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 ///     let path = path.as_ref();
-///     
+///
 ///     // Either works or returns an DecoderError::IoError containing
 ///     // the error thrown by fs::read.
-///     let file_bytes = match fs::read(path)?;
-///     
-///     // Checks if file_bytes starts with the PNG header and retuns
+///     let file_bytes = fs::read(path)?;
+///
+///     // Checks if file_bytes starts with the PNG header and returns
 ///     // a TypeError if not.
-///     if !bytes[0..8] == png_header {
+///     if !file_bytes.starts_with(&png_header) {
 ///         return Err(DecoderError::TypeError(format!("{:?}", path)));
 ///     }
 ///
 ///     Ok(file_bytes)
 /// }
-/// '''
+///
+/// assert!(matches!(get_file_bytes("missing.png"), Err(DecoderError::IoError(_))));
+/// ```
 #[derive(Debug)]
 pub enum DecoderError {
     TypeError(String),
     IoError(io::Error),
     NoMoreChunks(usize),
+    Truncated {
+        within: String,
+        offset: usize,
+    },
+    CrcError {
+        kind: String,
+        expected: u32,
+        actual: u32,
+    },
+    IhdrError(String),
+    FilterError(u8),
+    PlteError(String),
+    TrnsError(String),
+    TextError(String),
+    ColorError(String),
+    MetadataError(String),
+    AnimationError(String),
+    ImageDataError(String),
+    ZlibError(ZlibError),
+    LimitError(LimitError),
 }
 
 // Defines how DecoderErrors are displayed.
@@ -204,6 +646,55 @@ impl Display for DecoderError {
             DecoderError::NoMoreChunks(v) => {
                 write!(f, "Error: No more chunks left to iterate over, reached end of file at index '{v}'")
             }
+            DecoderError::Truncated { within, offset } => {
+                write!(
+                    f,
+                    "Error: Stream ends within {within}, which runs to index '{offset}'."
+                )
+            }
+            DecoderError::CrcError {
+                kind,
+                expected,
+                actual,
+            } => {
+                write!(
+                    f,
+                    "Error: CRC mismatch in chunk '{kind}', expected '{expected:08X}' but found '{actual:08X}'."
+                )
+            }
+            DecoderError::IhdrError(e) => {
+                write!(f, "Error: Invalid IHDR chunk, '{e}'.")
+            }
+            DecoderError::FilterError(v) => {
+                write!(f, "Error: Unknown filter type '{v}'.")
+            }
+            DecoderError::PlteError(e) => {
+                write!(f, "Error: Invalid PLTE chunk, '{e}'.")
+            }
+            DecoderError::TrnsError(e) => {
+                write!(f, "Error: Invalid tRNS chunk, '{e}'.")
+            }
+            DecoderError::TextError(e) => {
+                write!(f, "Error: Invalid text chunk, '{e}'.")
+            }
+            DecoderError::ColorError(e) => {
+                write!(f, "Error: Invalid colorimetry chunk, '{e}'.")
+            }
+            DecoderError::MetadataError(e) => {
+                write!(f, "Error: Invalid metadata chunk, '{e}'.")
+            }
+            DecoderError::AnimationError(e) => {
+                write!(f, "Error: Invalid animation, '{e}'.")
+            }
+            DecoderError::ImageDataError(e) => {
+                write!(f, "Error: Invalid image data, '{e}'.")
+            }
+            DecoderError::ZlibError(e) => {
+                write!(f, "Error: Could not decompress image data, '{e}'")
+            }
+            DecoderError::LimitError(e) => {
+                write!(f, "{e}")
+            }
         }
     }
 }
@@ -215,13 +706,186 @@ impl From<io::Error> for DecoderError {
     }
 }
 
-// Implements the Error interface for CliError.
-impl Error for DecoderError {}
+// Allows for conversion from ZlibError to DecoderError.
+impl From<ZlibError> for DecoderError {
+    fn from(error: ZlibError) -> Self {
+        match error {
+            ZlibError::DeflateError(DeflateError::LimitExceeded(e)) => DecoderError::LimitError(e),
+            error => DecoderError::ZlibError(error),
+        }
+    }
+}
+
+// Allows for conversion from LimitError to DecoderError.
+impl From<LimitError> for DecoderError {
+    fn from(error: LimitError) -> Self {
+        DecoderError::LimitError(error)
+    }
+}
+
+// Implements the Error interface for DecoderError, with the wrapped
+// error as its source.
+impl Error for DecoderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecoderError::IoError(e) => Some(e),
+            DecoderError::ZlibError(e) => Some(e),
+            DecoderError::LimitError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 //      +----------+
 //      | UTILITES |
 //      +----------+
 
+/// Reverses the filtering and, if used, the Adam7 interlacing of
+/// decompressed image data. Used for the default image as well as for
+/// the frames of animated images, which share its layout.
+///
+/// # Arguments
+///
+/// * 'header' - The IHDR describing the layout of the data.
+/// * 'filtered' - The decompressed image data.
+/// * 'on_pass' - Called with each non-empty Adam7 pass, see
+///   Png::scanlines_progressive.
+///
+/// # Returns
+///
+/// A result containing either the packed rows of the image or a
+/// DecoderError.
+pub fn reconstruct<F>(header: &Ihdr, filtered: &[u8], on_pass: F) -> Result<Vec<u8>, DecoderError>
+where
+    F: FnMut(&Adam7Pass, &[u8]),
+{
+    let (image, result) = reconstruct_partial(header, filtered, on_pass);
+    result.map(|_| image)
+}
+
+/// Same as reconstruct, but recovers as many rows as possible from
+/// truncated or corrupted data. For interlaced images this is every
+/// complete pass along with the complete rows of the next one.
+///
+/// # Returns
+///
+/// The packed rows of the image, with pixels which could not be
+/// recovered left as zeroes, along with the error which stopped it.
+pub fn reconstruct_partial<F>(
+    header: &Ihdr,
+    filtered: &[u8],
+    mut on_pass: F,
+) -> (Vec<u8>, Result<(), DecoderError>)
+where
+    F: FnMut(&Adam7Pass, &[u8]),
+{
+    let stride = header.filter_stride();
+    let row_bytes = header.row_bytes(header.width);
+
+    if !header.interlaced {
+        return filter::unfilter_partial(filtered, row_bytes, header.height as usize, stride);
+    }
+
+    let mut image = vec![0u8; row_bytes * header.height as usize];
+    let mut offset = 0;
+
+    for pass in interlace::passes(header.width, header.height) {
+        if pass.is_empty() {
+            continue;
+        }
+        let pass_row_bytes = header.row_bytes(pass.width);
+        let pass_length = (pass_row_bytes + 1) * pass.height as usize;
+        let pass_filtered = filtered.get(offset..).unwrap_or_default();
+
+        let (pass_data, result) =
+            filter::unfilter_partial(pass_filtered, pass_row_bytes, pass.height as usize, stride);
+        interlace::scatter_pass(
+            &pass,
+            header.bits_per_pixel(),
+            &pass_data,
+            &mut image,
+            row_bytes,
+        );
+        if result.is_err() {
+            return (image, result);
+        }
+        on_pass(&pass, &pass_data);
+
+        offset += pass_length;
+    }
+
+    (image, Ok(()))
+}
+
+/// Decompresses image data laid out as described by header, checking
+/// first that it and the image reconstructed from it fit within
+/// limits.max_alloc.
+///
+/// # Arguments
+///
+/// * 'header' - The header of the image, or of the APNG frame.
+/// * 'compressed' - The zlib stream of the image data.
+/// * 'held' - The bytes already allocated for the image, such as the
+///   file and the image data, which count towards
+///   limits.max_alloc.
+/// * 'limits' - The limits to decompress within.
+///
+/// # Returns
+///
+/// A result containing either the filtered image data or a DecoderError,
+/// DecoderError::LimitError when the data would not fit.
+pub fn decompress_image(
+    header: &Ihdr,
+    compressed: &[u8],
+    held: usize,
+    limits: &Limits,
+) -> Result<Vec<u8>, DecoderError> {
+    let (filtered, result) = decompress_image_partial(header, compressed, held, limits)?;
+    result?;
+    Ok(filtered)
+}
+
+/// Same as decompress_image, but keeps whatever could be decompressed
+/// when the stream is damaged, see zlib::decompress_partial.
+pub fn decompress_image_partial(
+    header: &Ihdr,
+    compressed: &[u8],
+    held: usize,
+    limits: &Limits,
+) -> Result<(Vec<u8>, Result<(), ZlibError>), LimitError> {
+    let (filtered, image) = (header.filtered_bytes(), header.image_bytes());
+    let total = filtered
+        .zip(image)
+        .and_then(|(filtered, image)| filtered.checked_add(image))
+        .and_then(|total| total.checked_add(held));
+    limits.check_alloc(total)?;
+
+    // Data past the end of the image is ignored, so only the rest of the
+    // budget bounds the stream rather than the expected length.
+    let max_output = limits.max_alloc - held - image.unwrap_or_default();
+    Ok(zlib::decompress_partial(compressed, max_output))
+}
+
+/// Passes a result through, unless problems are being collected, in
+/// which case an error is recorded against the chunk and None returned.
+fn tolerate<T>(
+    result: Result<T, DecoderError>,
+    kind: &[u8; 4],
+    problems: &mut Option<&mut Vec<Problem>>,
+) -> Result<Option<T>, DecoderError> {
+    match (result, problems) {
+        (Ok(value), _) => Ok(Some(value)),
+        (Err(error), Some(problems)) => {
+            problems.push(Problem::InvalidChunk {
+                kind: *kind,
+                error: error.to_string(),
+            });
+            Ok(None)
+        }
+        (Err(error), None) => Err(error),
+    }
+}
+
 /// Takes in a byte array and checks for the png file header.
 ///
 /// # Arguments
@@ -234,7 +898,7 @@ impl Error for DecoderError {}
 /// either the given byte array is too small, or the png file
 /// header is not found. If somehow neither cases are true,
 /// returns false.
-pub fn is_png(bytes: Vec<u8>) -> bool {
+pub fn is_png(bytes: &[u8]) -> bool {
     if bytes.len() < PNG_HEADER.len() {
         return false;
     }
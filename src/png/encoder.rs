@@ -0,0 +1,792 @@
+//! Encoding of images into PNG files.
+//!
+//! The file is written as the signature followed by these chunks:
+//!
+//! ```text
+//! IHDR  [cHRM, gAMA, iCCP, sRGB, cICP, sBIT]  [PLTE]  [tRNS]
+//!     [bKGD, hIST, pHYs, sPLT..., eXIf, tIME]  [tEXt, zTXt, iTXt...]
+//!     IDAT...  IEND
+//! ```
+//!
+//! Each chunk is stored as its length, type, data and the CRC-32 of
+//! its type and data:
+//!
+//! name:    LENGTH  TYPE    DATA    CRC
+//! bytes:   4       4       n       4
+use crate::compression::checksum::Crc32;
+use crate::compression::zlib;
+use crate::image::buffer::Image;
+use crate::image::dynamic::DynamicImage;
+use crate::image::pixel::{Luma, Luma8, LumaA, Pixel, Primitive, Rgb, Rgb8, Rgba};
+use crate::png::colorimetry::Colorimetry;
+use crate::png::decoder::{DecoderError, PNG_HEADER};
+use crate::png::filter::{self, FilterStrategy};
+use crate::png::ihdr::{ColorType, Ihdr};
+use crate::png::interlace::{self, set_packed};
+use crate::png::metadata::Metadata;
+use crate::png::palette::{self, Transparency};
+use crate::png::text::TextChunk;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+};
+
+//      +---------+
+//      | OPTIONS |
+//      +---------+
+
+/// Options controlling how image data is filtered and compressed.
+///
+/// # Attributes
+///
+/// * 'filter' - How the filter of each row is picked.
+/// * 'compression_level' - The DEFLATE level from 0 through 9.
+/// * 'idat_size' - The most bytes of compressed data put in each IDAT chunk.
+/// * 'threads' - The number of threads the image data is compressed on,
+///   1 compresses it on the calling thread. With more, the data
+///   is split into segments compressed in parallel, see
+///   zlib::compress_parallel, which gives slightly larger files.
+/// * 'prime_segments' - Whether each segment compressed in parallel starts
+///   with the last 32K of the one before it as a dictionary.
+/// * 'interlace' - Whether encode_image and friends write Adam7 interlaced
+///   images. encode_scanlines follows the header it is given.
+/// * 'text' - Textual metadata, written before the image data.
+/// * 'colorimetry' - The gAMA, cHRM, sRGB, iCCP and cICP chunks to write.
+/// * 'metadata' - The pHYs, tIME, bKGD, sBIT, hIST, sPLT and eXIf chunks
+///   to write. bKGD, sBIT and hIST have to match the header
+///   and palette the image is written with.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::image::buffer::Image;
+/// # use chameleon::image::pixel::Rgb8;
+/// # use chameleon::png::encoder::{encode_image, EncoderOptions};
+/// # use chameleon::png::filter::FilterStrategy;
+/// # let image: Image<Rgb8> = Image::new(8, 8);
+/// let options = EncoderOptions {
+///     filter: FilterStrategy::BruteForce,
+///     compression_level: 9,
+///     ..Default::default()
+/// };
+///
+/// let bytes = encode_image(&image, &options)?;
+/// # Ok::<(), chameleon::png::encoder::EncoderError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncoderOptions {
+    pub filter: FilterStrategy,
+    pub compression_level: u8,
+    pub idat_size: usize,
+    pub threads: usize,
+    pub prime_segments: bool,
+    pub interlace: bool,
+    pub text: Vec<TextChunk>,
+    pub colorimetry: Colorimetry,
+    pub metadata: Metadata,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> Self {
+        Self {
+            filter: FilterStrategy::MinimumSum,
+            compression_level: 6,
+            idat_size: 8192,
+            threads: 1,
+            prime_segments: true,
+            interlace: false,
+            text: Vec::new(),
+            colorimetry: Colorimetry::default(),
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+//      +---------+
+//      | ENCODER |
+//      +---------+
+
+/// Encodes unfiltered image data, in any color type and bit depth, into
+/// a complete PNG file.
+///
+/// # Arguments
+///
+/// * 'header' - The IHDR to write, describing the layout of 'scanlines'.
+///   If it is interlaced the image data is written as Adam7 passes.
+/// * 'palette' - The PLTE entries, required for indexed images and
+///   optional as a suggested palette for truecolor images.
+/// * 'transparency' - The tRNS chunk matching the color type, if any.
+/// * 'scanlines' - The rows of the full image packed back to back, each
+///   being Ihdr::row_bytes(width) long, as returned by
+///   Png::scanlines.
+/// * 'options' - How to filter and compress the image data.
+///
+/// # Returns
+///
+/// A result containing either the bytes of the PNG file or an EncoderError
+/// if the chunks are inconsistent with each other.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::decoder::Png;
+/// # use chameleon::png::encoder::{encode_scanlines, EncoderOptions};
+/// let png = Png::from_path("input.png")?;
+/// let scanlines = png.scanlines()?;
+///
+/// let bytes = encode_scanlines(
+///     &png.header,
+///     png.palette.as_deref(),
+///     png.transparency.as_ref(),
+///     &scanlines,
+///     &EncoderOptions::default(),
+/// )?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn encode_scanlines(
+    header: &Ihdr,
+    palette: Option<&[Rgb8]>,
+    transparency: Option<&Transparency>,
+    scanlines: &[u8],
+    options: &EncoderOptions,
+) -> Result<Vec<u8>, EncoderError> {
+    let chunks = header_chunks(header, palette, transparency, options)?;
+
+    let expected = header.row_bytes(header.width) * header.height as usize;
+    if scanlines.len() != expected {
+        return Err(EncoderError::ImageDataError(format!(
+            "Expected {expected} bytes of scanlines but found {}",
+            scanlines.len()
+        )));
+    }
+
+    let filtered = filter_scanlines(header, scanlines, options);
+    let compressed = compress(&filtered, options);
+
+    let mut output = PNG_HEADER.to_vec();
+    for (kind, data) in &chunks {
+        write_chunk(&mut output, kind, data);
+    }
+    for idat in compressed.chunks(options.idat_size.max(1)) {
+        write_chunk(&mut output, b"IDAT", idat);
+    }
+    write_chunk(&mut output, b"IEND", &[]);
+
+    Ok(output)
+}
+
+/// Builds and checks every chunk which goes before the image data,
+/// starting with IHDR, in the order they are written.
+///
+/// # Arguments
+///
+/// * 'header' - The IHDR to write.
+/// * 'palette' - The PLTE entries, required for indexed images.
+/// * 'transparency' - The tRNS chunk matching the color type, if any.
+/// * 'options' - The text, colorimetry and metadata to write.
+///
+/// # Returns
+///
+/// A result containing either the chunks or an EncoderError if they are
+/// inconsistent with each other.
+pub fn header_chunks(
+    header: &Ihdr,
+    palette: Option<&[Rgb8]>,
+    transparency: Option<&Transparency>,
+    options: &EncoderOptions,
+) -> Result<Vec<RawChunk>, EncoderError> {
+    // Run the chunks through the decoder's checks so nothing is written
+    // which could not be read back.
+    let ihdr = header.to_bytes();
+    Ihdr::parse(&ihdr).map_err(|e| EncoderError::HeaderError(reason(e)))?;
+
+    let plte = match palette {
+        Some(palette) => {
+            let bytes: Vec<u8> = palette.iter().flat_map(|entry| entry.0).collect();
+            palette::parse_palette(&bytes, header)
+                .map_err(|e| EncoderError::PaletteError(reason(e)))?;
+            Some(bytes)
+        }
+        None if header.color_type == ColorType::Indexed => {
+            return Err(EncoderError::PaletteError(
+                "Indexed image has no palette".to_string(),
+            ));
+        }
+        None => None,
+    };
+
+    let trns = match transparency {
+        Some(transparency) => {
+            let bytes = transparency.to_bytes();
+            Transparency::parse(&bytes, header, palette)
+                .map_err(|e| EncoderError::TransparencyError(reason(e)))?;
+            Some(bytes)
+        }
+        None => None,
+    };
+
+    let colorimetry = options
+        .colorimetry
+        .to_chunks(options.compression_level)
+        .map_err(EncoderError::ColorError)?;
+
+    let metadata = options
+        .metadata
+        .to_chunks()
+        .map_err(EncoderError::MetadataError)?;
+    let mut parsed = Metadata::default();
+    for (kind, data) in &metadata {
+        parsed
+            .parse_chunk(kind, data, header, palette)
+            .map_err(|e| EncoderError::MetadataError(reason(e)))?;
+    }
+    let (before_plte, after_plte): (Vec<_>, Vec<_>) =
+        metadata.iter().partition(|(kind, _)| kind == b"sBIT");
+
+    let text = options
+        .text
+        .iter()
+        .map(|chunk| {
+            Ok((
+                chunk.chunk_type(),
+                chunk.to_bytes(options.compression_level)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(EncoderError::TextError)?;
+
+    let mut chunks: Vec<RawChunk> = vec![(*b"IHDR", ihdr.to_vec())];
+    chunks.extend(colorimetry);
+    chunks.extend(before_plte.into_iter().cloned());
+    if let Some(plte) = plte {
+        chunks.push((*b"PLTE", plte));
+    }
+    if let Some(trns) = trns {
+        chunks.push((*b"tRNS", trns));
+    }
+    chunks.extend(after_plte.into_iter().cloned());
+    chunks.extend(text);
+    Ok(chunks)
+}
+
+/// Filters the scanlines of an image, splitting them into Adam7 passes
+/// first if the header is interlaced.
+///
+/// # Returns
+///
+/// The uncompressed image data, each row preceded by its filter type byte.
+pub fn filter_scanlines(header: &Ihdr, scanlines: &[u8], options: &EncoderOptions) -> Vec<u8> {
+    let stride = header.filter_stride();
+    let row_bytes = header.row_bytes(header.width);
+    let level = options.compression_level;
+
+    if !header.interlaced {
+        return filter::filter(scanlines, row_bytes, stride, options.filter, level);
+    }
+
+    let mut filtered = Vec::new();
+    for pass in interlace::passes(header.width, header.height) {
+        if pass.is_empty() {
+            continue;
+        }
+        let pass_data =
+            interlace::gather_pass(&pass, header.bits_per_pixel(), scanlines, row_bytes);
+        let pass_row_bytes = header.row_bytes(pass.width);
+        filtered.extend(filter::filter(
+            &pass_data,
+            pass_row_bytes,
+            stride,
+            options.filter,
+            level,
+        ));
+    }
+    filtered
+}
+
+/// Compresses filtered image data on as many threads as the options ask
+/// for.
+pub fn compress(filtered: &[u8], options: &EncoderOptions) -> Vec<u8> {
+    let level = options.compression_level;
+    match options.threads {
+        0 | 1 => zlib::compress(filtered, level),
+        threads => zlib::compress_parallel(filtered, level, threads, options.prime_segments),
+    }
+}
+
+/// A chunk ready to be written, its four letter type and its data.
+pub type RawChunk = ([u8; 4], Vec<u8>);
+
+/// Appends a chunk with its length and CRC.
+///
+/// # Arguments
+///
+/// * 'output' - The PNG file being written.
+/// * 'kind' - The four letter chunk type.
+/// * 'data' - The data of the chunk.
+pub fn write_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(kind);
+    output.extend_from_slice(data);
+
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    output.extend_from_slice(&crc.finish().to_be_bytes());
+}
+
+/// Pulls the message out of the DecoderError raised when validating a
+/// chunk, so it can be reported as an EncoderError.
+fn reason(error: DecoderError) -> String {
+    match error {
+        DecoderError::IhdrError(e)
+        | DecoderError::PlteError(e)
+        | DecoderError::TrnsError(e)
+        | DecoderError::MetadataError(e) => e,
+        other => other.to_string(),
+    }
+}
+
+//      +--------+
+//      | IMAGES |
+//      +--------+
+
+/// A sample type PNG can store directly.
+///
+/// # Constants
+///
+/// * 'BIT_DEPTH' - The bit depth written to the IHDR.
+///
+/// # Methods
+///
+/// * 'write_be' - Appends the sample as big-endian bytes.
+pub trait PngSample: Primitive {
+    const BIT_DEPTH: u8;
+    fn write_be(self, output: &mut Vec<u8>);
+}
+
+impl PngSample for u8 {
+    const BIT_DEPTH: u8 = 8;
+
+    fn write_be(self, output: &mut Vec<u8>) {
+        output.push(self);
+    }
+}
+
+impl PngSample for u16 {
+    const BIT_DEPTH: u8 = 16;
+
+    fn write_be(self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+/// A pixel type PNG can store directly, one of the four pixel types with
+/// 8 or 16 bit samples.
+///
+/// # Constants
+///
+/// * 'COLOR_TYPE' - The color type written to the IHDR.
+pub trait PngPixel: Pixel<Subpixel: PngSample> {
+    const COLOR_TYPE: ColorType;
+}
+
+impl<T: PngSample> PngPixel for Luma<T> {
+    const COLOR_TYPE: ColorType = ColorType::Grayscale;
+}
+
+impl<T: PngSample> PngPixel for LumaA<T> {
+    const COLOR_TYPE: ColorType = ColorType::GrayscaleAlpha;
+}
+
+impl<T: PngSample> PngPixel for Rgb<T> {
+    const COLOR_TYPE: ColorType = ColorType::Rgb;
+}
+
+impl<T: PngSample> PngPixel for Rgba<T> {
+    const COLOR_TYPE: ColorType = ColorType::Rgba;
+}
+
+/// Encodes an image into a PNG file using the color type and bit depth
+/// matching its pixel type.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::image::buffer::Image;
+/// # use chameleon::image::pixel::{Rgba, Rgba8};
+/// # use chameleon::png::encoder::{encode_image, EncoderOptions};
+/// # use std::fs;
+/// let image: Image<Rgba8> = Image::from_pixel(16, 16, Rgba([255, 0, 0, 128]));
+/// fs::write("red.png", encode_image(&image, &EncoderOptions::default())?)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn encode_image<P: PngPixel>(
+    image: &Image<P>,
+    options: &EncoderOptions,
+) -> Result<Vec<u8>, EncoderError> {
+    let header = Ihdr {
+        width: image.width(),
+        height: image.height(),
+        bit_depth: P::Subpixel::BIT_DEPTH,
+        color_type: P::COLOR_TYPE,
+        interlaced: options.interlace,
+    };
+
+    let mut scanlines = Vec::with_capacity(header.row_bytes(header.width) * header.height as usize);
+    for row in image.rows() {
+        for pixel in row {
+            for &sample in pixel.channels() {
+                sample.write_be(&mut scanlines);
+            }
+        }
+    }
+
+    encode_scanlines(&header, None, None, &scanlines, options)
+}
+
+/// Encodes an image of palette indices as an indexed PNG, using the
+/// smallest bit depth which can address every palette entry.
+///
+/// # Arguments
+///
+/// * 'indices' - An image of palette indices.
+/// * 'palette' - The colors of the palette, at most 256.
+/// * 'alpha' - The alpha of each palette entry in order, entries past the
+///   end are fully opaque. Written as a tRNS chunk.
+/// * 'options' - How to filter and compress the image data.
+pub fn encode_indexed(
+    indices: &Image<Luma8>,
+    palette: &[Rgb8],
+    alpha: Option<&[u8]>,
+    options: &EncoderOptions,
+) -> Result<Vec<u8>, EncoderError> {
+    let bit_depth = [1u8, 2, 4, 8]
+        .into_iter()
+        .find(|&depth| palette.len() <= 1 << depth)
+        .ok_or_else(|| {
+            EncoderError::PaletteError(format!("{} entries exceed 256", palette.len()))
+        })?;
+
+    let header = Ihdr {
+        width: indices.width(),
+        height: indices.height(),
+        bit_depth,
+        color_type: ColorType::Indexed,
+        interlaced: options.interlace,
+    };
+
+    let row_bytes = header.row_bytes(header.width);
+    let mut scanlines = vec![0u8; row_bytes * header.height as usize];
+    for (row, packed) in indices.rows().zip(scanlines.chunks_exact_mut(row_bytes)) {
+        for (x, index) in row.iter().enumerate() {
+            let index = index.0[0];
+            if index as usize >= palette.len() {
+                return Err(EncoderError::ImageDataError(format!(
+                    "Index {index} is past the end of the {} entry palette",
+                    palette.len()
+                )));
+            }
+            set_packed(packed, x, bit_depth as usize, index);
+        }
+    }
+
+    let transparency = alpha.map(|alpha| Transparency::PaletteAlpha(alpha.to_vec()));
+    encode_scanlines(
+        &header,
+        Some(palette),
+        transparency.as_ref(),
+        &scanlines,
+        options,
+    )
+}
+
+/// Encodes an image of any of the integer pixel types.
+pub fn encode_dynamic(
+    image: &DynamicImage,
+    options: &EncoderOptions,
+) -> Result<Vec<u8>, EncoderError> {
+    match image {
+        DynamicImage::Luma8(image) => encode_image(image, options),
+        DynamicImage::LumaA8(image) => encode_image(image, options),
+        DynamicImage::Rgb8(image) => encode_image(image, options),
+        DynamicImage::Rgba8(image) => encode_image(image, options),
+        DynamicImage::Luma16(image) => encode_image(image, options),
+        DynamicImage::LumaA16(image) => encode_image(image, options),
+        DynamicImage::Rgb16(image) => encode_image(image, options),
+        DynamicImage::Rgba16(image) => encode_image(image, options),
+    }
+}
+
+//       +--------+
+//       | ERRORS |
+//       +--------+
+
+/// Enum containing possible errors raised while encoding a PNG.
+///
+/// # Fields
+///
+/// * 'HeaderError' - The IHDR describes an image PNG cannot store.
+/// * 'PaletteError' - The palette is missing, too long or not allowed
+///   for the color type.
+/// * 'TransparencyError' - The tRNS form does not match the color type,
+///   or does not fit the palette or bit depth.
+/// * 'ImageDataError' - The scanlines do not match the header, or an
+///   index is past the end of the palette.
+/// * 'TextError' - A text chunk has an invalid keyword or text which its
+///   format cannot store.
+/// * 'ColorError' - An ICC profile has an invalid name.
+/// * 'MetadataError' - A metadata chunk does not match the header or
+///   palette, or a suggested palette has an invalid name.
+/// * 'IoError' - Writing to the stream given to a StreamEncoder failed.
+#[derive(Debug)]
+pub enum EncoderError {
+    HeaderError(String),
+    PaletteError(String),
+    TransparencyError(String),
+    ImageDataError(String),
+    TextError(String),
+    ColorError(String),
+    MetadataError(String),
+    IoError(io::Error),
+}
+
+// Defines how EncoderErrors are displayed.
+impl Display for EncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncoderError::HeaderError(e) => {
+                write!(f, "Error: Cannot encode header, '{e}'.")
+            }
+            EncoderError::PaletteError(e) => {
+                write!(f, "Error: Cannot encode palette, '{e}'.")
+            }
+            EncoderError::TransparencyError(e) => {
+                write!(f, "Error: Cannot encode transparency, '{e}'.")
+            }
+            EncoderError::ImageDataError(e) => {
+                write!(f, "Error: Cannot encode image data, '{e}'.")
+            }
+            EncoderError::TextError(e) => {
+                write!(f, "Error: Cannot encode text chunk, '{e}'.")
+            }
+            EncoderError::ColorError(e) => {
+                write!(f, "Error: Cannot encode colorimetry chunk, '{e}'.")
+            }
+            EncoderError::MetadataError(e) => {
+                write!(f, "Error: Cannot encode metadata chunk, '{e}'.")
+            }
+            EncoderError::IoError(e) => {
+                write!(f, "Error: Could not write PNG stream, '{e}'.")
+            }
+        }
+    }
+}
+
+// Allows for conversion from io::Error to EncoderError.
+impl From<io::Error> for EncoderError {
+    fn from(error: io::Error) -> Self {
+        EncoderError::IoError(error)
+    }
+}
+
+// Implements the Error interface for EncoderError, with the wrapped
+// io::Error as its source.
+impl Error for EncoderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EncoderError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::decoder::Png;
+    use crate::png::filter::FilterType;
+    use crate::png::pixels::OutputFormat;
+
+    /// Noisy scanlines for the header, with the unused bits at the end of
+    /// each row clear as the decoder leaves them.
+    fn scanlines(header: &Ihdr) -> Vec<u8> {
+        let row_bytes = header.row_bytes(header.width);
+        let padding = row_bytes * 8 - header.width as usize * header.bits_per_pixel();
+        let mut state = header.width * 31 + header.bit_depth as u32;
+        let mut scanlines: Vec<u8> = (0..row_bytes * header.height as usize)
+            .map(|i| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                // Smooth stretches give the filters something to find.
+                if i % 64 < 32 {
+                    (i / 3) as u8
+                } else {
+                    (state >> 16) as u8
+                }
+            })
+            .collect();
+        for row in scanlines.chunks_exact_mut(row_bytes) {
+            row[row_bytes - 1] &= 0xFFu8.checked_shl(padding as u32).unwrap_or(0);
+        }
+        scanlines
+    }
+
+    fn round_trip(header: &Ihdr, options: &EncoderOptions) {
+        let scanlines = scanlines(header);
+        // A palette with an entry for every index the bit depth can hold.
+        let palette: Option<Vec<Rgb8>> = (header.color_type == ColorType::Indexed).then(|| {
+            (0..1u32 << header.bit_depth)
+                .map(|i| Rgb([i as u8, (i * 3) as u8, 255 - i as u8]))
+                .collect()
+        });
+        let bytes =
+            encode_scanlines(header, palette.as_deref(), None, &scanlines, options).unwrap();
+
+        let png = Png::from_bytes(bytes).unwrap();
+        assert_eq!(png.header, *header);
+        assert_eq!(png.palette, palette);
+        assert_eq!(png.scanlines().unwrap(), scanlines);
+    }
+
+    #[test]
+    fn every_color_type_and_bit_depth() {
+        let formats: [(ColorType, &[u8]); 5] = [
+            (ColorType::Grayscale, &[1, 2, 4, 8, 16]),
+            (ColorType::Rgb, &[8, 16]),
+            (ColorType::Indexed, &[1, 2, 4, 8]),
+            (ColorType::GrayscaleAlpha, &[8, 16]),
+            (ColorType::Rgba, &[8, 16]),
+        ];
+        for (color_type, bit_depths) in formats {
+            for &bit_depth in bit_depths {
+                for interlaced in [false, true] {
+                    // Sizes under 8 leave some Adam7 passes empty, odd
+                    // widths leave bits over in packed rows.
+                    for (width, height) in [(1, 1), (5, 3), (13, 9), (33, 17)] {
+                        let header = Ihdr {
+                            width,
+                            height,
+                            bit_depth,
+                            color_type,
+                            interlaced,
+                        };
+                        round_trip(&header, &EncoderOptions::default());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_filter_strategy() {
+        let filters = [
+            FilterStrategy::Fixed(FilterType::None),
+            FilterStrategy::Fixed(FilterType::Sub),
+            FilterStrategy::Fixed(FilterType::Up),
+            FilterStrategy::Fixed(FilterType::Average),
+            FilterStrategy::Fixed(FilterType::Paeth),
+            FilterStrategy::MinimumSum,
+            FilterStrategy::BruteForce,
+        ];
+        for filter in filters {
+            for (color_type, bit_depth) in [(ColorType::Grayscale, 2), (ColorType::Rgba, 16)] {
+                let header = Ihdr {
+                    width: 19,
+                    height: 11,
+                    bit_depth,
+                    color_type,
+                    interlaced: false,
+                };
+                // Small IDAT chunks split the stream across many of them.
+                let options = EncoderOptions {
+                    filter,
+                    compression_level: 0,
+                    idat_size: 100,
+                    ..Default::default()
+                };
+                round_trip(&header, &options);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_image_round_trip() {
+        let image: Image<Rgba<u16>> = Image::from_fn(7, 5, |x, y| {
+            Rgba([x as u16 * 9000, y as u16 * 300, 65535, 1234])
+        });
+        for interlace in [false, true] {
+            let options = EncoderOptions {
+                interlace,
+                ..Default::default()
+            };
+            let bytes = encode_image(&image, &options).unwrap();
+            let decoded = Png::from_bytes(bytes).unwrap().to_image().unwrap();
+            assert_eq!(decoded, DynamicImage::Rgba16(image.clone()));
+        }
+    }
+
+    #[test]
+    fn encode_indexed_round_trip() {
+        let palette = [Rgb([255, 0, 0]), Rgb([0, 255, 0]), Rgb([0, 0, 255])];
+        let indices: Image<Luma8> = Image::from_fn(6, 4, |x, y| Luma([((x + y) % 3) as u8]));
+        let bytes =
+            encode_indexed(&indices, &palette, Some(&[0, 128]), &Default::default()).unwrap();
+
+        let png = Png::from_bytes(bytes).unwrap();
+        assert_eq!(png.header.bit_depth, 2);
+        let pixels = png.decode_as(OutputFormat::Rgba8).unwrap();
+        assert_eq!(pixels.pixel(0, 0), [255, 0, 0, 0]);
+        assert_eq!(pixels.pixel(1, 0), [0, 255, 0, 128]);
+        assert_eq!(pixels.pixel(2, 0), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn inconsistent_input_is_rejected() {
+        let header = Ihdr {
+            width: 4,
+            height: 4,
+            bit_depth: 8,
+            color_type: ColorType::Rgb,
+            interlaced: false,
+        };
+        let options = EncoderOptions::default();
+        assert!(matches!(
+            encode_scanlines(&header, None, None, &[0; 47], &options),
+            Err(EncoderError::ImageDataError(_))
+        ));
+
+        let indexed = Ihdr {
+            color_type: ColorType::Indexed,
+            ..header
+        };
+        assert!(matches!(
+            encode_scanlines(&indexed, None, None, &[0; 16], &options),
+            Err(EncoderError::PaletteError(_))
+        ));
+
+        let indices: Image<Luma8> = Image::from_pixel(2, 2, Luma([3]));
+        assert!(matches!(
+            encode_indexed(&indices, &[Rgb([0, 0, 0]); 3], None, &options),
+            Err(EncoderError::ImageDataError(_))
+        ));
+    }
+
+    #[test]
+    fn parallel_compression() {
+        let header = Ihdr {
+            width: 300,
+            height: 200,
+            bit_depth: 8,
+            color_type: ColorType::Rgba,
+            interlaced: false,
+        };
+        for prime_segments in [false, true] {
+            let options = EncoderOptions {
+                threads: 4,
+                prime_segments,
+                ..Default::default()
+            };
+            round_trip(&header, &options);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! The per-row filters PNG applies before compression, and their reversal,
+//! see section 9 of the PNG specification.
+//!
+//! Each row of the decompressed data starts with a byte giving the
+//! filter type, and every byte of the row is predicted from the bytes
+//! to its left (a), above (b) and above-left (c):
+//!
+//! ```text
+//! c b
+//! a x
+//! ```
+//!
+//! Bytes outside the image, to the left of the first pixel or above
+//! the first row, are treated as 0.
+use crate::compression::deflate::deflate;
+use crate::png::decoder::DecoderError;
+
+/// The five filter types defined for filter method 0.
+///
+/// # Fields
+///
+/// * 'None' - The byte is stored as is.
+/// * 'Sub' - The byte is stored as the difference from a.
+/// * 'Up' - The byte is stored as the difference from b.
+/// * 'Average' - The byte is stored as the difference from the floored
+///   average of a and b.
+/// * 'Paeth' - The byte is stored as the difference from whichever of
+///   a, b or c is closest to a + b - c.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+}
+
+impl FilterType {
+    /// Converts a filter type byte, returning None for values the
+    /// specification does not define.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(FilterType::None),
+            1 => Some(FilterType::Sub),
+            2 => Some(FilterType::Up),
+            3 => Some(FilterType::Average),
+            4 => Some(FilterType::Paeth),
+            _ => None,
+        }
+    }
+}
+
+/// The Paeth predictor from section 9.4 of the PNG specification.
+pub fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reverses the filter of a single row in place.
+///
+/// # Arguments
+///
+/// * 'filter' - The filter type the row was stored with.
+/// * 'stride' - The number of bytes per complete pixel, rounded up to 1.
+/// * 'previous' - The already unfiltered previous row, all zeroes for
+///   the first row.
+/// * 'row' - The filtered row without its filter type byte.
+pub fn unfilter_row(filter: FilterType, stride: usize, previous: &[u8], row: &mut [u8]) {
+    match filter {
+        FilterType::None => {}
+        FilterType::Sub => {
+            for i in stride..row.len() {
+                row[i] = row[i].wrapping_add(row[i - stride]);
+            }
+        }
+        FilterType::Up => {
+            for (x, &b) in row.iter_mut().zip(previous) {
+                *x = x.wrapping_add(b);
+            }
+        }
+        FilterType::Average => {
+            for i in 0..row.len() {
+                let a = if i >= stride { row[i - stride] } else { 0 };
+                let average = ((a as u16 + previous[i] as u16) / 2) as u8;
+                row[i] = row[i].wrapping_add(average);
+            }
+        }
+        FilterType::Paeth => {
+            for i in 0..row.len() {
+                let (a, c) = if i >= stride {
+                    (row[i - stride], previous[i - stride])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth(a, previous[i], c));
+            }
+        }
+    }
+}
+
+/// Reverses the filters of a complete sub-image, either a whole
+/// non-interlaced image or a single Adam7 pass.
+///
+/// # Arguments
+///
+/// * 'data' - The filtered rows, each preceded by its filter type byte.
+/// * 'row_bytes' - The number of bytes in each row, excluding the filter
+///   type byte.
+/// * 'height' - The number of rows.
+/// * 'stride' - The number of bytes per complete pixel, rounded up to 1.
+///
+/// # Returns
+///
+/// A result containing either the unfiltered rows packed back to back
+/// without filter type bytes, or a DecoderError if the data is too
+/// short or uses an unknown filter type.
+pub fn unfilter(
+    data: &[u8],
+    row_bytes: usize,
+    height: usize,
+    stride: usize,
+) -> Result<Vec<u8>, DecoderError> {
+    let (output, result) = unfilter_partial(data, row_bytes, height, stride);
+    result.map(|_| output)
+}
+
+/// Same as unfilter, but reverses as many rows as possible when the data
+/// is truncated or a row uses an unknown filter type.
+///
+/// # Returns
+///
+/// The unfiltered rows, with every row from the first missing or
+/// invalid one onward left as zeroes, along with the error which
+/// stopped it, if any.
+pub fn unfilter_partial(
+    data: &[u8],
+    row_bytes: usize,
+    height: usize,
+    stride: usize,
+) -> (Vec<u8>, Result<(), DecoderError>) {
+    let mut output = vec![0u8; row_bytes * height];
+    let zeroes = vec![0u8; row_bytes];
+
+    for (y, filtered) in data.chunks_exact(row_bytes + 1).take(height).enumerate() {
+        let Some(filter) = FilterType::from_byte(filtered[0]) else {
+            return (output, Err(DecoderError::FilterError(filtered[0])));
+        };
+
+        let (above, current) = output.split_at_mut(y * row_bytes);
+        let previous = match y {
+            0 => &zeroes[..],
+            _ => &above[(y - 1) * row_bytes..],
+        };
+        let row = &mut current[..row_bytes];
+        row.copy_from_slice(&filtered[1..]);
+        unfilter_row(filter, stride, previous, row);
+    }
+
+    let expected = (row_bytes + 1) * height;
+    if data.len() < expected {
+        let error = DecoderError::ImageDataError(format!(
+            "Expected {expected} bytes of filtered data but found {}",
+            data.len()
+        ));
+        return (output, Err(error));
+    }
+    (output, Ok(()))
+}
+
+//      +-----------+
+//      | FILTERING |
+//      +-----------+
+
+/// The filter types in the order they are numbered.
+pub const FILTER_TYPES: [FilterType; 5] = [
+    FilterType::None,
+    FilterType::Sub,
+    FilterType::Up,
+    FilterType::Average,
+    FilterType::Paeth,
+];
+
+/// How the encoder picks the filter of each row.
+///
+/// # Fields
+///
+/// * 'Fixed' - Every row uses the same filter.
+/// * 'MinimumSum' - Each row uses the filter whose output has the smallest
+///   sum of absolute values, treating bytes as signed. This is
+///   the heuristic suggested by the PNG specification.
+/// * 'BruteForce' - Each row is compressed with every filter following
+///   the rows before it, keeping whichever filter compresses
+///   smallest. Slow but usually gives the smallest files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterStrategy {
+    Fixed(FilterType),
+    #[default]
+    MinimumSum,
+    BruteForce,
+}
+
+/// The number of bytes of already filtered rows compressed along with
+/// each candidate row by FilterStrategy::BruteForce, enough for the
+/// candidate to match against the rows just above it.
+pub const BRUTE_FORCE_CONTEXT: usize = 8192;
+
+/// Applies a filter to a single row, the reverse of unfilter_row.
+///
+/// # Arguments
+///
+/// * 'filter' - The filter type to apply.
+/// * 'stride' - The number of bytes per complete pixel, rounded up to 1.
+/// * 'previous' - The unfiltered previous row, all zeroes for the first row.
+/// * 'row' - The unfiltered row.
+/// * 'output' - Receives the filtered row, without a filter type byte.
+pub fn filter_row(
+    filter: FilterType,
+    stride: usize,
+    previous: &[u8],
+    row: &[u8],
+    output: &mut [u8],
+) {
+    for i in 0..row.len() {
+        let (a, c) = if i >= stride {
+            (row[i - stride], previous[i - stride])
+        } else {
+            (0, 0)
+        };
+        let b = previous[i];
+
+        let prediction = match filter {
+            FilterType::None => 0,
+            FilterType::Sub => a,
+            FilterType::Up => b,
+            FilterType::Average => ((a as u16 + b as u16) / 2) as u8,
+            FilterType::Paeth => paeth(a, b, c),
+        };
+        output[i] = row[i].wrapping_sub(prediction);
+    }
+}
+
+/// Filters a complete sub-image, either a whole non-interlaced image or
+/// a single Adam7 pass, ready to be compressed.
+///
+/// # Arguments
+///
+/// * 'data' - The unfiltered rows packed back to back.
+/// * 'row_bytes' - The number of bytes in each row.
+/// * 'stride' - The number of bytes per complete pixel, rounded up to 1.
+/// * 'strategy' - How to pick the filter of each row.
+/// * 'level' - The compression level, used by FilterStrategy::BruteForce.
+///
+/// # Returns
+///
+/// The filtered rows, each preceded by its filter type byte.
+pub fn filter(
+    data: &[u8],
+    row_bytes: usize,
+    stride: usize,
+    strategy: FilterStrategy,
+    level: u8,
+) -> Vec<u8> {
+    let height = data.len().checked_div(row_bytes).unwrap_or(0);
+    let mut output = Vec::with_capacity((row_bytes + 1) * height);
+    let zeroes = vec![0u8; row_bytes];
+
+    for y in 0..height {
+        let row = &data[y * row_bytes..(y + 1) * row_bytes];
+        let previous = match y {
+            0 => &zeroes[..],
+            _ => &data[(y - 1) * row_bytes..y * row_bytes],
+        };
+        filter_next_row(previous, row, stride, strategy, level, &mut output);
+    }
+
+    output
+}
+
+/// Picks the filter for a single row and appends the filtered row.
+///
+/// # Arguments
+///
+/// * 'previous' - The unfiltered previous row, all zeroes for the first row.
+/// * 'row' - The unfiltered row.
+/// * 'stride' - The number of bytes per complete pixel, rounded up to 1.
+/// * 'strategy' - How to pick the filter.
+/// * 'level' - The compression level, used by FilterStrategy::BruteForce.
+/// * 'output' - The rows filtered so far, of which BruteForce only looks
+///   at the last BRUTE_FORCE_CONTEXT bytes. Receives the filter
+///   type byte followed by the filtered row.
+pub fn filter_next_row(
+    previous: &[u8],
+    row: &[u8],
+    stride: usize,
+    strategy: FilterStrategy,
+    level: u8,
+    output: &mut Vec<u8>,
+) {
+    let mut best = vec![0u8; row.len()];
+
+    let filter = match strategy {
+        FilterStrategy::Fixed(filter) => {
+            filter_row(filter, stride, previous, row, &mut best);
+            filter
+        }
+        FilterStrategy::MinimumSum | FilterStrategy::BruteForce => {
+            let context_start = output.len().saturating_sub(BRUTE_FORCE_CONTEXT);
+            let mut candidate = vec![0u8; row.len()];
+            let mut best_filter = FilterType::None;
+            let mut best_score = usize::MAX;
+
+            for filter in FILTER_TYPES {
+                filter_row(filter, stride, previous, row, &mut candidate);
+                let score = match strategy {
+                    FilterStrategy::BruteForce => {
+                        let mut sample = output[context_start..].to_vec();
+                        sample.push(filter as u8);
+                        sample.extend_from_slice(&candidate);
+                        deflate(&sample, level).len()
+                    }
+                    _ => candidate
+                        .iter()
+                        .map(|&byte| (byte as i8).unsigned_abs() as usize)
+                        .sum(),
+                };
+                if score < best_score {
+                    best_score = score;
+                    best_filter = filter;
+                    std::mem::swap(&mut best, &mut candidate);
+                }
+            }
+            best_filter
+        }
+    };
+
+    output.push(filter as u8);
+    output.extend_from_slice(&best);
+}
@@ -0,0 +1,201 @@
+//! Parsing of the IHDR chunk, which describes the dimensions and
+//! pixel layout every other part of the decoder depends on.
+//!
+//! name:    WIDTH   HEIGHT  DEPTH   COLOR   COMPRESSION FILTER  INTERLACE
+//! bytes:   4       4       1       1       1           1       1
+use crate::png::decoder::DecoderError;
+use crate::png::interlace;
+
+/// The color types a PNG can store its pixels in, the discriminant
+/// is the value stored in the IHDR.
+///
+/// # Fields
+///
+/// * 'Grayscale' - A single luminance sample, bit depths 1, 2, 4, 8, 16.
+/// * 'Rgb' - Red, green and blue samples, bit depths 8, 16.
+/// * 'Indexed' - An index into the PLTE chunk, bit depths 1, 2, 4, 8.
+/// * 'GrayscaleAlpha' - Luminance followed by alpha, bit depths 8, 16.
+/// * 'Rgba' - Red, green, blue and alpha samples, bit depths 8, 16.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    /// Converts the IHDR color type byte, returning None for values
+    /// the specification does not define.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(ColorType::Grayscale),
+            2 => Some(ColorType::Rgb),
+            3 => Some(ColorType::Indexed),
+            4 => Some(ColorType::GrayscaleAlpha),
+            6 => Some(ColorType::Rgba),
+            _ => None,
+        }
+    }
+
+    /// The number of samples making up each pixel.
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Checks if the bit depth is allowed in combination with this
+    /// color type, see table 11.1 of the PNG specification.
+    pub fn allows_bit_depth(&self, bit_depth: u8) -> bool {
+        match self {
+            ColorType::Grayscale => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            ColorType::Indexed => matches!(bit_depth, 1 | 2 | 4 | 8),
+            _ => matches!(bit_depth, 8 | 16),
+        }
+    }
+}
+
+/// A struct containing the parsed contents of the IHDR chunk.
+///
+/// # Attributes
+///
+/// * 'width' - The width of the image in pixels.
+/// * 'height' - The height of the image in pixels.
+/// * 'bit_depth' - The number of bits per sample, or per palette index.
+/// * 'color_type' - How each pixel is made up of samples.
+/// * 'interlaced' - Whether the image data uses Adam7 interlacing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub interlaced: bool,
+}
+
+impl Ihdr {
+    /// Parses and validates the 13 data bytes of an IHDR chunk.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bytes' - The data of the IHDR chunk, without length, type or CRC.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the Ihdr or a DecoderError::IhdrError
+    /// describing which field is invalid.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecoderError> {
+        if bytes.len() != 13 {
+            return Err(DecoderError::IhdrError(format!(
+                "IHDR is {} bytes long instead of 13",
+                bytes.len()
+            )));
+        }
+
+        let width = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let height = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let bit_depth = bytes[8];
+
+        // Dimensions are limited to 2^31 - 1 so they fit in a signed integer.
+        if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+            return Err(DecoderError::IhdrError(format!(
+                "Invalid dimensions {width}x{height}"
+            )));
+        }
+
+        let color_type = ColorType::from_byte(bytes[9])
+            .ok_or_else(|| DecoderError::IhdrError(format!("Unknown color type {}", bytes[9])))?;
+        if !color_type.allows_bit_depth(bit_depth) {
+            return Err(DecoderError::IhdrError(format!(
+                "Bit depth {bit_depth} is not allowed for color type {color_type:?}"
+            )));
+        }
+
+        if bytes[10] != 0 {
+            return Err(DecoderError::IhdrError(format!(
+                "Unknown compression method {}",
+                bytes[10]
+            )));
+        }
+        if bytes[11] != 0 {
+            return Err(DecoderError::IhdrError(format!(
+                "Unknown filter method {}",
+                bytes[11]
+            )));
+        }
+        let interlaced = match bytes[12] {
+            0 => false,
+            1 => true,
+            v => {
+                return Err(DecoderError::IhdrError(format!(
+                    "Unknown interlace method {v}"
+                )))
+            }
+        };
+
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced,
+        })
+    }
+
+    /// Serializes the header into the 13 data bytes of an IHDR chunk.
+    pub fn to_bytes(&self) -> [u8; 13] {
+        let mut bytes = [0u8; 13];
+        bytes[0..4].copy_from_slice(&self.width.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.height.to_be_bytes());
+        bytes[8] = self.bit_depth;
+        bytes[9] = self.color_type as u8;
+        // Compression and filter method are always 0.
+        bytes[12] = self.interlaced as u8;
+        bytes
+    }
+
+    /// The number of bits used to store each pixel.
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// The number of bytes per complete pixel rounded up to one, which
+    /// is the distance filters look back to find the pixel to the left.
+    pub fn filter_stride(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    /// The number of bytes needed to store a row of the given width,
+    /// not including the filter type byte.
+    pub fn row_bytes(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// The number of bytes of unfiltered image data, None if it does
+    /// not fit in a usize.
+    pub fn image_bytes(&self) -> Option<usize> {
+        self.row_bytes(self.width).checked_mul(self.height as usize)
+    }
+
+    /// The number of bytes of filtered image data, which includes the
+    /// filter type byte of every row of every Adam7 pass. None if it
+    /// does not fit in a usize.
+    pub fn filtered_bytes(&self) -> Option<usize> {
+        let filtered =
+            |width, height: u32| (self.row_bytes(width) + 1).checked_mul(height as usize);
+        match self.interlaced {
+            true => interlace::passes(self.width, self.height)
+                .iter()
+                .filter(|pass| !pass.is_empty())
+                .try_fold(0usize, |total, pass| {
+                    total.checked_add(filtered(pass.width, pass.height)?)
+                }),
+            false => filtered(self.width, self.height),
+        }
+    }
+}
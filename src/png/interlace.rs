@@ -0,0 +1,196 @@
+//! Adam7 interlacing, see section 8.2 of the PNG specification.
+//!
+//! The image is split into seven reduced images, called passes, by
+//! repeating the following 8x8 pattern, where each number is the pass
+//! the pixel is stored in:
+//!
+//! ```text
+//! 1 6 4 6 2 6 4 6
+//! 7 7 7 7 7 7 7 7
+//! 5 6 5 6 5 6 5 6
+//! 7 7 7 7 7 7 7 7
+//! 3 6 4 6 3 6 4 6
+//! 7 7 7 7 7 7 7 7
+//! 5 6 5 6 5 6 5 6
+//! 7 7 7 7 7 7 7 7
+//! ```
+//!
+//! Each pass is stored one after the other, filtered as if it was an
+//! image of its own, so the first passes give a coarse preview of the
+//! whole image.
+
+/// The starting column, starting row, column step and row step of
+/// each pass, in the format:
+///
+/// ```text
+/// [X START, Y START, X STEP, Y STEP]
+/// ```
+pub const ADAM7: [[u32; 4]; 7] = [
+    [0, 0, 8, 8],
+    [4, 0, 8, 8],
+    [0, 4, 4, 8],
+    [2, 0, 4, 4],
+    [0, 2, 2, 4],
+    [1, 0, 2, 2],
+    [0, 1, 1, 2],
+];
+
+/// A struct describing the reduced image of a single Adam7 pass.
+///
+/// # Attributes
+///
+/// * 'index' - The pass number, from 0 through 6.
+/// * 'x_start', 'y_start' - The position of the first pixel of the pass
+///   within the full image.
+/// * 'x_step', 'y_step' - The distance between pixels of the pass within
+///   the full image.
+/// * 'width', 'height' - The size of the reduced image, either can be 0
+///   for small images in which case the pass is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adam7Pass {
+    pub index: usize,
+    pub x_start: u32,
+    pub y_start: u32,
+    pub x_step: u32,
+    pub y_step: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Adam7Pass {
+    /// Describes the given pass for an image of the given size.
+    ///
+    /// # Arguments
+    ///
+    /// * 'index' - The pass number, from 0 through 6.
+    /// * 'width', 'height' - The size of the full image.
+    pub fn new(index: usize, width: u32, height: u32) -> Self {
+        let [x_start, y_start, x_step, y_step] = ADAM7[index];
+
+        Self {
+            index,
+            x_start,
+            y_start,
+            x_step,
+            y_step,
+            width: (width + x_step - 1 - x_start) / x_step,
+            height: (height + y_step - 1 - y_start) / y_step,
+        }
+    }
+
+    /// Checks if the pass contains any pixels. Empty passes take up no
+    /// space in the image data, not even a filter type byte.
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// Returns all seven passes for an image of the given size.
+pub fn passes(width: u32, height: u32) -> [Adam7Pass; 7] {
+    std::array::from_fn(|index| Adam7Pass::new(index, width, height))
+}
+
+/// Copies the pixels of an unfiltered pass into their positions in
+/// the full image. Works on packed rows, so pixels smaller than a byte
+/// are moved bit by bit.
+///
+/// # Arguments
+///
+/// * 'pass' - The pass being scattered.
+/// * 'bits_per_pixel' - The size of each pixel.
+/// * 'pass_data' - The unfiltered rows of the pass, without filter bytes.
+/// * 'image' - The packed rows of the full image.
+/// * 'image_row_bytes' - The number of bytes in each row of the full image.
+pub fn scatter_pass(
+    pass: &Adam7Pass,
+    bits_per_pixel: usize,
+    pass_data: &[u8],
+    image: &mut [u8],
+    image_row_bytes: usize,
+) {
+    let pass_row_bytes = (pass.width as usize * bits_per_pixel).div_ceil(8);
+
+    for (y, row) in pass_data
+        .chunks_exact(pass_row_bytes)
+        .take(pass.height as usize)
+        .enumerate()
+    {
+        let image_y = (pass.y_start + y as u32 * pass.y_step) as usize;
+        let image_row = &mut image[image_y * image_row_bytes..(image_y + 1) * image_row_bytes];
+
+        for x in 0..pass.width as usize {
+            let image_x = (pass.x_start + x as u32 * pass.x_step) as usize;
+
+            if bits_per_pixel >= 8 {
+                let size = bits_per_pixel / 8;
+                image_row[image_x * size..(image_x + 1) * size]
+                    .copy_from_slice(&row[x * size..(x + 1) * size]);
+            } else {
+                // Pixels are packed starting from the most significant bit.
+                let value = get_packed(row, x, bits_per_pixel);
+                set_packed(image_row, image_x, bits_per_pixel, value);
+            }
+        }
+    }
+}
+
+/// Reads the 'index'th pixel of a row of pixels smaller than a byte.
+pub fn get_packed(row: &[u8], index: usize, bits: usize) -> u8 {
+    let bit = index * bits;
+    let shift = 8 - bits - bit % 8;
+    (row[bit / 8] >> shift) & ((1u16 << bits) - 1) as u8
+}
+
+/// Writes the 'index'th pixel of a row of pixels smaller than a byte.
+pub fn set_packed(row: &mut [u8], index: usize, bits: usize, value: u8) {
+    let bit = index * bits;
+    let shift = 8 - bits - bit % 8;
+    let mask = (((1u16 << bits) - 1) as u8) << shift;
+    row[bit / 8] = (row[bit / 8] & !mask) | ((value << shift) & mask);
+}
+
+/// Copies the pixels of a pass out of the full image, the reverse of
+/// scatter_pass, as needed when writing an interlaced image.
+///
+/// # Arguments
+///
+/// * 'pass' - The pass being gathered.
+/// * 'bits_per_pixel' - The size of each pixel.
+/// * 'image' - The packed rows of the full image.
+/// * 'image_row_bytes' - The number of bytes in each row of the full image.
+///
+/// # Returns
+///
+/// The packed rows of the reduced image, without filter bytes.
+pub fn gather_pass(
+    pass: &Adam7Pass,
+    bits_per_pixel: usize,
+    image: &[u8],
+    image_row_bytes: usize,
+) -> Vec<u8> {
+    let pass_row_bytes = (pass.width as usize * bits_per_pixel).div_ceil(8);
+    let mut pass_data = vec![0u8; pass_row_bytes * pass.height as usize];
+
+    for (y, row) in pass_data
+        .chunks_exact_mut(pass_row_bytes.max(1))
+        .enumerate()
+    {
+        let image_y = (pass.y_start + y as u32 * pass.y_step) as usize;
+        let image_row = &image[image_y * image_row_bytes..(image_y + 1) * image_row_bytes];
+
+        for x in 0..pass.width as usize {
+            let image_x = (pass.x_start + x as u32 * pass.x_step) as usize;
+
+            if bits_per_pixel >= 8 {
+                let size = bits_per_pixel / 8;
+                row[x * size..(x + 1) * size]
+                    .copy_from_slice(&image_row[image_x * size..(image_x + 1) * size]);
+            } else {
+                let value = get_packed(image_row, image_x, bits_per_pixel);
+                set_packed(row, x, bits_per_pixel, value);
+            }
+        }
+    }
+
+    pass_data
+}
@@ -0,0 +1,787 @@
+//! The remaining standard ancillary chunks, see sections 11.3.4 and 11.3.5
+//! of the PNG specification:
+//!
+//! ```text
+//! pHYs    Pixel density, or just the pixel aspect ratio.
+//! tIME    When the image was last modified.
+//! bKGD    A background color to show the image against.
+//! sBIT    How many bits of each sample are significant.
+//! hIST    How often each palette entry is used.
+//! sPLT    Suggested palettes for displays with few colors.
+//! eXIf    Exif metadata, such as the camera an image was taken with.
+//! ```
+//!
+//! bKGD, sBIT and hIST are stored in terms of the color type, bit depth
+//! and palette, so they are parsed against the IHDR and PLTE.
+use crate::image::pixel::Rgb8;
+use crate::png::decoder::DecoderError;
+use crate::png::encoder::RawChunk;
+use crate::png::ihdr::{ColorType, Ihdr};
+use crate::png::text::{latin1_decode, latin1_encode, split_at_null, validate_keyword};
+use std::fmt::{self, Display};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of meters in an inch, used to convert pHYs to and from DPI.
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// The unit of a pHYs chunk.
+///
+/// # Fields
+///
+/// * 'Unknown' - Only the ratio of the two densities is meaningful,
+///   giving the aspect ratio of the pixels.
+/// * 'Meter' - The densities are in pixels per meter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysicalUnit {
+    Unknown = 0,
+    Meter = 1,
+}
+
+/// The pHYs chunk: the intended pixel density or aspect ratio.
+///
+/// # Attributes
+///
+/// * 'x' - Pixels per unit along the width.
+/// * 'y' - Pixels per unit along the height.
+/// * 'unit' - What the densities are measured in.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::png::encoder::EncoderOptions;
+/// # use chameleon::png::metadata::{Metadata, PhysicalDimensions};
+/// // Print at 300 DPI.
+/// let options = EncoderOptions {
+///     metadata: Metadata {
+///         physical: Some(PhysicalDimensions::from_dpi(300.0, 300.0)),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    pub x: u32,
+    pub y: u32,
+    pub unit: PhysicalUnit,
+}
+
+impl PhysicalDimensions {
+    /// Converts dots per inch into the nearest pixels per meter, which is
+    /// how PNG stores densities. 300 DPI is stored as 11811.
+    pub fn from_dpi(x: f64, y: f64) -> Self {
+        let to_meters = |dpi: f64| (dpi / METERS_PER_INCH).round() as u32;
+        Self {
+            x: to_meters(x),
+            y: to_meters(y),
+            unit: PhysicalUnit::Meter,
+        }
+    }
+
+    /// The density in dots per inch along the width and height, or None
+    /// when the unit is unknown. Since densities are stored as whole
+    /// pixels per meter, 300 DPI reads back as 299.9994, so round the
+    /// result when a whole number is expected.
+    pub fn dpi(&self) -> Option<[f64; 2]> {
+        match self.unit {
+            PhysicalUnit::Meter => Some([
+                self.x as f64 * METERS_PER_INCH,
+                self.y as f64 * METERS_PER_INCH,
+            ]),
+            PhysicalUnit::Unknown => None,
+        }
+    }
+
+    /// Parses the data of a pHYs chunk.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecoderError> {
+        if bytes.len() != 9 {
+            return Err(error("pHYs", format!("Length {} is not 9", bytes.len())));
+        }
+        let unit = match bytes[8] {
+            0 => PhysicalUnit::Unknown,
+            1 => PhysicalUnit::Meter,
+            unit => return Err(error("pHYs", format!("Unknown unit {unit}"))),
+        };
+
+        Ok(Self {
+            x: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            y: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            unit,
+        })
+    }
+
+    /// Serializes the density into the data of a pHYs chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9);
+        bytes.extend(self.x.to_be_bytes());
+        bytes.extend(self.y.to_be_bytes());
+        bytes.push(self.unit as u8);
+        bytes
+    }
+}
+
+/// The tIME chunk: when the image was last modified, in UTC.
+///
+/// # Attributes
+///
+/// * 'year' - The full year, such as 2024.
+/// * 'month' - 1 to 12.
+/// * 'day' - 1 to 31.
+/// * 'hour' - 0 to 23.
+/// * 'minute' - 0 to 59.
+/// * 'second' - 0 to 60, allowing for leap seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Time {
+    /// The current time, for stamping images as they are written.
+    pub fn now() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        Self::from_unix(seconds)
+    }
+
+    /// Converts seconds since 1970-01-01 00:00:00 UTC into a date and
+    /// time, using the days-to-civil algorithm for the proleptic
+    /// Gregorian calendar.
+    pub fn from_unix(seconds: u64) -> Self {
+        let days = (seconds / 86400) as i64;
+        let time = seconds % 86400;
+
+        // Shift the epoch to 0000-03-01 so leap days end each 400 year era.
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    /// Parses the data of a tIME chunk.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecoderError> {
+        let [year_high, year_low, month, day, hour, minute, second] = *bytes else {
+            return Err(error("tIME", format!("Length {} is not 7", bytes.len())));
+        };
+        let time = Self {
+            year: u16::from_be_bytes([year_high, year_low]),
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+
+        if !(1..=12).contains(&month)
+            || !(1..=31).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 60
+        {
+            return Err(error("tIME", format!("Invalid date {time}")));
+        }
+        Ok(time)
+    }
+
+    /// Serializes the time into the data of a tIME chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.year.to_be_bytes().to_vec();
+        bytes.extend([self.month, self.day, self.hour, self.minute, self.second]);
+        bytes
+    }
+}
+
+// Displays the time in ISO 8601 form, '2024-03-09T14:05:00Z'.
+impl Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// The three forms the bKGD chunk takes depending on the color type.
+///
+/// # Fields
+///
+/// * 'Gray' - A gray level at the bit depth of the image, for grayscale
+///   images with or without alpha.
+/// * 'Rgb' - Red, green and blue samples at the bit depth of the image,
+///   for truecolor images with or without alpha.
+/// * 'Index' - A palette entry, for indexed images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Gray(u16),
+    Rgb([u16; 3]),
+    Index(u8),
+}
+
+impl Background {
+    /// Parses the data of a bKGD chunk into the form matching the color
+    /// type of the image.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bytes' - The data of the bKGD chunk.
+    /// * 'header' - The IHDR of the image.
+    /// * 'palette' - The parsed PLTE chunk, required for indexed images.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the Background or a
+    /// DecoderError::MetadataError.
+    pub fn parse(
+        bytes: &[u8],
+        header: &Ihdr,
+        palette: Option<&[Rgb8]>,
+    ) -> Result<Self, DecoderError> {
+        let expected = match header.color_type {
+            ColorType::Indexed => 1,
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb | ColorType::Rgba => 6,
+        };
+        if bytes.len() != expected {
+            return Err(error(
+                "bKGD",
+                format!(
+                    "Length {} is invalid for color type {:?}",
+                    bytes.len(),
+                    header.color_type
+                ),
+            ));
+        }
+
+        // Like tRNS keys, samples are 16 bit but limited to the bit depth.
+        let read_sample = |i: usize| -> Result<u16, DecoderError> {
+            let sample = u16::from_be_bytes([bytes[i * 2], bytes[i * 2 + 1]]);
+            if header.bit_depth < 16 && sample >> header.bit_depth != 0 {
+                return Err(error(
+                    "bKGD",
+                    format!(
+                        "Sample {sample} does not fit in bit depth {}",
+                        header.bit_depth
+                    ),
+                ));
+            }
+            Ok(sample)
+        };
+
+        match header.color_type {
+            ColorType::Indexed => {
+                let entries = palette
+                    .ok_or_else(|| error("bKGD", "bKGD precedes PLTE".to_string()))?
+                    .len();
+                if bytes[0] as usize >= entries {
+                    return Err(error(
+                        "bKGD",
+                        format!("Index {} is past the {entries} palette entries", bytes[0]),
+                    ));
+                }
+                Ok(Background::Index(bytes[0]))
+            }
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => {
+                Ok(Background::Gray(read_sample(0)?))
+            }
+            ColorType::Rgb | ColorType::Rgba => Ok(Background::Rgb([
+                read_sample(0)?,
+                read_sample(1)?,
+                read_sample(2)?,
+            ])),
+        }
+    }
+
+    /// Serializes the background into the data of a bKGD chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Background::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Background::Rgb(rgb) => rgb.iter().flat_map(|sample| sample.to_be_bytes()).collect(),
+            Background::Index(index) => vec![*index],
+        }
+    }
+}
+
+/// The sBIT chunk: how many of the high bits of each sample held the
+/// original data, before it was scaled up to the bit depth of the image.
+///
+/// # Attributes
+///
+/// * 'bits' - One count per channel of the color type, red, green and
+///   blue for indexed images, each from 1 to the bit depth, or
+///   to 8 for indexed images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignificantBits {
+    pub bits: Vec<u8>,
+}
+
+impl SignificantBits {
+    /// Parses the data of an sBIT chunk, checking it against the IHDR.
+    pub fn parse(bytes: &[u8], header: &Ihdr) -> Result<Self, DecoderError> {
+        let (channels, depth) = match header.color_type {
+            ColorType::Indexed => (3, 8),
+            color_type => (color_type.channels(), header.bit_depth),
+        };
+        if bytes.len() != channels {
+            return Err(error(
+                "sBIT",
+                format!(
+                    "Length {} is invalid for color type {:?}",
+                    bytes.len(),
+                    header.color_type
+                ),
+            ));
+        }
+        if let Some(bits) = bytes.iter().find(|&&bits| bits == 0 || bits > depth) {
+            return Err(error(
+                "sBIT",
+                format!("{bits} significant bits is not between 1 and {depth}"),
+            ));
+        }
+
+        Ok(Self {
+            bits: bytes.to_vec(),
+        })
+    }
+
+    /// Serializes the counts into the data of an sBIT chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bits.clone()
+    }
+}
+
+/// A single color of a suggested palette.
+///
+/// # Attributes
+///
+/// * 'color' - The red, green, blue and alpha samples, at the sample
+///   depth of the palette. Alpha is not premultiplied.
+/// * 'frequency' - How often the color is used, relative to the other
+///   entries, or 0 if unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuggestedEntry {
+    pub color: [u16; 4],
+    pub frequency: u16,
+}
+
+/// The sPLT chunk: a palette suggested for reducing the colors of the
+/// image, which any color type may have several of.
+///
+/// # Attributes
+///
+/// * 'name' - The name of the palette, following the rules of text keywords.
+/// * 'sample_depth' - 8 or 16, the depth of the samples of each entry.
+/// * 'entries' - The colors of the palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestedPalette {
+    pub name: String,
+    pub sample_depth: u8,
+    pub entries: Vec<SuggestedEntry>,
+}
+
+impl SuggestedPalette {
+    /// Parses the data of an sPLT chunk.
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecoderError> {
+        let (name, rest) = split_at_null(bytes)
+            .ok_or_else(|| error("sPLT", "Missing palette name separator".to_string()))?;
+        let name = latin1_decode(name);
+        validate_keyword(&name).map_err(|e| error("sPLT", e))?;
+
+        let (&sample_depth, entries) = rest.split_first().unwrap_or((&0, &[]));
+        let entry_size = match sample_depth {
+            8 => 6,
+            16 => 10,
+            depth => return Err(error("sPLT", format!("Invalid sample depth {depth}"))),
+        };
+        if !entries.len().is_multiple_of(entry_size) {
+            return Err(error(
+                "sPLT",
+                format!(
+                    "Length {} of the entries is not a multiple of {entry_size}",
+                    entries.len()
+                ),
+            ));
+        }
+
+        let entries = entries
+            .chunks_exact(entry_size)
+            .map(|entry| {
+                let read = |i: usize| u16::from_be_bytes([entry[i], entry[i + 1]]);
+                match sample_depth {
+                    8 => SuggestedEntry {
+                        color: [entry[0], entry[1], entry[2], entry[3]].map(u16::from),
+                        frequency: read(4),
+                    },
+                    _ => SuggestedEntry {
+                        color: [read(0), read(2), read(4), read(6)],
+                        frequency: read(8),
+                    },
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            name,
+            sample_depth,
+            entries,
+        })
+    }
+
+    /// Serializes the palette into the data of an sPLT chunk.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the chunk data or a description of why
+    /// the palette cannot be stored, such as an invalid name or samples
+    /// which do not fit in the sample depth.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        validate_keyword(&self.name)?;
+        let mut bytes = latin1_encode(&self.name).ok_or("Palette name is not Latin-1")?;
+        bytes.extend([0, self.sample_depth]);
+
+        for entry in &self.entries {
+            match self.sample_depth {
+                8 => {
+                    for sample in entry.color {
+                        let sample = u8::try_from(sample)
+                            .map_err(|_| format!("Sample {sample} does not fit in 8 bits"))?;
+                        bytes.push(sample);
+                    }
+                }
+                16 => bytes.extend(entry.color.iter().flat_map(|sample| sample.to_be_bytes())),
+                depth => return Err(format!("Invalid sample depth {depth}")),
+            }
+            bytes.extend(entry.frequency.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+}
+
+/// All ancillary metadata of an image other than text and colorimetry.
+///
+/// # Attributes
+///
+/// * 'physical' - The pHYs chunk.
+/// * 'time' - The tIME chunk.
+/// * 'background' - The bKGD chunk.
+/// * 'significant_bits' - The sBIT chunk.
+/// * 'histogram' - The hIST chunk, one frequency per palette entry.
+/// * 'suggested_palettes' - Every sPLT chunk in file order.
+/// * 'exif' - The raw data of the eXIf chunk, starting with the 'II' or
+///   'MM' byte order mark of its TIFF header.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::decoder::Png;
+/// let png = Png::from_path("scan.png")?;
+///
+/// if let Some([x, y]) = png.metadata.physical.and_then(|physical| physical.dpi()) {
+///     println!("Scanned at {:.0}x{:.0} DPI", x, y);
+/// }
+/// # Ok::<(), chameleon::png::decoder::DecoderError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Metadata {
+    pub physical: Option<PhysicalDimensions>,
+    pub time: Option<Time>,
+    pub background: Option<Background>,
+    pub significant_bits: Option<SignificantBits>,
+    pub histogram: Option<Vec<u16>>,
+    pub suggested_palettes: Vec<SuggestedPalette>,
+    pub exif: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Parses a metadata chunk into the matching field. Only the first of
+    /// each chunk is used, other than sPLT of which there can be several.
+    ///
+    /// # Arguments
+    ///
+    /// * 'kind' - The chunk type.
+    /// * 'bytes' - The data of the chunk.
+    /// * 'header' - The IHDR of the image.
+    /// * 'palette' - The parsed PLTE chunk, if any.
+    ///
+    /// # Returns
+    ///
+    /// A result which is either empty or holds a DecoderError::MetadataError.
+    pub fn parse_chunk(
+        &mut self,
+        kind: &[u8; 4],
+        bytes: &[u8],
+        header: &Ihdr,
+        palette: Option<&[Rgb8]>,
+    ) -> Result<(), DecoderError> {
+        match kind {
+            b"pHYs" if self.physical.is_none() => {
+                self.physical = Some(PhysicalDimensions::parse(bytes)?)
+            }
+            b"tIME" if self.time.is_none() => self.time = Some(Time::parse(bytes)?),
+            b"bKGD" if self.background.is_none() => {
+                self.background = Some(Background::parse(bytes, header, palette)?)
+            }
+            b"sBIT" if self.significant_bits.is_none() => {
+                self.significant_bits = Some(SignificantBits::parse(bytes, header)?)
+            }
+            b"hIST" if self.histogram.is_none() => {
+                self.histogram = Some(parse_histogram(bytes, palette)?)
+            }
+            b"sPLT" => {
+                let suggested = SuggestedPalette::parse(bytes)?;
+                if self
+                    .suggested_palettes
+                    .iter()
+                    .any(|palette| palette.name == suggested.name)
+                {
+                    return Err(error(
+                        "sPLT",
+                        format!("Palette name '{}' is used twice", suggested.name),
+                    ));
+                }
+                self.suggested_palettes.push(suggested);
+            }
+            b"eXIf" if self.exif.is_none() => {
+                if !(bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*")) {
+                    return Err(error("eXIf", "Missing TIFF header".to_string()));
+                }
+                self.exif = Some(bytes.to_vec());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Serializes every chunk present, sBIT first as it has to come
+    /// before PLTE, and the rest in the order they are written after it.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either each chunk type along with its data, or
+    /// a description of why a chunk cannot be written.
+    pub fn to_chunks(&self) -> Result<Vec<RawChunk>, String> {
+        let mut chunks = Vec::new();
+        if let Some(significant_bits) = &self.significant_bits {
+            chunks.push((*b"sBIT", significant_bits.to_bytes()));
+        }
+        if let Some(background) = &self.background {
+            chunks.push((*b"bKGD", background.to_bytes()));
+        }
+        if let Some(histogram) = &self.histogram {
+            let bytes = histogram.iter().flat_map(|v| v.to_be_bytes()).collect();
+            chunks.push((*b"hIST", bytes));
+        }
+        if let Some(physical) = &self.physical {
+            chunks.push((*b"pHYs", physical.to_bytes()));
+        }
+        for palette in &self.suggested_palettes {
+            chunks.push((*b"sPLT", palette.to_bytes()?));
+        }
+        if let Some(exif) = &self.exif {
+            chunks.push((*b"eXIf", exif.clone()));
+        }
+        if let Some(time) = &self.time {
+            chunks.push((*b"tIME", time.to_bytes()));
+        }
+        Ok(chunks)
+    }
+}
+
+/// Parses the data of an hIST chunk, one frequency per palette entry.
+fn parse_histogram(bytes: &[u8], palette: Option<&[Rgb8]>) -> Result<Vec<u16>, DecoderError> {
+    let entries = palette
+        .ok_or_else(|| error("hIST", "hIST without a PLTE chunk".to_string()))?
+        .len();
+    if bytes.len() != entries * 2 {
+        return Err(error(
+            "hIST",
+            format!(
+                "Length {} does not match the {entries} palette entries",
+                bytes.len()
+            ),
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|v| u16::from_be_bytes([v[0], v[1]]))
+        .collect())
+}
+
+/// Builds a DecoderError::MetadataError naming the chunk at fault.
+fn error(kind: &str, e: String) -> DecoderError {
+    DecoderError::MetadataError(format!("{kind}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::pixel::Rgb;
+
+    fn header(color_type: ColorType, bit_depth: u8) -> Ihdr {
+        Ihdr {
+            width: 1,
+            height: 1,
+            bit_depth,
+            color_type,
+            interlaced: false,
+        }
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let header = header(ColorType::Indexed, 4);
+        let palette = [Rgb([0, 0, 0]), Rgb([255, 255, 255]), Rgb([255, 0, 0])];
+        let metadata = Metadata {
+            physical: Some(PhysicalDimensions::from_dpi(300.0, 150.0)),
+            time: Some(Time::from_unix(1_700_000_000)),
+            background: Some(Background::Index(2)),
+            significant_bits: Some(SignificantBits {
+                bits: vec![5, 6, 5],
+            }),
+            histogram: Some(vec![10, 0, 65535]),
+            suggested_palettes: vec![
+                SuggestedPalette {
+                    name: "Web safe".to_string(),
+                    sample_depth: 8,
+                    entries: vec![SuggestedEntry {
+                        color: [51, 102, 153, 255],
+                        frequency: 7,
+                    }],
+                },
+                SuggestedPalette {
+                    name: "Deep".to_string(),
+                    sample_depth: 16,
+                    entries: vec![SuggestedEntry {
+                        color: [1000, 2000, 3000, 65535],
+                        frequency: 0,
+                    }],
+                },
+            ],
+            exif: Some(b"MM\0*\0\0\0\x08\0\0".to_vec()),
+        };
+
+        let chunks = metadata.to_chunks().unwrap();
+        assert_eq!(chunks[0].0, *b"sBIT");
+        let mut parsed = Metadata::default();
+        for (kind, bytes) in &chunks {
+            parsed
+                .parse_chunk(kind, bytes, &header, Some(&palette))
+                .unwrap();
+        }
+        assert_eq!(parsed, metadata);
+    }
+
+    #[test]
+    fn physical_dimensions() {
+        let physical = PhysicalDimensions::from_dpi(300.0, 72.0);
+        assert_eq!((physical.x, physical.y), (11811, 2835));
+        let [x, y] = physical.dpi().unwrap();
+        assert_eq!((x.round(), y.round()), (300.0, 72.0));
+
+        let aspect = PhysicalDimensions::parse(&[0, 0, 0, 2, 0, 0, 0, 1, 0]).unwrap();
+        assert_eq!(aspect.unit, PhysicalUnit::Unknown);
+        assert_eq!(aspect.dpi(), None);
+    }
+
+    #[test]
+    fn time_from_unix() {
+        assert_eq!(Time::from_unix(0).to_string(), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            Time::from_unix(951_782_400).to_string(),
+            "2000-02-29T00:00:00Z"
+        );
+        assert_eq!(
+            Time::from_unix(1_700_000_000).to_string(),
+            "2023-11-14T22:13:20Z"
+        );
+    }
+
+    #[test]
+    fn background_follows_color_type() {
+        let gray = header(ColorType::GrayscaleAlpha, 8);
+        assert_eq!(
+            Background::parse(&[0, 200], &gray, None).unwrap(),
+            Background::Gray(200)
+        );
+        let rgb = header(ColorType::Rgb, 16);
+        assert_eq!(
+            Background::parse(&[1, 0, 2, 0, 3, 0], &rgb, None).unwrap(),
+            Background::Rgb([256, 512, 768])
+        );
+        let palette = [Rgb([0, 0, 0])];
+        let indexed = header(ColorType::Indexed, 1);
+        assert_eq!(
+            Background::parse(&[0], &indexed, Some(&palette)).unwrap(),
+            Background::Index(0)
+        );
+    }
+
+    #[test]
+    fn invalid_chunks_are_rejected() {
+        let gray = header(ColorType::Grayscale, 4);
+        let indexed = header(ColorType::Indexed, 2);
+        let palette = [Rgb([0, 0, 0]), Rgb([9, 9, 9])];
+        let invalid: [(&[u8; 4], &[u8], &Ihdr); 12] = [
+            (b"pHYs", &[0; 8], &gray),
+            (b"pHYs", &[0, 0, 0, 1, 0, 0, 0, 1, 2], &gray),
+            (b"tIME", &[7, 232, 13, 1, 0, 0, 0], &gray),
+            (b"tIME", &[7, 232, 1, 1, 24, 0, 0], &gray),
+            (b"bKGD", &[0, 16], &gray),
+            (b"bKGD", &[2], &indexed),
+            (b"sBIT", &[5], &gray),
+            (b"sBIT", &[1, 1], &gray),
+            (b"hIST", &[0, 1], &indexed),
+            (b"sPLT", b"Name\0\x04", &gray),
+            (b"sPLT", b"Name\0\x08\0\0\0", &gray),
+            (b"eXIf", b"not tiff", &gray),
+        ];
+        for (kind, bytes, header) in invalid {
+            let result = Metadata::default().parse_chunk(kind, bytes, header, Some(&palette));
+            assert!(
+                matches!(result, Err(DecoderError::MetadataError(_))),
+                "{kind:?} {bytes:?}"
+            );
+        }
+
+        // sPLT names have to be unique.
+        let mut metadata = Metadata::default();
+        let splt = b"Name\0\x08\0\0\0\0\0\0";
+        metadata.parse_chunk(b"sPLT", splt, &gray, None).unwrap();
+        assert!(metadata.parse_chunk(b"sPLT", splt, &gray, None).is_err());
+
+        let wide = SuggestedPalette {
+            name: "Wide".to_string(),
+            sample_depth: 8,
+            entries: vec![SuggestedEntry {
+                color: [256, 0, 0, 0],
+                frequency: 0,
+            }],
+        };
+        assert!(wide.to_bytes().is_err());
+    }
+}
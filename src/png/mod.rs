@@ -1 +1,17 @@
+pub mod apng;
+pub mod chunk;
+pub mod colorimetry;
 pub mod decoder;
+pub mod encoder;
+pub mod filter;
+pub mod ihdr;
+pub mod interlace;
+pub mod metadata;
+pub mod optimize;
+pub mod palette;
+pub mod pixels;
+pub mod recovery;
+pub mod stream;
+pub mod text;
+
+pub use stream::{StreamDecoder, StreamEncoder};
@@ -0,0 +1,656 @@
+//! Lossless optimization of PNG files, re-encoding them as small as
+//! possible without changing a single pixel.
+//!
+//! The pixels are first analysed to find every smaller layout which can
+//! still hold them exactly:
+//!
+//! ```text
+//! RGBA -> RGB         When every pixel is opaque.
+//! RGBA -> RGB + tRNS  When alpha is only 0 or full, and all transparent
+//!                     pixels share a color no opaque pixel uses.
+//! RGB  -> Grayscale   When every pixel has red = green = blue.
+//! RGB  -> Indexed     When there are at most 256 colors.
+//! 16   -> 8, 4, 2, 1  When no sample needs the extra precision.
+//! ```
+//!
+//! Each layout, along with the original one, is then filtered with every
+//! filter strategy and compressed at every level, keeping the smallest.
+use crate::compression::zlib;
+use crate::image::pixel::{Rgb, Rgb8, Rgba16};
+use crate::png::chunk::BEFORE_PLTE;
+use crate::png::decoder::{DecoderError, Png, PNG_HEADER};
+use crate::png::encoder::{self, EncoderOptions};
+use crate::png::filter::{FilterStrategy, FILTER_TYPES};
+use crate::png::ihdr::{ColorType, Ihdr};
+use crate::png::interlace::set_packed;
+use crate::png::palette::Transparency;
+use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+//      +---------+
+//      | OPTIONS |
+//      +---------+
+
+/// Which optional chunks to remove.
+///
+/// # Fields
+///
+/// * 'None' - Keep every chunk which is still valid for the new image data.
+/// * 'Safe' - Keep only chunks which change how the image is displayed:
+///   cHRM, gAMA, iCCP, sRGB, cICP, mDCV, cLLI and pHYs.
+/// * 'All' - Remove every optional chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StripMode {
+    #[default]
+    None,
+    Safe,
+    All,
+}
+
+/// Options controlling how hard the optimizer tries.
+///
+/// # Attributes
+///
+/// * 'filters' - The filter strategies to try.
+/// * 'levels' - The compression levels to try, the first is also used
+///   when picking filters with FilterStrategy::BruteForce.
+/// * 'strip' - Which optional chunks to remove.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::png::filter::FilterStrategy;
+/// # use chameleon::png::optimize::{OptimizeOptions, StripMode};
+/// // A quick pass which also removes metadata.
+/// let options = OptimizeOptions {
+///     filters: vec![FilterStrategy::MinimumSum],
+///     levels: vec![6],
+///     strip: StripMode::Safe,
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizeOptions {
+    pub filters: Vec<FilterStrategy>,
+    pub levels: Vec<u8>,
+    pub strip: StripMode,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        let mut filters: Vec<FilterStrategy> = FILTER_TYPES.map(FilterStrategy::Fixed).to_vec();
+        filters.push(FilterStrategy::MinimumSum);
+        filters.push(FilterStrategy::BruteForce);
+
+        Self {
+            filters,
+            levels: vec![6, 9],
+            strip: StripMode::None,
+        }
+    }
+}
+
+//      +-----------+
+//      | OPTIMIZER |
+//      +-----------+
+
+/// Chunks kept by StripMode::Safe.
+pub(crate) const DISPLAY_CHUNKS: [&[u8; 4]; 8] = [
+    b"cHRM", b"gAMA", b"iCCP", b"sRGB", b"cICP", b"mDCV", b"cLLI", b"pHYs",
+];
+
+/// Chunks whose contents depend on the color type, bit depth or palette,
+/// which tie the image to its original layout while they are kept.
+const LAYOUT_CHUNKS: [&[u8; 4]; 3] = [b"bKGD", b"sBIT", b"hIST"];
+
+/// Optional chunks whose meaning is known not to depend on the image
+/// data, so they stay valid after it is re-encoded.
+const KNOWN_CHUNKS: [&[u8; 4]; 17] = [
+    b"cHRM", b"gAMA", b"iCCP", b"sRGB", b"cICP", b"mDCV", b"cLLI", b"sBIT", b"bKGD", b"hIST",
+    b"pHYs", b"sPLT", b"tIME", b"tEXt", b"zTXt", b"iTXt", b"eXIf",
+];
+
+/// A way of storing the image, ready to be filtered and compressed.
+///
+/// # Attributes
+///
+/// * 'header' - The IHDR, never interlaced.
+/// * 'palette' - The PLTE entries, for indexed images.
+/// * 'transparency' - The tRNS chunk.
+/// * 'scanlines' - The unfiltered rows packed back to back.
+struct Candidate {
+    header: Ihdr,
+    palette: Option<Vec<Rgb8>>,
+    transparency: Option<Transparency>,
+    scanlines: Vec<u8>,
+}
+
+/// An optional chunk carried over from the original file.
+///
+/// # Attributes
+///
+/// * 'kind' - The four letter chunk type.
+/// * 'data' - The data of the chunk.
+/// * 'after_idat' - Whether the chunk came after the image data.
+struct KeptChunk {
+    kind: [u8; 4],
+    data: Vec<u8>,
+    after_idat: bool,
+}
+
+/// Re-encodes a PNG as small as possible while keeping every pixel,
+/// including the color of fully transparent pixels, unchanged.
+///
+/// Interlacing is always removed, as it nearly always makes files larger.
+/// When no optional chunks are stripped and nothing smaller is found, the
+/// original file is returned as is.
+///
+/// # Arguments
+///
+/// * 'png' - The decoded PNG to optimize.
+/// * 'options' - Which strategies and levels to try and what to strip.
+///
+/// # Returns
+///
+/// A result containing either the bytes of the smallest PNG file found,
+/// or an OptimizeError if the file cannot be decoded or contains chunks
+/// which cannot be carried over.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::decoder::Png;
+/// # use chameleon::png::optimize::{optimize, OptimizeOptions};
+/// let png = Png::from_path("asset.png")?;
+/// let optimized = optimize(&png, &OptimizeOptions::default())?;
+///
+/// println!("{} -> {} bytes", png.data.raw_data.len(), optimized.len());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn optimize(png: &Png, options: &OptimizeOptions) -> Result<Vec<u8>, OptimizeError> {
+    let chunks = kept_chunks(png, options.strip)?;
+
+    let mut candidates = vec![Candidate {
+        header: Ihdr {
+            interlaced: false,
+            ..png.header
+        },
+        palette: png.palette.clone(),
+        transparency: png.transparency.clone(),
+        scanlines: png.scanlines()?,
+    }];
+
+    // Chunks such as bKGD describe values in the original layout, so it
+    // has to be kept for them to stay valid.
+    let layout_fixed = chunks
+        .iter()
+        .any(|chunk| LAYOUT_CHUNKS.contains(&&chunk.kind));
+    if !layout_fixed {
+        candidates.extend(reduced_candidates(png)?);
+    }
+
+    let first_level = options.levels.first().copied().unwrap_or(9);
+    let mut best: Option<Vec<u8>> = None;
+
+    for candidate in &candidates {
+        for &filter in &options.filters {
+            let encoder_options = EncoderOptions {
+                filter,
+                compression_level: first_level,
+                ..Default::default()
+            };
+            let filtered = encoder::filter_scanlines(
+                &candidate.header,
+                &candidate.scanlines,
+                &encoder_options,
+            );
+
+            for &level in &options.levels {
+                let idat = zlib::compress(&filtered, level);
+                let output = assemble(candidate, &idat, &chunks);
+                if best.as_ref().is_none_or(|best| output.len() < best.len()) {
+                    best = Some(output);
+                }
+            }
+        }
+    }
+
+    let best = best.ok_or(OptimizeError::NothingToTry)?;
+    if options.strip == StripMode::None && best.len() >= png.data.raw_data.len() {
+        return Ok(png.data.raw_data.clone());
+    }
+    Ok(best)
+}
+
+/// Collects the optional chunks to carry over into the optimized file.
+fn kept_chunks(png: &Png, strip: StripMode) -> Result<Vec<KeptChunk>, OptimizeError> {
+    let mut kept = Vec::new();
+    let mut after_idat = false;
+
+    for &(kind, start, end) in &png.data.chunks {
+        match &kind {
+            b"IHDR" | b"PLTE" | b"tRNS" | b"IEND" => continue,
+            b"IDAT" => {
+                after_idat = true;
+                continue;
+            }
+            b"acTL" | b"fcTL" | b"fdAT" => {
+                return Err(OptimizeError::UnsupportedChunk(
+                    "Animated PNGs cannot be optimized".to_string(),
+                ));
+            }
+            _ => {}
+        }
+
+        // A capital first letter marks a chunk needed to display the image.
+        if kind[0].is_ascii_uppercase() {
+            return Err(OptimizeError::UnsupportedChunk(format!(
+                "Unknown critical chunk {}",
+                String::from_utf8_lossy(&kind)
+            )));
+        }
+
+        let keep = match strip {
+            StripMode::None => true,
+            StripMode::Safe => DISPLAY_CHUNKS.contains(&&kind),
+            StripMode::All => false,
+        };
+        // Unknown chunks with a capital last letter are not safe to copy
+        // once the image data has been changed.
+        let safe_to_copy = KNOWN_CHUNKS.contains(&&kind) || kind[3].is_ascii_lowercase();
+
+        if keep && safe_to_copy {
+            kept.push(KeptChunk {
+                kind,
+                data: png.data.raw_data[start..end].to_vec(),
+                after_idat,
+            });
+        }
+    }
+
+    Ok(kept)
+}
+
+/// Finds the smaller layouts which can hold the pixels of the image
+/// exactly, see the module documentation.
+fn reduced_candidates(png: &Png) -> Result<Vec<Candidate>, OptimizeError> {
+    let (width, height) = (png.header.width, png.header.height);
+    let mut pixels: Vec<[u16; 4]> = png
+        .to_image()?
+        .convert::<Rgba16>()
+        .into_vec()
+        .into_iter()
+        .map(|pixel| pixel.0)
+        .collect();
+
+    // Native decoding ignores color keys, so apply them here by comparing
+    // against the key scaled up to 16 bits.
+    if let Some(key) = &png.transparency {
+        let scale = 65535 / ((1u32 << png.header.bit_depth) - 1) as u16;
+        let key = match key {
+            Transparency::GrayKey(gray) => Some([*gray; 3]),
+            Transparency::RgbKey(rgb) => Some(*rgb),
+            Transparency::PaletteAlpha(_) => None,
+        };
+        if let Some(key) = key.map(|key| key.map(|sample| sample * scale)) {
+            for pixel in pixels.iter_mut().filter(|pixel| pixel[..3] == key) {
+                pixel[3] = 0;
+            }
+        }
+    }
+
+    let depth8 = pixels.iter().flatten().all(|&sample| sample % 257 == 0);
+    let opaque = pixels.iter().all(|pixel| pixel[3] == 65535);
+    let gray = pixels
+        .iter()
+        .all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+
+    // A color key can replace the alpha channel when alpha is only ever
+    // 0 or full, and all transparent pixels share a color which no
+    // opaque pixel has.
+    let key = if opaque || pixels.iter().any(|p| p[3] != 0 && p[3] != 65535) {
+        None
+    } else {
+        let first = pixels
+            .iter()
+            .find(|pixel| pixel[3] == 0)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]]);
+        first.filter(|key| {
+            pixels
+                .iter()
+                .all(|pixel| (pixel[3] == 0) == (pixel[..3] == key[..]))
+        })
+    };
+    let alpha_channel = !opaque && key.is_none();
+
+    let color_type = match (gray, alpha_channel) {
+        (true, false) => ColorType::Grayscale,
+        (true, true) => ColorType::GrayscaleAlpha,
+        (false, false) => ColorType::Rgb,
+        (false, true) => ColorType::Rgba,
+    };
+    let bit_depth = match (depth8, color_type) {
+        (false, _) => 16,
+        // Grayscale can go below 8 bits when every value is a multiple of
+        // the step between levels at the lower depth.
+        (true, ColorType::Grayscale) => [1u8, 2, 4, 8]
+            .into_iter()
+            .find(|&depth| {
+                let step = 65535 / ((1u32 << depth) - 1) as u16;
+                pixels.iter().all(|pixel| pixel[0] % step == 0)
+            })
+            .unwrap_or(8),
+        (true, _) => 8,
+    };
+
+    let mut candidates = Vec::new();
+
+    let header = Ihdr {
+        width,
+        height,
+        bit_depth,
+        color_type,
+        interlaced: false,
+    };
+    let channels = color_type.channels();
+    let step = 65535 / ((1u32 << bit_depth) - 1) as u16;
+    let samples = |pixel: &[u16; 4]| -> [u16; 4] {
+        let scaled = pixel.map(|sample| sample / step);
+        // Gray with alpha keeps alpha as its second sample.
+        match channels {
+            2 => [scaled[0], scaled[3], 0, 0],
+            _ => scaled,
+        }
+    };
+    let transparency = key.map(|key| {
+        let key = key.map(|sample| sample / step);
+        match gray {
+            true => Transparency::GrayKey(key[0]),
+            false => Transparency::RgbKey(key),
+        }
+    });
+    candidates.push(Candidate {
+        header,
+        palette: None,
+        transparency,
+        scanlines: pack(&header, &pixels, |pixel| samples(pixel)),
+    });
+
+    if depth8 {
+        if let Some(candidate) = indexed_candidate(width, height, &pixels) {
+            candidates.push(candidate);
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Builds an indexed layout if the image has at most 256 colors.
+fn indexed_candidate(width: u32, height: u32, pixels: &[[u16; 4]]) -> Option<Candidate> {
+    let mut colors: Vec<[u8; 4]> = Vec::new();
+    let mut seen: HashMap<[u8; 4], u8> = HashMap::new();
+    for pixel in pixels {
+        let color = pixel.map(|sample| (sample / 257) as u8);
+        if let Entry::Vacant(entry) = seen.entry(color) {
+            if colors.len() == 256 {
+                return None;
+            }
+            entry.insert(0);
+            colors.push(color);
+        }
+    }
+
+    // Transparent entries go first so the tRNS chunk can stop at the last
+    // of them, and the rest are sorted by brightness so neighboring
+    // indices tend to be similar colors, which filters better.
+    colors.sort_by_key(|color| {
+        let brightness: u16 = color[..3].iter().map(|&sample| sample as u16).sum();
+        (color[3] == 255, brightness, *color)
+    });
+    for (index, color) in colors.iter().enumerate() {
+        seen.insert(*color, index as u8);
+    }
+
+    let bit_depth = [1u8, 2, 4, 8]
+        .into_iter()
+        .find(|&depth| colors.len() <= 1 << depth)
+        .unwrap_or(8);
+    let header = Ihdr {
+        width,
+        height,
+        bit_depth,
+        color_type: ColorType::Indexed,
+        interlaced: false,
+    };
+
+    let alpha: Vec<u8> = colors
+        .iter()
+        .map(|color| color[3])
+        .take_while(|&alpha| alpha != 255)
+        .collect();
+    let transparency = (!alpha.is_empty()).then_some(Transparency::PaletteAlpha(alpha));
+
+    let scanlines = pack(&header, pixels, |pixel| {
+        let color = pixel.map(|sample| (sample / 257) as u8);
+        [seen[&color] as u16, 0, 0, 0]
+    });
+
+    Some(Candidate {
+        header,
+        palette: Some(colors.iter().map(|c| Rgb([c[0], c[1], c[2]])).collect()),
+        transparency,
+        scanlines,
+    })
+}
+
+/// Packs pixels into scanlines for the given header.
+///
+/// # Arguments
+///
+/// * 'header' - The layout to pack into.
+/// * 'pixels' - The pixels of the image in row order.
+/// * 'samples' - Gives the samples of a pixel at the header's bit depth,
+///   only the first channels() of which are used.
+fn pack<F>(header: &Ihdr, pixels: &[[u16; 4]], samples: F) -> Vec<u8>
+where
+    F: Fn(&[u16; 4]) -> [u16; 4],
+{
+    let row_bytes = header.row_bytes(header.width);
+    let channels = header.color_type.channels();
+    let depth = header.bit_depth as usize;
+    let mut scanlines = vec![0u8; row_bytes * header.height as usize];
+
+    for (row, packed) in pixels
+        .chunks_exact(header.width as usize)
+        .zip(scanlines.chunks_exact_mut(row_bytes))
+    {
+        let mut index = 0;
+        for pixel in row {
+            for &sample in &samples(pixel)[..channels] {
+                match depth {
+                    16 => packed[index * 2..index * 2 + 2].copy_from_slice(&sample.to_be_bytes()),
+                    8 => packed[index] = sample as u8,
+                    _ => set_packed(packed, index, depth, sample as u8),
+                }
+                index += 1;
+            }
+        }
+    }
+
+    scanlines
+}
+
+/// Writes a complete file for a candidate and its compressed image data.
+fn assemble(candidate: &Candidate, idat: &[u8], chunks: &[KeptChunk]) -> Vec<u8> {
+    let mut output = PNG_HEADER.to_vec();
+    encoder::write_chunk(&mut output, b"IHDR", &candidate.header.to_bytes());
+
+    let (after, before): (Vec<&KeptChunk>, Vec<&KeptChunk>) =
+        chunks.iter().partition(|chunk| chunk.after_idat);
+    let (early, late): (Vec<&KeptChunk>, Vec<&KeptChunk>) = before
+        .into_iter()
+        .partition(|chunk| BEFORE_PLTE.contains(&&chunk.kind));
+
+    for chunk in early {
+        encoder::write_chunk(&mut output, &chunk.kind, &chunk.data);
+    }
+    if let Some(palette) = &candidate.palette {
+        let bytes: Vec<u8> = palette.iter().flat_map(|entry| entry.0).collect();
+        encoder::write_chunk(&mut output, b"PLTE", &bytes);
+    }
+    if let Some(transparency) = &candidate.transparency {
+        encoder::write_chunk(&mut output, b"tRNS", &transparency.to_bytes());
+    }
+    for chunk in late {
+        encoder::write_chunk(&mut output, &chunk.kind, &chunk.data);
+    }
+    encoder::write_chunk(&mut output, b"IDAT", idat);
+    for chunk in after {
+        encoder::write_chunk(&mut output, &chunk.kind, &chunk.data);
+    }
+    encoder::write_chunk(&mut output, b"IEND", &[]);
+
+    output
+}
+
+//       +--------+
+//       | ERRORS |
+//       +--------+
+
+/// Enum containing possible errors raised while optimizing a PNG.
+///
+/// # Fields
+///
+/// * 'DecoderError' - Wrapper for errors while decoding the original image.
+/// * 'UnsupportedChunk' - The file contains a chunk which cannot be carried
+///   over, such as an unknown critical chunk or APNG animation.
+/// * 'NothingToTry' - The options list no filters or no levels.
+#[derive(Debug)]
+pub enum OptimizeError {
+    DecoderError(DecoderError),
+    UnsupportedChunk(String),
+    NothingToTry,
+}
+
+// Defines how OptimizeErrors are displayed.
+impl Display for OptimizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptimizeError::DecoderError(e) => {
+                write!(f, "Error: Could not decode the image to optimize, '{e}'")
+            }
+            OptimizeError::UnsupportedChunk(e) => {
+                write!(f, "Error: Cannot optimize file, '{e}'.")
+            }
+            OptimizeError::NothingToTry => {
+                write!(
+                    f,
+                    "Error: No filter strategies or compression levels to try."
+                )
+            }
+        }
+    }
+}
+
+// Allows for conversion from DecoderError to OptimizeError.
+impl From<DecoderError> for OptimizeError {
+    fn from(error: DecoderError) -> Self {
+        OptimizeError::DecoderError(error)
+    }
+}
+
+// Implements the Error interface for OptimizeError, with the wrapped
+// DecoderError as its source.
+impl Error for OptimizeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OptimizeError::DecoderError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::buffer::Image;
+    use crate::image::pixel::{Rgba, Rgba8};
+    use crate::png::pixels::OutputFormat;
+
+    /// Encodes an RGBA8 image with an extra chunk just before IEND.
+    fn encode(image: &Image<Rgba8>, extra: (&[u8; 4], &[u8])) -> Vec<u8> {
+        let mut bytes = encoder::encode_image(image, &EncoderOptions::default()).unwrap();
+        let iend = bytes.split_off(bytes.len() - 12);
+        encoder::write_chunk(&mut bytes, extra.0, extra.1);
+        bytes.extend(iend);
+        bytes
+    }
+
+    fn pixels(png: &Png) -> Vec<u8> {
+        png.decode_as(OutputFormat::Rgba8).unwrap().data
+    }
+
+    #[test]
+    fn reduces_layout_losslessly() {
+        // Opaque with four colors, so alpha can go either way.
+        let colors = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [9, 9, 9, 255],
+        ];
+        let image = Image::from_fn(24, 16, |x, y| Rgba(colors[((x / 3 + y) % 4) as usize]));
+        let original = Png::from_bytes(encode(&image, (b"noTe", b"kept"))).unwrap();
+
+        let optimized = optimize(&original, &OptimizeOptions::default()).unwrap();
+        assert!(optimized.len() < original.data.raw_data.len());
+
+        let png = Png::from_bytes(optimized).unwrap();
+        assert_ne!(png.header.color_type, ColorType::Rgba);
+        assert_eq!(pixels(&png), pixels(&original));
+        assert!(png.data.chunks.iter().any(|chunk| &chunk.0 == b"noTe"));
+    }
+
+    #[test]
+    fn reduces_gray_and_keeps_transparent_colors() {
+        // Over 256 colors, gray, with binary alpha and one transparent color.
+        let image = Image::from_fn(40, 20, |x, y| match (x + y) % 7 {
+            0 => Rgba([17, 17, 17, 0]),
+            _ => Rgba([(x * 6) as u8, (x * 6) as u8, (x * 6) as u8, 255]),
+        });
+        let original = Png::from_bytes(encode(&image, (b"noTe", b""))).unwrap();
+        let options = OptimizeOptions {
+            filters: vec![FilterStrategy::MinimumSum],
+            levels: vec![6],
+            strip: StripMode::All,
+        };
+
+        let png = Png::from_bytes(optimize(&original, &options).unwrap()).unwrap();
+        assert_eq!(png.header.color_type, ColorType::Grayscale);
+        assert!(matches!(png.transparency, Some(Transparency::GrayKey(17))));
+        assert_eq!(pixels(&png), pixels(&original));
+        assert!(!png.data.chunks.iter().any(|chunk| &chunk.0 == b"noTe"));
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_carry_over() {
+        let image = Image::from_pixel(2, 2, Rgba([1, 2, 3, 255]));
+        let png = Png::from_bytes(encode(&image, (b"HeLo", b""))).unwrap();
+        assert!(matches!(
+            optimize(&png, &OptimizeOptions::default()),
+            Err(OptimizeError::UnsupportedChunk(_))
+        ));
+
+        let png = Png::from_bytes(encode(&image, (b"noTe", b""))).unwrap();
+        let options = OptimizeOptions {
+            levels: Vec::new(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            optimize(&png, &options),
+            Err(OptimizeError::NothingToTry)
+        ));
+    }
+}
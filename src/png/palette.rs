@@ -0,0 +1,152 @@
+//! Parsing and serialization of the PLTE and tRNS chunks, which give
+//! indexed images their colors and let any image without an alpha
+//! channel be transparent.
+use crate::image::pixel::{Rgb, Rgb8};
+use crate::png::decoder::DecoderError;
+use crate::png::ihdr::{ColorType, Ihdr};
+
+/// The three forms the tRNS chunk takes depending on the color type.
+///
+/// # Fields
+///
+/// * 'PaletteAlpha' - The alpha of each palette entry in order, entries
+///   past the end of the list are fully opaque.
+/// * 'GrayKey' - Grayscale pixels with exactly this sample value are fully
+///   transparent, all others are fully opaque.
+/// * 'RgbKey' - Truecolor pixels with exactly these red, green and blue
+///   samples are fully transparent, all others are fully opaque.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    PaletteAlpha(Vec<u8>),
+    GrayKey(u16),
+    RgbKey([u16; 3]),
+}
+
+/// Parses the data of a PLTE chunk into its entries.
+///
+/// # Arguments
+///
+/// * 'bytes' - The data of the PLTE chunk, three bytes per entry.
+/// * 'header' - The IHDR of the image, which limits the number of entries
+///   to what its bit depth can index.
+///
+/// # Returns
+///
+/// A result containing either the entries or a DecoderError::PlteError.
+pub fn parse_palette(bytes: &[u8], header: &Ihdr) -> Result<Vec<Rgb8>, DecoderError> {
+    if matches!(
+        header.color_type,
+        ColorType::Grayscale | ColorType::GrayscaleAlpha
+    ) {
+        return Err(DecoderError::PlteError(format!(
+            "PLTE is not allowed for color type {:?}",
+            header.color_type
+        )));
+    }
+    if bytes.is_empty() || !bytes.len().is_multiple_of(3) {
+        return Err(DecoderError::PlteError(format!(
+            "Length {} is not a non-zero multiple of 3",
+            bytes.len()
+        )));
+    }
+
+    let entries = bytes.len() / 3;
+    // Truecolor images may suggest a palette of up to 256 entries, indexed
+    // images are limited to what their bit depth can address.
+    let limit = match header.color_type {
+        ColorType::Indexed => 1 << header.bit_depth,
+        _ => 256,
+    };
+    if entries > limit {
+        return Err(DecoderError::PlteError(format!(
+            "{entries} entries exceed the {limit} allowed at bit depth {}",
+            header.bit_depth
+        )));
+    }
+
+    Ok(bytes
+        .chunks_exact(3)
+        .map(|entry| Rgb([entry[0], entry[1], entry[2]]))
+        .collect())
+}
+
+impl Transparency {
+    /// Parses the data of a tRNS chunk into the form matching the color
+    /// type of the image.
+    ///
+    /// # Arguments
+    ///
+    /// * 'bytes' - The data of the tRNS chunk.
+    /// * 'header' - The IHDR of the image.
+    /// * 'palette' - The parsed PLTE chunk, required for indexed images.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the Transparency or a
+    /// DecoderError::TrnsError.
+    pub fn parse(
+        bytes: &[u8],
+        header: &Ihdr,
+        palette: Option<&[Rgb8]>,
+    ) -> Result<Self, DecoderError> {
+        // Keys are stored as 16 bit values but only the lowest bit depth
+        // bits may be used.
+        let read_key = |i: usize| -> Result<u16, DecoderError> {
+            let key = u16::from_be_bytes([bytes[i * 2], bytes[i * 2 + 1]]);
+            if header.bit_depth < 16 && key >> header.bit_depth != 0 {
+                return Err(DecoderError::TrnsError(format!(
+                    "Key {key} does not fit in bit depth {}",
+                    header.bit_depth
+                )));
+            }
+            Ok(key)
+        };
+
+        match header.color_type {
+            ColorType::Indexed => {
+                let entries = palette
+                    .ok_or_else(|| DecoderError::TrnsError("tRNS precedes PLTE".to_string()))?
+                    .len();
+                if bytes.len() > entries {
+                    return Err(DecoderError::TrnsError(format!(
+                        "{} alpha values for {entries} palette entries",
+                        bytes.len()
+                    )));
+                }
+                Ok(Transparency::PaletteAlpha(bytes.to_vec()))
+            }
+            ColorType::Grayscale if bytes.len() == 2 => Ok(Transparency::GrayKey(read_key(0)?)),
+            ColorType::Rgb if bytes.len() == 6 => Ok(Transparency::RgbKey([
+                read_key(0)?,
+                read_key(1)?,
+                read_key(2)?,
+            ])),
+            ColorType::Grayscale | ColorType::Rgb => Err(DecoderError::TrnsError(format!(
+                "Length {} is invalid for color type {:?}",
+                bytes.len(),
+                header.color_type
+            ))),
+            _ => Err(DecoderError::TrnsError(format!(
+                "tRNS is not allowed for color type {:?} which has an alpha channel",
+                header.color_type
+            ))),
+        }
+    }
+
+    /// Serializes the transparency into the data of a tRNS chunk.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Transparency::PaletteAlpha(alpha) => alpha.clone(),
+            Transparency::GrayKey(key) => key.to_be_bytes().to_vec(),
+            Transparency::RgbKey(keys) => keys.iter().flat_map(|key| key.to_be_bytes()).collect(),
+        }
+    }
+
+    /// The alpha of the given palette entry, 255 for non-palette forms.
+    pub fn palette_alpha(&self, index: usize) -> u8 {
+        match self {
+            Transparency::PaletteAlpha(alpha) => alpha.get(index).copied().unwrap_or(255),
+            _ => 255,
+        }
+    }
+}
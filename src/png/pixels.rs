@@ -0,0 +1,288 @@
+//! Conversion of unfiltered scanlines into a pixel buffer, unpacking
+//! samples smaller than a byte and expanding palette indices so that
+//! every color type and bit depth ends up in one of a few layouts.
+use crate::image::pixel::{Rgb, Rgb8};
+use crate::png::decoder::DecoderError;
+use crate::png::ihdr::{ColorType, Ihdr};
+use crate::png::interlace::get_packed;
+use crate::png::palette::Transparency;
+
+/// The layouts a PixelBuffer can store its pixels in. Samples are
+/// stored in the order given by the name, 16 bit samples are stored
+/// big-endian as they are in the PNG itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Luma8,
+    LumaA8,
+    Rgb8,
+    Rgba8,
+    Luma16,
+    LumaA16,
+    Rgb16,
+    Rgba16,
+}
+
+impl PixelFormat {
+    /// The number of samples making up each pixel.
+    pub fn channels(&self) -> usize {
+        match self {
+            PixelFormat::Luma8 | PixelFormat::Luma16 => 1,
+            PixelFormat::LumaA8 | PixelFormat::LumaA16 => 2,
+            PixelFormat::Rgb8 | PixelFormat::Rgb16 => 3,
+            PixelFormat::Rgba8 | PixelFormat::Rgba16 => 4,
+        }
+    }
+
+    /// The number of bytes making up each sample.
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            PixelFormat::Luma8 | PixelFormat::LumaA8 | PixelFormat::Rgb8 | PixelFormat::Rgba8 => 1,
+            _ => 2,
+        }
+    }
+
+    /// The number of bytes making up each pixel.
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.channels() * self.bytes_per_sample()
+    }
+}
+
+/// Which layout decoded pixels should be normalized to.
+///
+/// # Fields
+///
+/// * 'Native' - The layout closest to how the PNG stores them. Samples
+///   smaller than a byte are scaled up to 8 bits, 16 bit samples
+///   are kept, and palette indices are expanded to Rgb8, or to
+///   Rgba8 if the palette has alpha values.
+/// * 'Rgba8' - Always 8 bit red, green, blue and alpha, with 16 bit samples
+///   rounded to 8 bits and alpha added where missing, taking any
+///   tRNS chunk into account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Native,
+    Rgba8,
+}
+
+/// A struct holding decoded pixels, stored row by row with no padding.
+///
+/// # Attributes
+///
+/// * 'width' - The width of the image in pixels.
+/// * 'height' - The height of the image in pixels.
+/// * 'format' - The layout of each pixel.
+/// * 'data' - The pixels, width * height * format.bytes_per_pixel() bytes.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::decoder::Png;
+/// # use chameleon::png::pixels::OutputFormat;
+/// let png = Png::from_path("image.png")?;
+/// let pixels = png.decode_as(OutputFormat::Rgba8)?;
+///
+/// let &[r, g, b, a] = pixels.pixel(0, 0) else { unreachable!() };
+/// # Ok::<(), chameleon::png::decoder::DecoderError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub data: Vec<u8>,
+}
+
+impl PixelBuffer {
+    /// Returns the bytes of the pixel at the given position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside the image.
+    pub fn pixel(&self, x: u32, y: u32) -> &[u8] {
+        assert!(x < self.width && y < self.height, "Pixel out of bounds.");
+        let size = self.format.bytes_per_pixel();
+        let start = (y as usize * self.width as usize + x as usize) * size;
+        &self.data[start..start + size]
+    }
+
+    /// Returns the bytes of the given row.
+    pub fn row(&self, y: u32) -> &[u8] {
+        let size = self.width as usize * self.format.bytes_per_pixel();
+        &self.data[y as usize * size..(y as usize + 1) * size]
+    }
+}
+
+/// Scales a sample of the given bit depth to the full 8 bit range,
+/// so the largest value of any depth becomes 255.
+pub fn scale_to_8_bits(value: u16, bit_depth: u8) -> u8 {
+    match bit_depth {
+        16 => ((value as u32 * 255 + 32895) >> 16) as u8,
+        8 => value as u8,
+        _ => (value as u32 * 255 / ((1u32 << bit_depth) - 1)) as u8,
+    }
+}
+
+/// Reads the 'index'th sample of a row at the given bit depth.
+fn read_sample(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => row[index] as u16,
+        _ => get_packed(row, index, bit_depth as usize) as u16,
+    }
+}
+
+/// The layout expand produces for an image.
+///
+/// # Arguments
+///
+/// * 'header' - The IHDR of the image.
+/// * 'has_transparency' - Whether the image has a tRNS chunk.
+/// * 'output' - The layout being normalized to.
+pub fn output_format(header: &Ihdr, has_transparency: bool, output: OutputFormat) -> PixelFormat {
+    match (output, header.color_type, header.bit_depth) {
+        (OutputFormat::Rgba8, _, _) => PixelFormat::Rgba8,
+        (_, ColorType::Grayscale, 16) => PixelFormat::Luma16,
+        (_, ColorType::Grayscale, _) => PixelFormat::Luma8,
+        (_, ColorType::GrayscaleAlpha, 16) => PixelFormat::LumaA16,
+        (_, ColorType::GrayscaleAlpha, _) => PixelFormat::LumaA8,
+        (_, ColorType::Rgb, 16) => PixelFormat::Rgb16,
+        (_, ColorType::Indexed, _) if has_transparency => PixelFormat::Rgba8,
+        (_, ColorType::Rgb, _) | (_, ColorType::Indexed, _) => PixelFormat::Rgb8,
+        (_, ColorType::Rgba, 16) => PixelFormat::Rgba16,
+        (_, ColorType::Rgba, _) => PixelFormat::Rgba8,
+    }
+}
+
+/// Converts unfiltered, deinterlaced scanlines into a PixelBuffer.
+///
+/// # Arguments
+///
+/// * 'header' - The IHDR of the image the scanlines belong to.
+/// * 'palette' - The parsed PLTE chunk, required for indexed images.
+/// * 'transparency' - The parsed tRNS chunk if there is one. Palette alpha
+///   makes indexed images expand to Rgba8 in either layout, while
+///   gray and RGB keys only take effect when normalizing to Rgba8.
+/// * 'scanlines' - The packed rows as returned by Png::scanlines.
+/// * 'output' - The layout to normalize the pixels to.
+///
+/// # Returns
+///
+/// A result containing either the PixelBuffer or a DecoderError if an
+/// indexed image has no palette or refers to an entry past its end.
+pub fn expand(
+    header: &Ihdr,
+    palette: Option<&[Rgb8]>,
+    transparency: Option<&Transparency>,
+    scanlines: &[u8],
+    output: OutputFormat,
+) -> Result<PixelBuffer, DecoderError> {
+    let width = header.width as usize;
+    let row_bytes = header.row_bytes(header.width);
+    let format = output_format(header, transparency.is_some(), output);
+
+    let mut data = Vec::with_capacity(width * header.height as usize * format.bytes_per_pixel());
+
+    for row in scanlines
+        .chunks_exact(row_bytes)
+        .take(header.height as usize)
+    {
+        expand_row(header, palette, transparency, row, format, &mut data)?;
+    }
+
+    if data.len() != width * header.height as usize * format.bytes_per_pixel() {
+        return Err(DecoderError::ImageDataError(format!(
+            "Expected {} rows of scanlines",
+            header.height
+        )));
+    }
+
+    Ok(PixelBuffer {
+        width: header.width,
+        height: header.height,
+        format,
+        data,
+    })
+}
+
+/// Converts a single unfiltered row, appending its pixels to 'data', see
+/// expand.
+///
+/// # Arguments
+///
+/// * 'header' - The IHDR of the image, with the width of the row.
+/// * 'palette', 'transparency' - As for expand.
+/// * 'row' - The packed samples of the row.
+/// * 'format' - The layout to convert to, as returned by output_format.
+/// * 'data' - The pixels to append to.
+pub fn expand_row(
+    header: &Ihdr,
+    palette: Option<&[Rgb8]>,
+    transparency: Option<&Transparency>,
+    row: &[u8],
+    format: PixelFormat,
+    data: &mut Vec<u8>,
+) -> Result<(), DecoderError> {
+    let width = header.width as usize;
+    let channels = header.color_type.channels();
+    let depth = header.bit_depth;
+
+    let palette = match header.color_type {
+        ColorType::Indexed => Some(palette.ok_or_else(|| {
+            DecoderError::ImageDataError("Indexed image has no PLTE chunk".to_string())
+        })?),
+        _ => None,
+    };
+
+    // Layouts which already match the scanlines can be copied as is.
+    if format.channels() == channels
+        && format.bytes_per_sample() * 8 == depth as usize
+        && palette.is_none()
+    {
+        data.extend_from_slice(row);
+        return Ok(());
+    }
+
+    for x in 0..width {
+        if let Some(palette) = palette {
+            let index = read_sample(row, x, depth) as usize;
+            let Rgb(entry) = palette.get(index).ok_or_else(|| {
+                DecoderError::ImageDataError(format!("Palette index {index} is out of range"))
+            })?;
+            data.extend_from_slice(entry);
+            if format == PixelFormat::Rgba8 {
+                data.push(transparency.map_or(255, |t| t.palette_alpha(index)));
+            }
+            continue;
+        }
+
+        let mut samples = [0u16; 4];
+        let mut pixel = [0u8; 4];
+        for c in 0..channels {
+            samples[c] = read_sample(row, x * channels + c, depth);
+            pixel[c] = scale_to_8_bits(samples[c], depth);
+        }
+
+        // Keys are compared against the samples before scaling.
+        let alpha = match transparency {
+            Some(Transparency::GrayKey(key)) if samples[0] == *key => 0,
+            Some(Transparency::RgbKey(key)) if samples[..3] == key[..] => 0,
+            _ => 255,
+        };
+
+        // Only reached when normalizing to 8 bits, either Luma8 from a
+        // sub-byte depth or Rgba8 from anything else.
+        match (format, header.color_type) {
+            (PixelFormat::Luma8, _) => data.push(pixel[0]),
+            (_, ColorType::Grayscale) => {
+                data.extend_from_slice(&[pixel[0], pixel[0], pixel[0], alpha])
+            }
+            (_, ColorType::GrayscaleAlpha) => {
+                data.extend_from_slice(&[pixel[0], pixel[0], pixel[0], pixel[1]])
+            }
+            (_, ColorType::Rgb) => data.extend_from_slice(&[pixel[0], pixel[1], pixel[2], alpha]),
+            _ => data.extend_from_slice(&pixel),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,383 @@
+//! Lenient decoding of damaged PNG files. Rather than stopping at the
+//! first problem, as Png::from_path does, recover keeps whatever can
+//! still be trusted and reports everything it had to work around:
+//!
+//! ```text
+//! ancillary chunk with a bad CRC    skipped
+//! critical chunk with a bad CRC     kept, as the image needs it,
+//!                                   other than IHDR
+//! ancillary chunk which won't parse skipped
+//! truncated chunk                   kept if IDAT, the file ends there
+//! missing IEND                      ignored
+//! truncated or corrupt image data   complete rows kept, rest zeroed
+//! ```
+//!
+//! Problems with IHDR or PLTE otherwise still fail, as without them nothing of the
+//! image can be decoded.
+use crate::limits::Limits;
+use crate::png::chunk::Chunks;
+use crate::png::decoder::{
+    decompress_image_partial, is_png, reconstruct_partial, DecodeOptions, DecoderError, Png,
+    PngData,
+};
+use crate::png::pixels::PixelBuffer;
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::Path,
+};
+
+/// Something wrong with a file which recover worked around.
+///
+/// # Fields
+///
+/// * 'CrcMismatch' - The CRC of a chunk does not match its contents.
+///   Ancillary chunks are skipped while critical chunks are
+///   kept.
+/// * 'InvalidChunk' - An ancillary chunk could not be parsed and was
+///   skipped, holds the error.
+/// * 'TruncatedChunk' - The file ends, or the length of a chunk runs past
+///   it, partway through a chunk. The type is missing when the
+///   file ends within the chunk header.
+/// * 'MissingEnd' - The file has no IEND chunk.
+/// * 'ImageData' - Decompressing or unfiltering the image data failed, so
+///   only part of the image could be recovered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    CrcMismatch {
+        kind: [u8; 4],
+        offset: usize,
+    },
+    InvalidChunk {
+        kind: [u8; 4],
+        error: String,
+    },
+    TruncatedChunk {
+        kind: Option<[u8; 4]>,
+        offset: usize,
+    },
+    MissingEnd,
+    ImageData(String),
+}
+
+/// The result of recovering a damaged file.
+///
+/// # Attributes
+///
+/// * 'png' - The chunks which could be recovered.
+/// * 'pixels' - The decoded image, with pixels which could not be
+///   recovered left as zeroes.
+/// * 'problems' - Everything which was worked around, in the order found.
+pub struct Recovered {
+    pub png: Png,
+    pub pixels: PixelBuffer,
+    pub problems: Vec<Problem>,
+}
+
+impl Recovered {
+    /// Whether the file decoded without any problems.
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Reads and recovers a possibly damaged PNG file, see recover.
+pub fn recover_path(
+    path: &Path,
+    options: &DecodeOptions,
+    limits: &Limits,
+) -> Result<Recovered, DecoderError> {
+    let length = fs::metadata(path)?.len();
+    limits.check_alloc(usize::try_from(length).ok())?;
+    recover(fs::read(path)?, options, limits)
+}
+
+/// Decodes as much of a possibly damaged PNG file as it can.
+///
+/// # Arguments
+///
+/// * 'bytes' - The contents of the file.
+/// * 'options' - How to decode the pixels, as for Png::decode_with.
+/// * 'limits' - The limits to decode within, which are never relaxed for
+///   damaged files.
+///
+/// # Returns
+///
+/// A result containing either the recovered image along with the
+/// problems found, or a DecoderError if the signature, IHDR or PLTE are
+/// unusable or the image exceeds the limits.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::limits::Limits;
+/// # use chameleon::png::decoder::DecodeOptions;
+/// # use chameleon::png::recovery;
+/// # use std::fs;
+/// let recovered = recovery::recover(
+///     fs::read("damaged.png")?,
+///     &DecodeOptions::default(),
+///     &Limits::default(),
+/// )?;
+/// for problem in &recovered.problems {
+///     println!("{problem}");
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn recover(
+    bytes: Vec<u8>,
+    options: &DecodeOptions,
+    limits: &Limits,
+) -> Result<Recovered, DecoderError> {
+    limits.check_alloc(Some(bytes.len()))?;
+    if !is_png(&bytes) {
+        return Err(DecoderError::TypeError(
+            "Bytes do not start with the PNG signature.".to_string(),
+        ));
+    }
+
+    let mut problems = Vec::new();
+    let chunks = find_chunks(&bytes, &mut problems)?;
+    let data = PngData::from_chunks(bytes, chunks)?;
+    let png = Png::from_data(data, limits, Some(&mut problems))?;
+
+    let (idat, held) = png.data.image_data(limits)?;
+    let (filtered, result) = decompress_image_partial(&png.header, &idat, held, limits)?;
+    if let Err(e) = result {
+        problems.push(Problem::ImageData(e.to_string()));
+    }
+    let (scanlines, result) = reconstruct_partial(&png.header, &filtered, |_, _| {});
+    if let Err(e) = result {
+        problems.push(Problem::ImageData(e.to_string()));
+    }
+    let pixels = png.expand_scanlines(&scanlines, options)?;
+
+    Ok(Recovered {
+        png,
+        pixels,
+        problems,
+    })
+}
+
+/// Locates the chunks which can be used, recording any problems.
+///
+/// # Returns
+///
+/// The type of each chunk, along with the start and end index of its
+/// data in bytes, or a DecoderError if the CRC of IHDR does not match.
+fn find_chunks(
+    bytes: &[u8],
+    problems: &mut Vec<Problem>,
+) -> Result<Vec<([u8; 4], usize, usize)>, DecoderError> {
+    let mut chunks = Vec::new();
+    let mut found_end = false;
+    let mut iter = Chunks::new(bytes);
+
+    while let Some(chunk) = iter.next() {
+        let Ok(chunk) = chunk else {
+            // Keep the part of a truncated IDAT which is in the file.
+            let offset = iter.position();
+            let kind = bytes
+                .get(offset + 4..offset + 8)
+                .map(|kind| kind.try_into().unwrap_or_default());
+            if kind == Some(*b"IDAT") {
+                let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+                let end = (offset + 8 + length as usize).min(bytes.len());
+                chunks.push((*b"IDAT", offset + 8, end));
+            }
+            problems.push(Problem::TruncatedChunk { kind, offset });
+            break;
+        };
+
+        if !chunk.crc_is_valid() {
+            // Every other chunk depends on the layout IHDR gives.
+            if &chunk.kind == b"IHDR" {
+                return Err(DecoderError::CrcError {
+                    kind: "IHDR".to_string(),
+                    expected: chunk.crc,
+                    actual: chunk.computed_crc(),
+                });
+            }
+            problems.push(Problem::CrcMismatch {
+                kind: chunk.kind,
+                offset: chunk.offset - 8,
+            });
+            if chunk.kind[0].is_ascii_lowercase() {
+                continue;
+            }
+        }
+
+        found_end |= &chunk.kind == b"IEND";
+        chunks.push((chunk.kind, chunk.offset, chunk.offset + chunk.data.len()));
+    }
+
+    if !found_end {
+        problems.push(Problem::MissingEnd);
+    }
+    Ok(chunks)
+}
+
+// Display implementation for Problem.
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::CrcMismatch { kind, offset } => {
+                let kind = String::from_utf8_lossy(kind);
+                if kind.starts_with(|c: char| c.is_ascii_lowercase()) {
+                    write!(f, "CRC mismatch in '{kind}' at byte {offset}, skipped.")
+                } else {
+                    write!(f, "CRC mismatch in '{kind}' at byte {offset}, kept.")
+                }
+            }
+            Problem::InvalidChunk { kind, error } => {
+                let kind = String::from_utf8_lossy(kind);
+                write!(f, "Skipped invalid '{kind}' chunk. {error}")
+            }
+            Problem::TruncatedChunk { kind, offset } => match kind {
+                Some(kind) => {
+                    let kind = String::from_utf8_lossy(kind);
+                    write!(f, "File ends within '{kind}' chunk at byte {offset}.")
+                }
+                None => write!(f, "File ends within a chunk header at byte {offset}."),
+            },
+            Problem::MissingEnd => write!(f, "Missing IEND chunk."),
+            Problem::ImageData(e) => write!(f, "Image data is damaged. {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::buffer::Image;
+    use crate::image::pixel::{Rgb, Rgb8};
+    use crate::png::encoder::{self, EncoderOptions};
+    use crate::png::pixels::PixelFormat;
+
+    fn image() -> Image<Rgb8> {
+        Image::from_fn(64, 48, |x, y| Rgb([(x * 4) as u8, (y * 5) as u8, 77]))
+    }
+
+    /// Encodes the test image with a tEXt chunk just before IEND.
+    fn encode() -> Vec<u8> {
+        let mut bytes = encoder::encode_image(&image(), &EncoderOptions::default()).unwrap();
+        let iend = bytes.split_off(bytes.len() - 12);
+        encoder::write_chunk(&mut bytes, b"tEXt", b"Title\0Damaged");
+        bytes.extend(iend);
+        bytes
+    }
+
+    /// The index of the first chunk of the given type, from its length.
+    fn find(bytes: &[u8], kind: &[u8; 4]) -> usize {
+        Chunks::new(bytes)
+            .flatten()
+            .find(|chunk| &chunk.kind == kind)
+            .map(|chunk| chunk.offset - 8)
+            .unwrap()
+    }
+
+    fn rgb(pixels: &PixelBuffer) -> Vec<u8> {
+        assert_eq!(pixels.format, PixelFormat::Rgb8);
+        pixels.data.clone()
+    }
+
+    #[test]
+    fn intact_files_decode_normally() {
+        let bytes = encode();
+        let recovered =
+            recover(bytes.clone(), &DecodeOptions::default(), &Limits::default()).unwrap();
+        assert!(recovered.is_intact());
+        assert_eq!(recovered.png.text.len(), 1);
+
+        let png = Png::from_bytes(bytes).unwrap();
+        assert_eq!(recovered.pixels, png.decode().unwrap());
+    }
+
+    #[test]
+    fn truncated_image_data_keeps_complete_rows() {
+        let bytes = encode();
+        let idat = find(&bytes, b"IDAT");
+        let length = u32::from_be_bytes(bytes[idat..idat + 4].try_into().unwrap()) as usize;
+        let truncated = bytes[..idat + 8 + length / 2].to_vec();
+        assert!(Png::from_bytes(truncated.clone()).is_err());
+
+        let recovered = recover(truncated, &DecodeOptions::default(), &Limits::default()).unwrap();
+        assert_eq!(
+            recovered.problems[..2],
+            [
+                Problem::TruncatedChunk {
+                    kind: Some(*b"IDAT"),
+                    offset: idat
+                },
+                Problem::MissingEnd
+            ]
+        );
+        assert!(matches!(recovered.problems[2], Problem::ImageData(_)));
+
+        // The first rows are intact and the last row was never reached.
+        let expected: Vec<u8> = image().rows().flatten().flat_map(|pixel| pixel.0).collect();
+        let pixels = rgb(&recovered.pixels);
+        let row = 64 * 3;
+        assert_eq!(pixels[..row * 4], expected[..row * 4]);
+        assert!(pixels[pixels.len() - row..].iter().all(|&v| v == 0));
+    }
+
+    #[test]
+    fn bad_ancillary_chunks_are_skipped() {
+        let mut bytes = encode();
+        let text = find(&bytes, b"tEXt");
+        let crc = text + 8 + b"Title\0Damaged".len();
+        bytes[crc] ^= 1;
+        assert!(matches!(
+            Png::from_bytes(bytes.clone()),
+            Err(DecoderError::CrcError { .. })
+        ));
+
+        let recovered = recover(bytes, &DecodeOptions::default(), &Limits::default()).unwrap();
+        assert_eq!(
+            recovered.problems,
+            [Problem::CrcMismatch {
+                kind: *b"tEXt",
+                offset: text
+            }]
+        );
+        assert!(recovered.png.text.is_empty());
+        assert_eq!(
+            rgb(&recovered.pixels),
+            image()
+                .rows()
+                .flatten()
+                .flat_map(|pixel| pixel.0)
+                .collect::<Vec<_>>()
+        );
+
+        // A chunk with a valid CRC which does not parse is skipped too.
+        let mut bytes = encode();
+        let iend = bytes.split_off(bytes.len() - 12);
+        encoder::write_chunk(&mut bytes, b"tIME", &[0; 7]);
+        bytes.extend(iend);
+        let recovered = recover(bytes, &DecodeOptions::default(), &Limits::default()).unwrap();
+        assert!(matches!(
+            recovered.problems[..],
+            [Problem::InvalidChunk { kind, .. }] if &kind == b"tIME"
+        ));
+    }
+
+    #[test]
+    fn unusable_files_still_fail() {
+        let mut bytes = encode();
+        bytes[8 + 8 + 13] ^= 1;
+        assert!(matches!(
+            recover(bytes, &DecodeOptions::default(), &Limits::default()),
+            Err(DecoderError::CrcError { .. })
+        ));
+        assert!(matches!(
+            recover(
+                b"GIF89a".to_vec(),
+                &DecodeOptions::default(),
+                &Limits::default()
+            ),
+            Err(DecoderError::TypeError(_))
+        ));
+    }
+}
@@ -0,0 +1,1181 @@
+//! Streaming decoding and encoding of PNG files. Chunks are read from
+//! any Read as they are needed, StreamDecoder yields the image one row
+//! at a time and StreamEncoder takes it one row at a time, holding only
+//! the current and previous rows along with the 32K window of the
+//! DEFLATE stream rather than the whole file.
+use crate::compression::checksum::Crc32;
+use crate::compression::zlib::{Compressor, Decompressor, ZlibError};
+use crate::image::pixel::Rgb8;
+use crate::limits::Limits;
+use crate::png::colorimetry::GammaTable;
+use crate::png::decoder::{reconstruct, DecodeOptions, DecoderError, Png, PngData, PNG_HEADER};
+use crate::png::encoder::{header_chunks, write_chunk, EncoderError, EncoderOptions};
+use crate::png::filter::{self, FilterType, BRUTE_FORCE_CONTEXT};
+use crate::png::ihdr::Ihdr;
+use crate::png::palette::Transparency;
+use crate::png::pixels::{self, PixelFormat};
+use std::{
+    io::{self, BufReader, Read, Write},
+    mem,
+};
+
+//      +--------+
+//      | CHUNKS |
+//      +--------+
+
+/// Reads the chunks of a PNG file from a stream, checking the CRC of
+/// each and keeping them laid out as in the file for PngData.
+///
+/// # Attributes
+///
+/// * 'reader' - The stream, positioned at the start of a chunk.
+/// * 'raw_data' - The signature and every chunk kept so far, less the
+///   first 'lent' bytes while they are held by a Png, see
+///   take_data.
+/// * 'lent' - The number of bytes of raw_data handed over by take_data.
+/// * 'chunks' - The type of each chunk kept, along with the start and end
+///   index of its data in the whole of raw_data.
+/// * 'limits' - The limits, of which raw_data is kept within max_alloc.
+/// * 'position' - The number of bytes read from the stream so far.
+pub(crate) struct ChunkReader<R> {
+    reader: R,
+    raw_data: Vec<u8>,
+    lent: usize,
+    chunks: Vec<([u8; 4], usize, usize)>,
+    limits: Limits,
+    position: usize,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Reads and checks the PNG signature.
+    pub(crate) fn new(mut reader: R, limits: &Limits) -> Result<Self, DecoderError> {
+        let mut signature = [0u8; 8];
+        if read_full(&mut reader, &mut signature)? < signature.len() || signature != PNG_HEADER {
+            return Err(DecoderError::TypeError(
+                "Stream does not start with the PNG signature.".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            reader,
+            raw_data: signature.to_vec(),
+            lent: 0,
+            chunks: Vec::new(),
+            limits: *limits,
+            position: signature.len(),
+        })
+    }
+
+    /// Reads the length and type of the next chunk.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the type and length of the chunk, None
+    /// when the stream ends cleanly between chunks, or a DecoderError.
+    pub(crate) fn read_header(&mut self) -> Result<Option<([u8; 4], usize)>, DecoderError> {
+        let mut header = [0u8; 8];
+        match read_full(&mut self.reader, &mut header)? {
+            0 => return Ok(None),
+            8 => self.position += header.len(),
+            _ => {
+                return Err(DecoderError::Truncated {
+                    within: "a chunk header".to_string(),
+                    offset: self.position + header.len(),
+                })
+            }
+        }
+
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let kind = [header[4], header[5], header[6], header[7]];
+        if length > i32::MAX as u32 {
+            return Err(DecoderError::ImageDataError(format!(
+                "Chunk '{}' is longer than 2^31 - 1 bytes",
+                String::from_utf8_lossy(&kind)
+            )));
+        }
+        Ok(Some((kind, length as usize)))
+    }
+
+    /// Reads the data and CRC of a chunk whose header has just been read.
+    ///
+    /// # Arguments
+    ///
+    /// * 'kind' - The type of the chunk.
+    /// * 'length' - The length of its data.
+    /// * 'keep' - Whether to keep the chunk in raw_data, within
+    ///   limits.max_alloc, otherwise it is only checked as it is
+    ///   read and then dropped.
+    pub(crate) fn read_chunk(
+        &mut self,
+        kind: [u8; 4],
+        length: usize,
+        keep: bool,
+    ) -> Result<(), DecoderError> {
+        let offset = self.lent + self.raw_data.len();
+        let mut actual = Crc32::new();
+        actual.update(&kind);
+        let read = if keep {
+            self.limits.check_alloc(offset.checked_add(length + 12))?;
+            let start = self.raw_data.len();
+            self.raw_data
+                .extend_from_slice(&(length as u32).to_be_bytes());
+            self.raw_data.extend_from_slice(&kind);
+            let read = (&mut self.reader)
+                .take(length as u64)
+                .read_to_end(&mut self.raw_data)?;
+            actual.update(&self.raw_data[start + 8..]);
+            read
+        } else {
+            // Dropped chunks only go through the CRC, a piece at a time, so
+            // however long they claim to be nothing is held.
+            io::copy(&mut (&mut self.reader).take(length as u64), &mut actual)? as usize
+        };
+        let mut crc = [0u8; 4];
+        if read < length || read_full(&mut self.reader, &mut crc)? < crc.len() {
+            return Err(truncated(&kind, self.position + length + crc.len()));
+        }
+        self.position += length + crc.len();
+
+        let (expected, actual) = (u32::from_be_bytes(crc), actual.finish());
+        if expected != actual {
+            return Err(DecoderError::CrcError {
+                kind: String::from_utf8_lossy(&kind).to_string(),
+                expected,
+                actual,
+            });
+        }
+
+        if keep {
+            self.raw_data.extend_from_slice(&crc);
+            self.chunks.push((kind, offset + 8, offset + 8 + length));
+        }
+        Ok(())
+    }
+
+    /// Reads the chunks before the image data.
+    ///
+    /// # Arguments
+    ///
+    /// * 'keep' - Which chunks to keep, the others are checked and dropped.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the length of the first IDAT chunk,
+    /// whose header has just been read, None if IEND or the end of the
+    /// stream comes first, or a DecoderError.
+    pub(crate) fn read_to_idat<F>(&mut self, keep: F) -> Result<Option<usize>, DecoderError>
+    where
+        F: Fn(&[u8; 4]) -> bool,
+    {
+        while let Some((kind, length)) = self.read_header()? {
+            if &kind == b"IDAT" {
+                return Ok(Some(length));
+            }
+            self.read_chunk(kind, length, keep(&kind))?;
+            if &kind == b"IEND" {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads every remaining chunk up to and including IEND, or the end
+    /// of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * 'next' - The chunk whose header has already been read, if any.
+    /// * 'keep' - Which chunks to keep, the others are checked and dropped.
+    pub(crate) fn read_to_end<F>(
+        &mut self,
+        mut next: Option<([u8; 4], usize)>,
+        keep: F,
+    ) -> Result<(), DecoderError>
+    where
+        F: Fn(&[u8; 4]) -> bool,
+    {
+        while let Some((kind, length)) = next {
+            self.read_chunk(kind, length, keep(&kind))?;
+            if &kind == b"IEND" {
+                break;
+            }
+            next = self.read_header()?;
+        }
+        Ok(())
+    }
+
+    /// Hands the chunks kept so far over to a PngData without copying
+    /// them. Chunks read afterwards are kept apart until the data is put
+    /// back with restore.
+    ///
+    /// # Arguments
+    ///
+    /// * 'keep' - Which of the chunks kept to hand over.
+    fn take_data<F>(&mut self, keep: F) -> Result<PngData, DecoderError>
+    where
+        F: Fn(&[u8; 4]) -> bool,
+    {
+        let kept = self
+            .chunks
+            .iter()
+            .filter(|(kind, _, _)| keep(kind))
+            .copied()
+            .collect();
+        self.lent += self.raw_data.len();
+        PngData::from_chunks(mem::take(&mut self.raw_data), kept)
+    }
+
+    /// Puts the raw data handed over by take_data back in front of the
+    /// chunks read since.
+    ///
+    /// # Arguments
+    ///
+    /// * 'raw_data' - The raw data of the PngData built by take_data.
+    fn restore(&mut self, mut raw_data: Vec<u8>) {
+        raw_data.append(&mut self.raw_data);
+        self.raw_data = raw_data;
+        self.lent = 0;
+    }
+
+    /// Parses the chunks kept, consuming the reader.
+    pub(crate) fn into_png(mut self) -> Result<Png, DecoderError> {
+        let data = self.take_data(|_| true)?;
+        Png::from_data(data, &self.limits, None)
+    }
+}
+
+/// Builds the error for a stream which ends within a chunk.
+///
+/// # Arguments
+///
+/// * 'kind' - The type of the chunk.
+/// * 'offset' - The index in the stream the chunk would have ended at.
+fn truncated(kind: &[u8; 4], offset: usize) -> DecoderError {
+    DecoderError::Truncated {
+        within: format!("'{}' chunk", String::from_utf8_lossy(kind)),
+        offset,
+    }
+}
+
+/// Reads until buffer is full or the stream ends.
+///
+/// # Returns
+///
+/// A result containing either the number of bytes read, which is only
+/// less than buffer.len() at the end of the stream, or an io::Error.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Whether a chunk describes the default image rather than an APNG
+/// animation, which is all StreamDecoder keeps.
+fn is_still(kind: &[u8; 4]) -> bool {
+    !matches!(kind, b"acTL" | b"fcTL" | b"fdAT")
+}
+
+//      +-----------+
+//      | IMAGEDATA |
+//      +-----------+
+
+/// Reads the data of consecutive IDAT chunks as one stream, checking the
+/// CRC of each, which is what the zlib stream of the image is split over.
+///
+/// # Attributes
+///
+/// * 'chunks' - The chunks of the file, positioned within an IDAT chunk.
+/// * 'remaining' - The bytes left in the current IDAT chunk.
+/// * 'crc' - The CRC of the current IDAT chunk so far.
+/// * 'next' - The header of the chunk following the last IDAT chunk, once
+///   it has been read.
+/// * 'ended' - Whether the last IDAT chunk has been read.
+/// * 'error' - Why reading stopped, when it was the chunks rather than the
+///   stream which were at fault.
+struct IdatReader<R> {
+    chunks: ChunkReader<R>,
+    remaining: usize,
+    crc: Crc32,
+    next: Option<([u8; 4], usize)>,
+    ended: bool,
+    error: Option<DecoderError>,
+}
+
+impl<R: Read> IdatReader<R> {
+    /// Starts reading an IDAT chunk whose header has just been read.
+    fn new(chunks: ChunkReader<R>, length: usize) -> Self {
+        let mut crc = Crc32::new();
+        crc.update(b"IDAT");
+        Self {
+            chunks,
+            remaining: length,
+            crc,
+            next: None,
+            ended: false,
+            error: None,
+        }
+    }
+
+    /// Checks the CRC of the IDAT chunk just read and reads the header of
+    /// the next chunk.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either whether the next chunk is also IDAT or
+    /// a DecoderError.
+    fn next_chunk(&mut self) -> Result<bool, DecoderError> {
+        let mut crc = [0u8; 4];
+        if read_full(&mut self.chunks.reader, &mut crc)? < crc.len() {
+            return Err(truncated(b"IDAT", self.chunks.position + crc.len()));
+        }
+        self.chunks.position += crc.len();
+        let (expected, actual) = (u32::from_be_bytes(crc), self.crc.finish());
+        if expected != actual {
+            return Err(DecoderError::CrcError {
+                kind: "IDAT".to_string(),
+                expected,
+                actual,
+            });
+        }
+
+        match self.chunks.read_header()? {
+            Some((kind, length)) if &kind == b"IDAT" => {
+                self.crc = Crc32::new();
+                self.crc.update(&kind);
+                self.remaining = length;
+                Ok(true)
+            }
+            next => {
+                self.next = next;
+                self.ended = true;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Skips whatever is left of the IDAT chunks, still checking their
+    /// CRCs.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the header of the chunk which follows
+    /// them, None at the end of the stream, or a DecoderError.
+    fn skip(&mut self) -> Result<Option<([u8; 4], usize)>, DecoderError> {
+        let mut buffer = [0u8; 4096];
+        loop {
+            match self.read(&mut buffer) {
+                Ok(0) => return Ok(self.next.take()),
+                Ok(_) => {}
+                Err(e) => return Err(self.error.take().unwrap_or(e.into())),
+            }
+        }
+    }
+}
+
+// Reads the image data, which ends with the last IDAT chunk.
+impl<R: Read> Read for IdatReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            if self.ended || buffer.is_empty() {
+                return Ok(0);
+            }
+            if let Err(e) = self.next_chunk() {
+                let message = e.to_string();
+                self.error = Some(e);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+
+        let length = buffer.len().min(self.remaining);
+        let read = self.chunks.reader.read(&mut buffer[..length])?;
+        if read == 0 {
+            let end = self.chunks.position + self.remaining + 4;
+            self.error = Some(truncated(b"IDAT", end));
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.crc.update(&buffer[..read]);
+        self.remaining -= read;
+        self.chunks.position += read;
+        Ok(read)
+    }
+}
+
+//      +---------+
+//      | DECODER |
+//      +---------+
+
+/// Decodes a PNG file from a stream one row at a time.
+///
+/// Only the default image is decoded, APNG frames are skipped. Interlaced
+/// images cannot be put back together a row at a time, as each row is
+/// spread over all seven passes, so for those the whole image is held
+/// instead, within limits.max_alloc and limits.max_pixels as for
+/// Png::decode. Otherwise only the rows held and the chunks kept count
+/// against limits.max_alloc, and the image as a whole may be larger than
+/// limits.max_pixels.
+///
+/// # Attributes
+///
+/// * 'info' - The chunks before the image data, and once the last row
+///   has been read, the ones following it as well.
+/// * 'decompressor' - Decompresses the IDAT chunks as they are read.
+/// * 'format' - The layout of the rows yielded.
+/// * 'gamma' - Corrects each row for options.display_gamma, when both it
+///   and the gamma of the file are known.
+/// * 'limits' - The limits the image is decoded within.
+/// * 'previous' - The previous row after unfiltering, with its filter byte.
+/// * 'current' - The row being decoded, with its filter byte.
+/// * 'pixels' - The last row yielded.
+/// * 'scanlines' - The whole image, only for interlaced images.
+/// * 'row' - The index of the next row.
+/// * 'finished' - Whether the chunks after the image data have been read.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::StreamDecoder;
+/// # use std::{fs::File, io::Write};
+/// # let mut output = Vec::new();
+/// let mut decoder = StreamDecoder::new(File::open("large.png")?)?;
+/// println!("{}x{}", decoder.info().header.width, decoder.info().header.height);
+///
+/// while let Some(row) = decoder.next_row()? {
+///     output.write_all(row)?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct StreamDecoder<R> {
+    info: Png,
+    decompressor: Decompressor<IdatReader<BufReader<R>>>,
+    format: PixelFormat,
+    gamma: Option<GammaTable>,
+    limits: Limits,
+    previous: Vec<u8>,
+    current: Vec<u8>,
+    pixels: Vec<u8>,
+    scanlines: Option<Vec<u8>>,
+    row: u32,
+    finished: bool,
+}
+
+impl<R: Read> StreamDecoder<R> {
+    /// Creates a StreamDecoder yielding rows in the native layout of the
+    /// image, using the default Limits.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the StreamDecoder, having read every
+    /// chunk before the image data, or a DecoderError.
+    pub fn new(reader: R) -> Result<Self, DecoderError> {
+        Self::with_options(reader, &DecodeOptions::default(), &Limits::default())
+    }
+
+    /// Same as new, but with the given options and limits.
+    pub fn with_options(
+        reader: R,
+        options: &DecodeOptions,
+        limits: &Limits,
+    ) -> Result<Self, DecoderError> {
+        let mut chunks = ChunkReader::new(BufReader::new(reader), limits)?;
+        let Some(length) = chunks.read_to_idat(is_still)? else {
+            return Err(DecoderError::ImageDataError(
+                "Missing IDAT chunk".to_string(),
+            ));
+        };
+        Self::start(chunks, length, options, limits)
+    }
+
+    /// Starts decoding once every chunk before the image data has been
+    /// read, along with the header of the first IDAT chunk.
+    fn start(
+        mut chunks: ChunkReader<BufReader<R>>,
+        length: usize,
+        options: &DecodeOptions,
+        limits: &Limits,
+    ) -> Result<Self, DecoderError> {
+        let info = parse_info(&mut chunks, limits)?;
+        let format =
+            pixels::output_format(&info.header, info.transparency.is_some(), options.output);
+        let gamma = options
+            .display_gamma
+            .zip(info.colorimetry.file_gamma())
+            .map(|(display_gamma, file_gamma)| GammaTable::new(format, file_gamma, display_gamma));
+
+        // The previous and current rows with their filter bytes, and the
+        // row of pixels yielded.
+        let row_bytes = info.header.row_bytes(info.header.width);
+        let pixel_bytes = (info.header.width as usize).checked_mul(format.bytes_per_pixel());
+        limits.check_alloc(
+            (row_bytes + 1)
+                .checked_mul(2)
+                .zip(pixel_bytes)
+                .and_then(|(rows, pixels)| rows.checked_add(pixels)),
+        )?;
+
+        // Nothing past the image is held, but decompressing it still takes
+        // time, so it is bounded by max_alloc as for Png::decode.
+        let max_output = info
+            .header
+            .filtered_bytes()
+            .map_or(usize::MAX, |bytes| bytes.saturating_add(limits.max_alloc));
+        let decompressor = Decompressor::with_limit(IdatReader::new(chunks, length), max_output);
+
+        Ok(Self {
+            info,
+            decompressor,
+            format,
+            gamma,
+            limits: *limits,
+            previous: vec![0; row_bytes + 1],
+            current: vec![0; row_bytes + 1],
+            pixels: Vec::new(),
+            scanlines: None,
+            row: 0,
+            finished: false,
+        })
+    }
+
+    /// The parsed chunks of the file, without the image data. Chunks
+    /// after the image data are only included once every row has been
+    /// read.
+    pub fn info(&self) -> &Png {
+        &self.info
+    }
+
+    /// The layout of the rows yielded.
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Decodes the next row of the image.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the pixels of the row, width pixels in
+    /// the layout given by format, None once every row has been read and
+    /// the rest of the file checked, or a DecoderError.
+    pub fn next_row(&mut self) -> Result<Option<&[u8]>, DecoderError> {
+        let header = &self.info.header;
+        if self.row == header.height {
+            if !self.finished {
+                self.finish()?;
+            }
+            return Ok(None);
+        }
+
+        let row_bytes = header.row_bytes(header.width);
+        if header.interlaced {
+            if self.scanlines.is_none() {
+                self.scanlines = Some(self.read_interlaced()?);
+            }
+            let start = self.row as usize * row_bytes;
+            let scanlines = self.scanlines.as_deref().unwrap_or_default();
+            self.previous[1..].copy_from_slice(&scanlines[start..start + row_bytes]);
+        } else {
+            let mut current = mem::take(&mut self.current);
+            let result = self.fill(&mut current);
+            self.current = current;
+            result?;
+
+            let filter = FilterType::from_byte(self.current[0])
+                .ok_or(DecoderError::FilterError(self.current[0]))?;
+            filter::unfilter_row(
+                filter,
+                self.info.header.filter_stride(),
+                &self.previous[1..],
+                &mut self.current[1..],
+            );
+            mem::swap(&mut self.previous, &mut self.current);
+        }
+
+        self.pixels.clear();
+        pixels::expand_row(
+            &self.info.header,
+            self.info.palette.as_deref(),
+            self.info.transparency.as_ref(),
+            &self.previous[1..],
+            self.format,
+            &mut self.pixels,
+        )?;
+        if let Some(gamma) = &self.gamma {
+            gamma.apply(&mut self.pixels);
+        }
+
+        self.row += 1;
+        Ok(Some(&self.pixels))
+    }
+
+    /// Decompresses into 'buffer', telling problems with the chunks
+    /// apart from problems with the stream they hold.
+    fn decompress(&mut self, buffer: &mut [u8]) -> Result<usize, DecoderError> {
+        self.decompressor.decompress(buffer).map_err(|e| {
+            match self.decompressor.get_mut().error.take() {
+                Some(error) => error,
+                None => match e {
+                    ZlibError::IoError(e) => DecoderError::IoError(e),
+                    e => DecoderError::from(e),
+                },
+            }
+        })
+    }
+
+    /// Fills 'buffer' with image data.
+    fn fill(&mut self, buffer: &mut [u8]) -> Result<(), DecoderError> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match self.decompress(&mut buffer[filled..])? {
+                0 => {
+                    return Err(DecoderError::ImageDataError(format!(
+                        "Image data ends after {} of {} rows",
+                        self.row, self.info.header.height
+                    )))
+                }
+                written => filled += written,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads and reconstructs the whole of an interlaced image.
+    fn read_interlaced(&mut self) -> Result<Vec<u8>, DecoderError> {
+        let header = &self.info.header;
+        let (filtered, image) = (header.filtered_bytes(), header.image_bytes());
+        self.limits.check_alloc(
+            filtered
+                .zip(image)
+                .and_then(|(filtered, image)| filtered.checked_add(image)),
+        )?;
+
+        let mut data = vec![0u8; filtered.unwrap_or_default()];
+        self.fill(&mut data)?;
+        reconstruct(&self.info.header, &data, |_, _| {})
+    }
+
+    /// Reads the rest of the file without decoding it, keeping every
+    /// chunk as Png::from_reader does, rather than going on row by row.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the Png, checked against the limits as
+    /// a whole, or a DecoderError if rows have already been read.
+    pub(crate) fn into_png(self) -> Result<Png, DecoderError> {
+        if self.row > 0 {
+            return Err(DecoderError::ImageDataError(
+                "Rows have already been read".to_string(),
+            ));
+        }
+
+        // Nothing has been decompressed, so the reader is still at the
+        // start of the first IDAT chunk.
+        let IdatReader {
+            mut chunks,
+            remaining,
+            ..
+        } = self.decompressor.into_inner();
+        chunks.restore(self.info.data.raw_data);
+        chunks.read_chunk(*b"IDAT", remaining, true)?;
+        let next = chunks.read_header()?;
+        chunks.read_to_end(next, |_| true)?;
+        chunks.into_png()
+    }
+
+    /// Drains the image data, checking its Adler-32 checksum, then reads
+    /// the chunks following it through IEND.
+    fn finish(&mut self) -> Result<(), DecoderError> {
+        // Data past the end of the image is ignored, but still checked.
+        let mut buffer = [0u8; 4096];
+        while self.decompress(&mut buffer)? > 0 {}
+
+        let idat = self.decompressor.get_mut();
+        let next = idat.skip()?;
+        idat.chunks.read_to_end(next, is_still)?;
+        idat.chunks.restore(mem::take(&mut self.info.data.raw_data));
+        self.info = parse_info(&mut idat.chunks, &self.limits)?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+/// Reads a whole PNG file from a stream for Png::from_reader. The chunks
+/// before the image data are read as for StreamDecoder, which then reads
+/// the rest without decoding it, see StreamDecoder::into_png. Files
+/// without image data are read all the same, as by Png::from_bytes.
+pub(crate) fn read_png<R: Read>(reader: R, limits: &Limits) -> Result<Png, DecoderError> {
+    let mut chunks = ChunkReader::new(BufReader::new(reader), limits)?;
+    match chunks.read_to_idat(|_| true)? {
+        Some(length) => {
+            StreamDecoder::start(chunks, length, &DecodeOptions::default(), limits)?.into_png()
+        }
+        None => chunks.into_png(),
+    }
+}
+
+/// Parses the chunks kept so far into the info of a StreamDecoder, which
+/// holds their raw data until it is put back with ChunkReader::restore.
+///
+/// As only a few rows are held at a time, limits.max_pixels is only
+/// checked for interlaced images, which are held whole. APNG chunks are
+/// left out, as only the default image is decoded.
+fn parse_info<R: Read>(chunks: &mut ChunkReader<R>, limits: &Limits) -> Result<Png, DecoderError> {
+    let data = chunks.take_data(is_still)?;
+    let streamed = Limits {
+        max_pixels: u64::MAX,
+        ..*limits
+    };
+    let mut info = Png::from_data(data, &streamed, None)?;
+    if info.header.interlaced {
+        limits.check_dimensions(info.header.width, info.header.height)?;
+    }
+    info.limits = *limits;
+    Ok(info)
+}
+
+//      +---------+
+//      | ENCODER |
+//      +---------+
+
+/// Encodes a PNG file into a stream from rows given one at a time. Each
+/// row is filtered against the previous one and compressed as it arrives,
+/// and IDAT chunks are written out whenever idat_size bytes of compressed
+/// data have built up, so only a couple of rows and the compressor's
+/// window are held at once.
+///
+/// Interlaced images are not supported, as every Adam7 pass needs rows
+/// from all over the image.
+///
+/// # Attributes
+///
+/// * 'writer' - Where the file is written.
+/// * 'header' - The IHDR of the image.
+/// * 'options' - How rows are filtered and compressed.
+/// * 'compressor' - Compresses the filtered rows.
+/// * 'previous' - The previous unfiltered row, all zeroes for the first.
+/// * 'filtered' - The latest filtered rows, kept as context for
+///   FilterStrategy::BruteForce.
+/// * 'pending' - Compressed data not yet written out as IDAT.
+/// * 'row' - The number of rows written so far.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use chameleon::png::encoder::EncoderOptions;
+/// # use chameleon::png::ihdr::{ColorType, Ihdr};
+/// # use chameleon::png::StreamEncoder;
+/// # use std::fs::File;
+/// # let (width, height) = (256, 256);
+/// # let render_row = |y: u32| vec![y as u8; width as usize * 3];
+/// let header = Ihdr {
+///     width,
+///     height,
+///     bit_depth: 8,
+///     color_type: ColorType::Rgb,
+///     interlaced: false,
+/// };
+/// let mut encoder = StreamEncoder::new(
+///     File::create("tiles.png")?,
+///     &header,
+///     None,
+///     None,
+///     &EncoderOptions::default(),
+/// )?;
+///
+/// for y in 0..height {
+///     encoder.write_row(&render_row(y))?;
+/// }
+/// encoder.finish()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct StreamEncoder<W> {
+    writer: W,
+    header: Ihdr,
+    options: EncoderOptions,
+    compressor: Compressor,
+    previous: Vec<u8>,
+    filtered: Vec<u8>,
+    pending: Vec<u8>,
+    row: u32,
+}
+
+impl<W: Write> StreamEncoder<W> {
+    /// Writes the signature and every chunk before the image data.
+    ///
+    /// # Arguments
+    ///
+    /// * 'writer' - Where to write the file.
+    /// * 'header' - The IHDR to write, which must not be interlaced.
+    /// * 'palette' - The PLTE entries, required for indexed images.
+    /// * 'transparency' - The tRNS chunk matching the color type, if any.
+    /// * 'options' - How to filter and compress the rows, along with the
+    ///   chunks to write before them.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the StreamEncoder, ready for the first
+    /// row, or an EncoderError.
+    pub fn new(
+        mut writer: W,
+        header: &Ihdr,
+        palette: Option<&[Rgb8]>,
+        transparency: Option<&Transparency>,
+        options: &EncoderOptions,
+    ) -> Result<Self, EncoderError> {
+        if header.interlaced {
+            return Err(EncoderError::HeaderError(
+                "Interlaced images cannot be written a row at a time".to_string(),
+            ));
+        }
+
+        let mut output = PNG_HEADER.to_vec();
+        for (kind, data) in header_chunks(header, palette, transparency, options)? {
+            write_chunk(&mut output, &kind, &data);
+        }
+        writer.write_all(&output)?;
+
+        Ok(Self {
+            writer,
+            header: *header,
+            options: options.clone(),
+            compressor: Compressor::new(options.compression_level),
+            previous: vec![0; header.row_bytes(header.width)],
+            filtered: Vec::new(),
+            pending: Vec::new(),
+            row: 0,
+        })
+    }
+
+    /// Filters and compresses the next row of the image.
+    ///
+    /// # Arguments
+    ///
+    /// * 'row' - The unfiltered row, Ihdr::row_bytes(width) bytes laid out
+    ///   as in Png::scanlines.
+    pub fn write_row(&mut self, row: &[u8]) -> Result<(), EncoderError> {
+        if self.row == self.header.height {
+            return Err(EncoderError::ImageDataError(format!(
+                "All {} rows have already been written",
+                self.header.height
+            )));
+        }
+        if row.len() != self.previous.len() {
+            return Err(EncoderError::ImageDataError(format!(
+                "Expected a row of {} bytes but found {}",
+                self.previous.len(),
+                row.len()
+            )));
+        }
+
+        let start = self.filtered.len();
+        filter::filter_next_row(
+            &self.previous,
+            row,
+            self.header.filter_stride(),
+            self.options.filter,
+            self.options.compression_level,
+            &mut self.filtered,
+        );
+        self.compressor.write(&self.filtered[start..]);
+        self.previous.copy_from_slice(row);
+        self.row += 1;
+
+        // Only the tail is ever looked at, so let it grow a little before
+        // moving it back to the start.
+        if self.filtered.len() > 2 * BRUTE_FORCE_CONTEXT {
+            self.filtered
+                .drain(..self.filtered.len() - BRUTE_FORCE_CONTEXT);
+        }
+
+        self.pending.extend(self.compressor.take_output());
+        write_idat(
+            &mut self.writer,
+            &mut self.pending,
+            self.options.idat_size,
+            false,
+        )?;
+        Ok(())
+    }
+
+    /// The number of rows written so far.
+    pub fn rows_written(&self) -> u32 {
+        self.row
+    }
+
+    /// Writes the rest of the image data and IEND once every row has been
+    /// written.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the writer, flushed, or an EncoderError
+    /// if rows are missing.
+    pub fn finish(mut self) -> Result<W, EncoderError> {
+        if self.row < self.header.height {
+            return Err(EncoderError::ImageDataError(format!(
+                "Only {} of {} rows were written",
+                self.row, self.header.height
+            )));
+        }
+
+        self.pending.extend(self.compressor.finish());
+        write_idat(
+            &mut self.writer,
+            &mut self.pending,
+            self.options.idat_size,
+            true,
+        )?;
+
+        let mut output = Vec::new();
+        write_chunk(&mut output, b"IEND", &[]);
+        self.writer.write_all(&output)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes compressed data out as IDAT chunks of 'size' bytes, leaving
+/// what is left over in 'pending' unless 'all' is set.
+fn write_idat<W: Write>(
+    writer: &mut W,
+    pending: &mut Vec<u8>,
+    size: usize,
+    all: bool,
+) -> io::Result<()> {
+    let size = size.max(1);
+    let mut output = Vec::new();
+    let mut written = 0;
+    while pending.len() - written >= size || (all && written < pending.len()) {
+        let end = (written + size).min(pending.len());
+        write_chunk(&mut output, b"IDAT", &pending[written..end]);
+        written = end;
+    }
+
+    pending.drain(..written);
+    writer.write_all(&output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::pixel::Rgb;
+    use crate::png::encoder;
+    use crate::png::filter::FilterStrategy;
+    use crate::png::ihdr::ColorType;
+    use crate::png::pixels::OutputFormat;
+
+    /// Noise in the given layout, which is valid for any bit depth as the
+    /// decoder does not check the padding bits of a row.
+    fn scanlines(header: &Ihdr) -> Vec<u8> {
+        let mut state = header.width ^ (header.bit_depth as u32) << 8;
+        (0..header.row_bytes(header.width) * header.height as usize)
+            .map(|_| {
+                state = state.wrapping_mul(22_695_477).wrapping_add(1);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    /// A palette covering every index for indexed images.
+    fn palette(header: &Ihdr) -> Option<Vec<Rgb8>> {
+        (header.color_type == ColorType::Indexed).then(|| {
+            (0..1u32 << header.bit_depth)
+                .map(|i| Rgb([i as u8, 0, 255 - i as u8]))
+                .collect()
+        })
+    }
+
+    fn encode(header: &Ihdr, options: &EncoderOptions) -> Vec<u8> {
+        let palette = palette(header);
+        encoder::encode_scanlines(
+            header,
+            palette.as_deref(),
+            None,
+            &scanlines(header),
+            options,
+        )
+        .unwrap()
+    }
+
+    fn header(color_type: ColorType, bit_depth: u8, interlaced: bool) -> Ihdr {
+        Ihdr {
+            width: 21,
+            height: 13,
+            bit_depth,
+            color_type,
+            interlaced,
+        }
+    }
+
+    /// Reads every row of a stream, checking the number of rows.
+    fn read_rows<R: Read>(mut decoder: StreamDecoder<R>) -> Result<Vec<u8>, DecoderError> {
+        let mut rows = Vec::new();
+        let mut count = 0;
+        while let Some(row) = decoder.next_row()? {
+            rows.extend_from_slice(row);
+            count += 1;
+        }
+        assert_eq!(count, decoder.info().header.height);
+        Ok(rows)
+    }
+
+    #[test]
+    fn rows_match_whole_image_decoding() {
+        let formats: [(ColorType, &[u8]); 5] = [
+            (ColorType::Grayscale, &[1, 4, 16]),
+            (ColorType::Rgb, &[8, 16]),
+            (ColorType::Indexed, &[2, 8]),
+            (ColorType::GrayscaleAlpha, &[8]),
+            (ColorType::Rgba, &[16]),
+        ];
+        // Tiny IDAT chunks make rows straddle chunk boundaries.
+        let options = EncoderOptions {
+            idat_size: 37,
+            ..Default::default()
+        };
+        for (color_type, bit_depths) in formats {
+            for &bit_depth in bit_depths {
+                for interlaced in [false, true] {
+                    let bytes = encode(&header(color_type, bit_depth, interlaced), &options);
+                    let png = Png::from_bytes(bytes.clone()).unwrap();
+
+                    let rows = read_rows(StreamDecoder::new(&bytes[..]).unwrap()).unwrap();
+                    assert_eq!(rows, png.decode().unwrap().data);
+
+                    let rgba = DecodeOptions {
+                        output: OutputFormat::Rgba8,
+                        ..Default::default()
+                    };
+                    let decoder =
+                        StreamDecoder::with_options(&bytes[..], &rgba, &Limits::default()).unwrap();
+                    assert_eq!(decoder.format(), PixelFormat::Rgba8);
+                    let rows = read_rows(decoder).unwrap();
+                    assert_eq!(rows, png.decode_as(OutputFormat::Rgba8).unwrap().data);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_after_the_image_data_are_read_last() {
+        let mut bytes = encode(&header(ColorType::Rgb, 8, false), &Default::default());
+        let iend = bytes.split_off(bytes.len() - 12);
+        encoder::write_chunk(&mut bytes, b"tEXt", b"Comment\0after");
+        bytes.extend(iend);
+
+        let mut decoder = StreamDecoder::new(&bytes[..]).unwrap();
+        assert!(decoder.info().text.is_empty());
+        while decoder.next_row().unwrap().is_some() {}
+        assert_eq!(decoder.info().text[0].text, "after");
+    }
+
+    #[test]
+    fn from_reader_matches_from_bytes() {
+        for interlaced in [false, true] {
+            let bytes = encode(&header(ColorType::Rgba, 8, interlaced), &Default::default());
+            let streamed = Png::from_reader(&bytes[..]).unwrap();
+            let png = Png::from_bytes(bytes).unwrap();
+            assert_eq!(streamed.header, png.header);
+            assert_eq!(streamed.decode().unwrap(), png.decode().unwrap());
+        }
+    }
+
+    #[test]
+    fn damaged_streams_fail() {
+        let bytes = encode(&header(ColorType::Rgb, 8, false), &Default::default());
+
+        // Cut within the image data, after the header chunks.
+        let truncated = &bytes[..bytes.len() - 40];
+        let result = StreamDecoder::new(truncated).and_then(read_rows);
+        assert!(result.is_err());
+        assert!(Png::from_reader(truncated).is_err());
+        assert!(StreamDecoder::new(&bytes[..20]).is_err());
+
+        // The CRC of the last IDAT chunk, just before IEND.
+        let mut corrupt = bytes.clone();
+        let crc = corrupt.len() - 16;
+        corrupt[crc] ^= 1;
+        let result = StreamDecoder::new(&corrupt[..]).and_then(read_rows);
+        assert!(matches!(result, Err(DecoderError::CrcError { .. })));
+
+        assert!(matches!(
+            StreamDecoder::new(&b"not a png at all"[..]),
+            Err(DecoderError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn streamed_rows_round_trip() {
+        let filters = [
+            FilterStrategy::Fixed(FilterType::Paeth),
+            FilterStrategy::MinimumSum,
+            FilterStrategy::BruteForce,
+        ];
+        for (color_type, bit_depth) in [(ColorType::Indexed, 4), (ColorType::Rgba, 16)] {
+            for filter in filters {
+                let header = header(color_type, bit_depth, false);
+                let scanlines = scanlines(&header);
+                let palette = palette(&header);
+                let options = EncoderOptions {
+                    filter,
+                    idat_size: 50,
+                    ..Default::default()
+                };
+
+                let mut encoder =
+                    StreamEncoder::new(Vec::new(), &header, palette.as_deref(), None, &options)
+                        .unwrap();
+                for row in scanlines.chunks(header.row_bytes(header.width)) {
+                    encoder.write_row(row).unwrap();
+                }
+                assert_eq!(encoder.rows_written(), header.height);
+                let bytes = encoder.finish().unwrap();
+
+                let png = Png::from_bytes(bytes).unwrap();
+                assert_eq!(png.header, header);
+                assert_eq!(png.palette, palette);
+                assert_eq!(png.scanlines().unwrap(), scanlines);
+            }
+        }
+    }
+
+    #[test]
+    fn streamed_rows_are_checked() {
+        let header = header(ColorType::Rgb, 8, false);
+        let options = EncoderOptions::default();
+        let row = vec![0; header.row_bytes(header.width)];
+
+        let interlaced = Ihdr {
+            interlaced: true,
+            ..header
+        };
+        assert!(matches!(
+            StreamEncoder::new(Vec::new(), &interlaced, None, None, &options),
+            Err(EncoderError::HeaderError(_))
+        ));
+
+        let mut encoder = StreamEncoder::new(Vec::new(), &header, None, None, &options).unwrap();
+        assert!(matches!(
+            encoder.write_row(&row[1..]),
+            Err(EncoderError::ImageDataError(_))
+        ));
+        encoder.write_row(&row).unwrap();
+        assert!(matches!(
+            encoder.finish(),
+            Err(EncoderError::ImageDataError(_))
+        ));
+
+        let mut encoder = StreamEncoder::new(Vec::new(), &header, None, None, &options).unwrap();
+        for _ in 0..header.height {
+            encoder.write_row(&row).unwrap();
+        }
+        assert!(matches!(
+            encoder.write_row(&row),
+            Err(EncoderError::ImageDataError(_))
+        ));
+        assert!(encoder.finish().is_ok());
+    }
+}
@@ -0,0 +1,388 @@
+//! The textual metadata chunks tEXt, zTXt and iTXt, see section 11.3.3
+//! of the PNG specification. Each holds a keyword naming the kind of
+//! text, such as 'Author' or 'Copyright', followed by the text itself:
+//!
+//! tEXt:    KEYWORD 0   TEXT
+//! zTXt:    KEYWORD 0   METHOD  COMPRESSED TEXT
+//! iTXt:    KEYWORD 0   FLAG    METHOD  LANGUAGE 0  TRANSLATED 0    TEXT
+//!
+//! Keywords and the text of tEXt and zTXt are Latin-1, while the text of
+//! iTXt and its translated keyword are UTF-8.
+use crate::compression::deflate::DeflateError;
+use crate::compression::zlib::{self, ZlibError};
+use crate::limits::LimitError;
+use crate::png::decoder::DecoderError;
+
+/// How a TextChunk is stored.
+///
+/// # Fields
+///
+/// * 'Latin1' - A tEXt chunk, uncompressed Latin-1 text.
+/// * 'Compressed' - A zTXt chunk, zlib compressed Latin-1 text.
+/// * 'International' - An iTXt chunk, UTF-8 text along with the language
+///   it is written in and the keyword translated into it.
+///   The text can optionally be compressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextFormat {
+    Latin1,
+    Compressed,
+    International {
+        language_tag: String,
+        translated_keyword: String,
+        compressed: bool,
+    },
+}
+
+/// A single piece of textual metadata.
+///
+/// # Attributes
+///
+/// * 'keyword' - What the text describes, 1 to 79 printable Latin-1
+///   characters without leading, trailing or repeated spaces.
+/// * 'text' - The text itself.
+/// * 'format' - Which chunk the text is stored in.
+///
+/// # Examples
+///
+/// ```
+/// # use chameleon::png::encoder::EncoderOptions;
+/// # use chameleon::png::text::{TextChunk, TextFormat};
+/// let author = TextChunk::new("Author", "June");
+/// let notes = TextChunk {
+///     keyword: "Description".to_string(),
+///     text: "Rendered from commit 1a2b3c".to_string(),
+///     format: TextFormat::Compressed,
+/// };
+///
+/// let options = EncoderOptions {
+///     text: vec![author, notes],
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    pub keyword: String,
+    pub text: String,
+    pub format: TextFormat,
+}
+
+impl TextChunk {
+    /// Creates an uncompressed text chunk, stored as tEXt when the text
+    /// fits in Latin-1 and as iTXt without a language otherwise.
+    pub fn new(keyword: &str, text: &str) -> Self {
+        let format = match text.chars().all(|c| (c as u32) < 256) {
+            true => TextFormat::Latin1,
+            false => TextFormat::International {
+                language_tag: String::new(),
+                translated_keyword: String::new(),
+                compressed: false,
+            },
+        };
+
+        Self {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            format,
+        }
+    }
+
+    /// Parses the data of a tEXt, zTXt or iTXt chunk.
+    ///
+    /// # Arguments
+    ///
+    /// * 'kind' - The chunk type, which decides the layout of the data.
+    /// * 'bytes' - The data of the chunk.
+    /// * 'max_size' - The most bytes compressed text may decompress to.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the TextChunk or a DecoderError, which is
+    /// a LimitError if the text is larger than 'max_size'.
+    pub fn parse(kind: &[u8; 4], bytes: &[u8], max_size: usize) -> Result<Self, DecoderError> {
+        let error =
+            |e: &str| DecoderError::TextError(format!("{}: {e}", String::from_utf8_lossy(kind)));
+
+        let (keyword, rest) =
+            split_at_null(bytes).ok_or_else(|| error("Missing keyword separator"))?;
+        let keyword = latin1_decode(keyword);
+        validate_keyword(&keyword).map_err(|e| error(&e))?;
+
+        let (text, format) = match kind {
+            b"tEXt" => (latin1_decode(rest), TextFormat::Latin1),
+            b"zTXt" => {
+                let (&method, compressed) = rest
+                    .split_first()
+                    .ok_or_else(|| error("Missing compression method"))?;
+                if method != 0 {
+                    return Err(error(&format!("Unknown compression method {method}")));
+                }
+                let text = decompress_text(compressed, max_size, error)?;
+                (latin1_decode(&text), TextFormat::Compressed)
+            }
+            b"iTXt" => {
+                if rest.len() < 2 {
+                    return Err(error("Missing compression flag"));
+                }
+                let (flag, method) = (rest[0], rest[1]);
+                let compressed = match (flag, method) {
+                    (0, _) => false,
+                    (1, 0) => true,
+                    (1, _) => return Err(error(&format!("Unknown compression method {method}"))),
+                    _ => return Err(error(&format!("Invalid compression flag {flag}"))),
+                };
+
+                let (language_tag, rest) = split_at_null(&rest[2..])
+                    .ok_or_else(|| error("Missing language tag separator"))?;
+                let (translated_keyword, text) = split_at_null(rest)
+                    .ok_or_else(|| error("Missing translated keyword separator"))?;
+
+                let text = match compressed {
+                    true => decompress_text(text, max_size, error)?,
+                    false => text.to_vec(),
+                };
+                let utf8 = |bytes: Vec<u8>, field: &str| {
+                    String::from_utf8(bytes)
+                        .map_err(|_| error(&format!("{field} is not valid UTF-8")))
+                };
+
+                let format = TextFormat::International {
+                    language_tag: utf8(language_tag.to_vec(), "Language tag")?,
+                    translated_keyword: utf8(translated_keyword.to_vec(), "Translated keyword")?,
+                    compressed,
+                };
+                (utf8(text, "Text")?, format)
+            }
+            _ => return Err(error("Not a text chunk")),
+        };
+
+        Ok(Self {
+            keyword,
+            text,
+            format,
+        })
+    }
+
+    /// The type of the chunk the text is stored in.
+    pub fn chunk_type(&self) -> [u8; 4] {
+        match self.format {
+            TextFormat::Latin1 => *b"tEXt",
+            TextFormat::Compressed => *b"zTXt",
+            TextFormat::International { .. } => *b"iTXt",
+        }
+    }
+
+    /// Serializes the text into the data of its chunk.
+    ///
+    /// # Arguments
+    ///
+    /// * 'level' - The compression level used for zTXt and compressed iTXt.
+    ///
+    /// # Returns
+    ///
+    /// A result containing either the chunk data or a description of why
+    /// the text cannot be stored, such as an invalid keyword or Latin-1
+    /// text containing characters outside of Latin-1.
+    pub fn to_bytes(&self, level: u8) -> Result<Vec<u8>, String> {
+        validate_keyword(&self.keyword)?;
+        let mut bytes = latin1_encode(&self.keyword).ok_or("Keyword is not Latin-1")?;
+        bytes.push(0);
+
+        match &self.format {
+            TextFormat::Latin1 | TextFormat::Compressed => {
+                let text =
+                    latin1_encode(&self.text).ok_or("Text is not Latin-1, store it as iTXt")?;
+                if text.contains(&0) {
+                    return Err("Text contains a null character".to_string());
+                }
+                match self.format {
+                    TextFormat::Latin1 => bytes.extend(text),
+                    _ => {
+                        bytes.push(0);
+                        bytes.extend(zlib::compress(&text, level));
+                    }
+                }
+            }
+            TextFormat::International {
+                language_tag,
+                translated_keyword,
+                compressed,
+            } => {
+                // Language tags are RFC 3066 tags such as 'en' or 'x-klingon'.
+                if !language_tag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+                {
+                    return Err(format!("Invalid language tag '{language_tag}'"));
+                }
+                if translated_keyword.contains('\0') {
+                    return Err("Translated keyword contains a null character".to_string());
+                }
+
+                bytes.extend([*compressed as u8, 0]);
+                bytes.extend(language_tag.as_bytes());
+                bytes.push(0);
+                bytes.extend(translated_keyword.as_bytes());
+                bytes.push(0);
+                match compressed {
+                    true => bytes.extend(zlib::compress(self.text.as_bytes(), level)),
+                    false => bytes.extend(self.text.as_bytes()),
+                }
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// Decompresses the text of a zTXt or iTXt chunk, failing with
+/// LimitError::Text rather than growing past 'max_size' bytes.
+fn decompress_text(
+    bytes: &[u8],
+    max_size: usize,
+    error: impl Fn(&str) -> DecoderError,
+) -> Result<Vec<u8>, DecoderError> {
+    zlib::decompress_limited(bytes, max_size).map_err(|e| match e {
+        ZlibError::DeflateError(DeflateError::LimitExceeded(_)) => {
+            LimitError::Text { max: max_size }.into()
+        }
+        e => error(&e.to_string()),
+    })
+}
+
+/// Checks a keyword is 1 to 79 printable Latin-1 characters without
+/// leading, trailing or consecutive spaces.
+///
+/// # Returns
+///
+/// A result which is either empty or holds a description of the problem.
+pub fn validate_keyword(keyword: &str) -> Result<(), String> {
+    let length = keyword.chars().count();
+    if !(1..=79).contains(&length) {
+        return Err(format!(
+            "Keyword '{keyword}' is {length} characters, not 1 to 79"
+        ));
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(format!(
+            "Keyword '{keyword}' has leading, trailing or consecutive spaces"
+        ));
+    }
+    // Printable Latin-1 is 32 to 126 and 161 to 255.
+    if !keyword
+        .chars()
+        .all(|c| matches!(c as u32, 32..=126 | 161..=255))
+    {
+        return Err(format!(
+            "Keyword '{keyword}' contains a character which is not printable Latin-1"
+        ));
+    }
+    Ok(())
+}
+
+/// Splits bytes at the first null, dropping the null.
+pub(crate) fn split_at_null(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let index = bytes.iter().position(|&byte| byte == 0)?;
+    Some((&bytes[..index], &bytes[index + 1..]))
+}
+
+/// Decodes Latin-1, where every byte is the code point of its character.
+pub(crate) fn latin1_decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+/// Encodes Latin-1, returning None if a character is past U+00FF.
+pub(crate) fn latin1_encode(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|c| u8::try_from(c as u32).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(chunk: &TextChunk) -> TextChunk {
+        let bytes = chunk.to_bytes(6).unwrap();
+        TextChunk::parse(&chunk.chunk_type(), &bytes, usize::MAX).unwrap()
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let latin1 = TextChunk::new("Author", "Zoë Müller");
+        assert_eq!(latin1.format, TextFormat::Latin1);
+        assert_eq!(latin1.chunk_type(), *b"tEXt");
+        // Latin-1 is stored one byte per character.
+        assert_eq!(latin1.to_bytes(6).unwrap(), b"Author\0Zo\xEB M\xFCller");
+        assert_eq!(round_trip(&latin1), latin1);
+
+        let compressed = TextChunk {
+            keyword: "Comment".to_string(),
+            text: "Rendered again and again and again".to_string(),
+            format: TextFormat::Compressed,
+        };
+        assert_eq!(round_trip(&compressed), compressed);
+
+        for compressed in [false, true] {
+            let international = TextChunk {
+                keyword: "Title".to_string(),
+                text: "日本語のタイトル".to_string(),
+                format: TextFormat::International {
+                    language_tag: "ja-JP".to_string(),
+                    translated_keyword: "タイトル".to_string(),
+                    compressed,
+                },
+            };
+            assert_eq!(round_trip(&international), international);
+        }
+    }
+
+    #[test]
+    fn text_outside_latin1_becomes_itxt() {
+        let chunk = TextChunk::new("Title", "Snow ☃");
+        assert_eq!(chunk.chunk_type(), *b"iTXt");
+        assert_eq!(round_trip(&chunk), chunk);
+
+        let forced = TextChunk {
+            format: TextFormat::Latin1,
+            ..chunk
+        };
+        assert!(forced.to_bytes(6).is_err());
+    }
+
+    #[test]
+    fn keywords_are_validated() {
+        assert!(validate_keyword("Creation Time").is_ok());
+        assert!(validate_keyword(&"k".repeat(79)).is_ok());
+        for keyword in [
+            "",
+            " Lead",
+            "Trail ",
+            "Two  spaces",
+            "Tab\there",
+            &"k".repeat(80),
+        ] {
+            assert!(validate_keyword(keyword).is_err(), "{keyword:?}");
+        }
+        assert!(TextChunk::new(" Author", "June").to_bytes(6).is_err());
+        assert!(TextChunk::parse(b"tEXt", b" Author\0June", usize::MAX).is_err());
+    }
+
+    #[test]
+    fn malformed_chunks_are_rejected() {
+        let invalid: [(&[u8; 4], &[u8]); 6] = [
+            (b"tEXt", b"No separator"),
+            (b"zTXt", b"Comment\0"),
+            (b"zTXt", b"Comment\0\x01\x78\x9C"),
+            (b"zTXt", b"Comment\0\0not zlib"),
+            (b"iTXt", b"Title\0\x02\0\0\0text"),
+            (b"iTXt", b"Title\0\0\0en\0\0\xFF\xFE"),
+        ];
+        for (kind, bytes) in invalid {
+            assert!(
+                matches!(
+                    TextChunk::parse(kind, bytes, usize::MAX),
+                    Err(DecoderError::TextError(_))
+                ),
+                "{bytes:?}"
+            );
+        }
+    }
+}